@@ -159,7 +159,8 @@ fn get_features(
         Features::FULL_DRAW_INDEX_U32 |
         Features::FORMAT_BC |
         Features::INSTANCE_RATE |
-        Features::SAMPLER_MIP_LOD_BIAS;
+        Features::SAMPLER_MIP_LOD_BIAS |
+        Features::SAMPLER_MIRROR_CLAMP_EDGE;
 
     features
 }
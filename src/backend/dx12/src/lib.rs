@@ -1050,6 +1050,7 @@ impl hal::Instance for Instance {
                     Features::FORMAT_BC |
                     Features::INSTANCE_RATE |
                     Features::SAMPLER_MIP_LOD_BIAS |
+                    Features::SAMPLER_MIRROR_CLAMP_EDGE |
                     Features::SAMPLER_ANISOTROPY,
                 limits: Limits { // TODO
                     max_image_1d_size: d3d12::D3D12_REQ_TEXTURE1D_U_DIMENSION as _,
@@ -0,0 +1,38 @@
+//! Barriers not covered by `glow`.
+
+use std::os::raw::c_void;
+
+use crate::fns;
+
+type TextureBarrier = unsafe extern "system" fn();
+type MemoryBarrier = unsafe extern "system" fn(u32);
+type BlendBarrier = unsafe extern "system" fn();
+
+/// Entry points of `GL_ARB_texture_barrier`, `GL_ARB_shader_image_load_store`
+/// and `GL_KHR_blend_equation_advanced`.
+#[derive(Default)]
+pub(crate) struct BarrierFns {
+    /// Makes preceding framebuffer writes visible to texture fetches, which
+    /// allows sampling attachments of the bound framebuffer.
+    pub(crate) texture_barrier: Option<TextureBarrier>,
+    /// Makes preceding incoherent shader writes visible to the given accesses.
+    pub(crate) memory_barrier: Option<MemoryBarrier>,
+    /// Orders the framebuffer reads of advanced blending after preceding draws.
+    pub(crate) blend_barrier: Option<BlendBarrier>,
+}
+
+impl BarrierFns {
+    pub(crate) fn load<F>(fn_proc: &mut F) -> Self
+    where F: FnMut(&str) -> *const c_void {
+        unsafe {
+            BarrierFns {
+                texture_barrier: fns::load(fn_proc, &["glTextureBarrier", "glTextureBarrierNV"]),
+                memory_barrier: fns::load(fn_proc, &["glMemoryBarrier", "glMemoryBarrierEXT"]),
+                blend_barrier: fns::load(
+                    fn_proc,
+                    &["glBlendBarrier", "glBlendBarrierKHR", "glBlendBarrierNV"],
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Bindless textures (`GL_ARB_bindless_texture`).
+//!
+//! In bindless mode, see `PhysicalDevice::set_bindless_textures`, sampled
+//! image descriptors are resolved to resident texture handles when they are
+//! written. Each descriptor set stores its handles in a uniform buffer, which
+//! is bound instead of binding every texture to a texture unit.
+//!
+//! Shaders declare the handles of a set as a `std140` uniform block of
+//! samplers, holding the elements of all sampled image bindings of the set in
+//! binding order, e.g. `uniform Set0 { sampler2D textures[8]; };`. The block
+//! of each set is bound to the binding reported by
+//! `PipelineLayout::bindless_block`.
+
+use std::mem;
+use std::os::raw::c_void;
+
+use glow::Context;
+
+use crate::hal::pso;
+use crate::{fns, native as n, Backend as B, Device};
+
+type GetTextureHandle = unsafe extern "system" fn(u32) -> u64;
+type GetTextureSamplerHandle = unsafe extern "system" fn(u32, u32) -> u64;
+type TextureHandle = unsafe extern "system" fn(u64);
+type IsTextureHandleResident = unsafe extern "system" fn(u64) -> u8;
+
+/// `std140` array stride of sampler handles.
+pub(crate) const HANDLE_STRIDE: usize = 16;
+
+/// Entry points of `GL_ARB_bindless_texture`.
+#[derive(Default)]
+pub(crate) struct BindlessFns {
+    get_texture_handle: Option<GetTextureHandle>,
+    get_texture_sampler_handle: Option<GetTextureSamplerHandle>,
+    make_texture_handle_resident: Option<TextureHandle>,
+    is_texture_handle_resident: Option<IsTextureHandleResident>,
+}
+
+impl BindlessFns {
+    pub(crate) fn load<F>(fn_proc: &mut F) -> Self
+    where F: FnMut(&str) -> *const c_void {
+        unsafe {
+            BindlessFns {
+                get_texture_handle: fns::load(fn_proc, &["glGetTextureHandleARB"]),
+                get_texture_sampler_handle: fns::load(fn_proc, &["glGetTextureSamplerHandleARB"]),
+                make_texture_handle_resident: fns::load(
+                    fn_proc,
+                    &["glMakeTextureHandleResidentARB"],
+                ),
+                is_texture_handle_resident: fns::load(fn_proc, &["glIsTextureHandleResidentARB"]),
+            }
+        }
+    }
+
+    pub(crate) fn is_loaded(&self) -> bool {
+        self.get_texture_handle.is_some()
+            && self.get_texture_sampler_handle.is_some()
+            && self.make_texture_handle_resident.is_some()
+            && self.is_texture_handle_resident.is_some()
+    }
+}
+
+/// Handle slot of an array element of a sampled image binding.
+fn handle_slot(
+    layout: &n::DescriptorSetLayout,
+    binding: pso::DescriptorBinding,
+    index: pso::DescriptorArrayIndex,
+) -> Option<usize> {
+    let mut sampled = layout
+        .iter()
+        .filter(|b| match b.ty {
+            pso::DescriptorType::CombinedImageSampler | pso::DescriptorType::SampledImage => true,
+            _ => false,
+        })
+        .collect::<Vec<_>>();
+    sampled.sort_by_key(|b| b.binding);
+
+    let mut slot = 0;
+    for b in sampled {
+        if b.binding == binding {
+            return if index < b.count { Some(slot + index) } else { None };
+        }
+        slot += b.count;
+    }
+    None
+}
+
+impl Device {
+    /// Resolve a texture, and optionally a sampler object, to a resident
+    /// handle and write it to the handle buffer of the set.
+    ///
+    /// Handles stay resident until the texture is deleted. Creating a handle
+    /// makes the sampling state of the texture immutable.
+    unsafe fn write_texture_handle(
+        &self,
+        set: &n::DescriptorSet,
+        binding: pso::DescriptorBinding,
+        index: pso::DescriptorArrayIndex,
+        texture: n::Texture,
+        sampler: Option<n::Sampler>,
+    ) {
+        let gl = &self.share.context;
+        let fns = &gl.bindless;
+        let slot = match handle_slot(&set.layout, binding, index) {
+            Some(slot) => slot,
+            None => {
+                error!("Binding {} isn't a sampled image binding of the set", binding);
+                return;
+            }
+        };
+
+        let handle = match sampler {
+            Some(sampler) => (fns.get_texture_sampler_handle.unwrap())(texture, sampler),
+            None => (fns.get_texture_handle.unwrap())(texture),
+        };
+        if (fns.is_texture_handle_resident.unwrap())(handle) == 0 {
+            (fns.make_texture_handle_resident.unwrap())(handle);
+        }
+
+        let mut table = set.handles.lock().unwrap();
+        let (buffer, _) = *table.get_or_insert_with(|| {
+            let counts = n::descriptor_counts(&set.layout);
+            let handles = counts[pso::DescriptorType::CombinedImageSampler as usize]
+                + counts[pso::DescriptorType::SampledImage as usize];
+            let size = (handles * HANDLE_STRIDE) as i32;
+            let buffer = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::UNIFORM_BUFFER, Some(buffer));
+            gl.buffer_data_size(glow::UNIFORM_BUFFER, size, glow::DYNAMIC_DRAW);
+            set.pool_buffers.lock().unwrap().push(buffer);
+            (buffer, size)
+        });
+
+        let mut data = [0u8; HANDLE_STRIDE];
+        data[..8].copy_from_slice(&mem::transmute::<u64, [u8; 8]>(handle));
+        gl.bind_buffer(glow::UNIFORM_BUFFER, Some(buffer));
+        gl.buffer_sub_data_u8_slice(glow::UNIFORM_BUFFER, (slot * HANDLE_STRIDE) as i32, &data);
+        gl.bind_buffer(glow::UNIFORM_BUFFER, None);
+
+        if let Err(err) = self.share.check() {
+            error!("Error writing texture handle: {:?}", err);
+        }
+    }
+
+    /// Write the texture handle of a sampled image descriptor.
+    pub(crate) unsafe fn write_descriptor_handle(
+        &self,
+        set: &n::DescriptorSet,
+        binding: pso::DescriptorBinding,
+        index: pso::DescriptorArrayIndex,
+        descriptor: &pso::Descriptor<B>,
+    ) {
+        let (view, sampler) = match *descriptor {
+            pso::Descriptor::CombinedImageSampler(view, _, &n::FatSampler::Sampler(sampler)) => {
+                (view, Some(sampler))
+            }
+            pso::Descriptor::CombinedImageSampler(view, _, &n::FatSampler::Info(_))
+            | pso::Descriptor::Image(view, _) => (view, None),
+            _ => return,
+        };
+        match *view {
+            n::ImageView::Texture(texture, ..) | n::ImageView::TextureLayer(texture, ..) => {
+                self.write_texture_handle(set, binding, index, texture, sampler)
+            }
+            n::ImageView::Surface(_) => {}
+        }
+    }
+}
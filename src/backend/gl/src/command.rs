@@ -7,7 +7,7 @@ use crate::hal::range::RangeArg;
 use crate::hal::{self, buffer, command, image, memory, pass, pso, query, ColorSlot};
 
 use crate::pool::{self, BufferMemory};
-use crate::{native as n, Backend};
+use crate::{conv, native as n, AdvancedBlendOp, Backend};
 
 use std::borrow::Borrow;
 use std::ops::Range;
@@ -76,6 +76,8 @@ pub enum Command {
     BindRasterizer {
         rasterizer: pso::Rasterizer,
     },
+    /// Discard primitives before rasterization during draws.
+    SetRasterizerDiscard(bool),
     BindDepth {
         depth: pso::DepthTest,
     },
@@ -86,6 +88,7 @@ pub enum Command {
     },
     SetScissors(u32, BufferSlice),
     SetBlendColor(pso::ColorValue),
+    SetDepthBias(pso::DepthBias),
 
     /// Clear floating-point color drawbuffer of bound framebuffer.
     ClearBufferColorF(DrawBuffer, [f32; 4]),
@@ -103,15 +106,54 @@ pub enum Command {
     DrawBuffers(BufferSlice),
 
     BindFrameBuffer(FrameBufferTarget, Option<n::FrameBuffer>),
+    /// Encode color writes to sRGB attachments of the bound framebuffer.
+    SetFrameBufferSrgb(bool),
+    /// Enter a subpass of a render pass with input attachments, given the
+    /// framebuffer, the subpass index and if the subpass reads attachments.
+    BeginSubpass(n::FrameBuffer, pass::SubpassId, bool),
+    /// Synchronize incoherent shader writes (`glMemoryBarrier`).
+    MemoryBarrier(u32),
+    /// Make framebuffer writes visible to sampling the attachments of the
+    /// bound framebuffer (`glTextureBarrier`).
+    TextureBarrier,
+    /// Set or reset the flag of an event.
+    SetEvent(n::Event, bool),
+    /// Wait for the events to be set, the barriers are recorded separately.
+    WaitEvents(Vec<n::Event>),
+    /// Begin an occlusion query, which counts samples if precise.
+    BeginOcclusionQuery(u32, bool),
+    /// End the active occlusion query, which counts samples if precise.
+    EndOcclusionQuery(bool),
+    WriteTimestamp(u32),
+    /// Write the result of a query to a buffer at the given offset.
+    CopyQueryResult(u32, n::RawBuffer, buffer::Offset, query::ResultFlags),
+    /// Invalidate attachments of the bound framebuffer.
+    /// The buffer slice contains a list of `GLenum`.
+    InvalidateFrameBuffer(FrameBufferTarget, BufferSlice),
     BindTargetView(FrameBufferTarget, AttachmentPoint, n::ImageView),
     SetDrawColorBuffers(usize),
     SetPatchSize(i32),
+    /// Set the sample count of the bound framebuffer without attachments.
+    SetFrameBufferSamples(i32),
     BindProgram(<GlContext as glow::Context>::Program),
     BindBlendSlot(ColorSlot, pso::ColorBlendDesc),
+    /// Upload the flattened uniform blocks of the bound program before draws.
+    BindBlockUniforms(Option<Arc<n::BlockUniforms>>),
+    /// Blend draws with an advanced blend equation, or with the blend state of
+    /// the bound pipeline again.
+    SetAdvancedBlend(Option<AdvancedBlendOp>),
     BindAttribute(n::AttributeDesc, n::RawBuffer, i32, u32),
     //UnbindAttribute(n::AttributeDesc),
     CopyBufferToBuffer(n::RawBuffer, n::RawBuffer, command::BufferCopy),
-    CopyBufferToTexture(n::RawBuffer, n::Texture, n::TextureType, command::BufferImageCopy),
+    /// Write data to a buffer through the staging ring of the queue.
+    UpdateBuffer(n::RawBuffer, buffer::Offset, BufferSlice),
+    CopyBufferToTexture(
+        n::RawBuffer,
+        n::Texture,
+        n::TextureType,
+        n::TexelUpload,
+        command::BufferImageCopy,
+    ),
     CopyBufferToSurface(n::RawBuffer, n::Surface, command::BufferImageCopy),
     CopyTextureToBuffer(n::Texture, n::TextureType, n::RawBuffer, command::BufferImageCopy),
     CopySurfaceToBuffer(n::Surface, n::RawBuffer, command::BufferImageCopy),
@@ -119,7 +161,8 @@ pub enum Command {
     CopyImageToSurface(n::ImageKind, n::Surface, command::ImageCopy),
 
     BindBufferRange(u32, u32, n::RawBuffer, i32, i32),
-    BindTexture(u32, n::Texture, n::TextureType),
+    /// Bind a texture to a unit, restricted to the levels of a view.
+    BindTexture(u32, n::Texture, n::TextureType, Option<n::LevelRange>),
     BindSampler(u32, n::Sampler),
     SetTextureSamplerSettings(u32, n::Texture, n::TextureType, image::SamplerInfo),
 }
@@ -142,6 +185,51 @@ pub struct RenderPassCache {
     attachment_clears: Vec<AttachmentClear>,
 }
 
+impl RenderPassCache {
+    /// Attachment points to invalidate, `discard` selects if the color or depth,
+    /// and the stencil contents of an attachment are discarded.
+    fn invalidated_attachments<F>(&self, discard: F) -> Vec<u32>
+    where
+        F: Fn(pass::AttachmentId, &pass::Attachment) -> (bool, bool),
+    {
+        let is_default = self.framebuffer.is_none();
+        let mut color_attachment = glow::COLOR_ATTACHMENT0;
+        let mut invalidate = Vec::new();
+        for (id, attachment) in self.render_pass.attachments.iter().enumerate() {
+            let format = match attachment.format {
+                Some(format) => format,
+                None => continue,
+            };
+            let (discard, discard_stencil) = discard(id, attachment);
+            if format.is_depth() || format.is_stencil() {
+                let depth = format.is_depth() && discard;
+                let stencil = format.is_stencil() && discard_stencil;
+                if is_default {
+                    if depth {
+                        invalidate.push(glow::DEPTH);
+                    }
+                    if stencil {
+                        invalidate.push(glow::STENCIL);
+                    }
+                } else {
+                    match (depth, stencil) {
+                        (true, true) => invalidate.push(glow::DEPTH_STENCIL_ATTACHMENT),
+                        (true, false) => invalidate.push(glow::DEPTH_ATTACHMENT),
+                        (false, true) => invalidate.push(glow::STENCIL_ATTACHMENT),
+                        (false, false) => {}
+                    }
+                }
+            } else {
+                if discard {
+                    invalidate.push(if is_default { glow::COLOR } else { color_attachment });
+                }
+                color_attachment += 1;
+            }
+        }
+        invalidate
+    }
+}
+
 // Cache current states of the command buffer
 #[derive(Debug)]
 struct Cache {
@@ -162,8 +250,16 @@ struct Cache {
     patch_size: Option<i32>,
     // Active program name.
     program: Option<n::Program>,
+    // Rasterization samples of the current pipeline.
+    rasterization_samples: Option<i32>,
+    // Precision of the active occlusion query.
+    occlusion_query: Option<bool>,
     // Blend per attachment.
     blend_targets: Option<Vec<Option<pso::ColorBlendDesc>>>,
+    // Advanced blend equation overriding the blend state of the pipeline.
+    advanced_blend: Option<AdvancedBlendOp>,
+    // Indicates if the current pipeline has flattened uniform blocks.
+    block_uniforms: bool,
     // Maps bound vertex buffer offset (index) to handle / buffer range
     vertex_buffers: Vec<Option<(n::RawBuffer, Range<buffer::Offset>)>>,
     // Active vertex buffer descriptions.
@@ -185,7 +281,11 @@ impl Cache {
             error_state: false,
             patch_size: None,
             program: None,
+            rasterization_samples: None,
+            occlusion_query: None,
             blend_targets: None,
+            advanced_blend: None,
+            block_uniforms: false,
             vertex_buffers: Vec::new(),
             vertex_buffer_descs: Vec::new(),
             attributes: Vec::new(),
@@ -199,12 +299,14 @@ impl Cache {
 #[derive(Debug, Clone, Copy)]
 pub struct Limits {
     max_viewports: usize,
+    max_draw_indexed_index_value: hal::IndexCount,
 }
 
 impl From<hal::Limits> for Limits {
     fn from(l: hal::Limits) -> Self {
         Limits {
             max_viewports: l.max_viewports,
+            max_draw_indexed_index_value: l.max_draw_indexed_index_value,
         }
     }
 }
@@ -292,10 +394,256 @@ impl RawCommandBuffer {
         self.cur_subpass = !0;
     }
 
+    // Reset the buffer to its freshly allocated state, for reuse by the pool.
+    pub(crate) fn recycle(&mut self) {
+        self.soft_reset();
+        self.display_fb = None;
+        self.active_attribs = 0;
+    }
+
+    /// Write descriptors of a set directly into the command buffer, without
+    /// allocating a descriptor set. GL has no set objects, so the descriptors
+    /// are bound right away like the ones of a bound set.
+    ///
+    /// Each write is given as binding, first array element and descriptors.
+    pub unsafe fn push_graphics_descriptor_set<'a, I, J>(
+        &mut self,
+        layout: &n::PipelineLayout,
+        set: usize,
+        writes: I,
+    ) where
+        I: IntoIterator<Item = (pso::DescriptorBinding, pso::DescriptorArrayIndex, J)>,
+        J: IntoIterator,
+        J::Item: Borrow<pso::Descriptor<'a, Backend>>,
+    {
+        let set = set as pso::DescriptorSetIndex;
+        let drd = &*layout.desc_remap_data.read().unwrap();
+        let mut bindings = Vec::new();
+        for (binding, array_offset, descriptors) in writes {
+            for (index, descriptor) in (array_offset..).zip(descriptors) {
+                n::write_descriptor(&mut bindings, binding, index, descriptor.borrow());
+            }
+        }
+
+        // Texture handles of bindless sets live in a buffer of the set.
+        let bind_images = drd.get_bindless_block(set).is_none();
+        if !bind_images {
+            warn!("Images can't be pushed to set {} which uses bindless textures", set);
+        }
+        self.bind_descriptors(drd, set, &bindings, bind_images);
+    }
+
+    /// Blend the following draws with an advanced blend equation, replacing
+    /// the blend operations and factors of the bound pipeline until `None` is
+    /// set or another pipeline is bound.
+    ///
+    /// Only a single color attachment can be blended and fragment shaders
+    /// declare support for all equations. Blend barriers are inserted between
+    /// draws unless blending is coherent, see
+    /// `PhysicalDevice::supports_advanced_blend`.
+    pub fn set_advanced_blend(&mut self, operation: Option<AdvancedBlendOp>) {
+        if self.cache.advanced_blend == operation {
+            return;
+        }
+        self.cache.advanced_blend = operation;
+        self.push_cmd(Command::SetAdvancedBlend(operation));
+        if operation.is_none() {
+            // The advanced equation was set for all slots, restore the pipeline ones.
+            let targets = self.cache.blend_targets.clone().unwrap_or_default();
+            for (slot, target) in targets.into_iter().enumerate() {
+                if let Some(target) = target {
+                    self.push_cmd(Command::BindBlendSlot(slot as _, target));
+                }
+            }
+        }
+    }
+
     fn push_cmd(&mut self, cmd: Command) {
         push_cmd_internal(&self.id, &mut self.memory, &mut self.buf, cmd);
     }
 
+    /// Record the binding of the written descriptors of a set. Images are
+    /// skipped in bindless mode, where the texture handles replace them.
+    fn bind_descriptors(
+        &mut self,
+        drd: &n::DescRemapData,
+        set: pso::DescriptorSetIndex,
+        bindings: &[(pso::DescriptorArrayIndex, n::DescSetBindings)],
+        bind_images: bool,
+    ) {
+        for &(index, ref new_binding) in bindings {
+            // Array elements are bound to consecutive bindings.
+            let btype = match new_binding {
+                n::DescSetBindings::Buffer { .. } => n::BindingTypes::UniformBuffers,
+                _ => n::BindingTypes::Images,
+            };
+            if !bind_images && btype == n::BindingTypes::Images {
+                continue;
+            }
+            if index >= drd.get_array_size(btype, set, new_binding.binding()) {
+                warn!("Descriptor array element {} is out of bounds", index);
+                continue;
+            }
+            let element = index as pso::DescriptorBinding;
+
+            match new_binding {
+                n::DescSetBindings::Buffer {
+                    ty: btype,
+                    binding,
+                    buffer,
+                    offset,
+                    size,
+                } => {
+                    let btype = match btype {
+                        n::BindingTypes::UniformBuffers => glow::UNIFORM_BUFFER,
+                        n::BindingTypes::Images => panic!("Wrong desc set binding"),
+                    };
+                    for binding in drd
+                        .get_binding(n::BindingTypes::UniformBuffers, set, *binding)
+                        .unwrap()
+                    {
+                        self.push_cmd(Command::BindBufferRange(
+                            btype,
+                            *binding + element,
+                            *buffer,
+                            *offset as i32,
+                            *size as i32,
+                        ))
+                    }
+                }
+                n::DescSetBindings::Texture(binding, texture, textype, levels) => {
+                    for binding in drd
+                        .get_binding(n::BindingTypes::Images, set, *binding)
+                        .unwrap()
+                    {
+                        self.push_cmd(Command::BindTexture(
+                            *binding + element,
+                            *texture,
+                            *textype,
+                            Some(*levels),
+                        ))
+                    }
+                }
+                n::DescSetBindings::Sampler(binding, sampler) => {
+                    for binding in drd
+                        .get_binding(n::BindingTypes::Images, set, *binding)
+                        .unwrap()
+                    {
+                        self.push_cmd(Command::BindSampler(*binding + element, *sampler))
+                    }
+                }
+                n::DescSetBindings::SamplerInfo(binding, sinfo) => {
+                    let mut all_txts = drd
+                        .get_binding(n::BindingTypes::Images, set, *binding)
+                        .unwrap()
+                        .into_iter()
+                        .map(|binding| *binding + element)
+                        .flat_map(|binding| {
+                            bindings.iter().filter_map(move |&(i, ref b)| {
+                                if let n::DescSetBindings::Texture(b, t, ttype, _) = b {
+                                    let nbs =
+                                        drd.get_binding(n::BindingTypes::Images, set, *b)?;
+                                    let i = i as pso::DescriptorBinding;
+                                    if nbs.iter().any(|nb| nb + i == binding) {
+                                        Some((binding, *t, *ttype))
+                                    } else {
+                                        None
+                                    }
+                                } else {
+                                    None
+                                }
+                            })
+                        })
+                        .collect::<Vec<_>>();
+
+                    // TODO: Check that other samplers aren't using the same
+                    // textures as in `all_txts` unless all the bindings of that
+                    // texture are gonna be unbound or the two samplers have
+                    // identical properties.
+                    all_txts.sort_unstable_by(|a, b| a.1.cmp(&b.1));
+                    all_txts.dedup_by(|a, b| a.1 == b.1);
+
+                    for (binding, txt, textype) in all_txts {
+                        self.push_cmd(Command::SetTextureSamplerSettings(
+                            binding,
+                            txt,
+                            textype,
+                            sinfo.clone(),
+                        ))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Record the synchronization required by the memory barriers.
+    fn push_barriers<'a, T>(&mut self, barriers: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        // Only incoherent shader writes need to be synchronized, layouts and
+        // queue family transfers don't exist in GL.
+        let mut memory_barrier = 0;
+        // Attachments written in a render pass and sampled afterwards, e.g. for
+        // programmable blending, stay bound to the framebuffer.
+        let attachment_writes = image::Access::COLOR_ATTACHMENT_WRITE
+            | image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE;
+        let attachment_reads = image::Access::INPUT_ATTACHMENT_READ | image::Access::SHADER_READ;
+        let mut texture_barrier = false;
+        for barrier in barriers {
+            let image_access = match *barrier.borrow() {
+                memory::Barrier::AllImages(ref access) => Some(access.clone()),
+                memory::Barrier::Image { ref states, .. } => Some(states.start.0..states.end.0),
+                _ => None,
+            };
+            if let Some(access) = image_access {
+                texture_barrier |= self.pass_cache.is_some()
+                    && access.start.intersects(attachment_writes)
+                    && access.end.intersects(attachment_reads);
+            }
+            memory_barrier |= match *barrier.borrow() {
+                memory::Barrier::AllBuffers(ref access) => {
+                    conv::buffer_access_to_memory_barrier(access.start, access.end)
+                }
+                memory::Barrier::AllImages(ref access) => {
+                    conv::access_to_memory_barrier(access.start, access.end)
+                }
+                memory::Barrier::Buffer { ref states, .. } => {
+                    conv::buffer_access_to_memory_barrier(states.start, states.end)
+                }
+                memory::Barrier::Image { ref states, .. } => {
+                    conv::access_to_memory_barrier(states.start.0, states.end.0)
+                }
+            };
+        }
+        if memory_barrier != 0 {
+            self.push_cmd(Command::MemoryBarrier(memory_barrier));
+        }
+        if texture_barrier {
+            self.push_cmd(Command::TextureBarrier);
+        }
+    }
+
+    /// Copy `rows` rows of `row_size` bytes between buffers, given as buffer,
+    /// offset and row pitch. Used for copies involving linear images.
+    fn copy_rows(
+        &mut self,
+        (src, src_offset, src_pitch): (n::RawBuffer, buffer::Offset, buffer::Offset),
+        (dst, dst_offset, dst_pitch): (n::RawBuffer, buffer::Offset, buffer::Offset),
+        row_size: buffer::Offset,
+        rows: image::Size,
+    ) {
+        for row in 0..rows as buffer::Offset {
+            let copy = command::BufferCopy {
+                src: src_offset + row * src_pitch,
+                dst: dst_offset + row * dst_pitch,
+                size: row_size,
+            };
+            self.push_cmd(Command::CopyBufferToBuffer(src, dst, copy));
+        }
+    }
+
     /// Copy a given vector slice into the data buffer.
     fn add<T>(&mut self, data: &[T]) -> BufferSlice {
         self.add_raw(unsafe {
@@ -408,10 +756,32 @@ impl RawCommandBuffer {
 
     fn begin_subpass(&mut self) {
         // Split processing and command recording due to borrowchk.
-        let (draw_buffers, clear_cmds) = {
+        let (begin_cmd, memory_barrier, invalidate, draw_buffers, clear_cmds) = {
             let state = self.pass_cache.as_ref().unwrap();
             let subpass = &state.render_pass.subpasses[self.cur_subpass];
 
+            // Attachments are sampled from the attachments of the framebuffer,
+            // which needs a texture barrier or a framebuffer per subpass.
+            let begin_cmd = match state.framebuffer {
+                Some(fbo) if state.render_pass.reads_attachments() => Some(
+                    Command::BeginSubpass(fbo, self.cur_subpass, subpass.reads_attachments),
+                ),
+                _ => None,
+            };
+
+            // Attachments first used by this subpass with `DontCare` load ops are
+            // invalidated, which avoids loading them from memory on tilers.
+            let cur_subpass = self.cur_subpass;
+            let invalidate = state.invalidated_attachments(|id, attachment| {
+                match state.render_pass.attachment_uses(id) {
+                    Some((first, _)) if first == cur_subpass => (
+                        attachment.ops.load == pass::AttachmentLoadOp::DontCare,
+                        attachment.stencil_ops.load == pass::AttachmentLoadOp::DontCare,
+                    ),
+                    _ => (false, false),
+                }
+            });
+
             // See `begin_renderpass_cache` for clearing strategy
 
             // Bind draw buffers for mapping color output locations with
@@ -494,10 +864,20 @@ impl RawCommandBuffer {
                 })
                 .collect::<Vec<_>>();
 
-            (draw_buffers, clear_cmds)
+            (begin_cmd, subpass.memory_barrier, invalidate, draw_buffers, clear_cmds)
         };
 
         // Record commands
+        if let Some(cmd) = begin_cmd {
+            self.push_cmd(cmd);
+        }
+        if memory_barrier != 0 {
+            self.push_cmd(Command::MemoryBarrier(memory_barrier));
+        }
+        if !invalidate.is_empty() {
+            let attachments = self.add(&invalidate);
+            self.push_cmd(Command::InvalidateFrameBuffer(glow::DRAW_FRAMEBUFFER, attachments));
+        }
         let draw_buffers = self.add(&draw_buffers);
         self.push_cmd(Command::DrawBuffers(draw_buffers));
 
@@ -505,6 +885,46 @@ impl RawCommandBuffer {
             self.push_cmd(cmd);
         }
     }
+
+    /// Whether the current render pass renders to a framebuffer without attachments.
+    fn is_attachment_less_pass(&self) -> bool {
+        match self.pass_cache {
+            Some(ref state) => {
+                state.framebuffer.is_some() && state.render_pass.attachments.is_empty()
+            }
+            None => false,
+        }
+    }
+
+    fn end_subpass(&mut self) {
+        // Attachments last used by this subpass with `DontCare` store ops are
+        // invalidated, which avoids writing transient attachments back to memory
+        // on tilers. Unused attachments are invalidated by the last subpass.
+        let invalidate = {
+            let state = self.pass_cache.as_ref().unwrap();
+            let cur_subpass = self.cur_subpass;
+            let is_last = cur_subpass + 1 == state.render_pass.subpasses.len();
+            state.invalidated_attachments(|id, attachment| {
+                let ends = match state.render_pass.attachment_uses(id) {
+                    Some((_, last)) => last == cur_subpass,
+                    None => is_last,
+                };
+                if ends {
+                    (
+                        attachment.ops.store == pass::AttachmentStoreOp::DontCare,
+                        attachment.stencil_ops.store == pass::AttachmentStoreOp::DontCare,
+                    )
+                } else {
+                    (false, false)
+                }
+            })
+        };
+
+        if !invalidate.is_empty() {
+            let attachments = self.add(&invalidate);
+            self.push_cmd(Command::InvalidateFrameBuffer(glow::DRAW_FRAMEBUFFER, attachments));
+        }
+    }
 }
 
 impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
@@ -557,12 +977,12 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         &mut self,
         _stages: Range<hal::pso::PipelineStage>,
         _dependencies: memory::Dependencies,
-        _barriers: T,
+        barriers: T,
     ) where
         T: IntoIterator,
         T::Item: Borrow<memory::Barrier<'a, Backend>>,
     {
-        // TODO
+        self.push_barriers(barriers);
     }
 
     unsafe fn fill_buffer<R>(&mut self, _buffer: &n::Buffer, _range: R, _data: u32)
@@ -572,8 +992,10 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         unimplemented!()
     }
 
-    unsafe fn update_buffer(&mut self, _buffer: &n::Buffer, _offset: buffer::Offset, _data: &[u8]) {
-        unimplemented!()
+    unsafe fn update_buffer(&mut self, buffer: &n::Buffer, offset: buffer::Offset, data: &[u8]) {
+        let (raw, range) = buffer.as_bound();
+        let data = self.add_raw(data);
+        self.push_cmd(Command::UpdateBuffer(raw, range.start + offset, data));
     }
 
     unsafe fn begin_render_pass<T>(
@@ -597,15 +1019,19 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         //      select correct ClearBuffer variant.
         //    * Check for attachment loading clearing strategy
 
-        // TODO: store ops:
-        //   < GL 4.5: Ignore
-        //  >= GL 4.5: Invalidate framebuffer attachment when store op is `DONT_CARE`.
-
         // 2./3.
+        // Swapchain images on their own render to the display framebuffer.
         self.push_cmd(Command::BindFrameBuffer(
             glow::DRAW_FRAMEBUFFER,
-            *framebuffer,
+            framebuffer.or(self.display_fb),
         ));
+        // Only passes with sRGB color attachments encode their writes.
+        let srgb = render_pass.attachments.iter().any(|attachment| {
+            attachment
+                .format
+                .map_or(false, |format| format.base_format().1 == ChannelType::Srgb)
+        });
+        self.push_cmd(Command::SetFrameBufferSrgb(srgb));
 
         let mut clear_values_iter = clear_values.into_iter();
         let attachment_clears = render_pass
@@ -640,17 +1066,29 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
             attachment_clears,
         });
 
+        if let Some(samples) = self.cache.rasterization_samples {
+            if self.is_attachment_less_pass() {
+                self.push_cmd(Command::SetFrameBufferSamples(samples));
+            }
+        }
+
         // Enter first subpass
         self.cur_subpass = 0;
         self.begin_subpass();
     }
 
     unsafe fn next_subpass(&mut self, _contents: command::SubpassContents) {
-        unimplemented!()
+        self.end_subpass();
+        self.cur_subpass += 1;
+        self.begin_subpass();
     }
 
     unsafe fn end_render_pass(&mut self) {
-        // TODO
+        if self.pass_cache.is_none() {
+            return;
+        }
+        self.end_subpass();
+        self.pass_cache = None;
     }
 
     unsafe fn clear_image<T>(
@@ -669,15 +1107,25 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         //  2.  < GL 4.4: glClearBuffer
         //  3. >= GL 4.4: glClearTexSubImage
 
+        if let n::ImageKind::Linear(_) = image.kind {
+            error!("Linear images can't be cleared");
+            self.cache.error_state = true;
+            return;
+        }
+
         match self.fbo {
             Some(fbo) => {
                 // TODO: reset color mask
                 // 2. ClearBuffer
                 let view = match image.kind {
                     n::ImageKind::Surface(id) => n::ImageView::Surface(id),
-                    n::ImageKind::Texture(id, textype) => n::ImageView::Texture(id, textype, 0), //TODO
+                    n::ImageKind::Texture(id, textype) => {
+                        n::ImageView::Texture(id, textype, n::LevelRange::single(0)) //TODO
+                    }
+                    n::ImageKind::Linear(_) => unreachable!(),
                 };
                 self.push_cmd(Command::BindFrameBuffer(glow::DRAW_FRAMEBUFFER, Some(fbo)));
+                self.push_cmd(Command::SetFrameBufferSrgb(image.channel == ChannelType::Srgb));
                 self.push_cmd(Command::BindTargetView(
                     glow::DRAW_FRAMEBUFFER,
                     glow::COLOR_ATTACHMENT0,
@@ -704,9 +1152,10 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
                 let (tex, textype) = match image.kind {
                     n::ImageKind::Texture(id, textype) => (id, textype), //TODO
                     n::ImageKind::Surface(_id) => unimplemented!(),
+                    n::ImageKind::Linear(_) => unreachable!(),
                 };
 
-                self.push_cmd(Command::BindTexture(0, tex, textype));
+                self.push_cmd(Command::BindTexture(0, tex, textype, None));
                 self.push_cmd(Command::ClearTexture(color.float32));
             }
         }
@@ -754,6 +1203,16 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
     unsafe fn bind_index_buffer(&mut self, ibv: buffer::IndexBufferView<Backend>) {
         let (raw_buffer, range) = ibv.buffer.as_bound();
 
+        // GLES2 and WebGL1 only have 16-bit indices without `OES_element_index_uint`.
+        if ibv.index_type == hal::IndexType::U32
+            && self.limits.max_draw_indexed_index_value <= u16::max_value() as _
+        {
+            error!("32-bit indices are not supported, `OES_element_index_uint` is missing");
+            self.cache.index_type_range = None;
+            self.cache.error_state = true;
+            return;
+        }
+
         self.cache.index_type_range = Some((ibv.index_type, range.start + ibv.offset..range.end));
         self.push_cmd(Command::BindIndexBuffer(raw_buffer));
     }
@@ -903,19 +1362,22 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         unimplemented!()
     }
 
-    unsafe fn set_depth_bias(&mut self, _depth_bias: pso::DepthBias) {
-        unimplemented!()
+    unsafe fn set_depth_bias(&mut self, depth_bias: pso::DepthBias) {
+        self.push_cmd(Command::SetDepthBias(depth_bias));
     }
 
     unsafe fn bind_graphics_pipeline(&mut self, pipeline: &n::GraphicsPipeline) {
         let n::GraphicsPipeline {
             primitive,
+            rasterization_samples,
+            rasterizer_discard,
             patch_size,
             program,
             ref blend_targets,
             ref attributes,
             ref vertex_buffers,
             ref uniforms,
+            ref block_uniforms,
             rasterizer,
             depth,
         } = *pipeline;
@@ -936,17 +1398,38 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
             self.push_cmd(Command::BindProgram(program));
         }
 
+        if self.cache.rasterization_samples != Some(rasterization_samples) {
+            self.cache.rasterization_samples = Some(rasterization_samples);
+            if self.is_attachment_less_pass() {
+                self.push_cmd(Command::SetFrameBufferSamples(rasterization_samples));
+            }
+        }
+
         self.cache.attributes = attributes.clone();
 
         self.cache.vertex_buffer_descs = vertex_buffers.clone();
 
         self.cache.uniforms = uniforms.clone();
 
+        // Uniform blocks without UBO support are uploaded before each draw.
+        let has_block_uniforms = !block_uniforms.uniforms.is_empty();
+        if has_block_uniforms || self.cache.block_uniforms {
+            self.cache.block_uniforms = has_block_uniforms;
+            let block_uniforms = if has_block_uniforms {
+                Some(block_uniforms.clone())
+            } else {
+                None
+            };
+            self.push_cmd(Command::BindBlockUniforms(block_uniforms));
+        }
+
+        self.set_advanced_blend(None);
         self.update_blend_targets(blend_targets);
 
         self.push_cmd(Command::BindRasterizer { 
             rasterizer, 
         });
+        self.push_cmd(Command::SetRasterizerDiscard(rasterizer_discard));
         self.push_cmd(Command::BindDepth { 
             depth,
         });
@@ -971,89 +1454,22 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         for desc_set in sets {
             let desc_set = desc_set.borrow();
             let bindings = desc_set.bindings.lock().unwrap();
-            for new_binding in &*bindings {
-                match new_binding {
-                    n::DescSetBindings::Buffer {
-                        ty: btype,
-                        binding,
+            // In bindless mode, the texture handles replace texture units.
+            let bindless_block = drd.get_bindless_block(set);
+            if let Some(block) = bindless_block {
+                if let Some((buffer, size)) = *desc_set.handles.lock().unwrap() {
+                    self.push_cmd(Command::BindBufferRange(
+                        glow::UNIFORM_BUFFER,
+                        block,
                         buffer,
-                        offset,
+                        0,
                         size,
-                    } => {
-                        let btype = match btype {
-                            n::BindingTypes::UniformBuffers => glow::UNIFORM_BUFFER,
-                            n::BindingTypes::Images => panic!("Wrong desc set binding"),
-                        };
-                        for binding in drd
-                            .get_binding(n::BindingTypes::UniformBuffers, set, *binding)
-                            .unwrap()
-                        {
-                            self.push_cmd(Command::BindBufferRange(
-                                btype,
-                                *binding,
-                                *buffer,
-                                *offset as i32,
-                                *size as i32,
-                            ))
-                        }
-                    }
-                    n::DescSetBindings::Texture(binding, texture, textype) => {
-                        for binding in drd
-                            .get_binding(n::BindingTypes::Images, set, *binding)
-                            .unwrap()
-                        {
-                            self.push_cmd(Command::BindTexture(*binding, *texture, *textype))
-                        }
-                    }
-                    n::DescSetBindings::Sampler(binding, sampler) => {
-                        for binding in drd
-                            .get_binding(n::BindingTypes::Images, set, *binding)
-                            .unwrap()
-                        {
-                            self.push_cmd(Command::BindSampler(*binding, *sampler))
-                        }
-                    }
-                    n::DescSetBindings::SamplerInfo(binding, sinfo) => {
-                        let mut all_txts = drd
-                            .get_binding(n::BindingTypes::Images, set, *binding)
-                            .unwrap()
-                            .into_iter()
-                            .flat_map(|binding| {
-                                bindings.iter().filter_map(move |b| {
-                                    if let n::DescSetBindings::Texture(b, t, ttype) = b {
-                                        let nbs =
-                                            drd.get_binding(n::BindingTypes::Images, set, *b)?;
-                                        if nbs.contains(binding) {
-                                            Some((*binding, *t, *ttype))
-                                        } else {
-                                            None
-                                        }
-                                    } else {
-                                        None
-                                    }
-                                })
-                            })
-                            .collect::<Vec<_>>();
-
-                        // TODO: Check that other samplers aren't using the same
-                        // textures as in `all_txts` unless all the bindings of that
-                        // texture are gonna be unbound or the two samplers have
-                        // identical properties.
-                        all_txts.sort_unstable_by(|a, b| a.1.cmp(&b.1));
-                        all_txts.dedup_by(|a, b| a.1 == b.1);
-
-                        for (binding, txt, textype) in all_txts {
-                            self.push_cmd(Command::SetTextureSamplerSettings(
-                                binding,
-                                txt,
-                                textype,
-                                sinfo.clone(),
-                            ))
-                        }
-                    }
+                    ));
                 }
             }
 
+            self.bind_descriptors(drd, set, &bindings, bindless_block.is_none());
+
             set += 1;
         }
     }
@@ -1128,9 +1544,59 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
 
         for region in regions {
             let r = region.borrow().clone();
-            let cmd = match dst.kind {
-                n::ImageKind::Surface(s) => Command::CopyImageToSurface(src.kind, s, r),
-                n::ImageKind::Texture(t, tt) => Command::CopyImageToTexture(src.kind, t, tt, r),
+            let cmd = match (src.kind, dst.kind) {
+                // Linear images are copied like pixel buffers.
+                (n::ImageKind::Linear(src), n::ImageKind::Linear(dst)) => {
+                    let (src_raw, src_offset) = src.texel(r.src_offset);
+                    let (dst_raw, dst_offset) = dst.texel(r.dst_offset);
+                    self.copy_rows(
+                        (src_raw, src_offset, src.row_pitch()),
+                        (dst_raw, dst_offset, dst.row_pitch()),
+                        r.extent.width as buffer::Offset * src.bytes_per_texel as buffer::Offset,
+                        r.extent.height,
+                    );
+                    continue;
+                }
+                (n::ImageKind::Linear(src), dst_kind) => {
+                    let (src_raw, src_offset) = src.texel(r.src_offset);
+                    let copy = command::BufferImageCopy {
+                        buffer_offset: src_offset,
+                        buffer_width: src.width,
+                        buffer_height: src.height,
+                        image_layers: r.dst_subresource,
+                        image_offset: r.dst_offset,
+                        image_extent: r.extent,
+                    };
+                    match dst_kind {
+                        n::ImageKind::Surface(s) => Command::CopyBufferToSurface(src_raw, s, copy),
+                        n::ImageKind::Texture(t, tt) => {
+                            Command::CopyBufferToTexture(src_raw, t, tt, dst.upload, copy)
+                        }
+                        n::ImageKind::Linear(_) => unreachable!(),
+                    }
+                }
+                (src_kind, n::ImageKind::Linear(dst)) => {
+                    let (dst_raw, dst_offset) = dst.texel(r.dst_offset);
+                    let copy = command::BufferImageCopy {
+                        buffer_offset: dst_offset,
+                        buffer_width: dst.width,
+                        buffer_height: dst.height,
+                        image_layers: r.src_subresource,
+                        image_offset: r.src_offset,
+                        image_extent: r.extent,
+                    };
+                    match src_kind {
+                        n::ImageKind::Surface(s) => Command::CopySurfaceToBuffer(s, dst_raw, copy),
+                        n::ImageKind::Texture(t, tt) => {
+                            Command::CopyTextureToBuffer(t, tt, dst_raw, copy)
+                        }
+                        n::ImageKind::Linear(_) => unreachable!(),
+                    }
+                }
+                (src_kind, n::ImageKind::Surface(s)) => Command::CopyImageToSurface(src_kind, s, r),
+                (src_kind, n::ImageKind::Texture(t, tt)) => {
+                    Command::CopyImageToTexture(src_kind, t, tt, r)
+                }
             };
             self.push_cmd(cmd);
         }
@@ -1158,7 +1624,26 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
             r.buffer_offset += src_range.start;
             let cmd = match dst.kind {
                 n::ImageKind::Surface(s) => Command::CopyBufferToSurface(src_raw, s, r),
-                n::ImageKind::Texture(t, tt) => Command::CopyBufferToTexture(src_raw, t, tt, r),
+                n::ImageKind::Texture(t, tt) => {
+                    Command::CopyBufferToTexture(src_raw, t, tt, dst.upload, r)
+                }
+                n::ImageKind::Linear(dst) => {
+                    let (dst_raw, dst_offset) = dst.texel(r.image_offset);
+                    let row_size = r.image_extent.width as buffer::Offset
+                        * dst.bytes_per_texel as buffer::Offset;
+                    let src_pitch = if r.buffer_width == 0 {
+                        row_size
+                    } else {
+                        r.buffer_width as buffer::Offset * dst.bytes_per_texel as buffer::Offset
+                    };
+                    self.copy_rows(
+                        (src_raw, r.buffer_offset, src_pitch),
+                        (dst_raw, dst_offset, dst.row_pitch()),
+                        row_size,
+                        r.image_extent.height,
+                    );
+                    continue;
+                }
             };
             self.push_cmd(cmd);
         }
@@ -1187,6 +1672,23 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
             let cmd = match src.kind {
                 n::ImageKind::Surface(s) => Command::CopySurfaceToBuffer(s, dst_raw, r),
                 n::ImageKind::Texture(t, tt) => Command::CopyTextureToBuffer(t, tt, dst_raw, r),
+                n::ImageKind::Linear(src) => {
+                    let (src_raw, src_offset) = src.texel(r.image_offset);
+                    let row_size = r.image_extent.width as buffer::Offset
+                        * src.bytes_per_texel as buffer::Offset;
+                    let dst_pitch = if r.buffer_width == 0 {
+                        row_size
+                    } else {
+                        r.buffer_width as buffer::Offset * src.bytes_per_texel as buffer::Offset
+                    };
+                    self.copy_rows(
+                        (src_raw, src_offset, src.row_pitch()),
+                        (dst_raw, r.buffer_offset, dst_pitch),
+                        row_size,
+                        r.image_extent.height,
+                    );
+                    continue;
+                }
             };
             self.push_cmd(cmd);
         }
@@ -1278,54 +1780,89 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         unimplemented!()
     }
 
-    unsafe fn set_event(&mut self, _: &(), _: pso::PipelineStage) {
-        unimplemented!()
+    unsafe fn set_event(&mut self, event: &n::Event, _: pso::PipelineStage) {
+        self.push_cmd(Command::SetEvent(event.clone(), true));
     }
 
-    unsafe fn reset_event(&mut self, _: &(), _: pso::PipelineStage) {
-        unimplemented!()
+    unsafe fn reset_event(&mut self, event: &n::Event, _: pso::PipelineStage) {
+        self.push_cmd(Command::SetEvent(event.clone(), false));
     }
 
     unsafe fn wait_events<'a, I, J>(
         &mut self,
-        _: I,
+        events: I,
         _: Range<pso::PipelineStage>,
-        _: J
+        barriers: J
     ) where
         I: IntoIterator,
-    I::Item: Borrow<()>,
+    I::Item: Borrow<n::Event>,
     J: IntoIterator,
     J::Item: Borrow<memory::Barrier<'a, Backend>>,
     {
-        unimplemented!()
+        let events = events.into_iter().map(|event| event.borrow().clone()).collect();
+        self.push_cmd(Command::WaitEvents(events));
+        self.push_barriers(barriers);
     }
 
-    unsafe fn begin_query(&mut self, _query: query::Query<Backend>, _flags: query::ControlFlags) {
-        unimplemented!()
+    unsafe fn begin_query(&mut self, query: query::Query<Backend>, flags: query::ControlFlags) {
+        match query.pool.ty {
+            query::Type::Occlusion => {
+                let precise = flags.contains(query::ControlFlags::PRECISE);
+                self.cache.occlusion_query = Some(precise);
+                let name = query.pool.queries[query.id as usize];
+                self.push_cmd(Command::BeginOcclusionQuery(name, precise));
+            }
+            ty => {
+                error!("Query type {:?} can't be begun", ty);
+                self.cache.error_state = true;
+            }
+        }
     }
 
     unsafe fn copy_query_pool_results(
         &mut self,
-        _pool: &(),
-        _queries: Range<query::Id>,
-        _buffer: &n::Buffer,
-        _offset: buffer::Offset,
-        _stride: buffer::Offset,
-        _flags: query::ResultFlags,
+        pool: &n::QueryPool,
+        queries: Range<query::Id>,
+        buffer: &n::Buffer,
+        offset: buffer::Offset,
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
     ) {
-        unimplemented!()
+        let (raw_buffer, range) = buffer.as_bound();
+        for (i, id) in queries.enumerate() {
+            let offset = range.start + offset + i as buffer::Offset * stride;
+            let query = pool.queries[id as usize];
+            self.push_cmd(Command::CopyQueryResult(query, raw_buffer, offset, flags));
+        }
     }
 
-    unsafe fn end_query(&mut self, _query: query::Query<Backend>) {
-        unimplemented!()
+    unsafe fn end_query(&mut self, query: query::Query<Backend>) {
+        match (query.pool.ty, self.cache.occlusion_query.take()) {
+            (query::Type::Occlusion, Some(precise)) => {
+                self.push_cmd(Command::EndOcclusionQuery(precise));
+            }
+            (ty, _) => {
+                error!("Query type {:?} isn't active", ty);
+                self.cache.error_state = true;
+            }
+        }
     }
 
-    unsafe fn reset_query_pool(&mut self, _pool: &(), _queries: Range<query::Id>) {
-        unimplemented!()
+    unsafe fn reset_query_pool(&mut self, _pool: &n::QueryPool, _queries: Range<query::Id>) {
+        // Queries are reset when they begin.
     }
 
-    unsafe fn write_timestamp(&mut self, _: pso::PipelineStage, _: query::Query<Backend>) {
-        unimplemented!()
+    unsafe fn write_timestamp(&mut self, _: pso::PipelineStage, query: query::Query<Backend>) {
+        match query.pool.ty {
+            query::Type::Timestamp => {
+                let name = query.pool.queries[query.id as usize];
+                self.push_cmd(Command::WriteTimestamp(name));
+            }
+            ty => {
+                error!("Timestamps can't be written to queries of type {:?}", ty);
+                self.cache.error_state = true;
+            }
+        }
     }
 
     unsafe fn push_graphics_constants(
@@ -1,6 +1,6 @@
 use crate::hal::format::Format;
-use crate::hal::{image as i, Primitive};
-use crate::native::VertexAttribFunction;
+use crate::hal::{buffer, image as i, Primitive};
+use crate::native::{TexelConversion, VertexAttribFunction};
 
 /*
 pub fn _image_kind_to_gl(kind: i::Kind) -> t::GLenum {
@@ -41,6 +41,7 @@ pub fn wrap_to_gl(w: i::WrapMode) -> u32 {
         i::WrapMode::Mirror => glow::MIRRORED_REPEAT,
         i::WrapMode::Clamp => glow::CLAMP_TO_EDGE,
         i::WrapMode::Border => glow::CLAMP_TO_BORDER,
+        i::WrapMode::MirrorClamp => glow::MIRROR_CLAMP_TO_EDGE,
     }
 }
 
@@ -59,26 +60,146 @@ pub fn primitive_to_gl_primitive(primitive: Primitive) -> u32 {
     }
 }
 
+/// Memory barrier bits (`glMemoryBarrier`) making writes of the `src` accesses
+/// visible to the `dst` accesses.
+///
+/// Only shader writes are incoherent, attachment writes are synchronized by
+/// the implementation, or by texture barriers when sampled while attached.
+pub fn access_to_memory_barrier(src: i::Access, dst: i::Access) -> u32 {
+    if !src.intersects(i::Access::SHADER_WRITE | i::Access::MEMORY_WRITE) {
+        return 0;
+    }
+    if dst.intersects(i::Access::MEMORY_READ | i::Access::MEMORY_WRITE) {
+        return glow::ALL_BARRIER_BITS;
+    }
+
+    let mut barriers = 0;
+    if dst.intersects(i::Access::SHADER_READ | i::Access::SHADER_WRITE) {
+        barriers |= glow::SHADER_IMAGE_ACCESS_BARRIER_BIT | glow::TEXTURE_FETCH_BARRIER_BIT;
+    }
+    if dst.contains(i::Access::INPUT_ATTACHMENT_READ) {
+        barriers |= glow::TEXTURE_FETCH_BARRIER_BIT;
+    }
+    if dst.intersects(
+        i::Access::COLOR_ATTACHMENT_READ
+            | i::Access::COLOR_ATTACHMENT_WRITE
+            | i::Access::DEPTH_STENCIL_ATTACHMENT_READ
+            | i::Access::DEPTH_STENCIL_ATTACHMENT_WRITE,
+    ) {
+        barriers |= glow::FRAMEBUFFER_BARRIER_BIT;
+    }
+    if dst.intersects(i::Access::TRANSFER_READ | i::Access::TRANSFER_WRITE) {
+        barriers |= glow::TEXTURE_UPDATE_BARRIER_BIT;
+    }
+    if dst.intersects(i::Access::HOST_READ | i::Access::HOST_WRITE) {
+        barriers |= glow::PIXEL_BUFFER_BARRIER_BIT | glow::CLIENT_MAPPED_BUFFER_BARRIER_BIT;
+    }
+    barriers
+}
+
+/// Memory barrier bits (`glMemoryBarrier`) making buffer writes of the `src`
+/// accesses visible to the `dst` accesses.
+pub fn buffer_access_to_memory_barrier(src: buffer::Access, dst: buffer::Access) -> u32 {
+    if !src.intersects(buffer::Access::SHADER_WRITE | buffer::Access::MEMORY_WRITE) {
+        return 0;
+    }
+    if dst.intersects(buffer::Access::MEMORY_READ | buffer::Access::MEMORY_WRITE) {
+        return glow::ALL_BARRIER_BITS;
+    }
+
+    let mut barriers = 0;
+    if dst.contains(buffer::Access::INDIRECT_COMMAND_READ) {
+        barriers |= glow::COMMAND_BARRIER_BIT;
+    }
+    if dst.contains(buffer::Access::INDEX_BUFFER_READ) {
+        barriers |= glow::ELEMENT_ARRAY_BARRIER_BIT;
+    }
+    if dst.contains(buffer::Access::VERTEX_BUFFER_READ) {
+        barriers |= glow::VERTEX_ATTRIB_ARRAY_BARRIER_BIT;
+    }
+    if dst.contains(buffer::Access::CONSTANT_BUFFER_READ) {
+        barriers |= glow::UNIFORM_BARRIER_BIT;
+    }
+    if dst.intersects(buffer::Access::SHADER_READ | buffer::Access::SHADER_WRITE) {
+        // Storage buffers, texel buffers and atomic counters.
+        barriers |= glow::SHADER_STORAGE_BARRIER_BIT
+            | glow::TEXTURE_FETCH_BARRIER_BIT
+            | glow::SHADER_IMAGE_ACCESS_BARRIER_BIT
+            | glow::ATOMIC_COUNTER_BARRIER_BIT;
+    }
+    if dst.intersects(buffer::Access::TRANSFER_READ | buffer::Access::TRANSFER_WRITE) {
+        barriers |= glow::BUFFER_UPDATE_BARRIER_BIT
+            | glow::PIXEL_BUFFER_BARRIER_BIT
+            | glow::QUERY_BUFFER_BARRIER_BIT;
+    }
+    if dst.intersects(buffer::Access::HOST_READ | buffer::Access::HOST_WRITE) {
+        barriers |= glow::BUFFER_UPDATE_BARRIER_BIT | glow::CLIENT_MAPPED_BUFFER_BARRIER_BIT;
+    }
+    barriers
+}
+
+/// Type of half-float vertex attributes with `GL_OES_vertex_half_float`, which
+/// differs from the core `GL_HALF_FLOAT`.
+pub const HALF_FLOAT_OES: u32 = 0x8D61;
+
 pub fn format_to_gl_format(
     format: Format,
 ) -> Option<(i32, u32, VertexAttribFunction)> {
     use crate::hal::format::Format::*;
     use crate::native::VertexAttribFunction::*;
-    let _ = Double; //mark as used
-                    // TODO: Add more formats and error handling for `None`
+    // TODO: Add more formats and error handling for `None`
     let format = match format {
+        // Integer formats are never converted to floats, even if they are
+        // only used as indices, e.g. joint indices of skinned meshes.
+        R8Unorm => (1, glow::UNSIGNED_BYTE, Normalized),
+        R8Snorm => (1, glow::BYTE, Normalized),
+        R8Uscaled => (1, glow::UNSIGNED_BYTE, Float),
+        R8Sscaled => (1, glow::BYTE, Float),
         R8Uint => (1, glow::UNSIGNED_BYTE, Integer),
         R8Sint => (1, glow::BYTE, Integer),
+        Rg8Unorm => (2, glow::UNSIGNED_BYTE, Normalized),
+        Rg8Snorm => (2, glow::BYTE, Normalized),
+        Rg8Uscaled => (2, glow::UNSIGNED_BYTE, Float),
+        Rg8Sscaled => (2, glow::BYTE, Float),
         Rg8Uint => (2, glow::UNSIGNED_BYTE, Integer),
         Rg8Sint => (2, glow::BYTE, Integer),
+        Rgb8Unorm => (3, glow::UNSIGNED_BYTE, Normalized),
+        Rgb8Snorm => (3, glow::BYTE, Normalized),
+        Rgb8Uscaled => (3, glow::UNSIGNED_BYTE, Float),
+        Rgb8Sscaled => (3, glow::BYTE, Float),
+        Rgb8Uint => (3, glow::UNSIGNED_BYTE, Integer),
+        Rgb8Sint => (3, glow::BYTE, Integer),
+        Rgba8Unorm => (4, glow::UNSIGNED_BYTE, Normalized),
+        Rgba8Snorm => (4, glow::BYTE, Normalized),
+        Rgba8Uscaled => (4, glow::UNSIGNED_BYTE, Float),
+        Rgba8Sscaled => (4, glow::BYTE, Float),
         Rgba8Uint => (4, glow::UNSIGNED_BYTE, Integer),
         Rgba8Sint => (4, glow::BYTE, Integer),
+        R16Unorm => (1, glow::UNSIGNED_SHORT, Normalized),
+        R16Snorm => (1, glow::SHORT, Normalized),
+        R16Uscaled => (1, glow::UNSIGNED_SHORT, Float),
+        R16Sscaled => (1, glow::SHORT, Float),
         R16Uint => (1, glow::UNSIGNED_SHORT, Integer),
         R16Sint => (1, glow::SHORT, Integer),
         R16Sfloat => (1, glow::HALF_FLOAT, Float),
+        Rg16Unorm => (2, glow::UNSIGNED_SHORT, Normalized),
+        Rg16Snorm => (2, glow::SHORT, Normalized),
+        Rg16Uscaled => (2, glow::UNSIGNED_SHORT, Float),
+        Rg16Sscaled => (2, glow::SHORT, Float),
         Rg16Uint => (2, glow::UNSIGNED_SHORT, Integer),
         Rg16Sint => (2, glow::SHORT, Integer),
         Rg16Sfloat => (2, glow::HALF_FLOAT, Float),
+        Rgb16Unorm => (3, glow::UNSIGNED_SHORT, Normalized),
+        Rgb16Snorm => (3, glow::SHORT, Normalized),
+        Rgb16Uscaled => (3, glow::UNSIGNED_SHORT, Float),
+        Rgb16Sscaled => (3, glow::SHORT, Float),
+        Rgb16Uint => (3, glow::UNSIGNED_SHORT, Integer),
+        Rgb16Sint => (3, glow::SHORT, Integer),
+        Rgb16Sfloat => (3, glow::HALF_FLOAT, Float),
+        Rgba16Unorm => (4, glow::UNSIGNED_SHORT, Normalized),
+        Rgba16Snorm => (4, glow::SHORT, Normalized),
+        Rgba16Uscaled => (4, glow::UNSIGNED_SHORT, Float),
+        Rgba16Sscaled => (4, glow::SHORT, Float),
         Rgba16Uint => (4, glow::UNSIGNED_SHORT, Integer),
         Rgba16Sint => (4, glow::SHORT, Integer),
         Rgba16Sfloat => (4, glow::HALF_FLOAT, Float),
@@ -94,9 +215,125 @@ pub fn format_to_gl_format(
         Rgba32Uint => (4, glow::UNSIGNED_INT, Integer),
         Rgba32Sint => (4, glow::INT, Integer),
         Rgba32Sfloat => (4, glow::FLOAT, Float),
+        // Packed formats can't be read as integers, BGRA order requires
+        // normalization.
+        A2r10g10b10Unorm => (glow::BGRA as i32, glow::UNSIGNED_INT_2_10_10_10_REV, Normalized),
+        A2r10g10b10Snorm => (glow::BGRA as i32, glow::INT_2_10_10_10_REV, Normalized),
+        A2b10g10r10Unorm => (4, glow::UNSIGNED_INT_2_10_10_10_REV, Normalized),
+        A2b10g10r10Snorm => (4, glow::INT_2_10_10_10_REV, Normalized),
+        A2b10g10r10Uscaled => (4, glow::UNSIGNED_INT_2_10_10_10_REV, Float),
+        A2b10g10r10Sscaled => (4, glow::INT_2_10_10_10_REV, Float),
+        // Requires `GL_ARB_vertex_attrib_64bit`.
+        R64Sfloat => (1, glow::DOUBLE, Double),
+        Rg64Sfloat => (2, glow::DOUBLE, Double),
+        Rgb64Sfloat => (3, glow::DOUBLE, Double),
+        Rgba64Sfloat => (4, glow::DOUBLE, Double),
+        // Swizzled in the vertex shader without `GL_BGRA` attribute sizes.
+        Bgra8Unorm => (glow::BGRA as i32, glow::UNSIGNED_BYTE, Normalized),
 
         _ => return None,
     };
 
     Some(format)
 }
+
+/// Convert `height` rows of `width` texels, with `row_texels` texels from
+/// one row to the next, to tightly packed 4 byte texels.
+pub fn convert_texels(
+    conversion: TexelConversion,
+    src: &[u8],
+    width: usize,
+    height: usize,
+    row_texels: usize,
+) -> Vec<u8> {
+    let src_texel = conversion.src_bytes_per_texel();
+    let mut texels = Vec::with_capacity(width * height * 4);
+    for row in 0..height {
+        let start = row * row_texels * src_texel;
+        for texel in src[start..start + width * src_texel].chunks(src_texel) {
+            match conversion {
+                TexelConversion::None => texels.extend_from_slice(texel),
+                TexelConversion::ExpandRgb => {
+                    texels.extend_from_slice(&[texel[0], texel[1], texel[2], 255])
+                }
+                TexelConversion::SwizzleBgra => {
+                    texels.extend_from_slice(&[texel[2], texel[1], texel[0], texel[3]])
+                }
+            }
+        }
+    }
+    texels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert_texels, format_to_gl_format};
+    use crate::hal::format::Format;
+    use crate::native::{TexelConversion, VertexAttribFunction};
+
+    #[test]
+    fn test_integer_attributes() {
+        let formats = [
+            Format::R8Uint,
+            Format::Rg8Sint,
+            Format::Rgb8Uint,
+            Format::Rgba8Sint,
+            Format::R16Sint,
+            Format::Rgb16Uint,
+            Format::Rgba16Sint,
+            Format::Rg32Uint,
+            Format::Rgba32Sint,
+        ];
+        for &format in &formats {
+            let (_, _, function) = format_to_gl_format(format).unwrap();
+            assert_eq!(function, VertexAttribFunction::Integer, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_skinning_attributes() {
+        // Joint indices stay integers, while weights are normalized.
+        assert_eq!(
+            format_to_gl_format(Format::Rgba8Uint),
+            Some((4, glow::UNSIGNED_BYTE, VertexAttribFunction::Integer))
+        );
+        assert_eq!(
+            format_to_gl_format(Format::Rgba16Uint),
+            Some((4, glow::UNSIGNED_SHORT, VertexAttribFunction::Integer))
+        );
+        assert_eq!(
+            format_to_gl_format(Format::Rgba8Unorm),
+            Some((4, glow::UNSIGNED_BYTE, VertexAttribFunction::Normalized))
+        );
+        assert_eq!(
+            format_to_gl_format(Format::Rgba16Unorm),
+            Some((4, glow::UNSIGNED_SHORT, VertexAttribFunction::Normalized))
+        );
+        // Quantized normals are packed in 10-10-10-2 bits.
+        assert_eq!(
+            format_to_gl_format(Format::A2b10g10r10Snorm),
+            Some((4, glow::INT_2_10_10_10_REV, VertexAttribFunction::Normalized))
+        );
+        assert_eq!(format_to_gl_format(Format::A2b10g10r10Uint), None);
+        // Scaled integers are converted to floats without normalization.
+        assert_eq!(
+            format_to_gl_format(Format::Rgba8Uscaled),
+            Some((4, glow::UNSIGNED_BYTE, VertexAttribFunction::Float))
+        );
+    }
+
+    #[test]
+    fn test_convert_texels() {
+        // Two rows of two texels, with a padding texel at the end of each row.
+        let rgb = [1, 2, 3, 4, 5, 6, 0, 0, 0, 7, 8, 9, 10, 11, 12, 0, 0, 0];
+        assert_eq!(
+            convert_texels(TexelConversion::ExpandRgb, &rgb, 2, 2, 3),
+            vec![1, 2, 3, 255, 4, 5, 6, 255, 7, 8, 9, 255, 10, 11, 12, 255]
+        );
+        let bgra = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(
+            convert_texels(TexelConversion::SwizzleBgra, &bgra, 1, 2, 1),
+            vec![3, 2, 1, 4, 7, 6, 5, 8]
+        );
+    }
+}
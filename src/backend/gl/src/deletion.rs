@@ -0,0 +1,101 @@
+//! Deferred deletion of objects used by submissions in flight.
+//!
+//! Destroyed buffers, textures, renderbuffers and samplers are queued with
+//! the number of the last submission which may use them. Each submission
+//! ends with a fence, objects are deleted once the fence of their submission
+//! is signaled, which is polled at each submission and after waiting for
+//! the device to be idle.
+//!
+//! Without sync objects, objects are deleted right away, the driver keeps
+//! them alive for the commands already issued.
+
+use std::collections::VecDeque;
+
+use glow::Context;
+
+use crate::{native as n, GlContext, Share};
+
+type GlFence = <GlContext as glow::Context>::Fence;
+
+/// An object waiting to be deleted.
+#[derive(Debug)]
+pub(crate) enum Object {
+    Buffer(n::RawBuffer),
+    Texture(n::Texture),
+    Renderbuffer(n::Surface),
+    Sampler(n::Sampler),
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct DeletionQueue {
+    // Number of submissions so far.
+    submitted: u64,
+    // Number of the last submission known to be finished.
+    completed: u64,
+    // Fences at the end of submissions in flight with their numbers, oldest first.
+    fences: VecDeque<(u64, GlFence)>,
+    // Destroyed objects with the number of the last submission using them.
+    objects: VecDeque<(u64, Object)>,
+}
+
+impl Share {
+    /// Delete `object` once the submissions in flight are finished.
+    pub(crate) fn delete_deferred(&self, object: Object) {
+        let mut queue = self.deletion_queue.borrow_mut();
+        if !self.private_caps.sync || queue.completed == queue.submitted {
+            self.delete_object(object);
+        } else {
+            let submitted = queue.submitted;
+            queue.objects.push_back((submitted, object));
+        }
+    }
+
+    /// Insert the fence at the end of a submission.
+    pub(crate) fn end_submission(&self) {
+        let mut queue = self.deletion_queue.borrow_mut();
+        queue.submitted += 1;
+        if self.private_caps.sync {
+            let sync = unsafe { self.context.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+            let submitted = queue.submitted;
+            queue.fences.push_back((submitted, sync.unwrap()));
+        } else {
+            queue.completed = queue.submitted;
+        }
+    }
+
+    /// Delete the objects of finished submissions.
+    pub(crate) fn collect_deletions(&self) {
+        let gl = &self.context;
+        let mut queue = self.deletion_queue.borrow_mut();
+        while let Some(&(submission, sync)) = queue.fences.front() {
+            if unsafe { gl.client_wait_sync(sync, 0, 0) } == glow::TIMEOUT_EXPIRED {
+                break;
+            }
+            unsafe { gl.delete_sync(sync) };
+            queue.fences.pop_front();
+            queue.completed = submission;
+        }
+
+        let completed = queue.completed;
+        while queue
+            .objects
+            .front()
+            .map_or(false, |&(submission, _)| submission <= completed)
+        {
+            let (_, object) = queue.objects.pop_front().unwrap();
+            self.delete_object(object);
+        }
+    }
+
+    fn delete_object(&self, object: Object) {
+        let gl = &self.context;
+        unsafe {
+            match object {
+                Object::Buffer(buffer) => gl.delete_buffer(buffer),
+                Object::Texture(texture) => gl.delete_texture(texture),
+                Object::Renderbuffer(renderbuffer) => gl.delete_renderbuffer(renderbuffer),
+                Object::Sampler(sampler) => gl.delete_sampler(sampler),
+            }
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use std::borrow::Borrow;
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::ops::Range;
 use std::sync::{Arc, Mutex, RwLock};
 use std::slice;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 
 use glow::Context;
 use crate::{GlContainer, GlContext};
@@ -18,6 +21,7 @@ use crate::hal::{
 
 use spirv_cross::{glsl, spirv, ErrorCode as SpirvErrorCode};
 
+use crate::deletion::Object;
 use crate::info::LegacyFeatures;
 use crate::pool::{BufferMemory, OwnedBuffer, RawCommandPool};
 use crate::{conv, native as n, state};
@@ -33,6 +37,38 @@ fn gen_unexpected_error(err: SpirvErrorCode) -> d::ShaderError {
     d::ShaderError::CompilationFailed(msg)
 }
 
+/// Run `fun` and measure how long it took. The time is zero on the web,
+/// which has no `Instant`.
+fn timed<T, F: FnOnce() -> T>(fun: F) -> (T, Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let start = Instant::now();
+        let result = fun();
+        (result, start.elapsed())
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        (fun(), Duration::from_secs(0))
+    }
+}
+
+/// Error of a failed shader compilation, with the numbered source if the
+/// `debug-shaders` feature is enabled.
+fn compilation_error(log: String, source: &str) -> d::ShaderError {
+    #[cfg(feature = "debug-shaders")]
+    let log = {
+        let mut log = log;
+        log.push_str("\nSource:\n");
+        for (i, line) in source.lines().enumerate() {
+            log.push_str(&format!("{:4}: {}\n", i + 1, line));
+        }
+        log
+    };
+    #[cfg(not(feature = "debug-shaders"))]
+    let _ = source;
+    d::ShaderError::CompilationFailed(log)
+}
+
 fn create_fbo_internal(share: &Starc<Share>) -> Option<<GlContext as glow::Context>::Framebuffer> {
     if share.private_caps.framebuffer {
         let gl = &share.context;
@@ -56,12 +92,151 @@ impl Drop for Device {
     }
 }
 
+/// Allocate all levels of the texture bound to `target`, with `layers` for array targets.
+///
+/// Immutable storage is complete from the start, the driver doesn't validate it
+/// again for each draw. Otherwise each level is specified and the level range is
+/// limited to the allocated ones, which is required to render to levels on ES 3.0.
+unsafe fn allocate_texture(
+    share: &Share,
+    target: u32,
+    levels: i::Level,
+    (int_format, iformat, itype): (u32, u32, u32),
+    width: i::Size,
+    height: i::Size,
+    layers: i::Layer,
+) {
+    let gl = &share.context;
+    let layered = target == glow::TEXTURE_2D_ARRAY;
+    if share.private_caps.image_storage {
+        if layered {
+            let (w, h, l) = (width as _, height as _, layers as _);
+            gl.tex_storage_3d(target, levels as _, int_format, w, h, l);
+        } else {
+            gl.tex_storage_2d(target, levels as _, int_format, width as _, height as _);
+        }
+        return;
+    }
+
+    gl.tex_parameter_i32(target, glow::TEXTURE_MAX_LEVEL, (levels - 1) as _);
+    let mut w = width;
+    let mut h = height;
+    for level in 0..levels {
+        if layered {
+            gl.tex_image_3d(
+                target,
+                level as _,
+                int_format as _,
+                w as _,
+                h as _,
+                layers as _,
+                0,
+                iformat,
+                itype,
+                None,
+            );
+        } else {
+            gl.tex_image_2d(
+                target,
+                level as _,
+                int_format as _,
+                w as _,
+                h as _,
+                0,
+                iformat,
+                itype,
+                None,
+            );
+        }
+        w = std::cmp::max(w / 2, 1);
+        h = std::cmp::max(h / 2, 1);
+    }
+}
+
+/// Whether a texture target has layers, which are attached to framebuffers
+/// individually or all at once.
+fn is_layered(textype: n::TextureType) -> bool {
+    match textype {
+        glow::TEXTURE_2D_ARRAY
+        | glow::TEXTURE_CUBE_MAP
+        | glow::TEXTURE_CUBE_MAP_ARRAY
+        | glow::TEXTURE_3D
+        | glow::TEXTURE_2D_MULTISAMPLE_ARRAY => true,
+        _ => false,
+    }
+}
+
+/// Declare the renamed BGRA vertex inputs with four components, read in RGBA
+/// order, and swizzle them to the declared number of components.
+fn swizzle_bgra_inputs(glsl: &str, names: &[String]) -> String {
+    if names.is_empty() {
+        return glsl.to_owned();
+    }
+    let mut source = String::with_capacity(glsl.len());
+    for line in glsl.lines() {
+        let tokens = line.trim_end_matches(';').split_whitespace().collect::<Vec<_>>();
+        let declaration = match tokens.split_last() {
+            Some((name, qualifiers))
+                if line.ends_with(';')
+                    && names.iter().any(|n| n == name)
+                    && (qualifiers.contains(&"in") || qualifiers.contains(&"attribute")) =>
+            {
+                Some((name, qualifiers))
+            }
+            _ => None,
+        };
+        match declaration {
+            Some((name, qualifiers)) => {
+                let (ty, qualifiers) = qualifiers.split_last().unwrap();
+                let components = match *ty {
+                    "float" => 1,
+                    "vec2" => 2,
+                    "vec3" => 3,
+                    _ => 4,
+                };
+                source += &format!("{} vec4 {}_raw;\n", qualifiers.join(" "), name);
+                source += &format!("#define {} {}_raw.{}\n", name, name, &"zyxw"[..components]);
+            }
+            None => {
+                source += line;
+                source.push('\n');
+            }
+        }
+    }
+    source
+}
+
+/// Declare support for all advanced blend equations in a fragment shader,
+/// which is required to draw with them.
+fn declare_blend_support(glsl: &str) -> String {
+    let (version, body) = match glsl.find('\n') {
+        Some(end) => glsl.split_at(end + 1),
+        None => return glsl.to_owned(),
+    };
+    format!(
+        "{}#extension GL_KHR_blend_equation_advanced : enable\n\
+         #ifdef GL_KHR_blend_equation_advanced\n\
+         layout(blend_support_all_equations) out;\n\
+         #endif\n{}",
+        version, body
+    )
+}
+
 impl Device {
     /// Create a new `Device`.
     pub(crate) fn new(share: Starc<Share>) -> Self {
         Device { share: share }
     }
 
+    /// The GL context of the device, to use its objects with other GL code.
+    ///
+    /// Command queues cache the bound objects and state, which have to be
+    /// restored after changing them. `CommandQueue::with_gl` resets the cache
+    /// of its queue instead.
+    pub unsafe fn raw_context(&self) -> &GlContext {
+        &self.share.context
+    }
+
     pub fn create_shader_module_from_source(
         &self,
         shader: &str,
@@ -70,25 +245,31 @@ impl Device {
         let gl = &self.share.context;
 
         let can_compute = self.share.limits.max_compute_work_group_count[0] != 0;
-        let can_tessellate = self.share.limits.max_patch_size != 0;
+        let can_tessellate = self.share.features.contains(c::Features::TESSELLATION_SHADER);
+        let can_geometry = self.share.features.contains(c::Features::GEOMETRY_SHADER);
         let target = match stage {
             pso::Stage::Vertex => glow::VERTEX_SHADER,
             pso::Stage::Hull if can_tessellate => glow::TESS_CONTROL_SHADER,
             pso::Stage::Domain if can_tessellate => glow::TESS_EVALUATION_SHADER,
-            pso::Stage::Geometry => glow::GEOMETRY_SHADER,
+            pso::Stage::Geometry if can_geometry => glow::GEOMETRY_SHADER,
             pso::Stage::Fragment => glow::FRAGMENT_SHADER,
             pso::Stage::Compute if can_compute => glow::COMPUTE_SHADER,
             _ => return Err(d::ShaderError::UnsupportedStage(stage)),
         };
 
-        let name = unsafe { gl.create_shader(target) }.unwrap();
+        let name = unsafe { gl.create_shader(target) }.map_err(|err| {
+            error!("Error creating shader: {}", err);
+            d::OutOfMemory::OutOfHostMemory
+        })?;
         unsafe {
             gl.shader_source(name, shader);
             gl.compile_shader(name);
         }
         info!("\tCompiled shader {:?}", name);
         if let Err(err) = self.share.check() {
-            panic!("Error compiling shader: {:?}", err);
+            error!("Error compiling shader: {:?}", err);
+            unsafe { gl.delete_shader(name) };
+            return Err(d::OutOfMemory::OutOfHostMemory.into());
         }
 
         let compiled_ok = unsafe { gl.get_shader_compile_status(name) };
@@ -99,7 +280,8 @@ impl Device {
             }
             Ok(n::ShaderModule::Raw(name))
         } else {
-            Err(d::ShaderError::CompilationFailed(log))
+            unsafe { gl.delete_shader(name) };
+            Err(compilation_error(log, shader))
         }
     }
 
@@ -108,9 +290,17 @@ impl Device {
             n::ImageView::Surface(surface) => unsafe {
                 gl.framebuffer_renderbuffer(point, attachment, glow::RENDERBUFFER, Some(surface));
             },
-            n::ImageView::Texture(texture, textype, level) => unsafe {
+            n::ImageView::Texture(_, textype, _) if is_layered(textype) => {
+                error!("Layered attachments require glFramebufferTexture");
+            }
+            n::ImageView::Texture(texture, textype, levels) => unsafe {
+                let level = levels.start as _;
                 gl.bind_texture(textype, Some(texture));
-                gl.framebuffer_texture_2d(point, attachment, textype, Some(texture), level as _);
+                gl.framebuffer_texture_2d(point, attachment, textype, Some(texture), level);
+            },
+            n::ImageView::TextureLayer(texture, glow::TEXTURE_CUBE_MAP, level, layer) => unsafe {
+                let face = glow::TEXTURE_CUBE_MAP_POSITIVE_X + layer as u32;
+                gl.framebuffer_texture_2d(point, attachment, face, Some(texture), level as _);
             },
             n::ImageView::TextureLayer(texture, textype, level, layer) => unsafe {
                 gl.bind_texture(textype, Some(texture));
@@ -124,8 +314,13 @@ impl Device {
             n::ImageView::Surface(surface) => unsafe {
                 gl.framebuffer_renderbuffer(point, attachment, glow::RENDERBUFFER, Some(surface));
             },
-            n::ImageView::Texture(texture, _, level) => unsafe {
-                gl.framebuffer_texture(point, attachment, Some(texture), level as _);
+            // Views of all layers of layered textures are attached for layered rendering.
+            n::ImageView::Texture(texture, _, levels) => unsafe {
+                gl.framebuffer_texture(point, attachment, Some(texture), levels.start as _);
+            },
+            n::ImageView::TextureLayer(texture, glow::TEXTURE_CUBE_MAP, level, layer) => unsafe {
+                let face = glow::TEXTURE_CUBE_MAP_POSITIVE_X + layer as u32;
+                gl.framebuffer_texture_2d(point, attachment, face, Some(texture), level as _);
             },
             n::ImageView::TextureLayer(texture, _, level, layer) => unsafe {
                 gl.framebuffer_texture_layer(point, attachment, Some(texture), level as _, layer as _);
@@ -133,6 +328,113 @@ impl Device {
         }
     }
 
+    /// Create a linear tiled image, which is stored in buffer memory and
+    /// can only be mapped and used for copies.
+    fn create_linear_image(
+        &self,
+        kind: i::Kind,
+        num_levels: i::Level,
+        format: Format,
+        usage: i::Usage,
+    ) -> Result<n::Image, i::CreationError> {
+        let transfer = i::Usage::TRANSFER_SRC | i::Usage::TRANSFER_DST;
+        if !transfer.contains(usage) {
+            return Err(i::CreationError::Usage(usage));
+        }
+        let (width, height) = match kind {
+            i::Kind::D2(w, h, 1, 1) if num_levels == 1 => (w, h),
+            _ => return Err(i::CreationError::Kind),
+        };
+        let upload = match self.share.texel_upload(format) {
+            Some(upload) => upload,
+            None => return Err(i::CreationError::Format(format)),
+        };
+
+        let linear = n::LinearImage {
+            buffer: None,
+            width,
+            height,
+            bytes_per_texel: format.surface_desc().bits as u32 / 8,
+        };
+        let type_mask = self.share.buffer_memory_type_mask(
+            buffer::Usage::TRANSFER_SRC | buffer::Usage::TRANSFER_DST,
+        );
+
+        Ok(n::Image {
+            kind: n::ImageKind::Linear(linear),
+            channel: format.base_format().1,
+            requirements: memory::Requirements {
+                size: linear.size(),
+                alignment: 4,
+                type_mask,
+            },
+            host_image: None,
+            upload,
+        })
+    }
+
+    /// Upload the texels of images bound to host visible memory from its
+    /// client-side copy, for all images overlapping `range`.
+    unsafe fn upload_host_images(&self, memory: &n::Memory, range: Range<u64>) {
+        let gl = &self.share.context;
+        let ptr = memory
+            .emulate_map_allocation
+            .get()
+            .expect("Image memory is not mapped");
+        for (image_range, image) in memory.host_images.borrow().iter() {
+            if image_range.end <= range.start || image_range.start >= range.end {
+                continue;
+            }
+            let texels = slice::from_raw_parts(
+                ptr.offset(image_range.start as isize),
+                (image_range.end - image_range.start) as usize,
+            );
+            gl.bind_texture(image.target, Some(image.texture));
+            if self.share.pbo_uploads() {
+                // Respecifying the storage orphans the one of previous uploads in flight.
+                let pbo = match self.share.upload_pbo.get() {
+                    Some(pbo) => pbo,
+                    None => {
+                        let pbo = gl.create_buffer().unwrap();
+                        self.share.upload_pbo.set(Some(pbo));
+                        pbo
+                    }
+                };
+                gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, Some(pbo));
+                gl.buffer_data_u8_slice(glow::PIXEL_UNPACK_BUFFER, texels, glow::STREAM_DRAW);
+                gl.tex_sub_image_2d_pixel_buffer_offset(
+                    image.target,
+                    0,
+                    0,
+                    0,
+                    image.width as _,
+                    image.height as _,
+                    image.format,
+                    image.ty,
+                    0,
+                );
+                gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, None);
+            } else {
+                gl.tex_sub_image_2d_u8_slice(
+                    image.target,
+                    0,
+                    0,
+                    0,
+                    image.width as _,
+                    image.height as _,
+                    image.format,
+                    image.ty,
+                    Some(texels),
+                );
+            }
+            gl.bind_texture(image.target, None);
+        }
+
+        if let Err(err) = self.share.check() {
+            panic!("Error uploading image memory: {:?} for memory {:?}", err, memory);
+        }
+    }
+
     fn parse_spirv(&self, raw_data: &[u32]) -> Result<spirv::Ast<glsl::Target>, d::ShaderError> {
         let module = spirv::Module::from_words(raw_data);
 
@@ -209,6 +511,10 @@ impl Device {
                 (3, 30) => glsl::Version::V3_30,
                 (1, 50) => glsl::Version::V1_50,
                 (1, 40) => glsl::Version::V1_40,
+                // Uniform blocks are declared as plain uniforms before GLSL 1.30.
+                (1, 30) if self.share.private_caps.emulate_uniform_blocks => {
+                    glsl::Version::V1_20
+                }
                 (1, 30) => glsl::Version::V1_30,
                 (1, 20) => glsl::Version::V1_20,
                 (1, 10) => glsl::Version::V1_10,
@@ -216,7 +522,9 @@ impl Device {
                 other => panic!("GLSL version is not recognized: {:?}", other),
             }
         };
-        compile_options.vertex.invert_y = true;
+        // Without clip control, the clip space of Vulkan is converted.
+        compile_options.vertex.invert_y = self.share.flip_in_shaders();
+        compile_options.vertex.transform_clip_space = !self.share.clip_control();
         debug!("SPIR-V options {:?}", compile_options);
 
         ast.set_compiler_options(&compile_options)
@@ -244,6 +552,14 @@ impl Device {
             &res.sampled_images,
             n::BindingTypes::Images,
         );
+        // Subpass inputs are translated to texel fetches at the fragment position.
+        self.remap_binding(
+            ast,
+            desc_remap_data,
+            nb_map,
+            &res.subpass_inputs,
+            n::BindingTypes::Images,
+        );
         self.remap_binding(
             ast,
             desc_remap_data,
@@ -268,6 +584,7 @@ impl Device {
             let binding = ast
                 .get_decoration(res.id, spirv::Decoration::Binding)
                 .unwrap();
+            let count = desc_remap_data.get_array_size(btype, set as _, binding);
             let nbs = desc_remap_data
                 .get_binding(btype, set as _, binding)
                 .unwrap();
@@ -278,12 +595,20 @@ impl Device {
                     .legacy_features
                     .contains(LegacyFeatures::EXPLICIT_LAYOUTS_IN_SHADER)
                 {
+                    // Arrays take consecutive bindings starting at the given one.
                     ast.set_decoration(res.id, spirv::Decoration::Binding, *nb)
                         .unwrap()
                 } else {
                     ast.unset_decoration(res.id, spirv::Decoration::Binding)
                         .unwrap();
-                    assert!(nb_map.insert(res.name.clone(), *nb).is_none());
+                    if count > 1 {
+                        for i in 0..count {
+                            let name = format!("{}[{}]", res.name, i);
+                            assert!(nb_map.insert(name, *nb + i as u32).is_none());
+                        }
+                    } else {
+                        assert!(nb_map.insert(res.name.clone(), *nb).is_none());
+                    }
                 }
                 ast.unset_decoration(res.id, spirv::Decoration::DescriptorSet)
                     .unwrap();
@@ -291,6 +616,49 @@ impl Device {
         }
     }
 
+    /// Name uniform blocks after their binding and record the offsets of
+    /// their members, which are plain uniforms in GLSL versions without
+    /// uniform blocks, see `PrivateCaps::emulate_uniform_blocks`.
+    fn flatten_uniform_blocks(
+        &self,
+        ast: &mut spirv::Ast<glsl::Target>,
+        desc_remap_data: &n::DescRemapData,
+        block_members: &mut FastHashMap<String, (pso::DescriptorBinding, u32)>,
+    ) {
+        let res = ast.get_shader_resources().unwrap();
+        for block in &res.uniform_buffers {
+            let set = ast
+                .get_decoration(block.id, spirv::Decoration::DescriptorSet)
+                .unwrap();
+            let binding = ast
+                .get_decoration(block.id, spirv::Decoration::Binding)
+                .unwrap();
+            let btype = n::BindingTypes::UniformBuffers;
+            if desc_remap_data.get_array_size(btype, set as _, binding) > 1 {
+                warn!("Arrays of uniform blocks can't be emulated");
+                continue;
+            }
+            let nb = desc_remap_data.get_binding(btype, set as _, binding).unwrap()[0];
+            let name = format!("GFX_HAL_UBO_{}", nb);
+            ast.set_name(block.id, &name).unwrap();
+
+            let members = match ast.get_type(block.base_type_id).unwrap() {
+                spirv::Type::Struct { member_types, .. } => member_types.len() as u32,
+                _ => 0,
+            };
+            for index in 0..members {
+                let member = match ast.get_member_name(block.base_type_id, index) {
+                    Ok(ref member) if !member.is_empty() => member.clone(),
+                    _ => format!("_m{}", index),
+                };
+                let offset = ast
+                    .get_member_decoration(block.base_type_id, index, spirv::Decoration::Offset)
+                    .unwrap();
+                block_members.insert(format!("{}.{}", name, member), (nb, offset));
+            }
+        }
+    }
+
     fn combine_separate_images_and_samplers(
         &self,
         ast: &mut spirv::Ast<glsl::Target>,
@@ -304,11 +672,9 @@ impl Device {
         self.populate_id_map(ast, &mut id_map, &res.separate_samplers);
 
         for cis in ast.get_combined_image_samplers().unwrap() {
-            let (set, binding) = id_map.get(&cis.image_id).unwrap();
-            let nb = desc_remap_data.reserve_binding(n::BindingTypes::Images);
-            desc_remap_data.insert_missing_binding(nb, n::BindingTypes::Images, *set, *binding);
-            let (set, binding) = id_map.get(&cis.sampler_id).unwrap();
-            desc_remap_data.insert_missing_binding(nb, n::BindingTypes::Images, *set, *binding);
+            let image = *id_map.get(&cis.image_id).unwrap();
+            let sampler = *id_map.get(&cis.sampler_id).unwrap();
+            let nb = desc_remap_data.insert_combined(image, sampler);
 
             let new_name = "GFX_HAL_COMBINED_SAMPLER".to_owned()
                 + "_"
@@ -352,45 +718,115 @@ impl Device {
         }
     }
 
+    /// Rename the vertex inputs at the given locations, which are BGRA
+    /// attributes to swizzle in the generated source.
+    fn rename_bgra_inputs(
+        &self,
+        ast: &mut spirv::Ast<glsl::Target>,
+        locations: &[u32],
+    ) -> Vec<String> {
+        let res = ast.get_shader_resources().unwrap();
+        let mut names = Vec::new();
+        for input in &res.stage_inputs {
+            let location = ast
+                .get_decoration(input.id, spirv::Decoration::Location)
+                .unwrap();
+            if locations.contains(&location) {
+                let name = format!("GFX_HAL_BGRA_{}", location);
+                ast.set_name(input.id, &name).unwrap();
+                names.push(name);
+            }
+        }
+        names
+    }
+
     fn compile_shader(
         &self,
         point: &pso::EntryPoint<B>,
         stage: pso::Stage,
         desc_remap_data: &mut n::DescRemapData,
         name_binding_map: &mut FastHashMap<String, pso::DescriptorBinding>,
-    ) -> n::Shader {
-        assert_eq!(point.entry, "main");
+        block_members: &mut FastHashMap<String, (pso::DescriptorBinding, u32)>,
+        bgra_inputs: &[u32],
+    ) -> Result<n::Shader, d::ShaderError> {
+        if point.entry != "main" {
+            return Err(d::ShaderError::MissingEntryPoint(point.entry.to_string()));
+        }
         match *point.module {
             n::ShaderModule::Raw(raw) => {
                 debug!("Can't remap bindings for raw shaders. Assuming they are already rebound.");
-                raw
+                if !bgra_inputs.is_empty() {
+                    warn!("Can't swizzle BGRA vertex inputs of raw shaders");
+                }
+                Ok(raw)
             }
             n::ShaderModule::Spirv(ref spirv) => {
-                let mut ast = self.parse_spirv(spirv).unwrap();
+                let mut ast = self.parse_spirv(spirv)?;
 
-                self.specialize_ast(&mut ast, &point.specialization).unwrap();
+                self.specialize_ast(&mut ast, &point.specialization)?;
+                if self.share.private_caps.emulate_uniform_blocks {
+                    self.flatten_uniform_blocks(&mut ast, desc_remap_data, block_members);
+                }
                 self.remap_bindings(&mut ast, desc_remap_data, name_binding_map);
                 self.combine_separate_images_and_samplers(
                     &mut ast,
                     desc_remap_data,
                     name_binding_map,
                 );
-                self.set_push_const_layout(&mut ast).unwrap();
+                self.set_push_const_layout(&mut ast)?;
+                let bgra_names = self.rename_bgra_inputs(&mut ast, bgra_inputs);
 
-                let glsl = self.translate_spirv(&mut ast).unwrap();
+                let glsl = self.translate_spirv(&mut ast)?;
+                let mut glsl = swizzle_bgra_inputs(&glsl, &bgra_names);
+                if stage == pso::Stage::Fragment && self.share.advanced_blend() {
+                    glsl = declare_blend_support(&glsl);
+                }
                 debug!("SPIRV-Cross generated shader:\n{}", glsl);
-                let shader = match self
-                    .create_shader_module_from_source(&glsl, stage)
-                    .unwrap()
-                {
-                    n::ShaderModule::Raw(raw) => raw,
-                    _ => panic!("Unhandled"),
-                };
-
-                shader
+                match self.create_shader_module_from_source(&glsl, stage)? {
+                    n::ShaderModule::Raw(raw) => Ok(raw),
+                    n::ShaderModule::Spirv(_) => unreachable!(),
+                }
             }
         }
     }
+
+    /// Link a program and delete its shaders, returns the link time and the
+    /// info log. The program is deleted if linking failed.
+    unsafe fn link_program(
+        &self,
+        program: n::Program,
+        shaders: &[n::Shader],
+    ) -> Result<(Duration, String), pso::CreationError> {
+        let gl = &self.share.context;
+        let (linked_ok, link_time) = timed(|| {
+            gl.link_program(program);
+            // Querying the status waits for drivers linking in the background.
+            gl.get_program_link_status(program)
+        });
+        info!("\tLinked program {:?} in {:?}", program, link_time);
+
+        for &shader in shaders {
+            gl.detach_shader(program, shader);
+            gl.delete_shader(shader);
+        }
+
+        if let Err(err) = self.share.check() {
+            error!("Error linking program: {:?}", err);
+            gl.delete_program(program);
+            return Err(pso::CreationError::Other);
+        }
+        let log = gl.get_program_info_log(program);
+        if !linked_ok {
+            gl.delete_program(program);
+            return Err(pso::CreationError::Shader(
+                d::ShaderError::CompilationFailed(log),
+            ));
+        }
+        if !log.is_empty() {
+            warn!("\tLog: {}", log);
+        }
+        Ok((link_time, log))
+    }
 }
 
 pub(crate) unsafe fn set_sampler_info<SetParamFloat, SetParamFloatVec, SetParamInt>(
@@ -420,10 +856,21 @@ pub(crate) unsafe fn set_sampler_info<SetParamFloat, SetParamFloatVec, SetParamI
     set_param_int(glow::TEXTURE_MIN_FILTER, min as i32);
     set_param_int(glow::TEXTURE_MAG_FILTER, mag as i32);
 
+    let wrap_to_gl = |w| {
+        if w == i::WrapMode::MirrorClamp
+            && !share
+                .features
+                .contains(hal::Features::SAMPLER_MIRROR_CLAMP_EDGE)
+        {
+            error!("Mirror clamp to edge is not supported, clamping to edge instead");
+            return glow::CLAMP_TO_EDGE as i32;
+        }
+        conv::wrap_to_gl(w) as i32
+    };
     let (s, t, r) = info.wrap_mode;
-    set_param_int(glow::TEXTURE_WRAP_S, conv::wrap_to_gl(s) as i32);
-    set_param_int(glow::TEXTURE_WRAP_T, conv::wrap_to_gl(t) as i32);
-    set_param_int(glow::TEXTURE_WRAP_R, conv::wrap_to_gl(r) as i32);
+    set_param_int(glow::TEXTURE_WRAP_S, wrap_to_gl(s));
+    set_param_int(glow::TEXTURE_WRAP_T, wrap_to_gl(t));
+    set_param_int(glow::TEXTURE_WRAP_R, wrap_to_gl(r));
 
     if share
         .features
@@ -493,7 +940,7 @@ impl d::Device<B> for Device {
                     }
                 }
 
-                if self.share.private_caps.buffer_storage {
+                if self.share.buffer_storage() {
                     let mut storage_flags = 0;
 
                     if is_cpu_visible_memory {
@@ -528,6 +975,16 @@ impl d::Device<B> for Device {
                 if let Err(err) = self.share.check() {
                     panic!("Error allocating memory buffer {:?}", err);
                 }
+                // Only memory types of buffers read on the CPU have the
+                // shadowed usages, see `memory_types`.
+                let shadowed =
+                    crate::shadowed_buffer_usage(&self.share.private_caps, self.share.quirks);
+                if buffer_usage.intersects(shadowed) {
+                    self.share
+                        .buffer_shadows
+                        .borrow_mut()
+                        .insert(raw, vec![0; size as usize]);
+                }
 
                 Ok(n::Memory {
                     properties: memory_type.properties,
@@ -535,17 +992,34 @@ impl d::Device<B> for Device {
                     size,
                     map_flags,
                     emulate_map_allocation: Cell::new(None),
+                    host_images: RefCell::new(Vec::new()),
+                    memory_object: None,
                 })
             }
 
-            MemoryUsage::Image => {
+            MemoryUsage::Image | MemoryUsage::TransientImage => {
                 assert!(is_device_local_memory);
                 Ok(n::Memory {
-                    properties: memory::Properties::DEVICE_LOCAL,
+                    properties: memory_type.properties,
                     buffer: None,
                     size,
                     map_flags: 0,
                     emulate_map_allocation: Cell::new(None),
+                    host_images: RefCell::new(Vec::new()),
+                    memory_object: None,
+                })
+            }
+
+            MemoryUsage::HostImage => {
+                assert!(is_cpu_visible_memory);
+                Ok(n::Memory {
+                    properties: memory_type.properties,
+                    buffer: None,
+                    size,
+                    map_flags: 0,
+                    emulate_map_allocation: Cell::new(None),
+                    host_images: RefCell::new(Vec::new()),
+                    memory_object: None,
                 })
             }
         }
@@ -573,6 +1047,7 @@ impl d::Device<B> for Device {
             fbo,
             limits,
             memory: Arc::new(Mutex::new(memory)),
+            free_buffers: Vec::new(),
         })
     }
 
@@ -587,7 +1062,7 @@ impl d::Device<B> for Device {
         &self,
         attachments: IA,
         subpasses: IS,
-        _dependencies: ID,
+        dependencies: ID,
     ) -> Result<n::RenderPass, d::OutOfMemory>
     where
         IA: IntoIterator,
@@ -597,17 +1072,52 @@ impl d::Device<B> for Device {
         ID: IntoIterator,
         ID::Item: Borrow<pass::SubpassDependency>,
     {
+        let dependencies = dependencies
+            .into_iter()
+            .map(|dep| dep.borrow().clone())
+            .collect::<Vec<_>>();
+        let attachment_writes =
+            i::Access::COLOR_ATTACHMENT_WRITE | i::Access::DEPTH_STENCIL_ATTACHMENT_WRITE;
+        let attachment_reads = i::Access::INPUT_ATTACHMENT_READ | i::Access::SHADER_READ;
+
         let subpasses = subpasses
             .into_iter()
-            .map(|subpass| {
+            .enumerate()
+            .map(|(index, subpass)| {
                 let subpass = subpass.borrow();
                 let color_attachments = subpass.colors.iter().map(|&(index, _)| index).collect();
 
                 let depth_stencil = subpass.depth_stencil.map(|ds| ds.0);
+                let input_attachments = subpass
+                    .inputs
+                    .iter()
+                    .map(|&(index, _)| index)
+                    .collect::<Vec<_>>();
+
+                // Barriers for the dependencies of the subpass, issued when entering it.
+                let mut memory_barrier = 0;
+                let mut reads_attachments = !input_attachments.is_empty();
+                for dep in &dependencies {
+                    if dep.passes.end != pass::SubpassRef::Pass(index) {
+                        continue;
+                    }
+                    memory_barrier |=
+                        conv::access_to_memory_barrier(dep.accesses.start, dep.accesses.end);
+                    // Attachments written by a previous subpass are sampled by this one.
+                    if dep.passes.start != pass::SubpassRef::External
+                        && dep.accesses.start.intersects(attachment_writes)
+                        && dep.accesses.end.intersects(attachment_reads)
+                    {
+                        reads_attachments = true;
+                    }
+                }
 
                 n::SubpassDesc {
                     color_attachments,
                     depth_stencil,
+                    input_attachments,
+                    memory_barrier,
+                    reads_attachments,
                 }
             })
             .collect();
@@ -635,6 +1145,16 @@ impl d::Device<B> for Device {
         let mut drd = n::DescRemapData::new();
 
         layouts.into_iter().enumerate().for_each(|(set, layout)| {
+            // Texture handles of sets with sampled images are read from a uniform buffer.
+            if self.share.bindless_textures
+                && layout.borrow().iter().any(|binding| match binding.ty {
+                    pso::DescriptorType::CombinedImageSampler
+                    | pso::DescriptorType::SampledImage => true,
+                    _ => false,
+                })
+            {
+                drd.insert_bindless_block(set as _);
+            }
             layout.borrow().iter().for_each(|binding| {
                 // DescriptorType -> Descriptor
                 //
@@ -648,11 +1168,13 @@ impl d::Device<B> for Device {
                 assert!(!binding.immutable_samplers); //TODO: Implement immutable_samplers
                 use crate::pso::DescriptorType::*;
                 match binding.ty {
-                    CombinedImageSampler => {
+                    // Input attachments are sampled from the attachment textures.
+                    CombinedImageSampler | InputAttachment => {
                         drd.insert_missing_binding_into_spare(
                             n::BindingTypes::Images,
                             set as _,
                             binding.binding,
+                            binding.count,
                         );
                     }
                     Sampler | SampledImage => {
@@ -664,11 +1186,12 @@ impl d::Device<B> for Device {
                             n::BindingTypes::UniformBuffers,
                             set as _,
                             binding.binding,
+                            binding.count,
                         );
                     }
                     StorageImage | UniformTexelBuffer | UniformBufferDynamic
-                    | StorageTexelBuffer | StorageBufferDynamic | StorageBuffer
-                    | InputAttachment => unimplemented!(), // 6
+                    | StorageTexelBuffer | StorageBufferDynamic
+                    | StorageBuffer => unimplemented!(), // 6
                 }
             })
         });
@@ -716,8 +1239,112 @@ impl d::Device<B> for Device {
             }
         };
 
+        // Adjacency vertices are only accessible to geometry shaders, patches
+        // to tessellation shaders.
+        match desc.input_assembler.primitive {
+            c::Primitive::LineListAdjacency
+            | c::Primitive::LineStripAdjacency
+            | c::Primitive::TriangleListAdjacency
+            | c::Primitive::TriangleStripAdjacency
+                if !share.features.contains(c::Features::GEOMETRY_SHADER) =>
+            {
+                error!(
+                    "Primitive {:?} requires geometry shaders, which are not supported",
+                    desc.input_assembler.primitive
+                );
+                return Err(pso::CreationError::Other);
+            }
+            c::Primitive::PatchList(_)
+                if !share.features.contains(c::Features::TESSELLATION_SHADER) =>
+            {
+                error!("Patch lists require tessellation shaders, which are not supported");
+                return Err(pso::CreationError::Other);
+            }
+            c::Primitive::PatchList(size)
+                if size == 0 || size > share.limits.max_patch_size =>
+            {
+                error!(
+                    "Patch lists have {} control points, the supported range is 1 to {}",
+                    size, share.limits.max_patch_size
+                );
+                return Err(pso::CreationError::Other);
+            }
+            _ => {}
+        }
+
+        // `GL_MIN/GL_MAX` are missing on GLES2 and WebGL1 without `EXT_blend_minmax`.
+        if !share.private_caps.blend_minmax {
+            let minmax = desc.blender.targets.iter().any(|target| match target.1 {
+                pso::BlendState::On { color, alpha } => [color, alpha].iter().any(|op| match op {
+                    pso::BlendOp::Min | pso::BlendOp::Max => true,
+                    _ => false,
+                }),
+                pso::BlendState::Off => false,
+            });
+            if minmax {
+                error!("Min/max blend operations are not supported, `EXT_blend_minmax` is missing");
+                return Err(pso::CreationError::Other);
+            }
+        }
+
+        // Without indexed blend functions, all draw buffers blend like the first one.
+        if !share.private_caps.draw_buffers_indexed {
+            let mut blends = desc.blender.targets.iter().filter_map(|target| match target.1 {
+                pso::BlendState::On { color, alpha } => Some((color, alpha)),
+                pso::BlendState::Off => None,
+            });
+            if let Some(first) = blends.next() {
+                if blends.any(|blend| blend != first) {
+                    warn!("Independent blending is not supported, using the first blend state");
+                }
+            }
+        }
+
+        let mut attributes = Vec::with_capacity(desc.attributes.len());
+        for a in &desc.attributes {
+            let (size, format, vertex_attrib_fn) =
+                match share.vertex_attrib_format(a.element.format) {
+                    Ok(layout) => layout,
+                    Err(reason) => {
+                        error!(
+                            "Vertex attribute {} has format {:?}, {}",
+                            a.location, a.element.format, reason
+                        );
+                        return Err(pso::CreationError::Other);
+                    }
+                };
+            attributes.push(n::AttributeDesc {
+                location: a.location,
+                offset: a.element.offset,
+                binding: a.binding,
+                size,
+                format,
+                vertex_attrib_fn,
+            });
+        }
+
+        // Without `GL_BGRA` attribute sizes, BGRA attributes are read in RGBA
+        // order and swizzled in the vertex shader.
+        let bgra_inputs = if share.private_caps.vertex_array_bgra {
+            Vec::new()
+        } else {
+            desc.attributes
+                .iter()
+                .filter(|a| match conv::format_to_gl_format(a.element.format) {
+                    Some((size, _, _)) => size == glow::BGRA as i32,
+                    None => false,
+                })
+                .map(|a| a.location)
+                .collect()
+        };
+
+        let mut block_members = FastHashMap::default();
+        let mut feedback = n::PipelineFeedback::default();
         let program = {
-            let name = gl.create_program().unwrap();
+            let name = gl.create_program().map_err(|err| {
+                error!("Error creating program: {}", err);
+                pso::CreationError::Other
+            })?;
 
             // Attach shaders to program
             let shaders = [
@@ -729,21 +1356,48 @@ impl d::Device<B> for Device {
             ];
 
             let mut name_binding_map = FastHashMap::<String, pso::DescriptorBinding>::default();
-            let shader_names = &shaders
-                .iter()
-                .filter_map(|&(stage, point_maybe)| {
-                    point_maybe.map(|point| {
-                        let shader_name = self.compile_shader(
-                            point,
-                            stage,
-                            &mut desc.layout.desc_remap_data.write().unwrap(),
-                            &mut name_binding_map,
-                        );
-                        gl.attach_shader(name, shader_name);
-                        shader_name
-                    })
-                })
-                .collect::<Vec<_>>();
+            let mut shader_names = Vec::with_capacity(shaders.len());
+            for &(stage, point_maybe) in &shaders {
+                let point = match point_maybe {
+                    Some(point) => point,
+                    None => continue,
+                };
+                let bgra_inputs = match stage {
+                    pso::Stage::Vertex => &bgra_inputs[..],
+                    _ => &[],
+                };
+                let (result, compile_time) = timed(|| {
+                    self.compile_shader(
+                        point,
+                        stage,
+                        &mut desc.layout.desc_remap_data.write().unwrap(),
+                        &mut name_binding_map,
+                        &mut block_members,
+                        bgra_inputs,
+                    )
+                });
+                let shader_name = match result {
+                    Ok(shader_name) => shader_name,
+                    Err(err) => {
+                        for shader_name in shader_names {
+                            gl.detach_shader(name, shader_name);
+                            gl.delete_shader(shader_name);
+                        }
+                        gl.delete_program(name);
+                        return Err(pso::CreationError::Shader(err));
+                    }
+                };
+                feedback.stages.push(n::StageFeedback {
+                    stage,
+                    compile_time: match *point.module {
+                        n::ShaderModule::Raw(_) => Duration::from_secs(0),
+                        n::ShaderModule::Spirv(_) => compile_time,
+                    },
+                    log: gl.get_shader_info_log(shader_name),
+                });
+                gl.attach_shader(name, shader_name);
+                shader_names.push(shader_name);
+            }
 
             if !share.private_caps.program_interface && share.private_caps.frag_data_location {
                 for i in 0..subpass.color_attachments.len() {
@@ -752,16 +1406,9 @@ impl d::Device<B> for Device {
                 }
             }
 
-            gl.link_program(name);
-            info!("\tLinked program {:?}", name);
-            if let Err(err) = share.check() {
-                panic!("Error linking program: {:?}", err);
-            }
-
-            for shader_name in shader_names {
-                gl.detach_shader(name, *shader_name);
-                gl.delete_shader(*shader_name);
-            }
+            let (link_time, link_log) = self.link_program(name, &shader_names)?;
+            feedback.link_time = link_time;
+            feedback.link_log = link_log;
 
             if !self
                 .share
@@ -776,18 +1423,6 @@ impl d::Device<B> for Device {
                 }
             }
 
-            let linked_ok = gl.get_program_link_status(name);
-            let log = gl.get_program_info_log(name);
-            if linked_ok {
-                if !log.is_empty() {
-                    warn!("\tLog: {}", log);
-                }
-            } else {
-                return Err(pso::CreationError::Shader(
-                    d::ShaderError::CompilationFailed(log),
-                ));
-            }
-
             name
         };
 
@@ -805,6 +1440,7 @@ impl d::Device<B> for Device {
         }
 
         let mut uniforms = Vec::new();
+        let mut block_uniforms = Vec::new();
         {
             let gl = &self.share.context;
             let count = gl.get_active_uniforms(program);
@@ -820,6 +1456,20 @@ impl d::Device<B> for Device {
 
                 let location = gl.get_uniform_location(program, &name).unwrap();
 
+                // Arrays are reported by their first element.
+                if let Some(&(binding, member_offset)) =
+                    block_members.get(name.trim_end_matches("[0]"))
+                {
+                    block_uniforms.push(n::BlockUniform {
+                        location,
+                        utype,
+                        count: size as u32,
+                        binding,
+                        offset: member_offset,
+                    });
+                    continue;
+                }
+
                 // Sampler2D won't show up in UniformLocation and the only other uniforms
                 // should be push constants
                 uniforms.push(n::UniformDesc {
@@ -832,31 +1482,41 @@ impl d::Device<B> for Device {
             }
         }
 
+        // Without a fragment shader and attachments, nothing is rasterized.
+        let rasterizer_discard = share.private_caps.rasterizer_discard
+            && desc.shaders.fragment.is_none()
+            && subpass.color_attachments.is_empty()
+            && subpass.depth_stencil.is_none();
+
+        // Flipping Y in the shaders reverses the winding of primitives.
+        let mut rasterizer = desc.rasterizer;
+        if share.flip_in_shaders() {
+            rasterizer.front_face = match rasterizer.front_face {
+                pso::FrontFace::Clockwise => pso::FrontFace::CounterClockwise,
+                pso::FrontFace::CounterClockwise => pso::FrontFace::Clockwise,
+            };
+        }
+
         Ok(n::GraphicsPipeline {
             program,
             primitive: conv::primitive_to_gl_primitive(desc.input_assembler.primitive),
+            rasterizer_discard,
+            rasterization_samples: desc
+                .multisampling
+                .as_ref()
+                .map_or(1, |ms| ms.rasterization_samples as i32),
             patch_size,
             blend_targets: desc.blender.targets.clone(),
             vertex_buffers,
-            attributes: desc
-                .attributes
-                .iter()
-                .map(|&a| {
-                    let (size, format, vertex_attrib_fn) =
-                        conv::format_to_gl_format(a.element.format).unwrap();
-                    n::AttributeDesc {
-                        location: a.location,
-                        offset: a.element.offset,
-                        binding: a.binding,
-                        size,
-                        format,
-                        vertex_attrib_fn,
-                    }
-                })
-                .collect(),
+            attributes,
             uniforms,
-            rasterizer: desc.rasterizer,
+            block_uniforms: Arc::new(n::BlockUniforms {
+                uploaded: Mutex::new(vec![Vec::new(); block_uniforms.len()]),
+                uniforms: block_uniforms,
+            }),
+            rasterizer,
             depth: desc.depth_stencil.depth,
+            feedback,
         })
     }
 
@@ -866,28 +1526,27 @@ impl d::Device<B> for Device {
         _cache: Option<&()>,
     ) -> Result<n::ComputePipeline, pso::CreationError> {
         let gl = &self.share.context;
-        let share = &self.share;
 
         let program = {
-            let name = gl.create_program().unwrap();
-
             let mut name_binding_map = FastHashMap::<String, pso::DescriptorBinding>::default();
-            let shader = self.compile_shader(
-                &desc.shader,
-                pso::Stage::Compute,
-                &mut desc.layout.desc_remap_data.write().unwrap(),
-                &mut name_binding_map,
-            );
-
+            let shader = self
+                .compile_shader(
+                    &desc.shader,
+                    pso::Stage::Compute,
+                    &mut desc.layout.desc_remap_data.write().unwrap(),
+                    &mut name_binding_map,
+                    &mut FastHashMap::default(),
+                    &[],
+                )
+                .map_err(pso::CreationError::Shader)?;
+
+            let name = gl.create_program().map_err(|err| {
+                error!("Error creating program: {}", err);
+                gl.delete_shader(shader);
+                pso::CreationError::Other
+            })?;
             gl.attach_shader(name, shader);
-            gl.link_program(name);
-            info!("\tLinked program {:?}", name);
-            if let Err(err) = share.check() {
-                panic!("Error linking program: {:?}", err);
-            }
-
-            gl.detach_shader(name, shader);
-            gl.delete_shader(shader);
+            self.link_program(name, &[shader])?;
 
             if !self
                 .share
@@ -902,16 +1561,6 @@ impl d::Device<B> for Device {
                 }
             }
 
-            let linked_ok = gl.get_program_link_status(name);
-            let log = gl.get_program_info_log(name);
-            if linked_ok {
-                if !log.is_empty() {
-                    warn!("\tLog: {}", log);
-                }
-            } else {
-                return Err(pso::CreationError::Other);
-            }
-
             name
         };
 
@@ -922,28 +1571,84 @@ impl d::Device<B> for Device {
         &self,
         pass: &n::RenderPass,
         attachments: I,
-        _extent: i::Extent,
+        extent: i::Extent,
     ) -> Result<Option<n::FrameBuffer>, d::OutOfMemory>
     where
         I: IntoIterator,
         I::Item: Borrow<n::ImageView>,
     {
+        let attachments = attachments
+            .into_iter()
+            .map(|view| *view.borrow())
+            .collect::<Vec<_>>();
+        // A swapchain image on its own renders to the default framebuffer.
+        if let [n::ImageView::Surface(surface)] = attachments[..] {
+            if self.share.is_backbuffer(surface) {
+                return Ok(None);
+            }
+        }
+        // Only the default framebuffer can be rendered to without FBOs.
         if !self.share.private_caps.framebuffer {
+            error!("Offscreen framebuffers are not supported without FBOs");
             return Err(d::OutOfMemory::OutOfHostMemory);
         }
+        for view in &attachments {
+            if let n::ImageView::Surface(surface) = *view {
+                // The image would have to be blitted to the default framebuffer.
+                if self.share.is_backbuffer(surface) && !self.share.private_caps.framebuffer_blit {
+                    error!("Swapchain images can only be rendered to on their own without blits");
+                    return Err(d::OutOfMemory::OutOfHostMemory);
+                }
+                self.share.allocate_backbuffer(surface);
+            }
+        }
 
         let gl = &self.share.context;
         let target = glow::DRAW_FRAMEBUFFER;
+
+        // Framebuffers without attachments take their size from default parameters,
+        // the sample count is set by the bound pipeline.
+        if attachments.is_empty() {
+            if !self.share.framebuffer_no_attachments() {
+                error!("Framebuffers without attachments are not supported");
+                return Err(d::OutOfMemory::OutOfHostMemory);
+            }
+            let name = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(target, Some(name));
+            self.share.set_framebuffer_default(
+                target,
+                glow::FRAMEBUFFER_DEFAULT_WIDTH,
+                extent.width as _,
+            );
+            self.share.set_framebuffer_default(
+                target,
+                glow::FRAMEBUFFER_DEFAULT_HEIGHT,
+                extent.height as _,
+            );
+            if extent.depth > 1 {
+                self.share.set_framebuffer_default(
+                    target,
+                    glow::FRAMEBUFFER_DEFAULT_LAYERS,
+                    extent.depth as _,
+                );
+            }
+            gl.bind_framebuffer(target, None);
+            if let Err(err) = self.share.check() {
+                panic!("Error creating FBO without attachments: {:?}", err);
+            }
+            return Ok(Some(name));
+        }
+
         let name = gl.create_framebuffer().unwrap();
         gl.bind_framebuffer(target, Some(name));
 
         let mut render_attachments = Vec::with_capacity(pass.attachments.len());
         let mut color_attachment_index = 0;
         for attachment in &pass.attachments {
-            if color_attachment_index > self.share.limits.framebuffer_color_samples_count as _ {
+            if color_attachment_index > self.share.limits.max_color_attachments as _ {
                 panic!(
                     "Invalid number of color attachments: {} color_attachment of {}",
-                    color_attachment_index, self.share.limits.framebuffer_color_samples_count
+                    color_attachment_index, self.share.limits.max_color_attachments
                 );
             }
 
@@ -970,19 +1675,46 @@ impl d::Device<B> for Device {
             }
         }
 
-        let mut attachments_len = 0;
-        for (&render_attachment, view) in render_attachments.iter().zip(attachments.into_iter()) {
-            attachments_len += 1;
+        let bind_target = |render_attachment, view| {
             if self.share.private_caps.framebuffer_texture {
-                Self::bind_target(gl, target, render_attachment, view.borrow());
+                Self::bind_target(gl, target, render_attachment, view);
             } else {
-                Self::bind_target_compat(gl, target, render_attachment, view.borrow());
+                Self::bind_target_compat(gl, target, render_attachment, view);
             }
+        };
+
+        let mut attachments_len = 0;
+        for (&render_attachment, view) in render_attachments.iter().zip(attachments.iter()) {
+            attachments_len += 1;
+            bind_target(render_attachment, view);
         }
 
         assert!(pass.attachments.len() <= attachments_len);
 
         let _status = gl.check_framebuffer_status(target); //TODO: check status
+
+        // Without texture barriers, sampling an attachment of the bound framebuffer is
+        // a feedback loop. Each subpass renders to a framebuffer with only its own
+        // attachments instead, switching framebuffers makes the writes visible.
+        if pass.reads_attachments() && !self.share.supports_texture_barrier() {
+            let subpass_framebuffers = pass
+                .subpasses
+                .iter()
+                .map(|subpass| {
+                    let subpass_name = gl.create_framebuffer().unwrap();
+                    gl.bind_framebuffer(target, Some(subpass_name));
+                    let used = subpass.color_attachments.iter().chain(subpass.depth_stencil.iter());
+                    for &id in used {
+                        bind_target(render_attachments[id], &attachments[id]);
+                    }
+                    subpass_name
+                })
+                .collect();
+            self.share
+                .subpass_framebuffers
+                .borrow_mut()
+                .insert(name, subpass_framebuffers);
+        }
         gl.bind_framebuffer(target, None);
 
         if let Err(err) = self.share.check() {
@@ -1040,15 +1772,8 @@ impl d::Device<B> for Device {
         size: u64,
         usage: buffer::Usage,
     ) -> Result<n::Buffer, buffer::CreationError> {
-        if !self
-            .share
-            .legacy_features
-            .contains(LegacyFeatures::CONSTANT_BUFFER)
-            && usage.contains(buffer::Usage::UNIFORM)
-        {
-            return Err(buffer::CreationError::UnsupportedUsage { usage });
-        }
-
+        // Uniform buffers without `CONSTANT_BUFFER` support are emulated,
+        // see `PrivateCaps::emulate_uniform_blocks`.
         Ok(n::Buffer::Unbound {
             size,
             usage,
@@ -1063,9 +1788,7 @@ impl d::Device<B> for Device {
 
         memory::Requirements {
             size: size as u64,
-            // Alignment of 4 covers indexes of type u16 and u32 in index buffers, which is
-            // currently the only alignment requirement.
-            alignment: 4,
+            alignment: self.share.buffer_alignment(usage),
             type_mask: self.share.buffer_memory_type_mask(usage),
         }
     }
@@ -1076,13 +1799,27 @@ impl d::Device<B> for Device {
         offset: u64,
         buffer: &mut n::Buffer,
     ) -> Result<(), d::BindError> {
-        let size = match *buffer {
-            n::Buffer::Unbound { size, .. } => size,
+        let (size, usage) = match *buffer {
+            n::Buffer::Unbound { size, usage } => (size, usage),
             n::Buffer::Bound { .. } => panic!("Unexpected Buffer::Bound"),
         };
+        let alignment = self.share.buffer_alignment(usage);
+        if offset % alignment != 0 {
+            error!("Buffer offset {} isn't aligned to {}", offset, alignment);
+            return Err(d::BindError::WrongMemory);
+        }
+        let raw = match memory.buffer {
+            Some((raw, _)) => raw,
+            None => return Err(d::BindError::WrongMemory),
+        };
+        if offset + size > memory.size {
+            return Err(d::BindError::OutOfBounds);
+        }
 
+        // Several buffers may be bound to sub-ranges of the same GL buffer,
+        // all commands offset by the start of the range.
         *buffer = n::Buffer::Bound {
-            buffer: memory.buffer.expect("Improper memory type used for buffer memory").0,
+            buffer: raw,
             range: offset..offset + size,
         };
 
@@ -1100,8 +1837,12 @@ impl d::Device<B> for Device {
         let offset = *range.start().unwrap_or(&0);
         let size = *range.end().unwrap_or(&memory.size) - offset;
 
-        let (buffer, target) = memory.buffer.expect("cannot map image memory");
-        let ptr = if caps.emulate_map {
+        if !memory.properties.contains(memory::Properties::CPU_VISIBLE) {
+            return Err(mapping::Error::InvalidAccess);
+        }
+
+        // Host visible image memory is mapped through a client-side copy.
+        let ptr = if caps.emulate_map || memory.buffer.is_none() {
             let ptr: *mut u8 = if let Some(ptr) = memory.emulate_map_allocation.get() {
                 ptr
             } else {
@@ -1112,6 +1853,7 @@ impl d::Device<B> for Device {
 
             ptr.offset(offset as isize)
         } else {
+            let (buffer, target) = memory.buffer.unwrap();
             gl.bind_buffer(target, Some(buffer));
             let raw = gl.map_buffer_range(target, offset as i32, size as i32, memory.map_flags);
             gl.bind_buffer(target, None);
@@ -1127,7 +1869,15 @@ impl d::Device<B> for Device {
 
     unsafe fn unmap_memory(&self, memory: &n::Memory) {
         let gl = &self.share.context;
-        let (buffer, target) = memory.buffer.expect("cannot unmap image memory");
+        let (buffer, target) = match memory.buffer {
+            Some(buffer) => buffer,
+            None => {
+                self.upload_host_images(memory, 0..memory.size);
+                let ptr = memory.emulate_map_allocation.replace(None).unwrap();
+                let _ = Box::from_raw(slice::from_raw_parts_mut(ptr, memory.size as usize));
+                return;
+            }
+        };
 
         gl.bind_buffer(target, Some(buffer));
 
@@ -1155,16 +1905,23 @@ impl d::Device<B> for Device {
 
         for i in ranges {
             let (mem, range) = i.borrow();
-            let (buffer, target) = mem.buffer.expect("cannot flush image memory");
-            gl.bind_buffer(target, Some(buffer));
-
             let offset = *range.start().unwrap_or(&0);
             let size = *range.end().unwrap_or(&mem.size) - offset;
 
+            let (buffer, target) = match mem.buffer {
+                Some(buffer) => buffer,
+                None => {
+                    self.upload_host_images(mem, offset..offset + size);
+                    continue;
+                }
+            };
+            gl.bind_buffer(target, Some(buffer));
+
             if self.share.private_caps.emulate_map {
                 let ptr = mem.emulate_map_allocation.get().unwrap();
                 let slice = slice::from_raw_parts_mut(ptr.offset(offset as isize), size as usize);
                 gl.buffer_sub_data_u8_slice(target, offset as i32, slice);
+                self.share.write_buffer_shadow(buffer, offset, slice);
             } else {
                 gl.flush_mapped_buffer_range(target, offset as i32, size as i32);
             }
@@ -1190,7 +1947,11 @@ impl d::Device<B> for Device {
 
         for i in ranges {
             let (mem, range) = i.borrow();
-            let (buffer, target) = mem.buffer.expect("cannot invalidate image memory");
+            // Images are only written from the host, their client-side copy stays valid.
+            let (buffer, target) = match mem.buffer {
+                Some(buffer) => buffer,
+                None => continue,
+            };
             gl.bind_buffer(target, Some(buffer));
 
             let offset = *range.start().unwrap_or(&0);
@@ -1227,22 +1988,19 @@ impl d::Device<B> for Device {
         kind: i::Kind,
         num_levels: i::Level,
         format: Format,
-        _tiling: i::Tiling,
+        tiling: i::Tiling,
         usage: i::Usage,
         _view_caps: i::ViewCapabilities,
     ) -> Result<n::Image, i::CreationError> {
         let gl = &self.share.context;
 
-        let (int_format, iformat, itype) = match format {
-            Format::Rgba8Unorm => (glow::RGBA8, glow::RGBA, glow::UNSIGNED_BYTE),
-            Format::Bgra8Unorm => (glow::RGBA8, glow::BGRA, glow::UNSIGNED_BYTE),
-            Format::Rgba8Srgb => (glow::SRGB8_ALPHA8, glow::RGBA, glow::UNSIGNED_BYTE),
-            Format::D32Sfloat => (
-                glow::DEPTH32F_STENCIL8,
-                glow::DEPTH_STENCIL,
-                glow::FLOAT_32_UNSIGNED_INT_24_8_REV,
-            ),
-            _ => unimplemented!()
+        if tiling == i::Tiling::Linear {
+            return self.create_linear_image(kind, num_levels, format, usage);
+        }
+
+        let (int_format, iformat, itype) = match self.share.image_format(format) {
+            Some(formats) => formats,
+            None => return Err(i::CreationError::Format(format)),
         };
 
         let channel = format.base_format().1;
@@ -1255,85 +2013,34 @@ impl d::Device<B> for Device {
             match kind {
                 i::Kind::D2(w, h, 1, 1) => {
                     gl.bind_texture(glow::TEXTURE_2D, Some(name));
-                    if self.share.private_caps.image_storage {
-                        gl.tex_storage_2d(
-                            glow::TEXTURE_2D,
-                            num_levels as _,
-                            int_format,
-                            w as _,
-                            h as _,
-                        );
-                    } else {
-                        gl.tex_parameter_i32(
-                            glow::TEXTURE_2D,
-                            glow::TEXTURE_MAX_LEVEL,
-                            (num_levels - 1) as _,
-                        );
-                        let mut w = w;
-                        let mut h = h;
-                        for i in 0..num_levels {
-                            gl.tex_image_2d(
-                                glow::TEXTURE_2D,
-                                i as _,
-                                int_format as _,
-                                w as _,
-                                h as _,
-                                0,
-                                iformat,
-                                itype,
-                                None,
-                            );
-                            w = std::cmp::max(w / 2, 1);
-                            h = std::cmp::max(h / 2, 1);
-                        }
-                    }
+                    let formats = (int_format, iformat, itype);
+                    allocate_texture(&self.share, glow::TEXTURE_2D, num_levels, formats, w, h, 1);
                     n::ImageKind::Texture(name, glow::TEXTURE_2D)
                 }
                 i::Kind::D2(w, h, l, 1) => {
                     gl.bind_texture(glow::TEXTURE_2D_ARRAY, Some(name));
-                    if self.share.private_caps.image_storage {
-                        gl.tex_storage_3d(
-                            glow::TEXTURE_2D_ARRAY,
-                            num_levels as _,
-                            int_format,
-                            w as _,
-                            h as _,
-                            l as _,
-                        );
-                    } else {
-                        gl.tex_parameter_i32(
-                            glow::TEXTURE_2D_ARRAY,
-                            glow::TEXTURE_MAX_LEVEL,
-                            (num_levels - 1) as _,
-                        );
-                        let mut w = w;
-                        let mut h = h;
-                        for i in 0..num_levels {
-                            gl.tex_image_3d(
-                                glow::TEXTURE_2D_ARRAY,
-                                i as _,
-                                int_format as _,
-                                w as _,
-                                h as _,
-                                l as _,
-                                0,
-                                iformat,
-                                itype,
-                                None,
-                            );
-                            w = std::cmp::max(w / 2, 1);
-                            h = std::cmp::max(h / 2, 1);
-                        }
-                    }
+                    let formats = (int_format, iformat, itype);
+                    let target = glow::TEXTURE_2D_ARRAY;
+                    allocate_texture(&self.share, target, num_levels, formats, w, h, l);
                     n::ImageKind::Texture(name, glow::TEXTURE_2D_ARRAY)
                 }
                 _ => unimplemented!(),
             }
+        } else if !self.share.private_caps.framebuffer {
+            // Renderbuffers only serve as attachments, which require FBOs.
+            error!("Attachment-only images are not supported without FBOs");
+            return Err(i::CreationError::Usage(usage));
         } else {
             let name = gl.create_renderbuffer().unwrap();
             match kind {
                 i::Kind::D2(w, h, 1, 1) => {
                     gl.bind_renderbuffer(glow::RENDERBUFFER, Some(name));
+                    // Renderbuffers take sized formats, `OES_rgb8_rgba8` is ubiquitous on ES 2.0.
+                    let int_format = match int_format {
+                        glow::RGBA | glow::BGRA => glow::RGBA8,
+                        glow::SRGB_ALPHA => glow::SRGB8_ALPHA8,
+                        other => other,
+                    };
                     gl.renderbuffer_storage(glow::RENDERBUFFER, int_format, w as _, h as _);
                 }
                 _ => unimplemented!(),
@@ -1345,7 +2052,33 @@ impl d::Device<B> for Device {
         let bytes_per_texel = surface_desc.bits / 8;
         let ext = kind.extent();
         let size = (ext.width * ext.height * ext.depth) as u64 * bytes_per_texel as u64;
-        let type_mask = self.share.image_memory_type_mask();
+        // Renderbuffers of transient attachments are never read outside of a render pass.
+        let transient = usage.contains(i::Usage::TRANSIENT_ATTACHMENT)
+            && match image {
+                n::ImageKind::Surface(_) => true,
+                _ => false,
+            };
+        // Single level 2D textures can be written through mapped memory,
+        // unless their texels are converted.
+        let conversion = self.share.texel_conversion(format);
+        let host_image = match image {
+            n::ImageKind::Texture(texture, glow::TEXTURE_2D)
+                if num_levels == 1 && conversion == n::TexelConversion::None =>
+            {
+                Some(n::HostImage {
+                    texture,
+                    target: glow::TEXTURE_2D,
+                    width: ext.width,
+                    height: ext.height,
+                    format: iformat,
+                    ty: itype,
+                })
+            }
+            _ => None,
+        };
+        let type_mask = self
+            .share
+            .image_memory_type_mask(transient, host_image.is_some());
 
         if let Err(err) = self.share.check() {
             panic!(
@@ -1362,6 +2095,12 @@ impl d::Device<B> for Device {
                 alignment: 1,
                 type_mask,
             },
+            host_image,
+            upload: n::TexelUpload {
+                format: iformat,
+                ty: itype,
+                conversion,
+            },
         })
     }
 
@@ -1371,38 +2110,75 @@ impl d::Device<B> for Device {
 
     unsafe fn get_image_subresource_footprint(
         &self,
-        _image: &n::Image,
+        image: &n::Image,
         _sub: i::Subresource,
     ) -> i::SubresourceFootprint {
-        unimplemented!()
+        match image.kind {
+            // Linear images have a single level and layer.
+            n::ImageKind::Linear(linear) => i::SubresourceFootprint {
+                slice: 0..linear.size(),
+                row_pitch: linear.row_pitch(),
+                array_pitch: linear.size(),
+                depth_pitch: linear.size(),
+            },
+            _ => unimplemented!(),
+        }
     }
 
     unsafe fn bind_image_memory(
         &self,
-        _memory: &n::Memory,
-        _offset: u64,
-        _image: &mut n::Image,
+        memory: &n::Memory,
+        offset: u64,
+        image: &mut n::Image,
     ) -> Result<(), d::BindError> {
-        Ok(())
+        if offset + image.requirements.size > memory.size {
+            return Err(d::BindError::OutOfBounds);
+        }
+        match (&mut image.kind, memory.buffer) {
+            // Linear images live in the buffer of their memory.
+            (n::ImageKind::Linear(linear), Some((buffer, _))) => {
+                linear.buffer = Some((buffer, offset));
+                Ok(())
+            }
+            // Texels written to host visible memory are uploaded to the texture.
+            (n::ImageKind::Texture(..), None)
+                if memory.properties.contains(memory::Properties::CPU_VISIBLE) =>
+            {
+                match image.host_image {
+                    Some(host_image) => {
+                        let range = offset..offset + image.requirements.size;
+                        let mut host_images = memory.host_images.borrow_mut();
+                        // Images previously bound to the range have been destroyed.
+                        host_images.retain(|(r, _)| r.end <= range.start || r.start >= range.end);
+                        host_images.push((range, host_image));
+                        Ok(())
+                    }
+                    None => Err(d::BindError::WrongMemory),
+                }
+            }
+            // Other images own their storage, binding only validates the memory range.
+            // Images aliasing each other or buffers don't share their contents.
+            (n::ImageKind::Surface(_), None) | (n::ImageKind::Texture(..), None) => Ok(()),
+            _ => Err(d::BindError::WrongMemory),
+        }
     }
 
     unsafe fn create_image_view(
         &self,
         image: &n::Image,
-        _kind: i::ViewKind,
+        kind: i::ViewKind,
         _format: Format,
         swizzle: Swizzle,
         range: i::SubresourceRange,
     ) -> Result<n::ImageView, i::ViewError> {
         //TODO: check if `layers.end` covers all the layers
         let level = range.levels.start;
-        assert_eq!(level + 1, range.levels.end);
         //assert_eq!(format, image.format);
         assert_eq!(swizzle, Swizzle::NO);
         //TODO: check format
         match image.kind {
             n::ImageKind::Surface(surface) => {
-                if range.levels.start == 0 && range.layers.start == 0 {
+                if range.levels == (0..1) && range.layers.start == 0 {
                     Ok(n::ImageView::Surface(surface))
                 } else if level != 0 {
                     Err(i::ViewError::Level(level)) //TODO
@@ -1412,10 +2188,28 @@ impl d::Device<B> for Device {
                     )))
                 }
             }
+            n::ImageKind::Linear(_) => Err(i::ViewError::Usage(i::Usage::SAMPLED)),
             n::ImageKind::Texture(texture, textype) => {
                 //TODO: check that `level` exists
-                if range.layers.start == 0 {
-                    Ok(n::ImageView::Texture(texture, textype, level))
+                // Single layer views of layered textures are attached as that layer,
+                // other views are attached with all layers for layered rendering.
+                let single_layer = match kind {
+                    i::ViewKind::D1 | i::ViewKind::D2 => true,
+                    _ => false,
+                };
+                let levels = n::LevelRange {
+                    start: range.levels.start,
+                    end: range.levels.end,
+                };
+                if single_layer && is_layered(textype) {
+                    Ok(n::ImageView::TextureLayer(
+                        texture,
+                        textype,
+                        level,
+                        range.layers.start,
+                    ))
+                } else if range.layers.start == 0 {
+                    Ok(n::ImageView::Texture(texture, textype, levels))
                 } else if range.layers.start + 1 == range.layers.end {
                     Ok(n::ImageView::TextureLayer(
                         texture,
@@ -1434,15 +2228,29 @@ impl d::Device<B> for Device {
 
     unsafe fn create_descriptor_pool<I>(
         &self,
-        _: usize,
-        _: I,
-        _: pso::DescriptorPoolCreateFlags,
+        max_sets: usize,
+        descriptor_ranges: I,
+        flags: pso::DescriptorPoolCreateFlags,
     ) -> Result<n::DescriptorPool, d::OutOfMemory>
     where
         I: IntoIterator,
         I::Item: Borrow<pso::DescriptorRangeDesc>,
     {
-        Ok(n::DescriptorPool {})
+        let mut capacity = [0; 11];
+        for range in descriptor_ranges {
+            let range = range.borrow();
+            capacity[range.ty as usize] += range.count;
+        }
+
+        Ok(n::DescriptorPool {
+            max_sets,
+            capacity,
+            available_sets: max_sets,
+            available: capacity,
+            free_descriptor_set: flags
+                .contains(pso::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET),
+            buffers: Arc::new(Mutex::new(Vec::new())),
+        })
     }
 
     unsafe fn create_descriptor_set_layout<I, J>(
@@ -1470,61 +2278,17 @@ impl d::Device<B> for Device {
             let set = &mut write.set;
             let mut bindings = set.bindings.lock().unwrap();
             let binding = write.binding;
-            let mut offset = write.array_offset as i32;
+            let mut index = write.array_offset;
 
             for descriptor in write.descriptors {
-                match descriptor.borrow() {
-                    pso::Descriptor::Buffer(buffer, ref range) => {
-                        let (raw_buffer, buffer_range) = buffer.as_bound();
-                        let start = buffer_range.start as i32 + range.start.unwrap_or(0) as i32;
-                        let end = buffer_range.start as i32 + range.end.unwrap_or((buffer_range.end - buffer_range.start) as u64) as i32;
-                        let size = end - start;
-
-                        bindings.push(n::DescSetBindings::Buffer {
-                            ty: n::BindingTypes::UniformBuffers,
-                            binding,
-                            buffer: raw_buffer,
-                            offset: offset + start,
-                            size,
-                        });
-
-                        offset += size;
-                    }
-                    pso::Descriptor::CombinedImageSampler(view, _layout, sampler) => {
-                        match view {
-                            n::ImageView::Texture(tex, textype, _)
-                            | n::ImageView::TextureLayer(tex, textype, _, _) => {
-                                bindings.push(n::DescSetBindings::Texture(binding, *tex, *textype))
-                            }
-                            n::ImageView::Surface(_) => unimplemented!(),
-                        }
-                        match sampler {
-                            n::FatSampler::Sampler(sampler) => {
-                                bindings.push(n::DescSetBindings::Sampler(binding, *sampler))
-                            }
-                            n::FatSampler::Info(info) => bindings
-                                .push(n::DescSetBindings::SamplerInfo(binding, info.clone())),
-                        }
+                n::write_descriptor(&mut bindings, binding, index, descriptor.borrow());
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    if self.share.bindless_textures {
+                        self.write_descriptor_handle(*set, binding, index, descriptor.borrow());
                     }
-                    pso::Descriptor::Image(view, _layout) => match view {
-                        n::ImageView::Texture(tex, textype, _) | n::ImageView::TextureLayer(tex, textype, _, _) => {
-                            bindings.push(n::DescSetBindings::Texture(binding, *tex, *textype))
-                        }
-                        n::ImageView::Surface(_) => panic!(
-                            "Texture was created with only render target usage which is invalid."
-                        ),
-                    },
-                    pso::Descriptor::Sampler(sampler) => match sampler {
-                        n::FatSampler::Sampler(sampler) => {
-                            bindings.push(n::DescSetBindings::Sampler(binding, *sampler))
-                        }
-                        n::FatSampler::Info(info) => {
-                            bindings.push(n::DescSetBindings::SamplerInfo(binding, info.clone()))
-                        }
-                    },
-                    pso::Descriptor::UniformTexelBuffer(_view) => unimplemented!(),
-                    pso::Descriptor::StorageTexelBuffer(_view) => unimplemented!(),
                 }
+                index += 1;
             }
         }
     }
@@ -1534,13 +2298,37 @@ impl d::Device<B> for Device {
         I: IntoIterator,
         I::Item: Borrow<pso::DescriptorSetCopy<'a, B>>,
     {
-        for _copy in copies {
-            unimplemented!()
+        for copy in copies {
+            let copy = copy.borrow();
+            let src_range = copy.src_array_offset..copy.src_array_offset + copy.count;
+            let in_range = |index, range: &Range<pso::DescriptorArrayIndex>| {
+                range.start <= index && index < range.end
+            };
+
+            // Copying within a set has to release the lock before writing.
+            let copied = {
+                let src = copy.src_set.bindings.lock().unwrap();
+                src.iter()
+                    .filter(|&&(index, ref entry)| {
+                        entry.binding() == copy.src_binding && in_range(index, &src_range)
+                    })
+                    .map(|&(index, ref entry)| {
+                        let mut entry = entry.clone();
+                        entry.set_binding(copy.dst_binding);
+                        (index - copy.src_array_offset + copy.dst_array_offset, entry)
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            let mut dst = copy.dst_set.bindings.lock().unwrap();
+            for (index, entry) in copied {
+                n::write_binding(&mut dst, index, entry);
+            }
         }
     }
 
     fn create_semaphore(&self) -> Result<n::Semaphore, d::OutOfMemory> {
-        Ok(n::Semaphore)
+        Ok(n::Semaphore { external: None })
     }
 
     fn create_fence(&self, signalled: bool) -> Result<n::Fence, d::OutOfMemory> {
@@ -1576,10 +2364,11 @@ impl d::Device<B> for Device {
         fence: &n::Fence,
         timeout_ns: u64,
     ) -> Result<bool, d::OomOrDeviceLost> {
+        self.share.check_device_lost()?;
         if !self.share.private_caps.sync {
             return Ok(true);
         }
-        match wait_fence(fence, &self.share, timeout_ns) {
+        match wait_fence(fence, &self.share, timeout_ns)? {
             glow::TIMEOUT_EXPIRED => Ok(false),
             glow::WAIT_FAILED => {
                 if let Err(err) = self.share.check() {
@@ -1592,55 +2381,88 @@ impl d::Device<B> for Device {
     }
 
     unsafe fn get_fence_status(&self, fence: &n::Fence) -> Result<bool, d::DeviceLost> {
+        self.share.check_device_lost()?;
         let gl = &self.share.context;
 
         let status = gl.get_sync_status(fence.0.get().unwrap());
+        self.share.check_device_lost()?;
         Ok(status == glow::SIGNALED)
     }
 
-    fn create_event(&self) -> Result<(), d::OutOfMemory> {
-        unimplemented!()
+    fn create_event(&self) -> Result<n::Event, d::OutOfMemory> {
+        Ok(n::Event::default())
     }
 
-    unsafe fn get_event_status(&self, _event: &()) -> Result<bool, d::OomOrDeviceLost> {
-        unimplemented!()
+    unsafe fn get_event_status(&self, event: &n::Event) -> Result<bool, d::OomOrDeviceLost> {
+        Ok(event.is_set())
     }
 
-    unsafe fn set_event(&self, _event: &()) -> Result<(), d::OutOfMemory> {
-        unimplemented!()
+    unsafe fn set_event(&self, event: &n::Event) -> Result<(), d::OutOfMemory> {
+        event.set(true);
+        Ok(())
     }
 
-    unsafe fn reset_event(&self, _event: &()) -> Result<(), d::OutOfMemory> {
-        unimplemented!()
+    unsafe fn reset_event(&self, event: &n::Event) -> Result<(), d::OutOfMemory> {
+        event.set(false);
+        Ok(())
     }
 
     unsafe fn free_memory(&self, memory: n::Memory) {
         if let Some((buffer, _)) = memory.buffer {
-            self.share.context.delete_buffer(buffer);
+            self.share.buffer_shadows.borrow_mut().remove(&buffer);
+            self.share.delete_deferred(Object::Buffer(buffer));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(memory_object) = memory.memory_object {
+                self.share.context.external.delete_memory_object(memory_object);
+            }
         }
     }
 
     unsafe fn create_query_pool(
         &self,
-        _ty: query::Type,
-        _count: query::Id,
-    ) -> Result<(), query::CreationError> {
-        unimplemented!()
+        ty: query::Type,
+        count: query::Id,
+    ) -> Result<n::QueryPool, query::CreationError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.create_queries(ty, count)
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = count;
+            Err(query::CreationError::Unsupported(ty))
+        }
     }
 
-    unsafe fn destroy_query_pool(&self, _: ()) {
-        unimplemented!()
+    unsafe fn destroy_query_pool(&self, pool: n::QueryPool) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.share.context.query.delete_queries(&pool.queries);
+        #[cfg(target_arch = "wasm32")]
+        let _ = pool;
     }
 
     unsafe fn get_query_pool_results(
         &self,
-        _pool: &(),
-        _queries: Range<query::Id>,
-        _data: &mut [u8],
-        _stride: buffer::Offset,
-        _flags: query::ResultFlags,
+        pool: &n::QueryPool,
+        queries: Range<query::Id>,
+        data: &mut [u8],
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
     ) -> Result<bool, d::OomOrDeviceLost> {
-        unimplemented!()
+        if self.share.device_lost.get() {
+            return Err(d::DeviceLost.into());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            Ok(self.read_query_results(pool, queries, data, stride, flags))
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = (pool, queries, data, stride, flags);
+            unreachable!()
+        }
     }
 
     unsafe fn destroy_shader_module(&self, _: n::ShaderModule) {
@@ -1667,6 +2489,10 @@ impl d::Device<B> for Device {
         let gl = &self.share.context;
         if let Some(f) = frame_buffer {
             gl.delete_framebuffer(f);
+            let subpass_framebuffers = self.share.subpass_framebuffers.borrow_mut().remove(&f);
+            for subpass_framebuffer in subpass_framebuffers.into_iter().flatten() {
+                gl.delete_framebuffer(subpass_framebuffer);
+            }
         }
     }
 
@@ -1679,10 +2505,10 @@ impl d::Device<B> for Device {
     }
 
     unsafe fn destroy_image(&self, image: n::Image) {
-        let gl = &self.share.context;
         match image.kind {
-            n::ImageKind::Surface(rb) => gl.delete_renderbuffer(rb),
-            n::ImageKind::Texture(t, _) => gl.delete_texture(t),
+            n::ImageKind::Surface(rb) => self.share.delete_deferred(Object::Renderbuffer(rb)),
+            n::ImageKind::Texture(t, _) => self.share.delete_deferred(Object::Texture(t)),
+            n::ImageKind::Linear(_) => {}
         }
     }
 
@@ -1691,15 +2517,16 @@ impl d::Device<B> for Device {
     }
 
     unsafe fn destroy_sampler(&self, sampler: n::FatSampler) {
-        let gl = &self.share.context;
         match sampler {
-            n::FatSampler::Sampler(s) => gl.delete_sampler(s),
+            n::FatSampler::Sampler(s) => self.share.delete_deferred(Object::Sampler(s)),
             _ => (),
         }
     }
 
-    unsafe fn destroy_descriptor_pool(&self, _: n::DescriptorPool) {
-        // Nothing to do
+    unsafe fn destroy_descriptor_pool(&self, pool: n::DescriptorPool) {
+        for buffer in pool.buffers.lock().unwrap().drain(..) {
+            self.share.delete_deferred(Object::Buffer(buffer));
+        }
     }
 
     unsafe fn destroy_descriptor_set_layout(&self, _: n::DescriptorSetLayout) {
@@ -1715,12 +2542,19 @@ impl d::Device<B> for Device {
         }
     }
 
-    unsafe fn destroy_semaphore(&self, _: n::Semaphore) {
-        // Nothing to do
+    unsafe fn destroy_semaphore(&self, semaphore: n::Semaphore) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(raw) = semaphore.external {
+                self.share.context.external.delete_semaphore(raw);
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = semaphore;
     }
 
-    unsafe fn destroy_event(&self, _event: ()) {
-        unimplemented!()
+    unsafe fn destroy_event(&self, _event: n::Event) {
+        // Nothing to do
     }
 
     unsafe fn create_swapchain(
@@ -1729,7 +2563,7 @@ impl d::Device<B> for Device {
         config: c::SwapchainConfig,
         _old_swapchain: Option<Swapchain>,
     ) -> Result<(Swapchain, Vec<n::Image>), c::window::CreationError> {
-        Ok(self.create_swapchain_impl(surface, config))
+        self.create_swapchain_impl(surface, config)
     }
 
     unsafe fn destroy_swapchain(&self, _swapchain: Swapchain) {
@@ -1737,31 +2571,49 @@ impl d::Device<B> for Device {
     }
 
     fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
-        unsafe {
-            self.share.context.finish();
-        }
-        Ok(())
+        self.share
+            .wait_idle()
+            .map_err(|_| error::HostExecutionError::DeviceLost)
     }
 }
 
-pub(crate) fn wait_fence(fence: &n::Fence, share: &Starc<Share>, timeout_ns: u64) -> u32 {
+/// Wait for a fence for up to `timeout_ns`, returns the status of the wait.
+///
+/// Fences of a reset context may never be signaled, long waits are split
+/// and the device is checked for loss in between.
+pub(crate) fn wait_fence(
+    fence: &n::Fence,
+    share: &Starc<Share>,
+    timeout_ns: u64,
+) -> Result<u32, d::DeviceLost> {
     // TODO:
     // This can be called by multiple objects wanting to ensure they have exclusive
     // access to a resource. How much does this call costs ? The status of the fence
     // could be cached to avoid calling this more than once (in core or in the backend ?).
+    const WAIT_SLICE_NS: u64 = 1_000_000_000;
+
     let gl = &share.context;
     unsafe {
         if share.private_caps.sync {
-            // TODO: Could `wait_sync` be used here instead?
-            gl.client_wait_sync(
-                fence.0.get().expect("No fence was set"),
-                glow::SYNC_FLUSH_COMMANDS_BIT,
-                timeout_ns as i32,
-            )
+            let sync = fence.0.get().expect("No fence was set");
+            let mut remaining = timeout_ns;
+            // Only the first wait has to flush the commands.
+            let mut flags = glow::SYNC_FLUSH_COMMANDS_BIT;
+            loop {
+                let slice = remaining.min(WAIT_SLICE_NS);
+                // TODO: Could `wait_sync` be used here instead?
+                let status = gl.client_wait_sync(sync, flags, slice as i32);
+                share.check_device_lost()?;
+                if status != glow::TIMEOUT_EXPIRED || remaining == slice {
+                    return Ok(status);
+                }
+                remaining -= slice;
+                flags = 0;
+            }
         } else {
             // We fallback to waiting for *everything* to finish
             gl.flush();
-            glow::CONDITION_SATISFIED
+            Ok(glow::CONDITION_SATISFIED)
         }
     }
 }
@@ -0,0 +1,34 @@
+//! Multi-draw entry points not covered by `glow`.
+
+use std::os::raw::c_void;
+
+use crate::fns;
+
+type MultiDrawArrays = unsafe extern "system" fn(u32, *const i32, *const i32, i32);
+type MultiDrawElements = unsafe extern "system" fn(u32, *const i32, u32, *const *const c_void, i32);
+
+/// Entry points of `glMultiDrawArrays` and `glMultiDrawElements`, core since
+/// GL 1.4 and available through `GL_EXT_multi_draw_arrays` on ES.
+#[derive(Default)]
+pub(crate) struct DrawFns {
+    pub(crate) multi_draw_arrays: Option<MultiDrawArrays>,
+    pub(crate) multi_draw_elements: Option<MultiDrawElements>,
+}
+
+impl DrawFns {
+    pub(crate) fn load<F>(fn_proc: &mut F) -> Self
+    where F: FnMut(&str) -> *const c_void {
+        unsafe {
+            DrawFns {
+                multi_draw_arrays: fns::load(
+                    fn_proc,
+                    &["glMultiDrawArrays", "glMultiDrawArraysEXT"],
+                ),
+                multi_draw_elements: fns::load(
+                    fn_proc,
+                    &["glMultiDrawElements", "glMultiDrawElementsEXT"],
+                ),
+            }
+        }
+    }
+}
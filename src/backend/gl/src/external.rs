@@ -0,0 +1,380 @@
+//! Memory and semaphores shared with other APIs, e.g. the Vulkan backend,
+//! through `GL_EXT_memory_object`, `GL_EXT_semaphore` and their `_fd`/`_win32`
+//! handle extensions.
+//!
+//! GL can only import objects, exporting them is left to the other API.
+//!
+//! The entry points are not exposed by `glow`, they are loaded alongside the
+//! context and stay `None` if the driver doesn't provide them.
+
+use std::cell::{Cell, RefCell};
+use std::os::raw::c_void;
+use std::ptr;
+
+use glow::Context;
+
+use crate::hal::{buffer, format::Format, image as i, memory, MemoryTypeId};
+use crate::{fns, native as n, Device, MemoryUsage};
+
+#[cfg(unix)]
+const HANDLE_TYPE_OPAQUE_FD_EXT: u32 = 0x9586;
+#[cfg(windows)]
+const HANDLE_TYPE_OPAQUE_WIN32_EXT: u32 = 0x9587;
+const DEDICATED_MEMORY_OBJECT_EXT: u32 = 0x9581;
+
+type CreateMemoryObjects = unsafe extern "system" fn(i32, *mut u32);
+type DeleteMemoryObjects = unsafe extern "system" fn(i32, *const u32);
+type MemoryObjectParameteriv = unsafe extern "system" fn(u32, u32, *const i32);
+type ImportMemoryFd = unsafe extern "system" fn(u32, u64, u32, i32);
+type ImportMemoryWin32Handle = unsafe extern "system" fn(u32, u64, u32, *mut c_void);
+type BufferStorageMem = unsafe extern "system" fn(u32, isize, u32, u64);
+type TexStorageMem2D = unsafe extern "system" fn(u32, i32, u32, i32, i32, u32, u64);
+type TexStorageMem3D = unsafe extern "system" fn(u32, i32, u32, i32, i32, i32, u32, u64);
+type GenSemaphores = unsafe extern "system" fn(i32, *mut u32);
+type DeleteSemaphores = unsafe extern "system" fn(i32, *const u32);
+type ImportSemaphoreFd = unsafe extern "system" fn(u32, u32, i32);
+type ImportSemaphoreWin32Handle = unsafe extern "system" fn(u32, u32, *mut c_void);
+type SemaphoreBarrier =
+    unsafe extern "system" fn(u32, u32, *const u32, u32, *const u32, *const u32);
+
+/// Entry points of the external objects extensions.
+#[derive(Default)]
+pub(crate) struct ExternalFns {
+    create_memory_objects: Option<CreateMemoryObjects>,
+    delete_memory_objects: Option<DeleteMemoryObjects>,
+    memory_object_parameteriv: Option<MemoryObjectParameteriv>,
+    import_memory_fd: Option<ImportMemoryFd>,
+    import_memory_win32_handle: Option<ImportMemoryWin32Handle>,
+    buffer_storage_mem: Option<BufferStorageMem>,
+    tex_storage_mem_2d: Option<TexStorageMem2D>,
+    tex_storage_mem_3d: Option<TexStorageMem3D>,
+    gen_semaphores: Option<GenSemaphores>,
+    delete_semaphores: Option<DeleteSemaphores>,
+    import_semaphore_fd: Option<ImportSemaphoreFd>,
+    import_semaphore_win32_handle: Option<ImportSemaphoreWin32Handle>,
+    wait_semaphore: Option<SemaphoreBarrier>,
+    signal_semaphore: Option<SemaphoreBarrier>,
+}
+
+impl ExternalFns {
+    pub(crate) fn load<F>(fn_proc: &mut F) -> Self
+    where F: FnMut(&str) -> *const c_void {
+        unsafe {
+            ExternalFns {
+                create_memory_objects: fns::load(fn_proc, &["glCreateMemoryObjectsEXT"]),
+                delete_memory_objects: fns::load(fn_proc, &["glDeleteMemoryObjectsEXT"]),
+                memory_object_parameteriv: fns::load(fn_proc, &["glMemoryObjectParameterivEXT"]),
+                import_memory_fd: fns::load(fn_proc, &["glImportMemoryFdEXT"]),
+                import_memory_win32_handle: fns::load(fn_proc, &["glImportMemoryWin32HandleEXT"]),
+                buffer_storage_mem: fns::load(fn_proc, &["glBufferStorageMemEXT"]),
+                tex_storage_mem_2d: fns::load(fn_proc, &["glTexStorageMem2DEXT"]),
+                tex_storage_mem_3d: fns::load(fn_proc, &["glTexStorageMem3DEXT"]),
+                gen_semaphores: fns::load(fn_proc, &["glGenSemaphoresEXT"]),
+                delete_semaphores: fns::load(fn_proc, &["glDeleteSemaphoresEXT"]),
+                import_semaphore_fd: fns::load(fn_proc, &["glImportSemaphoreFdEXT"]),
+                import_semaphore_win32_handle: fns::load(
+                    fn_proc,
+                    &["glImportSemaphoreWin32HandleEXT"],
+                ),
+                wait_semaphore: fns::load(fn_proc, &["glWaitSemaphoreEXT"]),
+                signal_semaphore: fns::load(fn_proc, &["glSignalSemaphoreEXT"]),
+            }
+        }
+    }
+
+    /// Delete a memory object, the storage is released once all buffers and
+    /// textures created from it are deleted too.
+    pub(crate) fn delete_memory_object(&self, memory_object: u32) {
+        if let Some(delete_memory_objects) = self.delete_memory_objects {
+            unsafe { delete_memory_objects(1, &memory_object) };
+        }
+    }
+
+    pub(crate) fn delete_semaphore(&self, semaphore: u32) {
+        if let Some(delete_semaphores) = self.delete_semaphores {
+            unsafe { delete_semaphores(1, &semaphore) };
+        }
+    }
+
+    /// Make the following commands wait for an imported semaphore.
+    ///
+    /// No buffers or textures are listed, their layouts stay as they were
+    /// left by the other API.
+    pub(crate) fn wait_semaphore(&self, semaphore: u32) {
+        if let Some(wait_semaphore) = self.wait_semaphore {
+            unsafe { wait_semaphore(semaphore, 0, ptr::null(), 0, ptr::null(), ptr::null()) };
+        }
+    }
+
+    /// Signal an imported semaphore once the previous commands finished.
+    pub(crate) fn signal_semaphore(&self, semaphore: u32) {
+        if let Some(signal_semaphore) = self.signal_semaphore {
+            unsafe { signal_semaphore(semaphore, 0, ptr::null(), 0, ptr::null(), ptr::null()) };
+        }
+    }
+}
+
+/// OS handle of memory or a semaphore exported by another API.
+#[derive(Copy, Clone, Debug)]
+pub enum ExternalHandle {
+    /// Opaque file descriptor (`GL_EXT_memory_object_fd`, `GL_EXT_semaphore_fd`).
+    /// The ownership of the file descriptor is transferred to the driver.
+    #[cfg(unix)]
+    Fd(std::os::unix::io::RawFd),
+    /// Opaque Win32 handle (`GL_EXT_memory_object_win32`, `GL_EXT_semaphore_win32`).
+    /// The handle stays owned by the application.
+    #[cfg(windows)]
+    Win32(*mut c_void),
+}
+
+/// Error of importing an external object.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImportError {
+    /// The handle type isn't supported by the driver.
+    Unsupported,
+    /// The driver rejected the handle, e.g. because the size doesn't match
+    /// the exported memory.
+    InvalidHandle,
+}
+
+impl Device {
+    /// Import memory exported by another API, e.g. `VK_KHR_external_memory_fd`.
+    ///
+    /// `mem_type` decides whether buffers or images can be bound to the memory,
+    /// like for `allocate_memory`. `dedicated` has to match the exporting side.
+    /// Buffers are bound as usual, images have to be created with
+    /// `create_image_in_external_memory`.
+    pub unsafe fn import_external_memory(
+        &self,
+        mem_type: MemoryTypeId,
+        size: u64,
+        handle: ExternalHandle,
+        dedicated: bool,
+    ) -> Result<n::Memory, ImportError> {
+        let gl = &self.share.context;
+        let fns = &gl.external;
+        let (extension, import) = match handle {
+            #[cfg(unix)]
+            ExternalHandle::Fd(_) => {
+                ("GL_EXT_memory_object_fd", fns.import_memory_fd.is_some())
+            }
+            #[cfg(windows)]
+            ExternalHandle::Win32(_) => (
+                "GL_EXT_memory_object_win32",
+                fns.import_memory_win32_handle.is_some(),
+            ),
+        };
+        if !self.share.info.is_extension_supported(extension) || !import {
+            error!("Importing memory requires {}", extension);
+            return Err(ImportError::Unsupported);
+        }
+        let create_memory_objects = match fns.create_memory_objects {
+            Some(f) => f,
+            None => return Err(ImportError::Unsupported),
+        };
+
+        let mut memory_object = 0;
+        create_memory_objects(1, &mut memory_object);
+        if dedicated {
+            if let Some(memory_object_parameteriv) = fns.memory_object_parameteriv {
+                memory_object_parameteriv(memory_object, DEDICATED_MEMORY_OBJECT_EXT, &1);
+            }
+        }
+        match handle {
+            #[cfg(unix)]
+            ExternalHandle::Fd(fd) => {
+                (fns.import_memory_fd.unwrap())(memory_object, size, HANDLE_TYPE_OPAQUE_FD_EXT, fd)
+            }
+            #[cfg(windows)]
+            ExternalHandle::Win32(raw) => (fns.import_memory_win32_handle.unwrap())(
+                memory_object,
+                size,
+                HANDLE_TYPE_OPAQUE_WIN32_EXT,
+                raw,
+            ),
+        }
+        if let Err(err) = self.share.check() {
+            error!("Error importing memory: {:?}", err);
+            fns.delete_memory_object(memory_object);
+            return Err(ImportError::InvalidHandle);
+        }
+
+        let (memory_type, memory_role) = self.share.memory_types[mem_type.0 as usize];
+        let buffer = match memory_role {
+            MemoryUsage::Buffer(buffer_usage) => {
+                let buffer_storage_mem = match fns.buffer_storage_mem {
+                    Some(f) => f,
+                    None => {
+                        fns.delete_memory_object(memory_object);
+                        return Err(ImportError::Unsupported);
+                    }
+                };
+                let target = if buffer_usage.contains(buffer::Usage::INDEX)
+                    && !self.share.private_caps.index_buffer_role_change {
+                    glow::ELEMENT_ARRAY_BUFFER
+                } else {
+                    glow::ARRAY_BUFFER
+                };
+                let raw = gl.create_buffer().unwrap();
+                gl.bind_buffer(target, Some(raw));
+                buffer_storage_mem(target, size as isize, memory_object, 0);
+                gl.bind_buffer(target, None);
+
+                if let Err(err) = self.share.check() {
+                    error!("Error creating buffer from imported memory: {:?}", err);
+                    gl.delete_buffer(raw);
+                    fns.delete_memory_object(memory_object);
+                    return Err(ImportError::InvalidHandle);
+                }
+                Some((raw, target))
+            }
+            _ => None,
+        };
+
+        Ok(n::Memory {
+            // Imported memory is never mapped, use the vulkan side for that.
+            properties: memory_type.properties - memory::Properties::CPU_VISIBLE,
+            buffer,
+            size,
+            map_flags: 0,
+            emulate_map_allocation: Cell::new(None),
+            host_images: RefCell::new(Vec::new()),
+            memory_object: Some(memory_object),
+        })
+    }
+
+    /// Create a texture taking its storage from imported memory at `offset`.
+    ///
+    /// The layout of the texture has to match the image of the exporting API,
+    /// which has to use optimal tiling.
+    pub unsafe fn create_image_in_external_memory(
+        &self,
+        memory: &n::Memory,
+        offset: u64,
+        kind: i::Kind,
+        num_levels: i::Level,
+        format: Format,
+    ) -> Result<n::Image, i::CreationError> {
+        let gl = &self.share.context;
+        let fns = &gl.external;
+        let memory_object = match (memory.memory_object, memory.buffer) {
+            (Some(memory_object), None) => memory_object,
+            _ => {
+                error!("Images can only be created in imported image memory");
+                return Err(i::CreationError::Kind);
+            }
+        };
+        let (int_format, upload) = match self.share.image_format(format) {
+            Some((int_format, _, _)) => (int_format, self.share.texel_upload(format).unwrap()),
+            None => return Err(i::CreationError::Format(format)),
+        };
+
+        let name = gl.create_texture().unwrap();
+        let target = match (kind, fns.tex_storage_mem_2d, fns.tex_storage_mem_3d) {
+            (i::Kind::D2(w, h, 1, 1), Some(tex_storage_mem_2d), _) => {
+                gl.bind_texture(glow::TEXTURE_2D, Some(name));
+                tex_storage_mem_2d(
+                    glow::TEXTURE_2D,
+                    num_levels as _,
+                    int_format,
+                    w as _,
+                    h as _,
+                    memory_object,
+                    offset,
+                );
+                glow::TEXTURE_2D
+            }
+            (i::Kind::D2(w, h, l, 1), _, Some(tex_storage_mem_3d)) => {
+                gl.bind_texture(glow::TEXTURE_2D_ARRAY, Some(name));
+                tex_storage_mem_3d(
+                    glow::TEXTURE_2D_ARRAY,
+                    num_levels as _,
+                    int_format,
+                    w as _,
+                    h as _,
+                    l as _,
+                    memory_object,
+                    offset,
+                );
+                glow::TEXTURE_2D_ARRAY
+            }
+            _ => {
+                gl.delete_texture(name);
+                return Err(i::CreationError::Kind);
+            }
+        };
+        gl.bind_texture(target, None);
+
+        if let Err(err) = self.share.check() {
+            error!("Error creating image in imported memory: {:?}", err);
+            gl.delete_texture(name);
+            return Err(i::CreationError::Format(format));
+        }
+
+        let bytes_per_texel = format.surface_desc().bits / 8;
+        let ext = kind.extent();
+        Ok(n::Image {
+            kind: n::ImageKind::Texture(name, target),
+            channel: format.base_format().1,
+            requirements: memory::Requirements {
+                size: (ext.width * ext.height * ext.depth) as u64 * bytes_per_texel as u64,
+                alignment: 1,
+                type_mask: self.share.image_memory_type_mask(false, false),
+            },
+            host_image: None,
+            upload,
+        })
+    }
+
+    /// Import a semaphore exported by another API, e.g. `VK_KHR_external_semaphore_fd`.
+    ///
+    /// Submissions wait for the semaphore before executing their command
+    /// buffers and signal it afterwards.
+    pub unsafe fn import_external_semaphore(
+        &self,
+        handle: ExternalHandle,
+    ) -> Result<n::Semaphore, ImportError> {
+        let fns = &self.share.context.external;
+        let (extension, import) = match handle {
+            #[cfg(unix)]
+            ExternalHandle::Fd(_) => ("GL_EXT_semaphore_fd", fns.import_semaphore_fd.is_some()),
+            #[cfg(windows)]
+            ExternalHandle::Win32(_) => (
+                "GL_EXT_semaphore_win32",
+                fns.import_semaphore_win32_handle.is_some(),
+            ),
+        };
+        if !self.share.info.is_extension_supported(extension) || !import {
+            error!("Importing semaphores requires {}", extension);
+            return Err(ImportError::Unsupported);
+        }
+        let gen_semaphores = match fns.gen_semaphores {
+            Some(f) => f,
+            None => return Err(ImportError::Unsupported),
+        };
+
+        let mut semaphore = 0;
+        gen_semaphores(1, &mut semaphore);
+        match handle {
+            #[cfg(unix)]
+            ExternalHandle::Fd(fd) => {
+                (fns.import_semaphore_fd.unwrap())(semaphore, HANDLE_TYPE_OPAQUE_FD_EXT, fd)
+            }
+            #[cfg(windows)]
+            ExternalHandle::Win32(raw) => (fns.import_semaphore_win32_handle.unwrap())(
+                semaphore,
+                HANDLE_TYPE_OPAQUE_WIN32_EXT,
+                raw,
+            ),
+        }
+        if let Err(err) = self.share.check() {
+            error!("Error importing semaphore: {:?}", err);
+            fns.delete_semaphore(semaphore);
+            return Err(ImportError::InvalidHandle);
+        }
+
+        Ok(n::Semaphore {
+            external: Some(semaphore),
+        })
+    }
+}
@@ -0,0 +1,56 @@
+//! Conversion between the Y-down coordinate system of the HAL and the
+//! Y-up window coordinates of OpenGL.
+//!
+//! The strategy is selected with `PhysicalDevice::set_y_flip` and decides
+//! how shaders are translated, which clip control origin and front face
+//! pipelines use, and whether images are flipped when presented or read
+//! back from a swapchain.
+
+/// Where the Y axis of rendered images is flipped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum YFlip {
+    /// Flip when rendering, images are stored with rows from bottom to top
+    /// and presented as they are. Swapchain images can be rendered to the
+    /// default framebuffer directly, but rendered images are upside down
+    /// compared to uploaded ones when sampled.
+    RenderTarget,
+    /// Render without a flip, images are stored with rows from top to
+    /// bottom like uploaded ones and flipped by the blit on present.
+    Present,
+}
+
+impl Default for YFlip {
+    fn default() -> Self {
+        YFlip::RenderTarget
+    }
+}
+
+impl YFlip {
+    /// Whether rasterization flips the Y axis of the clip space.
+    pub(crate) fn flips_render_target(self) -> bool {
+        self == YFlip::RenderTarget
+    }
+
+    /// Whether images are flipped when blitted to the default framebuffer.
+    pub(crate) fn flips_on_present(self) -> bool {
+        self == YFlip::Present
+    }
+
+    /// Origin passed to `glClipControl`.
+    pub(crate) fn clip_origin(self) -> u32 {
+        if self.flips_render_target() {
+            glow::UPPER_LEFT
+        } else {
+            glow::LOWER_LEFT
+        }
+    }
+
+    /// Destination rows `(y0, y1)` of a present blit of `height` rows.
+    pub(crate) fn present_rows(self, height: i32) -> (i32, i32) {
+        if self.flips_on_present() {
+            (height, 0)
+        } else {
+            (0, height)
+        }
+    }
+}
@@ -0,0 +1,23 @@
+//! Loading of entry points not covered by `glow`.
+
+use std::mem;
+use std::os::raw::c_void;
+
+/// Load the first of `names` exported by the driver.
+///
+/// Core names are listed before the extension ones, which are only used if
+/// the core entry point is missing.
+///
+/// # Safety
+///
+/// `T` must be an `unsafe extern "system" fn` pointer type matching the
+/// signature of all the entry points in `names`.
+pub(crate) unsafe fn load<T, F>(fn_proc: &mut F, names: &[&str]) -> Option<T>
+where F: FnMut(&str) -> *const c_void {
+    debug_assert_eq!(mem::size_of::<T>(), mem::size_of::<*const c_void>());
+    names
+        .iter()
+        .map(|name| fn_proc(name))
+        .find(|address| !address.is_null())
+        .map(|address| mem::transmute_copy(&address))
+}
@@ -0,0 +1,22 @@
+//! Framebuffer entry points not covered by `glow`.
+
+use std::os::raw::c_void;
+
+use crate::fns;
+
+type FramebufferParameteri = unsafe extern "system" fn(u32, u32, i32);
+
+/// Entry points of `GL_ARB_framebuffer_no_attachments`.
+#[derive(Default)]
+pub(crate) struct FramebufferFns {
+    pub(crate) framebuffer_parameteri: Option<FramebufferParameteri>,
+}
+
+impl FramebufferFns {
+    pub(crate) fn load<F>(fn_proc: &mut F) -> Self
+    where F: FnMut(&str) -> *const c_void {
+        let framebuffer_parameteri = unsafe { fns::load(fn_proc, &["glFramebufferParameteri"]) };
+
+        FramebufferFns { framebuffer_parameteri }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::hal::{Features, Limits};
+use crate::hal::{image, Features, Limits};
 use crate::{Error, GlContainer};
 use std::collections::HashSet;
 use std::{fmt, str};
@@ -60,11 +60,15 @@ impl Version {
     pub fn parse(mut src: String) -> Result<Version, String> {
         // TODO: Parse version and optional vendor
         let webgl_sig = "WebGL ";
-        let is_webgl = src.contains(webgl_sig);
-        if is_webgl {
+        if let Some(pos) = src.find(webgl_sig) {
+            // WebGL 1 is based on OpenGL ES 2.0, WebGL 2 on OpenGL ES 3.0
+            let major = match src[pos + webgl_sig.len()..].chars().next() {
+                Some('1') => 2,
+                _ => 3,
+            };
             return Ok(Version {
                 is_embedded: true,
-                major: 2,
+                major,
                 minor: 0,
                 revision: None,
                 vendor_info: "".to_string(),
@@ -170,11 +174,17 @@ pub struct PrivateCaps {
     pub framebuffer: bool,
     /// FBO support to call `glFramebufferTexture`
     pub framebuffer_texture: bool,
+    /// Whether framebuffers can be blitted (`glBlitFramebuffer`), missing on ES 2.0 / WebGL 1
+    pub framebuffer_blit: bool,
     /// If true, then buffers used as ELEMENT_ARRAY_BUFFER may be created / initialized / used as
     /// other targets, if false they must not be mixed with other targets.
     pub index_buffer_role_change: bool,
     pub buffer_storage: bool,
+    /// Whether textures are allocated with immutable storage (`glTexStorage*`)
     pub image_storage: bool,
+    /// Whether the levels of textures accessed by samplers can be restricted
+    /// (`GL_TEXTURE_BASE_LEVEL`, `GL_TEXTURE_MAX_LEVEL`)
+    pub texture_level_range: bool,
     pub clear_buffer: bool,
     pub program_interface: bool,
     pub frag_data_location: bool,
@@ -192,6 +202,80 @@ pub struct PrivateCaps {
     pub depth_range_f64_precision: bool,
     /// Whether draw buffers are supported
     pub draw_buffers: bool,
+    /// Whether blend state can be set per draw buffer
+    pub draw_buffers_indexed: bool,
+    /// Whether blending can be enabled and color writes masked per draw buffer
+    pub draw_buffers_enable_indexed: bool,
+    /// Whether graphics resets can be queried (`KHR_robustness`)
+    pub robustness: bool,
+    /// Whether `GL_UNPACK_ROW_LENGTH` can be used for buffer to image copies
+    pub unpack_row_length: bool,
+    /// Whether uniform blocks are emulated with plain uniforms (GL 2.x, ES 2.0)
+    pub emulate_uniform_blocks: bool,
+    /// Whether textures only accept unsized internal formats (ES 2.0)
+    pub unsized_formats: bool,
+    /// Whether textures can have one or two channels (`GL_RED`, `GL_RG`)
+    pub texture_rg: bool,
+    /// Whether framebuffer attachments can be invalidated (`glInvalidateFramebuffer`)
+    pub invalidate_framebuffer: bool,
+    /// Whether textures can be accessed through handles (`ARB_bindless_texture`)
+    pub bindless_texture: bool,
+    /// Whether attachments of the bound framebuffer can be sampled after
+    /// `glTextureBarrier`
+    pub texture_barrier: bool,
+    /// Whether incoherent shader writes are synchronized with `glMemoryBarrier`
+    pub memory_barrier: bool,
+    /// Whether framebuffers can be created without attachments
+    pub framebuffer_no_attachments: bool,
+    /// Whether occlusion queries can count samples (`GL_SAMPLES_PASSED`)
+    pub samples_passed_query: bool,
+    /// Whether occlusion queries can check if any sample passed
+    pub any_samples_passed_query: bool,
+    /// Whether occlusion queries can be conservative, allowing false positives
+    pub conservative_occlusion_query: bool,
+    /// Whether timestamps can be queried
+    pub timer_query: bool,
+    /// Whether query results can be written to buffers on the GPU
+    pub query_buffer_object: bool,
+    /// Whether vertex attributes can be read in BGRA order (`GL_BGRA` size)
+    pub vertex_array_bgra: bool,
+    /// Whether double-precision vertex attributes are supported
+    pub vertex_attrib_64bit: bool,
+    /// Whether half-float vertex attributes are supported
+    pub half_float_vertex: bool,
+    /// Whether half-float vertex attributes use the `GL_HALF_FLOAT_OES` type
+    pub half_float_vertex_oes: bool,
+    /// Whether vertex attributes can be packed in 10-10-10-2 bit formats
+    pub vertex_type_2_10_10_10_rev: bool,
+    /// Whether consecutive draws can be issued in one call (`glMultiDraw*`)
+    pub multi_draw: bool,
+    /// Whether the depth bias can be clamped (`glPolygonOffsetClamp`)
+    pub polygon_offset_clamp: bool,
+    /// Whether primitives can be rasterized conservatively
+    pub conservative_raster: bool,
+    /// Whether primitives can be discarded before rasterization
+    pub rasterizer_discard: bool,
+    /// Whether the clip space can match Vulkan conventions (`glClipControl`)
+    pub clip_control: bool,
+    /// Whether the minimum or maximum of colors can be blended (`GL_MIN/GL_MAX`)
+    pub blend_minmax: bool,
+    /// Whether advanced blend equations are supported (`KHR_blend_equation_advanced`)
+    pub blend_equation_advanced: bool,
+    /// Whether advanced blending is coherent without blend barriers
+    pub blend_equation_advanced_coherent: bool,
+    /// Whether flat shaded attributes can be taken from the first vertex
+    pub provoking_vertex: bool,
+    /// Whether textures can be uploaded from buffers (`GL_PIXEL_UNPACK_BUFFER`)
+    pub pixel_buffer_object: bool,
+}
+
+/// OpenGL context profile
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Profile {
+    /// Core profile, without deprecated functionality.
+    Core,
+    /// Compatibility profile, including deprecated functionality.
+    Compatibility,
 }
 
 /// OpenGL implementation information
@@ -203,6 +287,8 @@ pub struct Info {
     pub version: Version,
     /// The GLSL version number
     pub shading_language: Version,
+    /// The context profile, `None` for OpenGL ES and WebGL
+    pub profile: Option<Profile>,
     /// The extensions supported by the implementation
     pub extensions: HashSet<String>,
 }
@@ -265,11 +351,15 @@ impl Info {
         )
         .unwrap();
         #[cfg(target_arch = "wasm32")]
-        let shading_language = Version::new_embedded(3, 0, String::from(""));
+        let shading_language = if version.major >= 3 {
+            Version::new_embedded(3, 0, String::from(""))
+        } else {
+            Version::new_embedded(1, 0, String::from(""))
+        };
         // TODO: Use separate path for WebGL extensions in `glow` somehow
         // Perhaps automatic fallback for NUM_EXTENSIONS to EXTENSIONS on native
         #[cfg(target_arch = "wasm32")]
-        let extensions = HashSet::new();
+        let extensions = gl.webgl_extensions.iter().cloned().collect();
         #[cfg(not(target_arch = "wasm32"))]
         let extensions = if version >= Version::new(3, 0, None, String::from("")) {
             let num_exts = get_usize(gl, glow::NUM_EXTENSIONS).unwrap();
@@ -284,10 +374,24 @@ impl Info {
                 .map(|s| s.to_string())
                 .collect()
         };
+        let profile = if version.is_embedded {
+            None
+        } else if version >= Version::new(3, 2, None, String::from("")) {
+            let mask = get_usize(gl, glow::CONTEXT_PROFILE_MASK).unwrap_or(0) as u32;
+            if mask & glow::CONTEXT_CORE_PROFILE_BIT != 0 {
+                Some(Profile::Core)
+            } else {
+                Some(Profile::Compatibility)
+            }
+        } else {
+            // Contexts prior to 3.2 expose all of the legacy functionality.
+            Some(Profile::Compatibility)
+        };
         Info {
             platform_name,
             version,
             shading_language,
+            profile,
             extensions,
         }
     }
@@ -327,6 +431,20 @@ impl Info {
     pub fn is_webgl(&self) -> bool {
         cfg!(target_arch = "wasm32")
     }
+
+    /// Returns `true` if the implementation is ANGLE, translating GL ES
+    /// to Direct3D, Vulkan or Metal.
+    pub fn is_angle(&self) -> bool {
+        self.platform_name.renderer.starts_with("ANGLE (")
+    }
+
+    /// Returns `true` if the implementation is ANGLE on top of Direct3D.
+    ///
+    /// Mesa's D3D12 driver reports "D3D12 (...)" without the ANGLE prefix.
+    pub(crate) fn is_angle_d3d(&self) -> bool {
+        let renderer = &self.platform_name.renderer;
+        self.is_angle() && (renderer.contains("Direct3D") || renderer.contains("D3D"))
+    }
 }
 
 /// Load the information pertaining to the driver and the corresponding device
@@ -335,27 +453,185 @@ pub(crate) fn query_all(gl: &GlContainer) -> (Info, Features, LegacyFeatures, Li
     use self::Requirement::*;
     let info = Info::get(gl);
     let max_texture_size = get_usize(gl, glow::MAX_TEXTURE_SIZE).unwrap_or(64) as u32;
-    let max_color_attachments = get_usize(gl, glow::MAX_COLOR_ATTACHMENTS).unwrap_or(8) as u8;
+    // Without draw buffers (ES 2 / WebGL 1) there is a single color attachment.
+    let max_color_attachments = get_usize(gl, glow::MAX_COLOR_ATTACHMENTS).unwrap_or(
+        if info.is_supported(&[Core(3, 0), Es(3, 0)]) { 8 } else { 1 },
+    );
+    let max_array_layers = get_usize(gl, glow::MAX_ARRAY_TEXTURE_LAYERS).unwrap_or(1) as u16;
+    let max_renderbuffer_size = get_usize(gl, glow::MAX_RENDERBUFFER_SIZE)
+        .unwrap_or(max_texture_size as usize) as u32;
+    // Every power of two up to the maximum.
+    let max_samples = get_usize(gl, glow::MAX_SAMPLES).unwrap_or(1).max(1).min(64) as u32;
+    let sample_count_mask = ((2 << (31 - max_samples.leading_zeros())) - 1) as u8;
+    let max_vertex_attributes = get_usize(gl, glow::MAX_VERTEX_ATTRIBS).unwrap_or(8);
+    let max_texture_units = get_usize(gl, glow::MAX_TEXTURE_IMAGE_UNITS).unwrap_or(8);
+    let max_combined_texture_units =
+        get_usize(gl, glow::MAX_COMBINED_TEXTURE_IMAGE_UNITS).unwrap_or(max_texture_units);
 
     let mut limits = Limits {
         max_image_1d_size: max_texture_size,
         max_image_2d_size: max_texture_size,
-        max_image_3d_size: max_texture_size,
-        max_image_cube_size: max_texture_size,
-        max_image_array_layers: get_usize(gl, glow::MAX_ARRAY_TEXTURE_LAYERS).unwrap_or(1) as u16,
+        max_image_3d_size: get_usize(gl, glow::MAX_3D_TEXTURE_SIZE).unwrap_or(0) as u32,
+        max_image_cube_size: get_usize(gl, glow::MAX_CUBE_MAP_TEXTURE_SIZE)
+            .unwrap_or(max_texture_size as usize) as u32,
+        max_image_array_layers: max_array_layers,
         max_texel_elements: get_usize(gl, glow::MAX_TEXTURE_BUFFER_SIZE).unwrap_or(0),
+        max_framebuffer_layers: max_array_layers as usize,
+        max_per_stage_descriptor_samplers: max_texture_units,
+        max_per_stage_descriptor_sampled_images: max_texture_units,
+        max_per_stage_resources: max_texture_units,
+        max_descriptor_set_samplers: max_combined_texture_units,
+        max_descriptor_set_sampled_images: max_combined_texture_units,
+        max_vertex_input_attributes: max_vertex_attributes,
+        max_vertex_input_bindings: max_vertex_attributes,
+        // Minimums of Vulkan, GL doesn't restrict these before 4.3.
+        max_vertex_input_attribute_offset: 2047,
+        max_vertex_input_binding_stride: 2048,
+        max_fragment_output_attachments: get_usize(gl, glow::MAX_DRAW_BUFFERS).unwrap_or(1),
         max_viewports: 1,
+        max_viewport_dimensions: [max_renderbuffer_size; 2],
+        max_framebuffer_extent: image::Extent {
+            width: max_renderbuffer_size,
+            height: max_renderbuffer_size,
+            depth: max_array_layers as u32,
+        },
         optimal_buffer_copy_offset_alignment: 1,
         optimal_buffer_copy_pitch_alignment: 1,
-        min_texel_buffer_offset_alignment: 1,   // TODO
-        min_uniform_buffer_offset_alignment: 1, // TODO
-        min_storage_buffer_offset_alignment: 1, // TODO
-        framebuffer_color_samples_count: max_color_attachments,
+        min_texel_buffer_offset_alignment: 1,
+        min_uniform_buffer_offset_alignment: 1,
+        min_storage_buffer_offset_alignment: 1,
+        framebuffer_color_samples_count: sample_count_mask,
+        framebuffer_depth_samples_count: sample_count_mask,
+        framebuffer_stencil_samples_count: sample_count_mask,
+        max_color_attachments,
         non_coherent_atom_size: 1,
         ..Limits::default()
     };
 
-    if info.is_supported(&[Core(4, 0), Ext("GL_ARB_tessellation_shader")]) {
+    if info.is_supported(&[Core(3, 1), Es(3, 0), Ext("GL_ARB_uniform_buffer_object")]) {
+        let max_vertex_blocks = get_usize(gl, glow::MAX_VERTEX_UNIFORM_BLOCKS).unwrap_or(0);
+        let max_fragment_blocks = get_usize(gl, glow::MAX_FRAGMENT_UNIFORM_BLOCKS).unwrap_or(0);
+        let max_bindings = get_usize(gl, glow::MAX_UNIFORM_BUFFER_BINDINGS).unwrap_or(0);
+        limits.max_uniform_buffer_range =
+            get_usize(gl, glow::MAX_UNIFORM_BLOCK_SIZE).unwrap_or(0) as _;
+        limits.max_per_stage_descriptor_uniform_buffers =
+            max_vertex_blocks.min(max_fragment_blocks);
+        limits.max_descriptor_set_uniform_buffers = max_bindings;
+        limits.max_descriptor_set_uniform_buffers_dynamic = max_bindings;
+        limits.max_per_stage_resources += limits.max_per_stage_descriptor_uniform_buffers;
+        limits.min_uniform_buffer_offset_alignment =
+            get_usize(gl, glow::UNIFORM_BUFFER_OFFSET_ALIGNMENT).unwrap_or(1) as _;
+    }
+    if info.is_supported(&[Core(4, 3), Es(3, 1), Ext("GL_ARB_shader_storage_buffer_object")]) {
+        let max_bindings = get_usize(gl, glow::MAX_SHADER_STORAGE_BUFFER_BINDINGS).unwrap_or(0);
+        limits.max_storage_buffer_range =
+            get_usize(gl, glow::MAX_SHADER_STORAGE_BLOCK_SIZE).unwrap_or(0) as _;
+        limits.max_per_stage_descriptor_storage_buffers =
+            get_usize(gl, glow::MAX_FRAGMENT_SHADER_STORAGE_BLOCKS).unwrap_or(0);
+        limits.max_descriptor_set_storage_buffers = max_bindings;
+        limits.max_descriptor_set_storage_buffers_dynamic = max_bindings;
+        limits.max_per_stage_resources += limits.max_per_stage_descriptor_storage_buffers;
+        limits.min_storage_buffer_offset_alignment =
+            get_usize(gl, glow::SHADER_STORAGE_BUFFER_OFFSET_ALIGNMENT).unwrap_or(1) as _;
+    }
+    if info.is_supported(&[Core(4, 2), Es(3, 1), Ext("GL_ARB_shader_image_load_store")]) {
+        limits.max_per_stage_descriptor_storage_images =
+            get_usize(gl, glow::MAX_FRAGMENT_IMAGE_UNIFORMS).unwrap_or(0);
+        limits.max_descriptor_set_storage_images =
+            get_usize(gl, glow::MAX_IMAGE_UNITS).unwrap_or(0);
+        limits.max_per_stage_resources += limits.max_per_stage_descriptor_storage_images;
+    }
+    if info.is_supported(&[Core(4, 3), Es(3, 2), Ext("GL_ARB_texture_buffer_range")]) {
+        limits.min_texel_buffer_offset_alignment =
+            get_usize(gl, glow::TEXTURE_BUFFER_OFFSET_ALIGNMENT).unwrap_or(1) as _;
+    }
+    if info.is_supported(&[Core(4, 3), Es(3, 1), Ext("GL_ARB_vertex_attrib_binding")]) {
+        limits.max_vertex_input_bindings =
+            get_usize(gl, glow::MAX_VERTEX_ATTRIB_BINDINGS).unwrap_or(max_vertex_attributes);
+        limits.max_vertex_input_attribute_offset =
+            get_usize(gl, glow::MAX_VERTEX_ATTRIB_RELATIVE_OFFSET).unwrap_or(2047);
+    }
+    if info.is_supported(&[Core(4, 4), Es(3, 1)]) {
+        limits.max_vertex_input_binding_stride =
+            get_usize(gl, glow::MAX_VERTEX_ATTRIB_STRIDE).unwrap_or(2048);
+    }
+    if info.is_supported(&[Core(3, 2), Es(3, 0)]) {
+        limits.max_vertex_output_components =
+            get_usize(gl, glow::MAX_VERTEX_OUTPUT_COMPONENTS).unwrap_or(0);
+        limits.max_fragment_input_components =
+            get_usize(gl, glow::MAX_FRAGMENT_INPUT_COMPONENTS).unwrap_or(0);
+    }
+    if info.is_supported(&[Core(3, 2), Es(3, 2), Ext("GL_ARB_geometry_shader4")]) {
+        limits.max_geometry_input_components =
+            get_usize(gl, glow::MAX_GEOMETRY_INPUT_COMPONENTS).unwrap_or(0);
+        limits.max_geometry_output_components =
+            get_usize(gl, glow::MAX_GEOMETRY_OUTPUT_COMPONENTS).unwrap_or(0);
+        limits.max_geometry_output_vertices =
+            get_usize(gl, glow::MAX_GEOMETRY_OUTPUT_VERTICES).unwrap_or(0);
+        limits.max_geometry_total_output_components =
+            get_usize(gl, glow::MAX_GEOMETRY_TOTAL_OUTPUT_COMPONENTS).unwrap_or(0);
+    }
+    if info.is_supported(&[Core(4, 0), Es(3, 2), Ext("GL_ARB_gpu_shader5")]) {
+        limits.max_geometry_shader_invocations =
+            get_usize(gl, glow::MAX_GEOMETRY_SHADER_INVOCATIONS).unwrap_or(0);
+    }
+    if info.is_supported(&[Core(3, 3), Ext("GL_ARB_blend_func_extended")]) {
+        limits.max_fragment_dual_source_attachments =
+            get_usize(gl, glow::MAX_DUAL_SOURCE_DRAW_BUFFERS).unwrap_or(0);
+    }
+    if info.is_supported(&[Core(4, 3), Es(3, 1)]) {
+        limits.max_fragment_combined_output_resources =
+            get_usize(gl, glow::MAX_COMBINED_SHADER_OUTPUT_RESOURCES).unwrap_or(0);
+        limits.max_framebuffer_extent = image::Extent {
+            width: get_usize(gl, glow::MAX_FRAMEBUFFER_WIDTH).unwrap_or(0) as u32,
+            height: get_usize(gl, glow::MAX_FRAMEBUFFER_HEIGHT).unwrap_or(0) as u32,
+            depth: get_usize(gl, glow::MAX_FRAMEBUFFER_LAYERS).unwrap_or(0) as u32,
+        };
+        limits.max_framebuffer_layers = limits.max_framebuffer_extent.depth as usize;
+    }
+    // Timestamps are in nanoseconds.
+    if info.is_supported(&[
+        Core(3, 3),
+        Ext("GL_ARB_timer_query"),
+        Ext("GL_EXT_disjoint_timer_query"),
+    ]) {
+        limits.timestamp_period = 1.0;
+    }
+    // Layered rendering selects layers with `gl_Layer` in geometry shaders and needs
+    // layered attachments (`glFramebufferTexture`).
+    if !info.is_supported(&[Core(3, 2), Es(3, 2)]) {
+        limits.max_framebuffer_layers = 1;
+    }
+    if info.is_supported(&[Core(4, 3), Es(3, 0), Ext("GL_ARB_ES3_compatibility")]) {
+        limits.max_draw_indexed_index_value =
+            get_usize(gl, glow::MAX_ELEMENT_INDEX).unwrap_or(0) as _;
+    } else if info.is_supported(&[
+        Core(1, 1),
+        Ext("GL_OES_element_index_uint"),
+        Ext("OES_element_index_uint"),
+    ]) {
+        // 32-bit indices are available unless restricted explicitly.
+        limits.max_draw_indexed_index_value = !0;
+    } else {
+        // GLES2 and WebGL1 without `OES_element_index_uint` only have 16-bit indices.
+        limits.max_draw_indexed_index_value = u16::max_value() as _;
+    }
+    // Float limits, the integer query rounds them.
+    limits.max_sampler_lod_bias = get_usize(gl, glow::MAX_TEXTURE_LOD_BIAS).unwrap_or(0) as f32;
+    if info.is_supported(&[
+        Core(4, 6),
+        Ext("GL_ARB_texture_filter_anisotropic"),
+        Ext("GL_EXT_texture_filter_anisotropic"),
+    ]) {
+        limits.max_sampler_anisotropy =
+            get_usize(gl, glow::MAX_TEXTURE_MAX_ANISOTROPY_EXT).unwrap_or(1) as f32;
+    }
+
+    if info.is_supported(&[
+        Core(4, 0),
+        Es(3, 2),
+        Ext("GL_ARB_tessellation_shader"),
+    ]) {
         limits.max_patch_size = get_usize(gl, glow::MAX_PATCH_VERTICES).unwrap_or(0) as _;
     }
     if info.is_supported(&[Core(4, 1)]) {
@@ -367,9 +643,14 @@ pub(crate) fn query_all(gl: &GlContainer) -> (Info, Features, LegacyFeatures, Li
         && info.is_supported(&[
             //TODO: enable when compute is implemented
             Core(4, 3),
+            Es(3, 1),
             Ext("GL_ARB_compute_shader"),
         ])
     {
+        limits.max_compute_shared_memory_size =
+            get_usize(gl, glow::MAX_COMPUTE_SHARED_MEMORY_SIZE).unwrap_or(0);
+        limits.max_compute_work_group_invocations =
+            get_usize(gl, glow::MAX_COMPUTE_WORK_GROUP_INVOCATIONS).unwrap_or(0);
         for (i, (count, size)) in limits
             .max_compute_work_group_count
             .iter_mut()
@@ -396,28 +677,78 @@ pub(crate) fn query_all(gl: &GlContainer) -> (Info, Features, LegacyFeatures, Li
         features |= Features::SAMPLER_ANISOTROPY;
     }
     if info.is_supported(&[Core(4, 2)]) {
+        // ES 3.1 allows explicit bindings too, but shaders are emitted as ESSL 3.00.
         legacy |= LegacyFeatures::EXPLICIT_LAYOUTS_IN_SHADER;
     }
-    if info.is_supported(&[Core(3, 3), Es(3, 0), Ext("GL_ARB_instanced_arrays")]) {
+    if info.is_supported(&[Core(3, 2), Es(3, 2)]) {
+        features |= Features::GEOMETRY_SHADER;
+    }
+    if !info.is_webgl()
+        && info.is_supported(&[
+            Core(4, 6),
+            Ext("GL_ARB_polygon_offset_clamp"),
+            Ext("GL_EXT_polygon_offset_clamp"),
+        ])
+    {
+        features |= Features::DEPTH_BIAS_CLAMP;
+    }
+    if info.is_supported(&[Core(1, 5)]) {
+        features |= Features::PRECISE_OCCLUSION_QUERY;
+    }
+    if info.is_supported(&[Core(4, 0), Es(3, 2), Ext("GL_ARB_tessellation_shader")]) {
+        features |= Features::TESSELLATION_SHADER;
+    }
+    if info.is_supported(&[Core(4, 0), Es(3, 2)]) {
+        features |= Features::INDEPENDENT_BLENDING;
+    }
+    if limits.max_draw_indexed_index_value == !0 {
+        features |= Features::FULL_DRAW_INDEX_U32;
+    }
+    let instanced_arrays = info.is_supported(&[
+        Core(3, 3),
+        Es(3, 0),
+        Ext("GL_ARB_instanced_arrays"),
+        Ext("GL_ANGLE_instanced_arrays"),
+        Ext("GL_EXT_instanced_arrays"),
+        Ext("ANGLE_instanced_arrays"),
+    ]);
+    // Drivers may list the extension without exporting the divisor entry point.
+    #[cfg(not(target_arch = "wasm32"))]
+    let instanced_arrays = instanced_arrays && gl.instancing.vertex_attrib_divisor.is_some();
+    if instanced_arrays {
         features |= Features::INSTANCE_RATE;
     }
     if info.is_supported(&[Core(3, 3)]) {
         // TODO: extension
         features |= Features::SAMPLER_MIP_LOD_BIAS;
     }
+    if info.is_supported(&[
+        Core(4, 4),
+        Ext("GL_ARB_texture_mirror_clamp_to_edge"),
+        Ext("GL_EXT_texture_mirror_clamp"),
+        Ext("GL_EXT_texture_mirror_clamp_to_edge"),
+        Ext("GL_ATI_texture_mirror_once"),
+    ]) {
+        features |= Features::SAMPLER_MIRROR_CLAMP_EDGE;
+    }
 
     // TODO
     if false && info.is_supported(&[Core(4, 3), Es(3, 1)]) {
         // TODO: extension
         legacy |= LegacyFeatures::INDIRECT_EXECUTION;
     }
-    if info.is_supported(&[Core(3, 1), Es(3, 0), Ext("GL_ARB_draw_instanced")]) {
+    if info.is_supported(&[
+        Core(3, 1),
+        Es(3, 0),
+        Ext("GL_ARB_draw_instanced"),
+        Ext("ANGLE_instanced_arrays"),
+    ]) {
         legacy |= LegacyFeatures::DRAW_INSTANCED;
     }
     if info.is_supported(&[Core(4, 2), Ext("GL_ARB_base_instance")]) {
         legacy |= LegacyFeatures::DRAW_INSTANCED_BASE;
     }
-    if info.is_supported(&[Core(3, 2)]) {
+    if info.is_supported(&[Core(3, 2), Es(3, 2)]) {
         // TODO: extension
         legacy |= LegacyFeatures::DRAW_INDEXED_BASE;
     }
@@ -425,7 +756,7 @@ pub(crate) fn query_all(gl: &GlContainer) -> (Info, Features, LegacyFeatures, Li
         // TODO: extension
         legacy |= LegacyFeatures::DRAW_INDEXED_INSTANCED;
     }
-    if info.is_supported(&[Core(3, 2)]) {
+    if info.is_supported(&[Core(3, 2), Es(3, 2)]) {
         // TODO: extension
         legacy |= LegacyFeatures::DRAW_INDEXED_INSTANCED_BASE_VERTEX;
     }
@@ -446,7 +777,7 @@ pub(crate) fn query_all(gl: &GlContainer) -> (Info, Features, LegacyFeatures, Li
     if info.is_supported(&[Core(3, 1), Es(3, 0), Ext("GL_ARB_uniform_buffer_object")]) {
         legacy |= LegacyFeatures::CONSTANT_BUFFER;
     }
-    if info.is_supported(&[Core(4, 0)]) {
+    if info.is_supported(&[Core(4, 0), Es(3, 1)]) {
         // TODO: extension
         legacy |= LegacyFeatures::UNORDERED_ACCESS_VIEW;
     }
@@ -461,36 +792,179 @@ pub(crate) fn query_all(gl: &GlContainer) -> (Info, Features, LegacyFeatures, Li
     if info.is_supported(&[Core(3, 3), Es(3, 0), Ext("GL_ARB_sampler_objects")]) {
         legacy |= LegacyFeatures::SAMPLER_OBJECTS;
     }
-    if info.is_supported(&[Core(3, 3)]) {
+    if info.is_supported(&[Core(3, 3), Es(3, 2)]) {
         // TODO: extension
         legacy |= LegacyFeatures::SAMPLER_BORDER_COLOR;
     }
-    if info.is_supported(&[Core(3, 3), Es(3, 0)]) {
+    if instanced_arrays {
         legacy |= LegacyFeatures::INSTANCED_ATTRIBUTE_BINDING;
     }
 
-    let emulate_map = info.version.is_embedded;
+    let emulate_uniform_blocks = !legacy.contains(LegacyFeatures::CONSTANT_BUFFER);
+    // Emulated uniform blocks are uploaded from the client-side copy of flushed memory.
+    let emulate_map = info.version.is_embedded || emulate_uniform_blocks;
 
     let private = PrivateCaps {
-        vertex_array: info.is_supported(&[Core(3, 0), Es(3, 0), Ext("GL_ARB_vertex_array_object")]),
+        vertex_array: info.is_supported(&[
+            Core(3, 0),
+            Es(3, 0),
+            Ext("GL_ARB_vertex_array_object"),
+            Ext("OES_vertex_array_object"),
+        ]),
         // TODO && gl.GenVertexArrays.is_loaded(),
         framebuffer: info.is_supported(&[Core(3, 0), Es(2, 0), Ext("GL_ARB_framebuffer_object")]),
         // TODO && gl.GenFramebuffers.is_loaded(),
-        framebuffer_texture: info.is_supported(&[Core(3, 0)]), //TODO: double check
+        framebuffer_texture: info.is_supported(&[Core(3, 2), Es(3, 2)]),
+        framebuffer_blit: info.is_supported(&[
+            Core(3, 0),
+            Es(3, 0),
+            Ext("GL_ARB_framebuffer_object"),
+        ]),
         index_buffer_role_change: !info.is_webgl(),
-        image_storage: info.is_supported(&[Core(4, 2), Ext("GL_ARB_texture_storage")]),
+        // `EXT_texture_storage` on ES 2.0 takes sized formats, which textures don't use there.
+        image_storage: info.is_supported(&[Core(4, 2), Es(3, 0), Ext("GL_ARB_texture_storage")]),
+        texture_level_range: info.is_supported(&[Core(1, 2), Es(3, 0)]),
         buffer_storage: info.is_supported(&[Core(4, 4), Ext("GL_ARB_buffer_storage")]),
         clear_buffer: info.is_supported(&[Core(3, 0), Es(3, 0)]),
         program_interface: info.is_supported(&[Core(4, 3), Ext("GL_ARB_program_interface_query")]),
         frag_data_location: !info.version.is_embedded,
         sync: !info.is_webgl() && info.is_supported(&[Core(3, 2), Es(3, 0), Ext("GL_ARB_sync")]), // TODO
-        map: !info.version.is_embedded, //TODO: OES extension
+        map: !emulate_map, //TODO: OES extension
         sampler_anisotropy_ext: !info
             .is_supported(&[Core(4, 6), Ext("GL_ARB_texture_filter_anisotropic")])
             && info.is_supported(&[Ext("GL_EXT_texture_filter_anisotropic")]),
         emulate_map, // TODO
         depth_range_f64_precision: !info.version.is_embedded, // TODO
-        draw_buffers: !info.version.is_embedded, // TODO
+        draw_buffers: info.is_supported(&[Core(2, 0), Es(3, 0)]),
+        draw_buffers_indexed: info.is_supported(&[Core(4, 0), Es(3, 2)]),
+        draw_buffers_enable_indexed: info.is_supported(&[Core(3, 0), Es(3, 2)]),
+        robustness: !info.is_webgl() && info.is_supported(&[
+            Core(4, 5),
+            Es(3, 2),
+            Ext("GL_KHR_robustness"),
+            Ext("GL_ARB_robustness"),
+            Ext("GL_EXT_robustness"),
+        ]),
+        // ANGLE's ES2 contexts lack `EXT_unpack_subimage` on D3D9.
+        unpack_row_length: info.is_supported(&[
+            Core(1, 1),
+            Es(3, 0),
+            Ext("GL_EXT_unpack_subimage"),
+        ]),
+        emulate_uniform_blocks,
+        unsized_formats: info.version.is_embedded && !info.is_supported(&[Es(3, 0)]),
+        texture_rg: info.is_supported(&[
+            Core(3, 0),
+            Es(3, 0),
+            Ext("GL_ARB_texture_rg"),
+            Ext("GL_EXT_texture_rg"),
+        ]),
+        invalidate_framebuffer: info.is_supported(&[
+            Core(4, 3),
+            Es(3, 0),
+            Ext("GL_ARB_invalidate_subdata"),
+        ]),
+        bindless_texture: info.is_supported(&[Ext("GL_ARB_bindless_texture")]),
+        texture_barrier: info.is_supported(&[
+            Core(4, 5),
+            Ext("GL_ARB_texture_barrier"),
+            Ext("GL_NV_texture_barrier"),
+        ]),
+        memory_barrier: info.is_supported(&[
+            Core(4, 2),
+            Es(3, 1),
+            Ext("GL_ARB_shader_image_load_store"),
+            Ext("GL_EXT_shader_image_load_store"),
+        ]),
+        framebuffer_no_attachments: info.is_supported(&[
+            Core(4, 3),
+            Es(3, 1),
+            Ext("GL_ARB_framebuffer_no_attachments"),
+        ]),
+        samples_passed_query: info.is_supported(&[Core(1, 5)]),
+        any_samples_passed_query: info.is_supported(&[
+            Core(3, 3),
+            Es(3, 0),
+            Ext("GL_ARB_occlusion_query2"),
+            Ext("GL_EXT_occlusion_query_boolean"),
+        ]),
+        conservative_occlusion_query: info.is_supported(&[
+            Core(4, 3),
+            Es(3, 0),
+            Ext("GL_ARB_ES3_compatibility"),
+            Ext("GL_EXT_occlusion_query_boolean"),
+        ]),
+        timer_query: info.is_supported(&[
+            Core(3, 3),
+            Ext("GL_ARB_timer_query"),
+            Ext("GL_EXT_disjoint_timer_query"),
+        ]),
+        query_buffer_object: info.is_supported(&[
+            Core(4, 4),
+            Ext("GL_ARB_query_buffer_object"),
+            Ext("GL_AMD_query_buffer_object"),
+        ]),
+        vertex_array_bgra: info.is_supported(&[
+            Core(3, 2),
+            Ext("GL_ARB_vertex_array_bgra"),
+            Ext("GL_EXT_vertex_array_bgra"),
+        ]),
+        vertex_attrib_64bit: info.is_supported(&[Core(4, 1), Ext("GL_ARB_vertex_attrib_64bit")]),
+        half_float_vertex: info.is_supported(&[
+            Core(3, 0),
+            Es(3, 0),
+            Ext("GL_ARB_half_float_vertex"),
+            Ext("GL_OES_vertex_half_float"),
+        ]),
+        half_float_vertex_oes: !info.is_supported(&[
+            Core(3, 0),
+            Es(3, 0),
+            Ext("GL_ARB_half_float_vertex"),
+        ]) && info.is_supported(&[Ext("GL_OES_vertex_half_float")]),
+        vertex_type_2_10_10_10_rev: info.is_supported(&[
+            Core(3, 3),
+            Es(3, 0),
+            Ext("GL_ARB_vertex_type_2_10_10_10_rev"),
+        ]),
+        multi_draw: info.is_supported(&[Core(1, 4), Ext("GL_EXT_multi_draw_arrays")]),
+        polygon_offset_clamp: info.is_supported(&[
+            Core(4, 6),
+            Ext("GL_ARB_polygon_offset_clamp"),
+            Ext("GL_EXT_polygon_offset_clamp"),
+        ]),
+        conservative_raster: info.is_supported(&[Ext("GL_NV_conservative_raster")]),
+        rasterizer_discard: info.is_supported(&[Core(3, 0), Es(3, 0)]),
+        clip_control: info.is_supported(&[
+            Core(4, 5),
+            Ext("GL_ARB_clip_control"),
+            Ext("GL_EXT_clip_control"),
+        ]),
+        blend_minmax: info.is_supported(&[
+            Core(1, 4),
+            Es(3, 0),
+            Ext("GL_EXT_blend_minmax"),
+            Ext("EXT_blend_minmax"),
+        ]),
+        blend_equation_advanced: info.is_supported(&[
+            Es(3, 2),
+            Ext("GL_KHR_blend_equation_advanced"),
+            Ext("GL_NV_blend_equation_advanced"),
+        ]),
+        blend_equation_advanced_coherent: info.is_supported(&[
+            Ext("GL_KHR_blend_equation_advanced_coherent"),
+            Ext("GL_NV_blend_equation_advanced_coherent"),
+        ]),
+        provoking_vertex: info.is_supported(&[
+            Core(3, 2),
+            Ext("GL_ARB_provoking_vertex"),
+            Ext("GL_EXT_provoking_vertex"),
+        ]),
+        pixel_buffer_object: info.is_supported(&[
+            Core(2, 1),
+            Es(3, 0),
+            Ext("GL_ARB_pixel_buffer_object"),
+            Ext("GL_NV_pixel_buffer_object"),
+        ]),
     };
 
     (info, features, legacy, limits, private)
@@ -508,6 +982,14 @@ mod tests {
         assert_eq!(Version::parse("1. h3l1o. W0rld".to_string()), Err("1. h3l1o. W0rld".to_string()));
         assert_eq!(Version::parse("1.2.3".to_string()), Ok(Version::new(1, 2, Some(3), "".to_string())));
         assert_eq!(Version::parse("1.2".to_string()), Ok(Version::new(1, 2, None, "".to_string())));
+        assert_eq!(
+            Version::parse("WebGL 1.0 (OpenGL ES 2.0 Chromium)".to_string()),
+            Ok(Version::new_embedded(2, 0, "".to_string()))
+        );
+        assert_eq!(
+            Version::parse("WebGL 2.0 (OpenGL ES 3.0 Chromium)".to_string()),
+            Ok(Version::new_embedded(3, 0, "".to_string()))
+        );
         assert_eq!(
             Version::parse("1.2 h3l1o. W0rld".to_string()),
             Ok(Version::new(1, 2, None, "h3l1o. W0rld".to_string()))
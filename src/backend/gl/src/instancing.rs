@@ -0,0 +1,29 @@
+//! Instanced arrays not covered by `glow`.
+
+use std::os::raw::c_void;
+
+use crate::fns;
+
+type VertexAttribDivisor = unsafe extern "system" fn(u32, u32);
+
+/// Entry points of `GL_ARB_instanced_arrays` and its ES 2.0 counterparts.
+#[derive(Default)]
+pub(crate) struct InstancingFns {
+    /// Sets the number of instances an attribute advances after.
+    pub(crate) vertex_attrib_divisor: Option<VertexAttribDivisor>,
+}
+
+impl InstancingFns {
+    pub(crate) fn load<F>(fn_proc: &mut F) -> Self
+    where F: FnMut(&str) -> *const c_void {
+        let names = [
+            "glVertexAttribDivisor",
+            "glVertexAttribDivisorARB",
+            "glVertexAttribDivisorANGLE",
+            "glVertexAttribDivisorEXT",
+        ];
+        let vertex_attrib_divisor = unsafe { fns::load(fn_proc, &names) };
+
+        InstancingFns { vertex_attrib_divisor }
+    }
+}
@@ -10,35 +10,99 @@ extern crate log;
 extern crate gfx_hal as hal;
 #[cfg(all(not(target_arch = "wasm32"), feature = "glutin"))]
 pub extern crate glutin;
+#[cfg(all(not(target_arch = "wasm32"), feature = "sdl2"))]
+pub extern crate sdl2;
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::fmt;
 use std::ops::Deref;
 use std::sync::{Arc, Weak};
 use std::thread::{self, ThreadId};
 
+use crate::hal::backend::FastHashMap;
 use crate::hal::queue::{QueueFamilyId, Queues};
 use crate::hal::{error, image, pso, buffer, memory};
 
 pub use self::device::Device;
-pub use self::info::{Info, PlatformName, Version};
-
+pub use self::flip::YFlip;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::external::{ExternalHandle, ImportError};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::query::CalibratedTimestamp;
+#[cfg(all(not(target_arch = "wasm32"), feature = "timings"))]
+pub use self::query::SubmissionTiming;
+pub use self::info::{Info, LegacyFeatures, PlatformName, Profile, Version};
+pub use self::native::{PipelineFeedback, StageFeedback};
+pub use self::quirks::Quirks;
+pub use self::state::AdvancedBlendOp;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod barrier;
+#[cfg(not(target_arch = "wasm32"))]
+mod bindless;
 mod command;
 mod conv;
+mod deletion;
 mod device;
+#[cfg(not(target_arch = "wasm32"))]
+mod draw;
+#[cfg(not(target_arch = "wasm32"))]
+mod external;
+mod flip;
+#[cfg(not(target_arch = "wasm32"))]
+mod fns;
+#[cfg(not(target_arch = "wasm32"))]
+mod framebuffer;
 mod info;
+#[cfg(not(target_arch = "wasm32"))]
+mod instancing;
 mod native;
 mod pool;
+#[cfg(not(target_arch = "wasm32"))]
+mod query;
 mod queue;
+mod quirks;
+#[cfg(not(target_arch = "wasm32"))]
+mod raster;
+#[cfg(not(target_arch = "wasm32"))]
+mod robustness;
+mod staging;
 mod state;
 mod window;
 
 #[cfg(all(not(target_arch = "wasm32"), feature = "glutin"))]
-pub use crate::window::glutin::{config_context, Headless, Surface, Swapchain};
+pub use crate::window::glutin::{
+    config_context, config_robust_context, config_version, Headless, Surface, Swapchain,
+};
+#[cfg(all(not(target_arch = "wasm32"), feature = "egl"))]
+pub use crate::window::egl;
+#[cfg(all(not(target_arch = "wasm32"), feature = "egl", not(feature = "glutin")))]
+pub use crate::window::egl::{Surface, Swapchain};
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    feature = "sdl2",
+    not(any(feature = "glutin", feature = "egl"))
+))]
+pub use crate::window::sdl2::{Surface, Swapchain, Window};
+#[cfg(all(
+    target_os = "windows",
+    feature = "wgl",
+    not(any(feature = "glutin", feature = "egl", feature = "sdl2"))
+))]
+pub use crate::window::wgl::{Surface, Swapchain, Window};
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "ios", target_os = "android")),
+    feature = "glx",
+    not(any(feature = "glutin", feature = "egl", feature = "sdl2"))
+))]
+pub use crate::window::glx::{Surface, Swapchain, Window};
 #[cfg(target_arch = "wasm32")]
-pub use crate::window::web::{Surface, Swapchain, Window};
+pub use crate::window::web::{
+    Canvas, ContextAttributes, PowerPreference, Surface, Swapchain, Window,
+};
 
-#[cfg(all(not(target_arch = "wasm32"), feature = "glutin"))]
+#[cfg(not(target_arch = "wasm32"))]
 pub use glow::native::Context as GlContext;
 #[cfg(target_arch = "wasm32")]
 pub use glow::web::Context as GlContext;
@@ -46,6 +110,41 @@ use glow::Context;
 
 pub(crate) struct GlContainer {
     context: GlContext,
+    /// Entry points for sharing objects with other APIs, not covered by `glow`.
+    #[cfg(not(target_arch = "wasm32"))]
+    external: external::ExternalFns,
+    /// Entry points of `ARB_bindless_texture`, not covered by `glow`.
+    #[cfg(not(target_arch = "wasm32"))]
+    bindless: bindless::BindlessFns,
+    /// Entry points of texture barriers, not covered by `glow`.
+    #[cfg(not(target_arch = "wasm32"))]
+    barrier: barrier::BarrierFns,
+    /// Entry points of framebuffers without attachments, not covered by `glow`.
+    #[cfg(not(target_arch = "wasm32"))]
+    framebuffer: framebuffer::FramebufferFns,
+    /// Entry points of query objects, not covered by `glow`.
+    #[cfg(not(target_arch = "wasm32"))]
+    query: query::QueryFns,
+    /// Entry points of instanced arrays, not covered by `glow` on ES 2.0.
+    #[cfg(not(target_arch = "wasm32"))]
+    instancing: instancing::InstancingFns,
+    /// Entry points of multi-draw calls, not covered by `glow`.
+    #[cfg(not(target_arch = "wasm32"))]
+    draw: draw::DrawFns,
+    /// Entry points of rasterizer state, not covered by `glow`.
+    #[cfg(not(target_arch = "wasm32"))]
+    raster: raster::RasterFns,
+    /// Entry points of graphics reset queries, not covered by `glow`.
+    #[cfg(not(target_arch = "wasm32"))]
+    robustness: robustness::RobustnessFns,
+    #[cfg(target_arch = "wasm32")]
+    context_loss: window::web::ContextLoss,
+    /// Extensions reported by `getSupportedExtensions`.
+    #[cfg(target_arch = "wasm32")]
+    webgl_extensions: Vec<String>,
+    /// Raw WebGL2 context, to bind objects not created through `glow`.
+    #[cfg(target_arch = "wasm32")]
+    webgl2: Option<web_sys::WebGl2RenderingContext>,
 }
 
 impl GlContainer {
@@ -54,45 +153,83 @@ impl GlContainer {
     }
 
     #[cfg(not(target_arch = "wasm32"))]
-    fn from_fn_proc<F>(fn_proc: F) -> GlContainer
+    fn from_fn_proc<F>(mut fn_proc: F) -> GlContainer
     where F: FnMut(&str) -> *const std::os::raw::c_void {
+        let external = external::ExternalFns::load(&mut fn_proc);
+        let bindless = bindless::BindlessFns::load(&mut fn_proc);
+        let barrier = barrier::BarrierFns::load(&mut fn_proc);
+        let framebuffer = framebuffer::FramebufferFns::load(&mut fn_proc);
+        let query = query::QueryFns::load(&mut fn_proc);
+        let instancing = instancing::InstancingFns::load(&mut fn_proc);
+        let draw = draw::DrawFns::load(&mut fn_proc);
+        let raster = raster::RasterFns::load(&mut fn_proc);
+        let robustness = robustness::RobustnessFns::load(&mut fn_proc);
         let context = glow::native::Context::from_loader_function(fn_proc);
-        GlContainer { context }
+        GlContainer {
+            context,
+            external,
+            bindless,
+            barrier,
+            framebuffer,
+            query,
+            instancing,
+            draw,
+            raster,
+            robustness,
+        }
     }
 
     #[cfg(target_arch = "wasm32")]
-    fn from_new_canvas() -> GlContainer {
-        let context = {
+    fn from_canvas(
+        canvas: &window::web::Canvas,
+        attributes: &window::web::ContextAttributes,
+        context_loss: window::web::ContextLoss,
+    ) -> GlContainer {
+        let (context, extensions, webgl2) = {
             use wasm_bindgen::JsCast;
-            let document = web_sys::window()
-                .and_then(|win| win.document())
-                .expect("Cannot get document");
-            let canvas = document
-                .create_element("canvas")
-                .expect("Cannot create canvas")
-                .dyn_into::<web_sys::HtmlCanvasElement>()
-                .expect("Cannot get canvas element");
-            // TODO: Remove hardcoded width/height
-            canvas.set_attribute("width", "640").expect("Cannot set width");
-            canvas.set_attribute("height", "480").expect("Cannot set height");
-            let context_options = js_sys::Object::new();
-            js_sys::Reflect::set(
-                &context_options,
-                &"antialias".into(),
-                &wasm_bindgen::JsValue::FALSE
-            ).expect("Cannot create context options");
+            let context_options = attributes.to_js();
             let webgl2_context = canvas
                 .get_context_with_context_options("webgl2", &context_options)
-                .expect("Cannot create WebGL2 context")
-                .and_then(|context| context.dyn_into::<web_sys::WebGl2RenderingContext>().ok())
-                .expect("Cannot convert into WebGL2 context");
-            document.body()
-                .expect("Cannot get document body")
-                .append_child(&canvas)
-                .expect("Cannot insert canvas into document body");
-            glow::web::Context::from_webgl2_context(webgl2_context)
+                .ok()
+                .and_then(|context| context)
+                .and_then(|context| context.dyn_into::<web_sys::WebGl2RenderingContext>().ok());
+            match webgl2_context {
+                Some(webgl2_context) => {
+                    let extensions = webgl2_context.get_supported_extensions();
+                    let raw = webgl2_context.clone();
+                    (glow::web::Context::from_webgl2_context(webgl2_context), extensions, Some(raw))
+                }
+                None => {
+                    warn!("WebGL2 is not available, falling back to WebGL1");
+                    let webgl1_context = canvas
+                        .get_context_with_context_options("webgl", &context_options)
+                        .expect("Cannot create WebGL1 context")
+                        .and_then(|context| context.dyn_into::<web_sys::WebGlRenderingContext>().ok())
+                        .expect("Cannot convert into WebGL1 context");
+                    // Enable the extensions, `glow` picks them up to emulate
+                    // vertex array objects and instancing. 32-bit indices and
+                    // min/max blending only need to be enabled.
+                    let names = [
+                        "OES_vertex_array_object",
+                        "ANGLE_instanced_arrays",
+                        "OES_element_index_uint",
+                        "EXT_blend_minmax",
+                    ];
+                    for name in &names {
+                        match webgl1_context.get_extension(name) {
+                            Ok(Some(_)) => {}
+                            _ => warn!("WebGL1 extension {} is not available", name),
+                        }
+                    }
+                    let extensions = webgl1_context.get_supported_extensions();
+                    (glow::web::Context::from_webgl1_context(webgl1_context), extensions, None)
+                }
+            }
         };
-        GlContainer { context }
+        let webgl_extensions = extensions
+            .map(|extensions| extensions.iter().filter_map(|name| name.as_string()).collect())
+            .unwrap_or_default();
+        GlContainer { context, context_loss, webgl_extensions, webgl2 }
     }
 }
 
@@ -141,8 +278,8 @@ impl hal::Backend for Backend {
 
     type Fence = native::Fence;
     type Semaphore = native::Semaphore;
-    type Event = ();
-    type QueryPool = ();
+    type Event = native::Event;
+    type QueryPool = native::QueryPool;
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -153,6 +290,7 @@ pub enum Error {
     InvalidOperation,
     InvalidFramebufferOperation,
     OutOfMemory,
+    ContextLost,
     UnknownError,
 }
 
@@ -165,6 +303,7 @@ impl Error {
             glow::INVALID_OPERATION => Error::InvalidOperation,
             glow::INVALID_FRAMEBUFFER_OPERATION => Error::InvalidFramebufferOperation,
             glow::OUT_OF_MEMORY => Error::OutOfMemory,
+            glow::CONTEXT_LOST => Error::ContextLost,
             _ => Error::UnknownError,
         }
     }
@@ -179,6 +318,12 @@ const CPU_VISIBLE_HEAP: usize = 1;
 enum MemoryUsage {
     Buffer(buffer::Usage),
     Image,
+    /// Lazily allocated memory for transient attachments, which are backed
+    /// by renderbuffers and invalidated instead of stored if possible.
+    TransientImage,
+    /// Host visible memory for textures, mapped through a client-side copy
+    /// which is uploaded when flushed or unmapped.
+    HostImage,
 }
 
 /// Internal struct of shared data between the physical and logical device.
@@ -189,12 +334,266 @@ struct Share {
     legacy_features: info::LegacyFeatures,
     limits: hal::Limits,
     private_caps: info::PrivateCaps,
+    // Driver bugs worked around, see `PhysicalDevice::set_quirks`.
+    quirks: Quirks,
     // Indicates if there is an active logical device.
     open: Cell<bool>,
+    // Indicates if a graphics reset has been observed on the context.
+    device_lost: Cell<bool>,
+    // Indicates if submissions are only flushed for fences and presentation,
+    // see `PhysicalDevice::set_batch_submissions`.
+    batch_submissions: bool,
+    // Indicates if sampled images are accessed through texture handles,
+    // see `PhysicalDevice::set_bindless_textures`.
+    bindless_textures: bool,
+    // Where the Y axis is flipped, see `PhysicalDevice::set_y_flip`.
+    y_flip: YFlip,
+    // Indicates if color writes are dithered, see `PhysicalDevice::set_dithering`.
+    dithering: bool,
+    // Indicates if client memory is uploaded through pixel buffers,
+    // see `PhysicalDevice::set_pbo_uploads`.
+    pbo_uploads: bool,
+    // Pixel buffer of host image uploads, orphaned by each upload.
+    upload_pbo: Cell<Option<native::RawBuffer>>,
+    // Index of the virtual queue which submitted last, the state caches of
+    // the other queues are stale.
+    active_queue: Cell<usize>,
     memory_types: Vec<(hal::MemoryType, MemoryUsage)>,
+    // Swapchain images currently rendered to the default framebuffer.
+    backbuffers: RefCell<Vec<window::swapchain::Backbuffer>>,
+    // Framebuffers of each subpass, used to read input attachments without
+    // texture barriers, see `Share::supports_texture_barrier`.
+    subpass_framebuffers: RefCell<FastHashMap<native::FrameBuffer, Vec<native::FrameBuffer>>>,
+    // Objects destroyed while submissions using them may be in flight.
+    deletion_queue: RefCell<deletion::DeletionQueue>,
+    // Client-side copies of buffer memory which is read on the CPU, see
+    // `shadowed_buffer_usage`.
+    buffer_shadows: RefCell<FastHashMap<native::RawBuffer, Vec<u8>>>,
 }
 
 impl Share {
+    /// Size, type and function of vertex attributes of a format, or why the
+    /// format can't be used for vertex attributes.
+    fn vertex_attrib_format(
+        &self,
+        format: hal::format::Format,
+    ) -> Result<(i32, u32, native::VertexAttribFunction), &'static str> {
+        let caps = &self.private_caps;
+        let (mut size, mut ty, function) = match conv::format_to_gl_format(format) {
+            Some(layout) => layout,
+            None => return Err("the format is not supported"),
+        };
+        if function == native::VertexAttribFunction::Double && !caps.vertex_attrib_64bit {
+            return Err("double-precision attributes are not supported");
+        }
+        if ty == glow::HALF_FLOAT {
+            if !caps.half_float_vertex {
+                return Err("half-float attributes are not supported");
+            }
+            if caps.half_float_vertex_oes {
+                ty = conv::HALF_FLOAT_OES;
+            }
+        }
+        let packed = ty == glow::UNSIGNED_INT_2_10_10_10_REV || ty == glow::INT_2_10_10_10_REV;
+        if packed && !caps.vertex_type_2_10_10_10_rev {
+            return Err("packed 10-10-10-2 bit attributes are not supported");
+        }
+        // BGRA attributes are swizzled in the vertex shader instead.
+        if size == glow::BGRA as i32 && !caps.vertex_array_bgra {
+            size = 4;
+        }
+        Ok((size, ty, function))
+    }
+
+    /// Whether buffers are allocated with `glBufferStorage`.
+    fn buffer_storage(&self) -> bool {
+        buffer_storage(&self.private_caps, self.quirks)
+    }
+
+    /// Whether texels in client memory are uploaded through pixel buffers.
+    fn pbo_uploads(&self) -> bool {
+        self.pbo_uploads && self.private_caps.pixel_buffer_object
+    }
+
+    /// Whether attachments of the bound framebuffer can be sampled after a
+    /// texture barrier.
+    fn supports_texture_barrier(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.private_caps.texture_barrier && self.context.barrier.texture_barrier.is_some()
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            false
+        }
+    }
+
+    /// Make preceding framebuffer writes visible to texture fetches of the
+    /// attachments of the bound framebuffer.
+    fn texture_barrier(&self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if self.supports_texture_barrier() {
+                unsafe { (self.context.barrier.texture_barrier.unwrap())() };
+                return;
+            }
+        }
+        warn!("Texture barriers are not supported, sampling bound attachments is undefined");
+    }
+
+    /// Whether advanced blend equations can be used, see `AdvancedBlendOp`.
+    fn advanced_blend(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.private_caps.blend_equation_advanced
+                && (self.private_caps.blend_equation_advanced_coherent
+                    || self.context.barrier.blend_barrier.is_some())
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            false
+        }
+    }
+
+    /// Whether draws with advanced blending need a blend barrier in between.
+    fn needs_blend_barrier(&self) -> bool {
+        self.advanced_blend() && !self.private_caps.blend_equation_advanced_coherent
+    }
+
+    /// Order the framebuffer reads of advanced blending after preceding draws.
+    fn blend_barrier(&self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(blend_barrier) = self.context.barrier.blend_barrier {
+                unsafe { blend_barrier() };
+            }
+        }
+    }
+
+    /// Whether framebuffers can be created without attachments.
+    fn framebuffer_no_attachments(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.private_caps.framebuffer_no_attachments
+                && self.context.framebuffer.framebuffer_parameteri.is_some()
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            false
+        }
+    }
+
+    /// Set a default parameter of the framebuffer bound to `target`, used for
+    /// rendering without attachments, e.g. `GL_FRAMEBUFFER_DEFAULT_WIDTH`.
+    fn set_framebuffer_default(&self, target: u32, parameter: u32, value: i32) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(framebuffer_parameteri) = self.context.framebuffer.framebuffer_parameteri {
+                unsafe { framebuffer_parameteri(target, parameter, value) };
+                return;
+            }
+        }
+        let _ = (target, parameter, value);
+        error!("Framebuffers without attachments are not supported");
+    }
+
+    /// Issue a memory barrier for incoherent shader writes, if supported.
+    ///
+    /// Without `glMemoryBarrier` there are no incoherent writes to synchronize.
+    fn memory_barrier(&self, barriers: u32) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(memory_barrier) = self.context.barrier.memory_barrier {
+                if self.private_caps.memory_barrier && barriers != 0 {
+                    unsafe { memory_barrier(barriers) };
+                }
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = barriers;
+        }
+    }
+
+    /// Whether the clip space is set up with `glClipControl`, with depth from
+    /// 0 to 1 and the origin chosen by the `YFlip` policy.
+    ///
+    /// Otherwise shaders remap the depth, see `Share::flip_in_shaders`.
+    fn clip_control(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.private_caps.clip_control && self.context.raster.clip_control.is_some()
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            false
+        }
+    }
+
+    /// Whether flat shaded attributes can be taken from the first vertex of
+    /// primitives like in Vulkan, instead of the last one.
+    fn provoking_vertex(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.private_caps.provoking_vertex && self.context.raster.provoking_vertex.is_some()
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            false
+        }
+    }
+
+    /// Whether shaders flip Y to render upside down, which reverses the
+    /// front face of pipelines as well.
+    ///
+    /// Only needed if the render target is flipped without clip control,
+    /// this decides for shader translation and pipeline creation.
+    fn flip_in_shaders(&self) -> bool {
+        self.y_flip.flips_render_target() && !self.clip_control()
+    }
+
+    /// Set the depth bias of polygons, clamped if supported.
+    fn polygon_offset(&self, bias: pso::DepthBias) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(polygon_offset_clamp) = self.context.raster.polygon_offset_clamp {
+                if self.private_caps.polygon_offset_clamp {
+                    unsafe {
+                        polygon_offset_clamp(bias.slope_factor, bias.const_factor, bias.clamp)
+                    };
+                    return;
+                }
+            }
+        }
+        if bias.clamp != 0.0 {
+            warn!("Depth bias clamping is not supported");
+        }
+        unsafe { self.context.polygon_offset(bias.slope_factor, bias.const_factor) };
+    }
+
+    /// Set the instance rate of a vertex attribute, a divisor of 0 advances
+    /// the attribute per vertex.
+    ///
+    /// Without instanced arrays every attribute advances per vertex.
+    fn vertex_attrib_divisor(&self, location: u32, divisor: u32) {
+        if !self.legacy_features.contains(info::LegacyFeatures::INSTANCED_ATTRIBUTE_BINDING) {
+            if divisor != 0 {
+                error!("Binding attribute with instanced input rate is not supported");
+            }
+            return;
+        }
+        // Only reported if the entry point is loaded, see `info::query_all`.
+        #[cfg(not(target_arch = "wasm32"))]
+        unsafe {
+            (self.context.instancing.vertex_attrib_divisor.unwrap())(location, divisor);
+        }
+        // `glow` uses `ANGLE_instanced_arrays` on WebGL1.
+        #[cfg(target_arch = "wasm32")]
+        unsafe {
+            self.context.vertex_attrib_divisor(location, divisor);
+        }
+    }
+
     /// Fails during a debug build if the implementation's error flag was set.
     fn check(&self) -> Result<(), Error> {
         if cfg!(debug_assertions) {
@@ -207,6 +606,172 @@ impl Share {
         Ok(())
     }
 
+    /// Check if the context has been reset, marking the device as lost if so.
+    ///
+    /// Once lost, the device stays lost: the context has to be re-created.
+    fn check_device_lost(&self) -> Result<(), hal::device::DeviceLost> {
+        if self.device_lost.get() {
+            return Err(hal::device::DeviceLost);
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            if self.context.context_loss.is_lost() {
+                error!("WebGL context lost");
+                self.device_lost.set(true);
+                return Err(hal::device::DeviceLost);
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let status = if self.private_caps.robustness {
+                self.context.robustness.reset_status()
+            } else {
+                None
+            };
+            if let Some(status) = status.filter(|&status| status != glow::NO_ERROR) {
+                let cause = match status {
+                    robustness::GUILTY_CONTEXT_RESET => "caused by this context",
+                    robustness::INNOCENT_CONTEXT_RESET => "caused by another context",
+                    _ => "of unknown cause",
+                };
+                error!("Graphics reset {} detected on the context", cause);
+                self.device_lost.set(true);
+                return Err(hal::device::DeviceLost);
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for all submitted commands to complete.
+    ///
+    /// Waits on a fence sync instead of `glFinish`, which blocks until the
+    /// whole pipeline drained, including work of other contexts on some
+    /// drivers. WebGL doesn't allow blocking waits on syncs.
+    fn wait_idle(&self) -> Result<(), hal::device::DeviceLost> {
+        // Timeout of a single wait, the device is checked for loss in between.
+        const WAIT_TIMEOUT_NS: i32 = 1_000_000_000;
+
+        self.check_device_lost()?;
+        let gl = &self.context;
+        if !self.private_caps.sync || cfg!(target_arch = "wasm32") {
+            unsafe { gl.finish() };
+            return self.check_device_lost();
+        }
+
+        unsafe {
+            let sync = gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0).unwrap();
+            // Only the first wait has to flush the commands.
+            let mut flags = glow::SYNC_FLUSH_COMMANDS_BIT;
+            let status = loop {
+                match gl.client_wait_sync(sync, flags, WAIT_TIMEOUT_NS) {
+                    glow::TIMEOUT_EXPIRED => {
+                        if let Err(err) = self.check_device_lost() {
+                            gl.delete_sync(sync);
+                            return Err(err);
+                        }
+                        flags = 0;
+                    }
+                    status => break status,
+                }
+            };
+            gl.delete_sync(sync);
+            if status == glow::WAIT_FAILED {
+                if let Err(err) = self.check() {
+                    error!("Error when waiting for idle: {:?}", err);
+                }
+            }
+        }
+        self.collect_deletions();
+        self.check_device_lost()
+    }
+
+    /// Internal format, pixel format and type of images with the given format,
+    /// `None` if such images can't be created.
+    ///
+    /// The pixel format and type describe the texels after the conversion of
+    /// `Share::texel_conversion`.
+    fn image_format(&self, format: hal::format::Format) -> Option<(u32, u32, u32)> {
+        use crate::hal::format::Format;
+        use crate::native::TexelConversion;
+
+        let conversion = self.texel_conversion(format);
+        let luminance =
+            !self.private_caps.texture_rg && self.quirks.contains(Quirks::CONVERT_UPLOADS);
+        let (int_format, iformat, itype) = match format {
+            Format::Rgba8Unorm => (glow::RGBA8, glow::RGBA, glow::UNSIGNED_BYTE),
+            Format::Bgra8Unorm if conversion == TexelConversion::SwizzleBgra => {
+                (glow::RGBA8, glow::RGBA, glow::UNSIGNED_BYTE)
+            }
+            Format::Bgra8Unorm if self.bgra_upload() => {
+                (glow::RGBA8, glow::BGRA, glow::UNSIGNED_BYTE)
+            }
+            Format::Rgba8Srgb => (glow::SRGB8_ALPHA8, glow::RGBA, glow::UNSIGNED_BYTE),
+            Format::Rgb8Unorm if conversion == TexelConversion::ExpandRgb => {
+                (glow::RGBA8, glow::RGBA, glow::UNSIGNED_BYTE)
+            }
+            Format::Rgb8Unorm => (glow::RGB8, glow::RGB, glow::UNSIGNED_BYTE),
+            // Luminance replicates the red channel, which has the same texels.
+            Format::R8Unorm if luminance => (glow::LUMINANCE, glow::LUMINANCE, glow::UNSIGNED_BYTE),
+            Format::R8Unorm if self.private_caps.texture_rg => {
+                (glow::R8, glow::RED, glow::UNSIGNED_BYTE)
+            }
+            Format::D32Sfloat => (
+                glow::DEPTH32F_STENCIL8,
+                glow::DEPTH_STENCIL,
+                glow::FLOAT_32_UNSIGNED_INT_24_8_REV,
+            ),
+            _ => return None,
+        };
+        if !self.private_caps.unsized_formats {
+            return Some((int_format, iformat, itype));
+        }
+
+        // ES 2.0 requires the internal format to match the pixel format.
+        let int_format = match format {
+            Format::Rgba8Srgb if self.info.is_extension_supported("GL_EXT_sRGB") => {
+                glow::SRGB_ALPHA
+            }
+            Format::Rgba8Unorm | Format::Bgra8Unorm | Format::Rgb8Unorm | Format::R8Unorm => {
+                iformat
+            }
+            _ => return None,
+        };
+        Some((int_format, int_format, itype))
+    }
+
+    /// Whether texels can be uploaded in BGRA order.
+    fn bgra_upload(&self) -> bool {
+        !self.info.version.is_embedded
+            || self.info.is_extension_supported("GL_EXT_texture_format_BGRA8888")
+    }
+
+    /// CPU conversion of texels copied from buffers to images with the given
+    /// format, for formats the driver can't upload as they are.
+    fn texel_conversion(&self, format: hal::format::Format) -> native::TexelConversion {
+        use crate::hal::format::Format;
+        use crate::native::TexelConversion;
+
+        if !self.quirks.contains(Quirks::CONVERT_UPLOADS) {
+            return TexelConversion::None;
+        }
+        match format {
+            Format::Rgb8Unorm => TexelConversion::ExpandRgb,
+            Format::Bgra8Unorm if !self.bgra_upload() => TexelConversion::SwizzleBgra,
+            _ => TexelConversion::None,
+        }
+    }
+
+    /// Pixel format, type and conversion of texels copied from buffers to
+    /// images with the given format.
+    fn texel_upload(&self, format: hal::format::Format) -> Option<native::TexelUpload> {
+        let (_, iformat, itype) = self.image_format(format)?;
+        Some(native::TexelUpload {
+            format: iformat,
+            ty: itype,
+            conversion: self.texel_conversion(format),
+        })
+    }
+
     fn buffer_memory_type_mask(&self, usage: buffer::Usage) -> u64 {
         let mut type_mask = 0;
         for (type_index, &(_, kind)) in self.memory_types.iter().enumerate() {
@@ -216,7 +781,7 @@ impl Share {
                         type_mask |= 1 << type_index;
                     }
                 }
-                MemoryUsage::Image => {},
+                MemoryUsage::Image | MemoryUsage::TransientImage | MemoryUsage::HostImage => {},
             }
         }
         if type_mask == 0 {
@@ -225,7 +790,55 @@ impl Share {
         type_mask
     }
 
-    fn image_memory_type_mask(&self) -> u64 {
+    /// Required offset alignment of a buffer with the given usage in its memory.
+    fn buffer_alignment(&self, usage: buffer::Usage) -> u64 {
+        // Alignment of 4 covers indexes of type u16 and u32 in index buffers.
+        // GL alignments are powers of two, the largest one satisfies all of them.
+        let mut alignment = 4;
+        if usage.contains(buffer::Usage::UNIFORM) {
+            alignment = alignment.max(self.limits.min_uniform_buffer_offset_alignment);
+        }
+        if usage.contains(buffer::Usage::STORAGE) {
+            alignment = alignment.max(self.limits.min_storage_buffer_offset_alignment);
+        }
+        if usage.intersects(buffer::Usage::UNIFORM_TEXEL | buffer::Usage::STORAGE_TEXEL) {
+            alignment = alignment.max(self.limits.min_texel_buffer_offset_alignment);
+        }
+        alignment
+    }
+
+    /// Write `data` at `offset` into the client-side copy of `buffer`, if it has one.
+    fn write_buffer_shadow(&self, buffer: native::RawBuffer, offset: u64, data: &[u8]) {
+        if let Some(shadow) = self.buffer_shadows.borrow_mut().get_mut(&buffer) {
+            let start = offset as usize;
+            shadow[start..start + data.len()].copy_from_slice(data);
+        }
+    }
+
+    /// Copy a region between the client-side copies of two buffers, if they have one.
+    fn copy_buffer_shadow(
+        &self,
+        src: native::RawBuffer,
+        dst: native::RawBuffer,
+        region: &hal::command::BufferCopy,
+    ) {
+        let mut shadows = self.buffer_shadows.borrow_mut();
+        if !shadows.contains_key(&dst) {
+            return;
+        }
+        let (src_start, size) = (region.src as usize, region.size as usize);
+        let data = match shadows.get(&src) {
+            Some(shadow) => shadow[src_start..src_start + size].to_vec(),
+            None => {
+                warn!("Copy source {:?} of a shadowed buffer has no client-side copy", src);
+                return;
+            }
+        };
+        let dst_start = region.dst as usize;
+        shadows.get_mut(&dst).unwrap()[dst_start..dst_start + size].copy_from_slice(&data);
+    }
+
+    fn image_memory_type_mask(&self, transient: bool, host_visible: bool) -> u64 {
         let mut type_mask = 0;
         for (type_index, &(_, kind)) in self.memory_types.iter().enumerate() {
             match kind {
@@ -233,6 +846,16 @@ impl Share {
                 MemoryUsage::Image => {
                     type_mask |= 1 << type_index;
                 },
+                MemoryUsage::TransientImage => {
+                    if transient {
+                        type_mask |= 1 << type_index;
+                    }
+                },
+                MemoryUsage::HostImage => {
+                    if host_visible {
+                        type_mask |= 1 << type_index;
+                    }
+                },
             }
         }
         assert_ne!(type_mask, 0);
@@ -265,7 +888,7 @@ impl<T: ?Sized> fmt::Debug for Starc<T> {
 
 impl<T> Starc<T> {
     #[inline]
-    fn new(value: T) -> Self {
+    pub(crate) fn new(value: T) -> Self {
         Starc {
             arc: Arc::new(value),
             thread: thread::current().id(),
@@ -323,17 +946,138 @@ impl<T> Wstarc<T> {
 unsafe impl<T: ?Sized> Send for Wstarc<T> {}
 unsafe impl<T: ?Sized> Sync for Wstarc<T> {}
 
+/// Buffer usages whose memory keeps a client-side copy, for buffers read on
+/// the CPU without `glGetBufferSubData` (ES, WebGL).
+///
+/// Emulated uniform blocks are uploaded from the copy of uniform buffers,
+/// converted texel uploads read the copy of the source buffer.
+fn shadowed_buffer_usage(private_caps: &info::PrivateCaps, quirks: Quirks) -> buffer::Usage {
+    let mut usage = buffer::Usage::empty();
+    if private_caps.emulate_uniform_blocks {
+        usage |= buffer::Usage::UNIFORM;
+    }
+    if quirks.contains(Quirks::CONVERT_UPLOADS) {
+        usage |= buffer::Usage::TRANSFER_SRC;
+    }
+    usage
+}
+
+/// Memory types exposed for the given capabilities, buffer memory types
+/// depend on the availability of `glBufferStorage`.
+fn memory_types(
+    private_caps: &info::PrivateCaps,
+    quirks: Quirks,
+) -> Vec<(hal::MemoryType, MemoryUsage)> {
+    let mut memory_types = Vec::new();
+
+    // Buffers which don't need a client-side copy are placed in memory types
+    // without one.
+    let shadowed = shadowed_buffer_usage(private_caps, quirks);
+    let mut add_memory_type = |memory_type: hal::MemoryType| {
+        let usages = if private_caps.index_buffer_role_change {
+            // If `index_buffer_role_change` is true, we can use a buffer for any role
+            vec![buffer::Usage::all()]
+        } else {
+            // If `index_buffer_role_change` is false, ELEMENT_ARRAY_BUFFER buffers may not be
+            // mixed with other targets, so we need to provide one type of memory for INDEX
+            // usage only and another type for all other uses.
+            vec![buffer::Usage::INDEX, buffer::Usage::all() - buffer::Usage::INDEX]
+        };
+        for usage in usages {
+            if usage.intersects(shadowed) {
+                memory_types.push((memory_type, MemoryUsage::Buffer(usage - shadowed)));
+            }
+            memory_types.push((memory_type, MemoryUsage::Buffer(usage)));
+        }
+    };
+
+    // Mimicking vulkan, memory types with more flags should come before those with fewer flags
+    if private_caps.map && buffer_storage(private_caps, quirks) {
+        // Coherent memory is only available if we have `glBufferStorage`
+        add_memory_type(hal::MemoryType {
+            properties: memory::Properties::CPU_VISIBLE | memory::Properties::CPU_CACHED | memory::Properties::COHERENT,
+            heap_index: CPU_VISIBLE_HEAP,
+        });
+        add_memory_type(hal::MemoryType {
+            properties: memory::Properties::CPU_VISIBLE | memory::Properties::COHERENT,
+            heap_index: CPU_VISIBLE_HEAP,
+        });
+    }
+
+    if private_caps.map || private_caps.emulate_map {
+        add_memory_type(hal::MemoryType {
+            properties: memory::Properties::CPU_VISIBLE | memory::Properties::CPU_CACHED,
+            heap_index: CPU_VISIBLE_HEAP,
+        });
+    }
+
+    add_memory_type(hal::MemoryType {
+        properties: memory::Properties::DEVICE_LOCAL,
+        heap_index: DEVICE_LOCAL_HEAP,
+    });
+
+    // Transient attachments may use lazily allocated memory instead
+    memory_types.push((
+        hal::MemoryType {
+            properties: memory::Properties::DEVICE_LOCAL | memory::Properties::LAZILY_ALLOCATED,
+            heap_index: DEVICE_LOCAL_HEAP,
+        },
+        MemoryUsage::TransientImage,
+    ));
+
+    // There is always a single device-local memory type for images
+    memory_types.push((
+        hal::MemoryType {
+            properties: memory::Properties::DEVICE_LOCAL,
+            heap_index: DEVICE_LOCAL_HEAP,
+        },
+        MemoryUsage::Image,
+    ));
+
+    // Textures may be placed in host visible memory to write their texels directly
+    memory_types.push((
+        hal::MemoryType {
+            properties: memory::Properties::CPU_VISIBLE,
+            heap_index: CPU_VISIBLE_HEAP,
+        },
+        MemoryUsage::HostImage,
+    ));
+
+    assert!(memory_types.len() <= 64);
+
+    memory_types
+}
+
+/// Whether buffers are allocated with `glBufferStorage`.
+fn buffer_storage(private_caps: &info::PrivateCaps, quirks: Quirks) -> bool {
+    private_caps.buffer_storage && !quirks.contains(Quirks::BROKEN_BUFFER_STORAGE)
+}
+
 #[derive(Debug)]
 pub struct PhysicalDevice(Starc<Share>);
 
 impl PhysicalDevice {
     #[allow(unused)]
     fn new_adapter(gl: GlContainer) -> hal::Adapter<Backend> {
+        Self::new_adapter_with_pci_ids(gl, None)
+    }
+
+    /// Create the adapter of the current context.
+    ///
+    /// `pci_ids` are the vendor and device ID queried from the window system
+    /// (`EGL_EXT_device_query`, `GLX_MESA_query_renderer`). Without them, the
+    /// vendor is guessed from the vendor string and the device is left at 0.
+    #[allow(unused)]
+    fn new_adapter_with_pci_ids(
+        gl: GlContainer,
+        pci_ids: Option<(usize, usize)>,
+    ) -> hal::Adapter<Backend> {
         // query information
         let (info, features, legacy_features, limits, private_caps) = info::query_all(&gl);
         info!("Vendor: {:?}", info.platform_name.vendor);
         info!("Renderer: {:?}", info.platform_name.renderer);
         info!("Version: {:?}", info.version);
+        info!("Profile: {:?}", info.profile);
         info!("Shading Language: {:?}", info.shading_language);
         info!("Features: {:?}", features);
         info!("Legacy Features: {:?}", legacy_features);
@@ -345,65 +1089,9 @@ impl PhysicalDevice {
         let vendor: std::string::String = info.platform_name.vendor.clone();
         let renderer: std::string::String = info.platform_name.renderer.clone();
 
-        let mut memory_types = Vec::new();
-
-        let mut add_memory_type = |memory_type: hal::MemoryType| {
-            if private_caps.index_buffer_role_change {
-                // If `index_buffer_role_change` is true, we can use a buffer for any role
-                memory_types.push((
-                    memory_type,
-                    MemoryUsage::Buffer(buffer::Usage::all()),
-                ));
-            } else {
-                // If `index_buffer_role_change` is false, ELEMENT_ARRAY_BUFFER buffers may not be
-                // mixed with other targets, so we need to provide one type of memory for INDEX
-                // usage only and another type for all other uses.
-                memory_types.push((
-                    memory_type,
-                    MemoryUsage::Buffer(buffer::Usage::INDEX),
-                ));
-                memory_types.push((
-                    memory_type,
-                    MemoryUsage::Buffer(buffer::Usage::all() - buffer::Usage::INDEX),
-                ));
-            }
-        };
-
-        // Mimicking vulkan, memory types with more flags should come before those with fewer flags
-        if private_caps.map && private_caps.buffer_storage {
-            // Coherent memory is only available if we have `glBufferStorage`
-            add_memory_type(hal::MemoryType {
-                properties: memory::Properties::CPU_VISIBLE | memory::Properties::CPU_CACHED | memory::Properties::COHERENT,
-                heap_index: CPU_VISIBLE_HEAP,
-            });
-            add_memory_type(hal::MemoryType {
-                properties: memory::Properties::CPU_VISIBLE | memory::Properties::COHERENT,
-                heap_index: CPU_VISIBLE_HEAP,
-            });
-        }
-
-        if private_caps.map || private_caps.emulate_map {
-            add_memory_type(hal::MemoryType {
-                properties: memory::Properties::CPU_VISIBLE | memory::Properties::CPU_CACHED,
-                heap_index: CPU_VISIBLE_HEAP,
-            });
-        }
-
-        add_memory_type(hal::MemoryType {
-            properties: memory::Properties::DEVICE_LOCAL,
-            heap_index: DEVICE_LOCAL_HEAP,
-        });
-
-        // There is always a single device-local memory type for images
-        memory_types.push((
-            hal::MemoryType {
-                properties: memory::Properties::DEVICE_LOCAL,
-                heap_index: DEVICE_LOCAL_HEAP,
-            },
-            MemoryUsage::Image,
-        ));
-
-        assert!(memory_types.len() <= 64);
+        let quirks = quirks::detect(&info);
+        info!("Quirks: {:?}", quirks);
+        let memory_types = memory_types(&private_caps, quirks);
 
         // create the shared context
         let share = Share {
@@ -413,8 +1101,21 @@ impl PhysicalDevice {
             legacy_features,
             limits,
             private_caps,
+            quirks,
             open: Cell::new(false),
+            device_lost: Cell::new(false),
+            batch_submissions: false,
+            bindless_textures: false,
+            y_flip: YFlip::default(),
+            dithering: false,
+            pbo_uploads: true,
+            upload_pbo: Cell::new(None),
+            active_queue: Cell::new(0),
             memory_types,
+            backbuffers: RefCell::new(Vec::new()),
+            subpass_framebuffers: RefCell::new(FastHashMap::default()),
+            deletion_queue: RefCell::new(deletion::DeletionQueue::default()),
+            buffer_shadows: RefCell::new(FastHashMap::default()),
         };
         if let Err(err) = share.check() {
             panic!("Error querying info: {:?}", err);
@@ -467,7 +1168,7 @@ impl PhysicalDevice {
         };
 
         // source: Sascha Willems at Vulkan
-        let vendor_id = if vendor_lower.contains("amd") {
+        let guessed_vendor_id = if vendor_lower.contains("amd") {
             0x1002
         } else if vendor_lower.contains("imgtec") {
             0x1010
@@ -482,23 +1183,156 @@ impl PhysicalDevice {
         } else {
             0
         };
+        let (vendor_id, device_id) = match pci_ids {
+            Some((vendor, device)) => {
+                info!("PCI IDs: {:04x}:{:04x}", vendor, device);
+                (vendor, device)
+            }
+            None => (guessed_vendor_id, 0),
+        };
 
         hal::Adapter {
             info: hal::AdapterInfo {
                 name,
                 vendor: vendor_id,
-                device: 0,
+                device: device_id,
                 device_type: inferred_device_type,
             },
             physical_device: PhysicalDevice(Starc::new(share)),
-            queue_families: vec![QueueFamily],
+            queue_families: vec![QueueFamily::default()],
         }
     }
 
     /// Get GL-specific legacy feature flags.
-    pub fn legacy_features(&self) -> &info::LegacyFeatures {
+    pub fn legacy_features(&self) -> &LegacyFeatures {
         &self.0.legacy_features
     }
+
+    /// Returns `true` if the driver is ANGLE.
+    pub fn is_angle(&self) -> bool {
+        self.0.info.is_angle()
+    }
+
+    /// Open a logical device, additionally requiring GL-specific legacy features.
+    ///
+    /// Fails with `MissingFeature` if any of `requested_features` or
+    /// `legacy_features` isn't supported, instead of failing at runtime
+    /// when e.g. constant buffers or instanced draws are used.
+    pub unsafe fn open_with_legacy_features(
+        &self,
+        families: &[(&QueueFamily, &[hal::QueuePriority])],
+        requested_features: hal::Features,
+        legacy_features: LegacyFeatures,
+    ) -> Result<hal::Gpu<Backend>, error::DeviceCreationError> {
+        if !self.0.legacy_features.contains(legacy_features) {
+            error!(
+                "Missing legacy features: {:?}",
+                legacy_features - self.0.legacy_features
+            );
+            return Err(error::DeviceCreationError::MissingFeature);
+        }
+        hal::PhysicalDevice::open(self, families, requested_features)
+    }
+
+    /// Driver bugs the backend works around, detected from the driver info.
+    pub fn quirks(&self) -> Quirks {
+        self.0.quirks
+    }
+
+    /// Whether command buffers can blend with advanced blend equations, see
+    /// `RawCommandBuffer::set_advanced_blend`.
+    pub fn supports_advanced_blend(&self) -> bool {
+        self.0.advanced_blend()
+    }
+
+    /// Override the quirks, e.g. to disable a workaround for a fixed driver
+    /// or to apply one for a driver that isn't matched yet.
+    ///
+    /// Memory types depend on the quirks and are updated as well, the
+    /// quirks can't be changed while a logical device is open.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        let share = Starc::get_mut(&mut self.0)
+            .expect("Quirks can't be changed while a logical device is open");
+        share.quirks = quirks;
+        share.memory_types = memory_types(&share.private_caps, quirks);
+    }
+
+    /// Batch submissions of logical devices opened afterwards.
+    ///
+    /// By default every submission is flushed to the driver. With batching,
+    /// the commands of all submissions are only flushed when a fence is
+    /// signaled or on present, which saves driver overhead for many small
+    /// submissions per frame. Can't be changed while a logical device is open.
+    pub fn set_batch_submissions(&mut self, batch: bool) {
+        let share = Starc::get_mut(&mut self.0)
+            .expect("Submission batching can't be changed while a logical device is open");
+        share.batch_submissions = batch;
+    }
+
+    /// Select where logical devices opened afterwards flip the Y axis.
+    ///
+    /// Flipping the render target (default) presents images as they are and
+    /// allows rendering to the default framebuffer directly. Flipping on
+    /// present keeps rendered images in the same orientation as uploaded
+    /// ones, at the cost of a blit. Can't be changed while a logical device
+    /// is open.
+    pub fn set_y_flip(&mut self, y_flip: YFlip) {
+        let share = Starc::get_mut(&mut self.0)
+            .expect("Y flip can't be changed while a logical device is open");
+        share.y_flip = y_flip;
+    }
+
+    /// Dither color writes of logical devices opened afterwards.
+    ///
+    /// Dithering is enabled by default in GL but has no equivalent in Vulkan,
+    /// so it's disabled unless requested. Some mobile drivers corrupt integer
+    /// render targets with it. Can't be changed while a logical device is open.
+    pub fn set_dithering(&mut self, enable: bool) {
+        let share = Starc::get_mut(&mut self.0)
+            .expect("Dithering can't be changed while a logical device is open");
+        share.dithering = enable;
+    }
+
+    /// Upload texels from client memory, e.g. of images bound to host visible
+    /// memory, through a pixel buffer (`GL_PIXEL_UNPACK_BUFFER`).
+    ///
+    /// `glTexSubImage2D` from client memory blocks until the driver copied
+    /// the texels, from a pixel buffer they are transferred asynchronously.
+    /// Enabled by default. Returns `false` if pixel buffers aren't supported.
+    /// Can't be changed while a logical device is open.
+    pub fn set_pbo_uploads(&mut self, enable: bool) -> bool {
+        let share = Starc::get_mut(&mut self.0)
+            .expect("Pixel buffer uploads can't be changed while a logical device is open");
+        if enable && !share.private_caps.pixel_buffer_object {
+            return false;
+        }
+        share.pbo_uploads = enable;
+        true
+    }
+
+    /// Access sampled images of logical devices opened afterwards through
+    /// resident texture handles (`ARB_bindless_texture`).
+    ///
+    /// Descriptor sets store the handles of their sampled images, in binding
+    /// order, in a uniform buffer bound to `PipelineLayout::bindless_block`.
+    /// Shaders declare it as a `std140` block of samplers instead of binding
+    /// them to texture units.
+    ///
+    /// Returns `false` if bindless textures aren't supported. Can't be changed
+    /// while a logical device is open.
+    pub fn set_bindless_textures(&mut self, enable: bool) -> bool {
+        let share = Starc::get_mut(&mut self.0)
+            .expect("Bindless textures can't be changed while a logical device is open");
+        #[cfg(not(target_arch = "wasm32"))]
+        let supported = share.private_caps.bindless_texture && share.context.bindless.is_loaded();
+        #[cfg(target_arch = "wasm32")]
+        let supported = false;
+        if enable && !supported {
+            return false;
+        }
+        share.bindless_textures = enable;
+        true
+    }
 }
 
 impl hal::PhysicalDevice<Backend> for PhysicalDevice {
@@ -512,26 +1346,40 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
         if self.0.open.get() {
             return Err(error::DeviceCreationError::TooManyObjects);
         }
-        self.0.open.set(true);
 
-        // TODO: Check for support in the LeagcyFeatures struct too
+        // Legacy features are checked in `open_with_legacy_features`.
         if !self.features().contains(requested_features) {
+            error!("Missing features: {:?}", requested_features - self.features());
             return Err(error::DeviceCreationError::MissingFeature);
         }
+        self.0.open.set(true);
 
         // initialize permanent states
+        // `GL_FRAMEBUFFER_SRGB` is toggled per render pass.
         let gl = &self.0.context;
-        if self
-            .0
-            .legacy_features
-            .contains(info::LegacyFeatures::SRGB_COLOR)
-        {
-            // TODO: Find way to emulate this on older Opengl versions.
-            gl.enable(glow::FRAMEBUFFER_SRGB);
+        if self.0.dithering {
+            gl.enable(glow::DITHER);
+        } else {
+            gl.disable(glow::DITHER);
         }
 
         gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
 
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if self.0.clip_control() {
+                let clip_control = self.0.context.raster.clip_control.unwrap();
+                clip_control(self.0.y_flip.clip_origin(), glow::ZERO_TO_ONE);
+            }
+            if self.0.provoking_vertex() {
+                let provoking_vertex = self.0.context.raster.provoking_vertex.unwrap();
+                provoking_vertex(glow::FIRST_VERTEX_CONVENTION);
+            }
+        }
+        if !self.0.provoking_vertex() {
+            warn!("Flat shaded attributes are taken from the last vertex of primitives");
+        }
+
         // create main VAO and bind it
         let mut vao = None;
         if self.0.private_caps.vertex_array {
@@ -549,10 +1397,16 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
                 families
                     .into_iter()
                     .map(|&(proto_family, priorities)| {
-                        assert_eq!(priorities.len(), 1);
+                        assert!(
+                            !priorities.is_empty() && priorities.len() <= proto_family.max_queues
+                        );
                         let mut family = hal::backend::RawQueueGroup::new(proto_family.clone());
-                        let queue = queue::CommandQueue::new(&self.0, vao);
-                        family.add_queue(queue);
+                        // Priorities are ignored, all queues share the command
+                        // stream of the context.
+                        for index in 0..priorities.len() {
+                            let queue = queue::CommandQueue::new(&self.0, vao, index);
+                            family.add_queue(queue);
+                        }
                         family
                     })
                     .collect(),
@@ -560,27 +1414,111 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
         })
     }
 
-    fn format_properties(&self, _: Option<hal::format::Format>) -> hal::format::Properties {
-        use hal::format::ImageFeature;
-        use hal::format::BufferFeature;
+    fn format_properties(&self, format: Option<hal::format::Format>) -> hal::format::Properties {
+        use hal::format::{Aspects, BufferFeature, Format, ImageFeature};
+
+        let format = match format {
+            Some(format) => format,
+            None => return hal::format::Properties::default(),
+        };
+
+        // Images support what `create_image` and render passes handle, linear
+        // images are created the same way as optimal ones.
+        let mut image_features = ImageFeature::empty();
+        if self.0.image_format(format).is_some() {
+            if format.surface_desc().aspects.contains(Aspects::COLOR) {
+                image_features |= ImageFeature::SAMPLED | ImageFeature::SAMPLED_LINEAR;
+                // `EXT_texture_format_BGRA8888` doesn't make BGRA color-renderable.
+                if !(self.0.private_caps.unsized_formats && format == Format::Bgra8Unorm) {
+                    image_features |=
+                        ImageFeature::COLOR_ATTACHMENT | ImageFeature::COLOR_ATTACHMENT_BLEND;
+                }
+                // `rgba8` is the only matching image unit format.
+                if format == Format::Rgba8Unorm
+                    && self
+                        .0
+                        .legacy_features
+                        .contains(info::LegacyFeatures::UNORDERED_ACCESS_VIEW)
+                {
+                    image_features |= ImageFeature::STORAGE;
+                }
+            } else {
+                image_features |= ImageFeature::SAMPLED | ImageFeature::DEPTH_STENCIL_ATTACHMENT;
+            }
+        }
+
+        // Only swapchain images are rendered to without FBOs.
+        if !self.0.private_caps.framebuffer {
+            image_features -= ImageFeature::COLOR_ATTACHMENT
+                | ImageFeature::COLOR_ATTACHMENT_BLEND
+                | ImageFeature::DEPTH_STENCIL_ATTACHMENT;
+        }
+
+        // Texel buffers aren't supported yet.
+        let mut buffer_features = BufferFeature::empty();
+        if self.0.vertex_attrib_format(format).is_ok() {
+            buffer_features |= BufferFeature::VERTEX;
+        }
 
-        // TODO: These are for show
         hal::format::Properties {
-            linear_tiling: ImageFeature::SAMPLED,
-            optimal_tiling: ImageFeature::SAMPLED,
-            buffer_features: BufferFeature::VERTEX,
+            linear_tiling: image_features,
+            optimal_tiling: image_features,
+            buffer_features,
         }
     }
 
     fn image_format_properties(
         &self,
-        _format: hal::format::Format,
-        _dimensions: u8,
-        _tiling: image::Tiling,
-        _usage: image::Usage,
-        _view_caps: image::ViewCapabilities,
+        format: hal::format::Format,
+        dimensions: u8,
+        tiling: image::Tiling,
+        usage: image::Usage,
+        view_caps: image::ViewCapabilities,
     ) -> Option<image::FormatProperties> {
-        None //TODO
+        // Matches `create_image`, which supports single-sampled 2D images and arrays.
+        self.0.image_format(format)?;
+        if dimensions != 2 || view_caps.contains(image::ViewCapabilities::KIND_CUBE) {
+            return None;
+        }
+        let attachment = image::Usage::COLOR_ATTACHMENT | image::Usage::DEPTH_STENCIL_ATTACHMENT;
+        if usage.intersects(attachment) && !self.0.private_caps.framebuffer {
+            return None;
+        }
+        if usage.contains(image::Usage::STORAGE)
+            && !self
+                .0
+                .legacy_features
+                .contains(info::LegacyFeatures::UNORDERED_ACCESS_VIEW)
+        {
+            return None;
+        }
+
+        let limits = &self.0.limits;
+        let max_size = limits.max_image_2d_size;
+        let (max_levels, max_layers) = match tiling {
+            // Linear images only have to support the basic case.
+            image::Tiling::Linear => (1, 1),
+            image::Tiling::Optimal => (
+                (32 - max_size.leading_zeros()) as image::Level,
+                limits.max_image_array_layers.max(1),
+            ),
+        };
+        let bytes_per_texel = format.base_format().0.desc().bits as usize / 8;
+
+        Some(image::FormatProperties {
+            max_extent: image::Extent {
+                width: max_size,
+                height: max_size,
+                depth: 1,
+            },
+            max_levels,
+            max_layers,
+            sample_count_mask: 0x1,
+            max_resource_size: (max_size as usize)
+                .saturating_mul(max_size as usize)
+                .saturating_mul(max_layers as usize)
+                .saturating_mul(bytes_per_texel),
+        })
     }
 
     fn memory_properties(&self) -> hal::MemoryProperties {
@@ -600,15 +1538,46 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
     }
 }
 
+/// The single queue family of the context.
+///
+/// All queues are virtual: GL has a single command stream per context, so
+/// submissions of all queues are executed in submission order, regardless
+/// of the queue priorities. Each queue tracks its own submissions, which
+/// allows engines written for separate graphics and transfer queues to run
+/// unmodified.
 #[derive(Debug, Clone, Copy)]
-pub struct QueueFamily;
+pub struct QueueFamily {
+    max_queues: usize,
+}
+
+impl QueueFamily {
+    /// Queue family exposing a single queue, the family of adapters by default.
+    pub fn new() -> Self {
+        QueueFamily::default()
+    }
+
+    /// Queue family exposing `count` virtual queues instead of one.
+    ///
+    /// Replace the family of the adapter before opening it:
+    /// `adapter.queue_families = vec![QueueFamily::with_virtual_queues(2)]`.
+    pub fn with_virtual_queues(count: usize) -> Self {
+        assert_ne!(count, 0);
+        QueueFamily { max_queues: count }
+    }
+}
+
+impl Default for QueueFamily {
+    fn default() -> Self {
+        QueueFamily { max_queues: 1 }
+    }
+}
 
 impl hal::QueueFamily for QueueFamily {
     fn queue_type(&self) -> hal::QueueType {
         hal::QueueType::General
     }
     fn max_queues(&self) -> usize {
-        1
+        self.max_queues
     }
     fn id(&self) -> QueueFamilyId {
         QueueFamilyId(0)
@@ -618,7 +1587,24 @@ impl hal::QueueFamily for QueueFamily {
 #[cfg(all(not(target_arch = "wasm32"), feature = "glutin"))]
 pub enum Instance {
     Headless(Headless),
-    Surface(Surface)
+    Surface(Surface),
+    #[cfg(feature = "egl")]
+    Egl(egl::Display),
+    /// Headless context backed by a hidden window, see `HEADLESS_EVENTS_LOOP`.
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    HeadlessWindow(Headless),
+}
+
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    feature = "glutin",
+    any(target_os = "windows", target_os = "macos")
+))]
+thread_local! {
+    /// Events loop of the hidden windows of headless instances. It has to
+    /// outlive their contexts and to be dropped on the thread which created
+    /// it, so it's kept alive with the thread instead of the instance.
+    static HEADLESS_EVENTS_LOOP: glutin::EventsLoop = glutin::EventsLoop::new();
 }
 
 #[cfg(all(not(target_arch = "wasm32"), feature = "glutin"))]
@@ -628,6 +1614,10 @@ impl hal::Instance for Instance {
         match self {
             Instance::Headless(instance) => instance.enumerate_adapters(),
             Instance::Surface(instance) => instance.enumerate_adapters(),
+            #[cfg(feature = "egl")]
+            Instance::Egl(instance) => instance.enumerate_adapters(),
+            #[cfg(any(target_os = "windows", target_os = "macos"))]
+            Instance::HeadlessWindow(instance) => instance.enumerate_adapters(),
         }
     }
 }
@@ -635,13 +1625,53 @@ impl hal::Instance for Instance {
 #[cfg(all(not(target_arch = "wasm32"), feature = "glutin"))]
 impl Instance {
     /// TODO: Update portability to make this more flexible
-    #[cfg(target_os = "linux")]
+    #[cfg(any(target_os = "linux", target_os = "windows", target_os = "macos"))]
     pub fn create(_: &str, _: u32) -> Instance {
+        Instance::create_context(None)
+    }
+
+    /// Create an instance with a desktop OpenGL context of a specific
+    /// version and profile, instead of the driver default.
+    #[cfg(any(target_os = "linux", target_os = "windows", target_os = "macos"))]
+    pub fn create_with_profile(
+        _: &str,
+        _: u32,
+        gl_version: (u8, u8),
+        profile: Profile,
+    ) -> Instance {
+        Instance::create_context(Some((gl_version, profile)))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn create_context(request: Option<((u8, u8), Profile)>) -> Instance {
+        // Prefer the GPU driver over OSMesa software rendering.
+        #[cfg(feature = "egl")]
+        {
+            let display = unsafe {
+                match request {
+                    Some((version, profile)) => egl::Display::with_profile(
+                        egl::Platform::Surfaceless,
+                        std::ptr::null_mut(),
+                        version,
+                        profile,
+                    ),
+                    None => egl::Display::surfaceless(egl::Api::Gl),
+                }
+            };
+            match display {
+                Ok(display) => return Instance::Egl(display),
+                Err(err) => warn!("Surfaceless EGL is not available ({}), using OSMesa", err),
+            }
+        }
+
         use glutin::os::unix::OsMesaContextExt;
         use glutin::ContextTrait;
         let size = glutin::dpi::PhysicalSize::from((800, 600));
-        let builder = glutin::ContextBuilder::new()
+        let mut builder = config_robust_context(glutin::ContextBuilder::new())
             .with_hardware_acceleration(Some(false));
+        if let Some((version, profile)) = request {
+            builder = config_version(builder, version, profile);
+        }
         let context: glutin::Context = OsMesaContextExt::new_osmesa(builder, size)
             .expect("failed to create osmesa context");
         unsafe {
@@ -651,4 +1681,24 @@ impl Instance {
         let headless = Headless(context);
         Instance::Headless(headless)
     }
+
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    fn create_context(request: Option<((u8, u8), Profile)>) -> Instance {
+        use glutin::ContextTrait;
+        // Backed by a hidden window with WGL and by an offscreen context with CGL.
+        let size = glutin::dpi::PhysicalSize::from((800, 600));
+        let mut builder = config_robust_context(glutin::ContextBuilder::new());
+        if let Some((version, profile)) = request {
+            builder = config_version(builder, version, profile);
+        }
+        let context = HEADLESS_EVENTS_LOOP
+            .with(|events_loop| glutin::Context::new_headless(events_loop, builder, size))
+            .expect("failed to create headless context");
+        unsafe {
+            context.make_current()
+                .expect("failed to make context current");
+        }
+        let headless = Headless(context);
+        Instance::HeadlessWindow(headless)
+    }
 }
@@ -1,10 +1,12 @@
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
 use crate::hal::backend::FastHashMap;
 use crate::hal::memory::{Properties, Requirements};
-use crate::hal::{buffer, format, image as i, pass, pso};
+use crate::hal::{buffer, format, image as i, pass, pso, query};
 
 use crate::{Backend, GlContext};
 
@@ -44,6 +46,16 @@ impl Buffer {
             } => (*buffer, range.clone()),
         }
     }
+
+    /// GL buffer holding this buffer with its range in it, `None` if the
+    /// buffer isn't bound to memory yet. Buffers share the GL buffer of
+    /// their memory.
+    pub fn raw_buffer(&self) -> Option<(RawBuffer, Range<buffer::Offset>)> {
+        match self {
+            Buffer::Unbound { .. } => None,
+            Buffer::Bound { buffer, range } => Some((*buffer, range.clone())),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -60,12 +72,43 @@ impl Fence {
     }
 }
 
+/// Signal flag shared between the host and the recorded command buffers.
+///
+/// GL executes commands in submission order, so set and reset operations
+/// recorded into command buffers only update the flag at replay time.
+#[derive(Clone, Debug, Default)]
+pub struct Event(Arc<AtomicBool>);
+
+impl Event {
+    pub(crate) fn is_set(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set(&self, value: bool) {
+        self.0.store(value, Ordering::Release)
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub enum BindingTypes {
     Images,
     UniformBuffers,
 }
 
+/// Combination of a separate image and sampler used by a shader.
+///
+/// Each combination is bound to a texture unit of its own, so the same image
+/// can be sampled with different samplers and vice versa.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct CombinedUse {
+    /// Texture unit of the combination.
+    pub unit: pso::DescriptorBinding,
+    /// Set and binding of the image.
+    pub image: (pso::DescriptorSetIndex, pso::DescriptorBinding),
+    /// Set and binding of the sampler.
+    pub sampler: (pso::DescriptorSetIndex, pso::DescriptorBinding),
+}
+
 #[derive(Clone, Debug)]
 pub struct DescRemapData {
     bindings: FastHashMap<
@@ -85,6 +128,20 @@ pub struct DescRemapData {
         ),
     >,
     next_binding: FastHashMap<BindingTypes, pso::DescriptorBinding>,
+    // Combinations of separate images and samplers used by the shaders.
+    combined: Vec<CombinedUse>,
+    // Uniform buffer bindings of the texture handles of each set, in bindless mode.
+    bindless_blocks: FastHashMap<pso::DescriptorSetIndex, pso::DescriptorBinding>,
+    // Number of elements of array bindings, which are remapped to
+    // consecutive bindings.
+    array_sizes: FastHashMap<
+        (
+            BindingTypes,
+            pso::DescriptorSetIndex,
+            pso::DescriptorBinding,
+        ),
+        pso::DescriptorArrayIndex,
+    >,
 }
 
 /// Stores where the descriptor bindings have been remaped too.
@@ -96,14 +153,33 @@ impl DescRemapData {
             bindings: FastHashMap::default(),
             names: FastHashMap::default(),
             next_binding: FastHashMap::default(),
+            combined: Vec::new(),
+            bindless_blocks: FastHashMap::default(),
+            array_sizes: FastHashMap::default(),
         }
     }
 
+    /// Reserve the uniform buffer binding of the texture handles of a set.
+    pub fn insert_bindless_block(&mut self, set: pso::DescriptorSetIndex) {
+        let nb = self.reserve_binding(BindingTypes::UniformBuffers);
+        self.bindless_blocks.insert(set, nb);
+    }
+
+    pub fn get_bindless_block(
+        &self,
+        set: pso::DescriptorSetIndex,
+    ) -> Option<pso::DescriptorBinding> {
+        self.bindless_blocks.get(&set).cloned()
+    }
+
+    /// Remap a binding of `count` array elements to consecutive spare
+    /// bindings, element `i` is bound to the first binding plus `i`.
     pub fn insert_missing_binding_into_spare(
         &mut self,
         btype: BindingTypes,
         set: pso::DescriptorSetIndex,
         binding: pso::DescriptorBinding,
+        count: pso::DescriptorArrayIndex,
     ) -> &[pso::DescriptorBinding] {
         let nb = self.next_binding.entry(btype).or_insert(0);
         let val = self
@@ -111,7 +187,10 @@ impl DescRemapData {
             .entry((btype, set, binding))
             .or_insert(Vec::new());
         val.push(*nb);
-        *nb += 1;
+        *nb += count.max(1) as pso::DescriptorBinding;
+        if count > 1 {
+            self.array_sizes.insert((btype, set, binding), count);
+        }
         &*val
     }
 
@@ -136,6 +215,39 @@ impl DescRemapData {
         &*val
     }
 
+    /// Texture unit of a combination of a separate image and sampler.
+    ///
+    /// Combinations used by several shaders, e.g. different stages or
+    /// pipelines sharing the layout, share their unit.
+    pub fn insert_combined(
+        &mut self,
+        image: (pso::DescriptorSetIndex, pso::DescriptorBinding),
+        sampler: (pso::DescriptorSetIndex, pso::DescriptorBinding),
+    ) -> pso::DescriptorBinding {
+        if let Some(combined) = self
+            .combined
+            .iter()
+            .find(|c| c.image == image && c.sampler == sampler)
+        {
+            return combined.unit;
+        }
+
+        let unit = self.reserve_binding(BindingTypes::Images);
+        self.insert_missing_binding(unit, BindingTypes::Images, image.0, image.1);
+        self.insert_missing_binding(unit, BindingTypes::Images, sampler.0, sampler.1);
+        self.combined.push(CombinedUse {
+            unit,
+            image,
+            sampler,
+        });
+        unit
+    }
+
+    /// Combinations of separate images and samplers, in order of first use.
+    pub fn combined_uses(&self) -> &[CombinedUse] {
+        &self.combined
+    }
+
     pub fn get_binding(
         &self,
         btype: BindingTypes,
@@ -144,19 +256,83 @@ impl DescRemapData {
     ) -> Option<&[pso::DescriptorBinding]> {
         self.bindings.get(&(btype, set, binding)).map(AsRef::as_ref)
     }
+
+    /// Number of array elements of a binding, 1 if it's not an array.
+    pub fn get_array_size(
+        &self,
+        btype: BindingTypes,
+        set: pso::DescriptorSetIndex,
+        binding: pso::DescriptorBinding,
+    ) -> pso::DescriptorArrayIndex {
+        self.array_sizes
+            .get(&(btype, set, binding))
+            .cloned()
+            .unwrap_or(1)
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct GraphicsPipeline {
     pub(crate) program: Program,
     pub(crate) primitive: u32,
+    pub(crate) rasterization_samples: i32,
+    /// Whether primitives are discarded before rasterization, for pipelines
+    /// which only process vertices.
+    pub(crate) rasterizer_discard: bool,
     pub(crate) patch_size: Option<i32>,
     pub(crate) blend_targets: Vec<pso::ColorBlendDesc>,
     pub(crate) attributes: Vec<AttributeDesc>,
     pub(crate) vertex_buffers: Vec<Option<pso::VertexBufferDesc>>,
     pub(crate) uniforms: Vec<UniformDesc>,
+    /// Members of uniform blocks flattened into plain uniforms.
+    pub(crate) block_uniforms: Arc<BlockUniforms>,
     pub(crate) rasterizer: pso::Rasterizer,
     pub(crate) depth: pso::DepthTest,
+    pub(crate) feedback: PipelineFeedback,
+}
+
+impl GraphicsPipeline {
+    /// GL program of the pipeline.
+    pub fn raw_program(&self) -> Program {
+        self.program
+    }
+
+    /// How the program of the pipeline was built.
+    pub fn feedback(&self) -> &PipelineFeedback {
+        &self.feedback
+    }
+}
+
+/// Compilation of a shader stage of a pipeline.
+#[derive(Clone, Debug)]
+pub struct StageFeedback {
+    /// Stage of the shader.
+    pub stage: pso::Stage,
+    /// Time spent translating and compiling the shader, zero for raw shader
+    /// modules, which are compiled when the module is created.
+    pub compile_time: Duration,
+    /// Info log of the shader object.
+    pub log: String,
+}
+
+/// Creation feedback of a pipeline, with the info logs of the driver.
+#[derive(Clone, Debug, Default)]
+pub struct PipelineFeedback {
+    /// Compiled stages, in pipeline order.
+    pub stages: Vec<StageFeedback>,
+    /// Time spent linking the program.
+    pub link_time: Duration,
+    /// Whether the program was taken from the program cache instead of being
+    /// linked. Always `false` for now, programs are not cached.
+    pub cache_hit: bool,
+    /// Info log of the program.
+    pub link_log: String,
+}
+
+#[derive(Debug)]
+pub struct QueryPool {
+    pub(crate) ty: query::Type,
+    pub(crate) queries: Vec<u32>,
 }
 
 #[derive(Clone, Debug)]
@@ -164,18 +340,121 @@ pub struct ComputePipeline {
     pub(crate) program: Program,
 }
 
+impl ComputePipeline {
+    /// GL program of the pipeline.
+    pub fn raw_program(&self) -> Program {
+        self.program
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Image {
     pub(crate) kind: ImageKind,
     // Required for clearing operations
     pub(crate) channel: format::ChannelType,
     pub(crate) requirements: Requirements,
+    // Upload description if the image may be bound to host visible memory
+    pub(crate) host_image: Option<HostImage>,
+    // Pixel format of texels copied from buffers
+    pub(crate) upload: TexelUpload,
+}
+
+impl Image {
+    /// GL texture of the image with its target, `None` for images backed by
+    /// renderbuffers or buffers.
+    pub fn raw_texture(&self) -> Option<(Texture, TextureType)> {
+        match self.kind {
+            ImageKind::Texture(texture, textype) => Some((texture, textype)),
+            _ => None,
+        }
+    }
+
+    /// GL renderbuffer of the image, `None` for images backed by textures or
+    /// buffers.
+    pub fn raw_renderbuffer(&self) -> Option<Surface> {
+        match self.kind {
+            ImageKind::Surface(renderbuffer) => Some(renderbuffer),
+            _ => None,
+        }
+    }
+}
+
+/// CPU conversion of texels copied from a buffer to an image, for formats the
+/// driver can't upload as they are, see `Quirks::CONVERT_UPLOADS`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TexelConversion {
+    /// Texels are uploaded as they are.
+    None,
+    /// RGB texels are expanded to RGBA with an opaque alpha.
+    ExpandRgb,
+    /// Red and blue of BGRA texels are swapped.
+    SwizzleBgra,
+}
+
+impl TexelConversion {
+    /// Size of the texels in the buffer, which are converted to 4 bytes.
+    pub(crate) fn src_bytes_per_texel(self) -> usize {
+        match self {
+            TexelConversion::ExpandRgb => 3,
+            TexelConversion::None | TexelConversion::SwizzleBgra => 4,
+        }
+    }
+}
+
+/// Pixel format, type and conversion of texels uploaded to an image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TexelUpload {
+    pub(crate) format: u32,
+    pub(crate) ty: u32,
+    pub(crate) conversion: TexelConversion,
+}
+
+/// Texture bound to host visible memory, uploaded from the mapped
+/// memory when it is flushed or unmapped.
+#[derive(Copy, Clone, Debug)]
+pub struct HostImage {
+    pub(crate) texture: Texture,
+    pub(crate) target: TextureType,
+    pub(crate) width: i::Size,
+    pub(crate) height: i::Size,
+    pub(crate) format: u32,
+    pub(crate) ty: u32,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum ImageKind {
     Surface(Surface),
     Texture(Texture, TextureType),
+    /// Linear tiled image, stored in the buffer of its memory.
+    Linear(LinearImage),
+}
+
+/// Tightly packed rows of texels in a buffer, acting as pixel buffer for copies.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct LinearImage {
+    /// Buffer and offset of the bound memory, `None` until bound.
+    pub(crate) buffer: Option<(RawBuffer, buffer::Offset)>,
+    pub(crate) width: i::Size,
+    pub(crate) height: i::Size,
+    pub(crate) bytes_per_texel: u32,
+}
+
+impl LinearImage {
+    pub(crate) fn row_pitch(&self) -> buffer::Offset {
+        self.width as buffer::Offset * self.bytes_per_texel as buffer::Offset
+    }
+
+    pub(crate) fn size(&self) -> buffer::Offset {
+        self.row_pitch() * self.height as buffer::Offset
+    }
+
+    /// Asserts that the image is bound and returns the buffer with the offset of a texel.
+    pub(crate) fn texel(&self, offset: i::Offset) -> (RawBuffer, buffer::Offset) {
+        let (buffer, start) = self.buffer.expect("Expected bound linear image!");
+        let offset = offset.y as buffer::Offset * self.row_pitch()
+            + offset.x as buffer::Offset * self.bytes_per_texel as buffer::Offset;
+        (buffer, start + offset)
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
@@ -186,13 +465,50 @@ pub enum FatSampler {
     Info(i::SamplerInfo),
 }
 
+/// Levels `start..end` of a texture accessed through a view.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct LevelRange {
+    pub(crate) start: i::Level,
+    pub(crate) end: i::Level,
+}
+
+impl LevelRange {
+    pub(crate) fn single(level: i::Level) -> Self {
+        LevelRange {
+            start: level,
+            end: level + 1,
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum ImageView {
     Surface(Surface),
-    Texture(Texture, TextureType, i::Level),
+    /// All layers of a range of levels, attached at the first level.
+    Texture(Texture, TextureType, LevelRange),
+    /// Single layer of a single level.
     TextureLayer(Texture, TextureType, i::Level, i::Layer),
 }
 
+impl ImageView {
+    /// GL texture of the view with its target, `None` for renderbuffers.
+    pub fn raw_texture(&self) -> Option<(Texture, TextureType)> {
+        match *self {
+            ImageView::Surface(_) => None,
+            ImageView::Texture(texture, textype, _)
+            | ImageView::TextureLayer(texture, textype, _, _) => Some((texture, textype)),
+        }
+    }
+
+    /// GL renderbuffer of the view, `None` for textures.
+    pub fn raw_renderbuffer(&self) -> Option<Surface> {
+        match *self {
+            ImageView::Surface(renderbuffer) => Some(renderbuffer),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub(crate) enum DescSetBindings {
     Buffer {
@@ -202,40 +518,219 @@ pub(crate) enum DescSetBindings {
         offset: i32,
         size: i32,
     },
-    Texture(pso::DescriptorBinding, Texture, TextureType),
+    Texture(pso::DescriptorBinding, Texture, TextureType, LevelRange),
     Sampler(pso::DescriptorBinding, Sampler),
     SamplerInfo(pso::DescriptorBinding, i::SamplerInfo),
 }
 
+impl DescSetBindings {
+    pub(crate) fn binding(&self) -> pso::DescriptorBinding {
+        match *self {
+            DescSetBindings::Buffer { binding, .. }
+            | DescSetBindings::Texture(binding, ..)
+            | DescSetBindings::Sampler(binding, _)
+            | DescSetBindings::SamplerInfo(binding, _) => binding,
+        }
+    }
+
+    pub(crate) fn set_binding(&mut self, new_binding: pso::DescriptorBinding) {
+        match *self {
+            DescSetBindings::Buffer {
+                ref mut binding, ..
+            }
+            | DescSetBindings::Texture(ref mut binding, ..)
+            | DescSetBindings::Sampler(ref mut binding, _)
+            | DescSetBindings::SamplerInfo(ref mut binding, _) => *binding = new_binding,
+        }
+    }
+
+    /// Entries of the same binding, array element and kind overwrite each
+    /// other: a combined image sampler element has a texture and a sampler entry.
+    pub(crate) fn kind(&self) -> usize {
+        match *self {
+            DescSetBindings::Buffer { .. } => 0,
+            DescSetBindings::Texture(..) => 1,
+            DescSetBindings::Sampler(..) | DescSetBindings::SamplerInfo(..) => 2,
+        }
+    }
+}
+
+/// Texture entry sampling the levels of a view.
+fn sampled_texture(binding: pso::DescriptorBinding, view: &ImageView) -> DescSetBindings {
+    match *view {
+        ImageView::Texture(tex, textype, levels) => {
+            DescSetBindings::Texture(binding, tex, textype, levels)
+        }
+        ImageView::TextureLayer(tex, textype, level, layer) => {
+            // Selecting a layer requires texture views.
+            warn!("Sampling layer {} of a layered texture samples all layers", layer);
+            DescSetBindings::Texture(binding, tex, textype, LevelRange::single(level))
+        }
+        ImageView::Surface(_) => unreachable!(),
+    }
+}
+
+/// Write `entry` to an array element of a set, replacing the previous one.
+pub(crate) fn write_binding(
+    bindings: &mut Vec<(pso::DescriptorArrayIndex, DescSetBindings)>,
+    index: pso::DescriptorArrayIndex,
+    entry: DescSetBindings,
+) {
+    bindings.retain(|&(i, ref e)| {
+        i != index || e.binding() != entry.binding() || e.kind() != entry.kind()
+    });
+    bindings.push((index, entry));
+}
+
+/// Write the bindings of a descriptor to an array element of a set.
+pub(crate) fn write_descriptor(
+    bindings: &mut Vec<(pso::DescriptorArrayIndex, DescSetBindings)>,
+    binding: pso::DescriptorBinding,
+    index: pso::DescriptorArrayIndex,
+    descriptor: &pso::Descriptor<Backend>,
+) {
+    match descriptor {
+        pso::Descriptor::Buffer(buffer, ref range) => {
+            let (raw_buffer, buffer_range) = buffer.as_bound();
+            let start = buffer_range.start as i32 + range.start.unwrap_or(0) as i32;
+            let end = buffer_range.start as i32
+                + range.end.unwrap_or((buffer_range.end - buffer_range.start) as u64) as i32;
+            let size = end - start;
+
+            write_binding(bindings, index, DescSetBindings::Buffer {
+                ty: BindingTypes::UniformBuffers,
+                binding,
+                buffer: raw_buffer,
+                offset: start,
+                size,
+            });
+        }
+        pso::Descriptor::CombinedImageSampler(view, _layout, sampler) => {
+            match view {
+                ImageView::Surface(_) => unimplemented!(),
+                _ => write_binding(bindings, index, sampled_texture(binding, view)),
+            }
+            match sampler {
+                FatSampler::Sampler(sampler) => write_binding(
+                    bindings,
+                    index,
+                    DescSetBindings::Sampler(binding, *sampler),
+                ),
+                FatSampler::Info(info) => write_binding(
+                    bindings,
+                    index,
+                    DescSetBindings::SamplerInfo(binding, info.clone()),
+                ),
+            }
+        }
+        pso::Descriptor::Image(view, _layout) => match view {
+            ImageView::Texture(..) | ImageView::TextureLayer(..) => {
+                write_binding(bindings, index, sampled_texture(binding, view))
+            }
+            ImageView::Surface(_) => panic!(
+                "Texture was created with only render target usage which is invalid."
+            ),
+        },
+        pso::Descriptor::Sampler(sampler) => match sampler {
+            FatSampler::Sampler(sampler) => write_binding(
+                bindings,
+                index,
+                DescSetBindings::Sampler(binding, *sampler),
+            ),
+            FatSampler::Info(info) => write_binding(
+                bindings,
+                index,
+                DescSetBindings::SamplerInfo(binding, info.clone()),
+            ),
+        },
+        pso::Descriptor::UniformTexelBuffer(_view) => unimplemented!(),
+        pso::Descriptor::StorageTexelBuffer(_view) => unimplemented!(),
+    }
+
+}
+
 #[derive(Clone, Debug)]
 pub struct DescriptorSet {
-    layout: DescriptorSetLayout,
-    pub(crate) bindings: Arc<Mutex<Vec<DescSetBindings>>>,
+    pub(crate) layout: DescriptorSetLayout,
+    /// Written bindings and the array element they are written to.
+    pub(crate) bindings: Arc<Mutex<Vec<(pso::DescriptorArrayIndex, DescSetBindings)>>>,
+    /// Buffer of texture handles and its size, in bindless mode.
+    pub(crate) handles: Arc<Mutex<Option<(RawBuffer, i32)>>>,
+    /// Handle buffers of the sets of the pool, deleted with the pool.
+    pub(crate) pool_buffers: Arc<Mutex<Vec<RawBuffer>>>,
+}
+
+/// Number of descriptors of each `pso::DescriptorType`, indexed by the type.
+pub(crate) type DescriptorCounts = [usize; 11];
+
+/// Number of descriptors of each type required by sets of the layout.
+pub(crate) fn descriptor_counts(layout: &DescriptorSetLayout) -> DescriptorCounts {
+    let mut counts = [0; 11];
+    for binding in layout {
+        counts[binding.ty as usize] += binding.count;
+    }
+    counts
 }
 
+/// OpenGL doesn't have a meaningful concept of a descriptor pool, the pool
+/// only tracks its capacity so that pool sizing bugs show up like on Vulkan.
 #[derive(Debug)]
-pub struct DescriptorPool {}
+pub struct DescriptorPool {
+    pub(crate) max_sets: usize,
+    pub(crate) capacity: DescriptorCounts,
+    // Sets and descriptors not allocated yet.
+    pub(crate) available_sets: usize,
+    pub(crate) available: DescriptorCounts,
+    // Whether freeing sets returns their descriptors to the pool.
+    pub(crate) free_descriptor_set: bool,
+    // Handle buffers of bindless sets allocated from the pool.
+    pub(crate) buffers: Arc<Mutex<Vec<RawBuffer>>>,
+}
 
 impl pso::DescriptorPool<Backend> for DescriptorPool {
     unsafe fn allocate_set(
         &mut self,
         layout: &DescriptorSetLayout,
     ) -> Result<DescriptorSet, pso::AllocationError> {
+        let counts = descriptor_counts(layout);
+        if self.available_sets == 0
+            || counts.iter().zip(self.available.iter()).any(|(count, available)| count > available)
+        {
+            return Err(pso::AllocationError::OutOfPoolMemory);
+        }
+        self.available_sets -= 1;
+        for (available, count) in self.available.iter_mut().zip(counts.iter()) {
+            *available -= count;
+        }
+
         Ok(DescriptorSet {
             layout: layout.clone(),
             bindings: Arc::new(Mutex::new(Vec::new())),
+            handles: Arc::new(Mutex::new(None)),
+            pool_buffers: self.buffers.clone(),
         })
     }
 
-    unsafe fn free_sets<I>(&mut self, _descriptor_sets: I)
+    unsafe fn free_sets<I>(&mut self, descriptor_sets: I)
     where
         I: IntoIterator<Item = DescriptorSet>,
     {
-        // Poof!  Does nothing, because OpenGL doesn't have a meaningful concept of a `DescriptorSet`.
+        if !self.free_descriptor_set {
+            error!("Descriptor sets can only be freed from pools created with FREE_DESCRIPTOR_SET");
+            return;
+        }
+        for set in descriptor_sets {
+            let counts = descriptor_counts(&set.layout);
+            self.available_sets += 1;
+            for (available, count) in self.available.iter_mut().zip(counts.iter()) {
+                *available += count;
+            }
+        }
     }
 
     unsafe fn reset(&mut self) {
-        // Poof!  Does nothing, because OpenGL doesn't have a meaningful concept of a `DescriptorSet`.
+        self.available_sets = self.max_sets;
+        self.available = self.capacity;
     }
 }
 
@@ -250,11 +745,17 @@ pub struct Memory {
     pub(crate) properties: Properties,
     /// Gl buffer and the target that should be used for map operations.  Image memory is faked and
     /// has no associated buffer, so this will be None for image memory.
+    ///
+    /// Buffers bound to the memory alias sub-ranges of this buffer.
     pub(crate) buffer: Option<(RawBuffer, u32)>,
     /// Allocation size
     pub(crate) size: u64,
     pub(crate) map_flags: u32,
     pub(crate) emulate_map_allocation: Cell<Option<*mut u8>>,
+    /// Images bound to host visible image memory and their memory ranges.
+    pub(crate) host_images: RefCell<Vec<(Range<u64>, HostImage)>>,
+    /// Memory object of imported memory (`GL_EXT_memory_object`).
+    pub(crate) memory_object: Option<u32>,
 }
 
 unsafe impl Send for Memory {}
@@ -266,10 +767,31 @@ pub struct RenderPass {
     pub(crate) subpasses: Vec<SubpassDesc>,
 }
 
+impl RenderPass {
+    /// Check if any sub-pass samples attachments of the framebuffer.
+    pub(crate) fn reads_attachments(&self) -> bool {
+        self.subpasses.iter().any(|sp| sp.reads_attachments)
+    }
+
+    /// Sub-passes using an attachment first and last.
+    pub(crate) fn attachment_uses(&self, at_id: pass::AttachmentId) -> Option<(usize, usize)> {
+        let first = self.subpasses.iter().position(|sp| sp.is_using(at_id))?;
+        let last = self.subpasses.iter().rposition(|sp| sp.is_using(at_id))?;
+        Some((first, last))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SubpassDesc {
     pub(crate) color_attachments: Vec<usize>,
     pub(crate) depth_stencil: Option<usize>,
+    /// Attachments sampled by the shaders of this sub-pass.
+    pub(crate) input_attachments: Vec<usize>,
+    /// Memory barrier bits for the dependencies of this sub-pass.
+    pub(crate) memory_barrier: u32,
+    /// Whether attachments written by previous sub-passes are sampled,
+    /// either as input attachments or declared by dependencies.
+    pub(crate) reads_attachments: bool,
 }
 
 impl SubpassDesc {
@@ -280,7 +802,8 @@ impl SubpassDesc {
             None => false,
         };
         let uses_color = self.color_attachments.iter().any(|id| *id == at_id);
-        uses_color || uses_ds
+        let uses_input = self.input_attachments.iter().any(|id| *id == at_id);
+        uses_color || uses_ds || uses_input
     }
 }
 
@@ -289,9 +812,20 @@ pub struct PipelineLayout {
     pub(crate) desc_remap_data: Arc<RwLock<DescRemapData>>,
 }
 
+impl PipelineLayout {
+    /// Uniform buffer binding of the texture handle block of a set, if the
+    /// layout was created in bindless mode and the set has sampled images.
+    pub fn bindless_block(&self, set: pso::DescriptorSetIndex) -> Option<pso::DescriptorBinding> {
+        self.desc_remap_data.read().unwrap().get_bindless_block(set)
+    }
+}
+
 #[derive(Debug)]
 // No inter-queue synchronization required for GL.
-pub struct Semaphore;
+pub struct Semaphore {
+    /// Semaphore imported from another API (`GL_EXT_semaphore`).
+    pub(crate) external: Option<u32>,
+}
 
 #[derive(Clone, Debug)]
 pub struct AttributeDesc {
@@ -310,9 +844,84 @@ pub struct UniformDesc {
     pub(crate) utype: u32,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Member of a uniform block flattened into a plain uniform, see
+/// `PrivateCaps::emulate_uniform_blocks`.
+#[derive(Clone, Copy, Debug)]
+pub struct BlockUniform {
+    pub(crate) location: UniformLocation,
+    pub(crate) utype: u32,
+    /// Number of array elements.
+    pub(crate) count: u32,
+    /// Uniform buffer binding of the block.
+    pub(crate) binding: u32,
+    /// Offset of the member in the `std140` block memory.
+    pub(crate) offset: u32,
+}
+
+/// Flattened uniform block members of a program, with the values uploaded
+/// last to skip unchanged `glUniform*` calls.
+#[derive(Debug, Default)]
+pub struct BlockUniforms {
+    pub(crate) uniforms: Vec<BlockUniform>,
+    pub(crate) uploaded: Mutex<Vec<Vec<u8>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum VertexAttribFunction {
-    Float,   // glVertexAttribPointer
-    Integer, // glVertexAttribIPointer
-    Double,  // glVertexAttribLPointer
+    Float,      // glVertexAttribPointer
+    Normalized, // glVertexAttribPointer with normalization
+    Integer,    // glVertexAttribIPointer
+    Double,     // glVertexAttribLPointer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BindingTypes, CombinedUse, DescRemapData};
+
+    #[test]
+    fn test_combined_separate_image_sampler() {
+        let mut drd = DescRemapData::new();
+        // A combined image sampler of the layout takes the first unit.
+        drd.insert_missing_binding_into_spare(BindingTypes::Images, 0, 0, 1);
+
+        // The same image with two samplers of another set.
+        let linear = drd.insert_combined((0, 1), (1, 0));
+        let nearest = drd.insert_combined((0, 1), (1, 1));
+        assert_eq!((linear, nearest), (1, 2));
+        // Another stage or pipeline reuses the units of known combinations.
+        assert_eq!(drd.insert_combined((0, 1), (1, 1)), nearest);
+        // Another image with the first sampler.
+        assert_eq!(drd.insert_combined((0, 2), (1, 0)), 3);
+
+        let images = BindingTypes::Images;
+        assert_eq!(drd.get_binding(images, 0, 0), Some(&[0][..]));
+        assert_eq!(drd.get_binding(images, 0, 1), Some(&[1, 2][..]));
+        assert_eq!(drd.get_binding(images, 0, 2), Some(&[3][..]));
+        assert_eq!(drd.get_binding(images, 1, 0), Some(&[1, 3][..]));
+        assert_eq!(drd.get_binding(images, 1, 1), Some(&[2][..]));
+        assert_eq!(drd.get_binding(BindingTypes::UniformBuffers, 0, 1), None);
+        assert_eq!(
+            drd.combined_uses(),
+            &[
+                CombinedUse { unit: 1, image: (0, 1), sampler: (1, 0) },
+                CombinedUse { unit: 2, image: (0, 1), sampler: (1, 1) },
+                CombinedUse { unit: 3, image: (0, 2), sampler: (1, 0) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_array_bindings() {
+        let mut drd = DescRemapData::new();
+        drd.insert_missing_binding_into_spare(BindingTypes::Images, 0, 0, 4);
+        drd.insert_missing_binding_into_spare(BindingTypes::Images, 0, 1, 1);
+        drd.insert_missing_binding_into_spare(BindingTypes::UniformBuffers, 0, 2, 2);
+
+        assert_eq!(drd.get_binding(BindingTypes::Images, 0, 1), Some(&[4][..]));
+        assert_eq!(drd.get_array_size(BindingTypes::Images, 0, 0), 4);
+        assert_eq!(drd.get_array_size(BindingTypes::Images, 0, 1), 1);
+        assert_eq!(drd.get_array_size(BindingTypes::UniformBuffers, 0, 2), 2);
+        // Combinations take units after the arrays.
+        assert_eq!(drd.insert_combined((1, 0), (1, 1)), 5);
+    }
 }
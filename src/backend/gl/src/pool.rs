@@ -24,6 +24,11 @@ impl OwnedBuffer {
         self.commands.clear();
         self.data.clear();
     }
+
+    fn shrink(&mut self) {
+        self.commands.shrink_to_fit();
+        self.data.shrink_to_fit();
+    }
 }
 
 // Storage of command buffer memory.
@@ -58,10 +63,13 @@ pub struct RawCommandPool {
     pub(crate) fbo: Option<n::FrameBuffer>,
     pub(crate) limits: command::Limits,
     pub(crate) memory: Arc<Mutex<BufferMemory>>,
+    // Freed command buffers, reused by later allocations together with
+    // their memory, which is cleared but keeps its capacity.
+    pub(crate) free_buffers: Vec<RawCommandBuffer>,
 }
 
 impl pool::RawCommandPool<Backend> for RawCommandPool {
-    unsafe fn reset(&mut self, _release_resources: bool) {
+    unsafe fn reset(&mut self, release_resources: bool) {
         let mut memory = self
             .memory
             .try_lock()
@@ -70,20 +78,37 @@ impl pool::RawCommandPool<Backend> for RawCommandPool {
         match *memory {
             BufferMemory::Linear(ref mut buffer) => {
                 buffer.clear();
+                if release_resources {
+                    buffer.shrink();
+                }
             }
             BufferMemory::Individual {
                 ref mut storage, ..
             } => {
+                if release_resources {
+                    for buffer in self.free_buffers.drain(..) {
+                        storage.remove(&buffer.id);
+                    }
+                }
                 for (_, ref mut buffer) in storage {
                     buffer.clear();
+                    if release_resources {
+                        buffer.shrink();
+                    }
                 }
             }
         }
+        if release_resources {
+            self.free_buffers.clear();
+        }
     }
 
     fn allocate_one(&mut self, _level: hal::command::RawLevel) -> RawCommandBuffer {
         // TODO: Implement secondary buffers
-        RawCommandBuffer::new(self.fbo, self.limits, self.memory.clone())
+        match self.free_buffers.pop() {
+            Some(buffer) => buffer,
+            None => RawCommandBuffer::new(self.fbo, self.limits, self.memory.clone()),
+        }
     }
 
     unsafe fn free<I>(&mut self, buffers: I)
@@ -95,16 +120,21 @@ impl pool::RawCommandPool<Backend> for RawCommandPool {
             .try_lock()
             .expect("Trying to free command buffers, while memory is still in-use.");
 
-        if let BufferMemory::Individual {
-            ref mut storage, ..
-        } = *memory
-        {
-            // Expecting that the buffers actually are allocated from this pool.
-            for buffer in buffers {
-                storage.remove(&buffer.id);
+        // Expecting that the buffers actually are allocated from this pool.
+        for mut buffer in buffers {
+            if let BufferMemory::Individual {
+                ref mut storage, ..
+            } = *memory
+            {
+                // Keep the memory of the buffer for reuse.
+                if let Some(owned) = storage.get_mut(&buffer.id) {
+                    owned.clear();
+                }
             }
+            // Linear: Freeing doesn't really matter here as everything is backed by
+            //         only one Vec.
+            buffer.recycle();
+            self.free_buffers.push(buffer);
         }
-        // Linear: Freeing doesn't really matter here as everything is backed by
-        //         only one Vec.
     }
 }
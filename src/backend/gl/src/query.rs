@@ -0,0 +1,443 @@
+//! Query objects, not covered by `glow`.
+//!
+//! Occlusion queries select the counting mode at recording time, precise
+//! queries count samples with `GL_SAMPLES_PASSED`. Other queries only return
+//! whether any sample passed, with the cheaper conservative mode if available.
+//!
+//! Results are copied to buffers on the GPU with `GL_ARB_query_buffer_object`,
+//! otherwise they are read back when the copy command is executed.
+//!
+//! With the `timings` feature, each submission is wrapped in a pair of
+//! timestamp queries, see `CommandQueue::timings`.
+
+#[cfg(feature = "timings")]
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::os::raw::c_void;
+#[cfg(feature = "timings")]
+use std::time::Duration;
+use std::time::Instant;
+
+use glow::Context;
+
+use crate::hal::{buffer, device as d, query};
+use crate::{fns, native as n, Device, Share};
+
+type GenQueries = unsafe extern "system" fn(i32, *mut u32);
+type DeleteQueries = unsafe extern "system" fn(i32, *const u32);
+type BeginQuery = unsafe extern "system" fn(u32, u32);
+type EndQuery = unsafe extern "system" fn(u32);
+type QueryCounter = unsafe extern "system" fn(u32, u32);
+type GetQueryObjectuiv = unsafe extern "system" fn(u32, u32, *mut u32);
+type GetQueryObjectui64v = unsafe extern "system" fn(u32, u32, *mut u64);
+type GetQueryBufferObject = unsafe extern "system" fn(u32, u32, u32, isize);
+type GetInteger64v = unsafe extern "system" fn(u32, *mut i64);
+
+/// Set when timestamps became meaningless, e.g. because the GPU frequency changed
+/// (`GL_EXT_disjoint_timer_query`).
+#[cfg(feature = "timings")]
+const GPU_DISJOINT_EXT: u32 = 0x8FBB;
+
+/// Entry points of occlusion and timer queries.
+#[derive(Default)]
+pub(crate) struct QueryFns {
+    gen_queries: Option<GenQueries>,
+    delete_queries: Option<DeleteQueries>,
+    begin_query: Option<BeginQuery>,
+    end_query: Option<EndQuery>,
+    query_counter: Option<QueryCounter>,
+    get_query_objectuiv: Option<GetQueryObjectuiv>,
+    get_query_objectui64v: Option<GetQueryObjectui64v>,
+    get_query_buffer_objectuiv: Option<GetQueryBufferObject>,
+    get_query_buffer_objectui64v: Option<GetQueryBufferObject>,
+    get_integer64v: Option<GetInteger64v>,
+}
+
+/// GPU timestamp paired with the CPU time it was taken at, to align GPU and
+/// CPU timelines (like `VK_EXT_calibrated_timestamps`).
+#[derive(Clone, Copy, Debug)]
+pub struct CalibratedTimestamp {
+    /// GPU timestamp in nanoseconds, comparable to timestamp queries.
+    pub gpu: u64,
+    /// CPU time of the GPU timestamp.
+    pub cpu: Instant,
+    /// Maximum deviation between both timestamps in nanoseconds.
+    pub max_deviation: u64,
+}
+
+/// GPU duration of a submission, measured with timestamp queries written
+/// before its first and after its last command buffer.
+#[cfg(feature = "timings")]
+#[derive(Clone, Copy, Debug)]
+pub struct SubmissionTiming {
+    /// Index of the submission on its queue, counting from zero.
+    pub submission: u64,
+    /// Time the GPU spent between the start and the end of the submission.
+    pub duration: Duration,
+}
+
+/// Timestamp queries written around the submissions of a queue.
+#[cfg(feature = "timings")]
+#[derive(Debug, Default)]
+pub(crate) struct Timings {
+    // Number of submissions so far, timed or not.
+    submissions: u64,
+    // Query pair of the submission being executed.
+    current: Option<(u64, [u32; 2])>,
+    // Query pairs waiting for their results, in submission order.
+    pending: VecDeque<(u64, [u32; 2])>,
+    // Query pairs which can be written again.
+    free: Vec<[u32; 2]>,
+}
+
+#[cfg(feature = "timings")]
+impl Timings {
+    fn is_supported(share: &Share) -> bool {
+        let fns = &share.context.query;
+        share.private_caps.timer_query && fns.is_loaded() && fns.query_counter.is_some()
+    }
+
+    /// Write the timestamp at the start of a submission.
+    pub(crate) fn begin(&mut self, share: &Share) {
+        let submission = self.submissions;
+        self.submissions += 1;
+        if !Self::is_supported(share) {
+            return;
+        }
+        let queries = self.free.pop().unwrap_or_else(|| {
+            let mut queries = [0; 2];
+            unsafe { (share.context.query.gen_queries.unwrap())(2, queries.as_mut_ptr()) };
+            queries
+        });
+        share.write_timestamp(queries[0]);
+        self.current = Some((submission, queries));
+    }
+
+    /// Write the timestamp at the end of the current submission.
+    pub(crate) fn end(&mut self, share: &Share) {
+        if let Some((submission, queries)) = self.current.take() {
+            share.write_timestamp(queries[1]);
+            self.pending.push_back((submission, queries));
+        }
+    }
+
+    /// Durations of the submissions finished since the last call, in
+    /// submission order. Doesn't wait for the GPU.
+    pub(crate) fn collect(&mut self, share: &Share) -> Vec<SubmissionTiming> {
+        let fns = &share.context.query;
+        let mut timings = Vec::new();
+        while let Some(&(submission, queries)) = self.pending.front() {
+            // The end of a submission is written last, the start is available too.
+            let end = match fns.result(queries[1], false) {
+                Some(end) => end,
+                None => break,
+            };
+            let start = fns.result(queries[0], true).unwrap();
+            self.pending.pop_front();
+            self.free.push(queries);
+            timings.push(SubmissionTiming {
+                submission,
+                duration: Duration::from_nanos(end.saturating_sub(start)),
+            });
+        }
+
+        if share.info.is_extension_supported("GL_EXT_disjoint_timer_query") {
+            // Reading the flag resets it.
+            let disjoint = unsafe { share.context.get_parameter_i32(GPU_DISJOINT_EXT) };
+            if disjoint != 0 {
+                warn!("Discarding submission timings, the GPU timer was disjoint");
+                timings.clear();
+            }
+        }
+        timings
+    }
+}
+
+impl QueryFns {
+    pub(crate) fn load<F>(fn_proc: &mut F) -> Self
+    where F: FnMut(&str) -> *const c_void {
+        // ES 2.0 only has the extension entry points.
+        unsafe {
+            QueryFns {
+                gen_queries: fns::load(fn_proc, &["glGenQueries", "glGenQueriesEXT"]),
+                delete_queries: fns::load(fn_proc, &["glDeleteQueries", "glDeleteQueriesEXT"]),
+                begin_query: fns::load(fn_proc, &["glBeginQuery", "glBeginQueryEXT"]),
+                end_query: fns::load(fn_proc, &["glEndQuery", "glEndQueryEXT"]),
+                query_counter: fns::load(fn_proc, &["glQueryCounter", "glQueryCounterEXT"]),
+                get_query_objectuiv: fns::load(
+                    fn_proc,
+                    &["glGetQueryObjectuiv", "glGetQueryObjectuivEXT"],
+                ),
+                get_query_objectui64v: fns::load(
+                    fn_proc,
+                    &["glGetQueryObjectui64v", "glGetQueryObjectui64vEXT"],
+                ),
+                get_query_buffer_objectuiv: fns::load(
+                    fn_proc,
+                    &["glGetQueryBufferObjectuiv", "glGetQueryBufferObjectuivEXT"],
+                ),
+                get_query_buffer_objectui64v: fns::load(
+                    fn_proc,
+                    &["glGetQueryBufferObjectui64v", "glGetQueryBufferObjectui64vEXT"],
+                ),
+                get_integer64v: fns::load(fn_proc, &["glGetInteger64v", "glGetInteger64vEXT"]),
+            }
+        }
+    }
+
+    fn is_loaded(&self) -> bool {
+        self.gen_queries.is_some()
+            && self.delete_queries.is_some()
+            && self.begin_query.is_some()
+            && self.end_query.is_some()
+            && self.get_query_objectuiv.is_some()
+    }
+
+    pub(crate) fn delete_queries(&self, queries: &[u32]) {
+        if let Some(delete_queries) = self.delete_queries {
+            unsafe { delete_queries(queries.len() as i32, queries.as_ptr()) };
+        }
+    }
+
+    /// Result of a query, `None` if it isn't available and `wait` is false.
+    pub(crate) fn result(&self, query: u32, wait: bool) -> Option<u64> {
+        let get_query_objectuiv = self.get_query_objectuiv.unwrap();
+        unsafe {
+            if !wait {
+                let mut available = 0;
+                get_query_objectuiv(query, glow::QUERY_RESULT_AVAILABLE, &mut available);
+                if available == 0 {
+                    return None;
+                }
+            }
+            // Timestamps don't fit into 32 bits.
+            match self.get_query_objectui64v {
+                Some(get_query_objectui64v) => {
+                    let mut result = 0;
+                    get_query_objectui64v(query, glow::QUERY_RESULT, &mut result);
+                    Some(result)
+                }
+                None => {
+                    let mut result = 0;
+                    get_query_objectuiv(query, glow::QUERY_RESULT, &mut result);
+                    Some(result as u64)
+                }
+            }
+        }
+    }
+}
+
+impl Share {
+    /// Target of occlusion queries, counting samples for precise queries.
+    pub(crate) fn occlusion_query_target(&self, precise: bool) -> u32 {
+        let caps = &self.private_caps;
+        if precise && caps.samples_passed_query {
+            glow::SAMPLES_PASSED
+        } else if caps.conservative_occlusion_query {
+            glow::ANY_SAMPLES_PASSED_CONSERVATIVE
+        } else if caps.any_samples_passed_query {
+            glow::ANY_SAMPLES_PASSED
+        } else {
+            glow::SAMPLES_PASSED
+        }
+    }
+
+    pub(crate) fn begin_query(&self, target: u32, query: u32) {
+        unsafe { (self.context.query.begin_query.unwrap())(target, query) };
+    }
+
+    pub(crate) fn end_query(&self, target: u32) {
+        unsafe { (self.context.query.end_query.unwrap())(target) };
+    }
+
+    pub(crate) fn write_timestamp(&self, query: u32) {
+        unsafe { (self.context.query.query_counter.unwrap())(query, glow::TIMESTAMP) };
+    }
+
+    /// Write the result of a query to a buffer at `offset`.
+    pub(crate) fn copy_query_result(
+        &self,
+        query: u32,
+        buffer: n::RawBuffer,
+        offset: buffer::Offset,
+        flags: query::ResultFlags,
+    ) {
+        let fns = &self.context.query;
+        let gl = &self.context;
+        if !self.private_caps.query_buffer_object {
+            // Read back the result, unavailable results are only written if partial.
+            let result = fns.result(query, flags.contains(query::ResultFlags::WAIT));
+            let value = match result {
+                None if flags.contains(query::ResultFlags::PARTIAL) => Some(0),
+                value => value,
+            };
+            let mut data = [0u8; 16];
+            write_result(&mut data, flags, value, result.is_some());
+
+            let size = if flags.contains(query::ResultFlags::BITS_64) { 8 } else { 4 };
+            let with_availability = flags.contains(query::ResultFlags::WITH_AVAILABILITY);
+            let (start, end) = match (value.is_some(), with_availability) {
+                (true, true) => (0, 2 * size),
+                (true, false) => (0, size),
+                (false, true) => (size, 2 * size),
+                (false, false) => return,
+            };
+            unsafe {
+                gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(buffer));
+                gl.buffer_sub_data_u8_slice(
+                    glow::COPY_WRITE_BUFFER,
+                    (offset + start as buffer::Offset) as i32,
+                    &data[start..end],
+                );
+                gl.bind_buffer(glow::COPY_WRITE_BUFFER, None);
+            }
+            return;
+        }
+
+        // Results which aren't available are left untouched without waiting.
+        let pname = if flags.contains(query::ResultFlags::WAIT) {
+            glow::QUERY_RESULT
+        } else {
+            glow::QUERY_RESULT_NO_WAIT
+        };
+        let (size, get_dsa) = if flags.contains(query::ResultFlags::BITS_64) {
+            (8, fns.get_query_buffer_objectui64v)
+        } else {
+            (4, fns.get_query_buffer_objectuiv)
+        };
+        let mut writes = vec![(pname, offset)];
+        if flags.contains(query::ResultFlags::WITH_AVAILABILITY) {
+            writes.push((glow::QUERY_RESULT_AVAILABLE, offset + size));
+        }
+
+        match get_dsa {
+            Some(get_query_buffer_object) => {
+                for (pname, offset) in writes {
+                    unsafe { get_query_buffer_object(query, buffer, pname, offset as isize) };
+                }
+            }
+            None => {
+                // The pointer is an offset into the bound query buffer.
+                unsafe {
+                    gl.bind_buffer(glow::QUERY_BUFFER, Some(buffer));
+                    for (pname, offset) in writes {
+                        if size == 8 {
+                            let get_query_objectui64v = fns.get_query_objectui64v.unwrap();
+                            get_query_objectui64v(query, pname, offset as usize as *mut u64);
+                        } else {
+                            let get_query_objectuiv = fns.get_query_objectuiv.unwrap();
+                            get_query_objectuiv(query, pname, offset as usize as *mut u32);
+                        }
+                    }
+                    gl.bind_buffer(glow::QUERY_BUFFER, None);
+                }
+            }
+        }
+    }
+}
+
+/// Write the result of a query to `data` in the layout selected by `flags`,
+/// the value is skipped if it's `None`.
+pub(crate) fn write_result(
+    data: &mut [u8],
+    flags: query::ResultFlags,
+    value: Option<u64>,
+    available: bool,
+) {
+    let bits_64 = flags.contains(query::ResultFlags::BITS_64);
+    let write = |data: &mut [u8], value: u64| {
+        if bits_64 {
+            data[..8].copy_from_slice(&unsafe { mem::transmute::<u64, [u8; 8]>(value) });
+        } else {
+            let value = value.min(u32::max_value() as u64) as u32;
+            data[..4].copy_from_slice(&unsafe { mem::transmute::<u32, [u8; 4]>(value) });
+        }
+    };
+    if let Some(value) = value {
+        write(data, value);
+    }
+    if flags.contains(query::ResultFlags::WITH_AVAILABILITY) {
+        let offset = if bits_64 { 8 } else { 4 };
+        write(&mut data[offset..], available as u64);
+    }
+}
+
+impl Device {
+    pub(crate) fn create_queries(
+        &self,
+        ty: query::Type,
+        count: query::Id,
+    ) -> Result<n::QueryPool, query::CreationError> {
+        let fns = &self.share.context.query;
+        let caps = &self.share.private_caps;
+        let supported = fns.is_loaded()
+            && match ty {
+                query::Type::Occlusion => {
+                    caps.samples_passed_query || caps.any_samples_passed_query
+                }
+                query::Type::Timestamp => caps.timer_query && fns.query_counter.is_some(),
+                query::Type::PipelineStatistics(_) => false,
+            };
+        if !supported {
+            return Err(query::CreationError::Unsupported(ty));
+        }
+
+        let mut queries = vec![0; count as usize];
+        unsafe { (fns.gen_queries.unwrap())(count as i32, queries.as_mut_ptr()) };
+        if let Err(err) = self.share.check() {
+            error!("Error creating queries: {:?}", err);
+            return Err(d::OutOfMemory::OutOfHostMemory.into());
+        }
+        Ok(n::QueryPool { ty, queries })
+    }
+
+    /// Read query results on the CPU, returns if all results were available.
+    pub(crate) fn read_query_results(
+        &self,
+        pool: &n::QueryPool,
+        queries: Range<query::Id>,
+        data: &mut [u8],
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    ) -> bool {
+        let fns = &self.share.context.query;
+        let wait = flags.contains(query::ResultFlags::WAIT);
+        let mut all_available = true;
+        for (i, id) in queries.enumerate() {
+            let result = fns.result(pool.queries[id as usize], wait);
+            all_available &= result.is_some();
+            // Partial results of unavailable queries are reported as zero.
+            let value = match result {
+                None if flags.contains(query::ResultFlags::PARTIAL) => Some(0),
+                value => value,
+            };
+            let offset = i * stride as usize;
+            write_result(&mut data[offset..], flags, value, result.is_some());
+        }
+        all_available
+    }
+
+    /// Current GPU timestamp paired with the CPU time, `None` if timestamps
+    /// are not supported.
+    ///
+    /// The GPU timestamp is read without waiting for submitted commands, the
+    /// CPU time is the middle of the query.
+    pub fn get_calibrated_timestamp(&self) -> Option<CalibratedTimestamp> {
+        let get_integer64v = self.share.context.query.get_integer64v?;
+        if !self.share.private_caps.timer_query {
+            return None;
+        }
+
+        let mut gpu = 0;
+        let before = Instant::now();
+        unsafe { get_integer64v(glow::TIMESTAMP, &mut gpu) };
+        let elapsed = before.elapsed();
+
+        let max_deviation = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+        Some(CalibratedTimestamp {
+            gpu: gpu as u64,
+            cpu: before + elapsed / 2,
+            max_deviation,
+        })
+    }
+}
@@ -1,5 +1,6 @@
 use crate::Starc;
 use std::borrow::Borrow;
+use std::sync::Arc;
 use std::{mem, slice};
 
 use crate::hal;
@@ -9,13 +10,16 @@ use glow::Context;
 use smallvec::SmallVec;
 
 use crate::info::LegacyFeatures;
-use crate::{command as com, device, native, state, window};
-use crate::{Backend, GlContext, Share};
+use crate::quirks::Quirks;
+use crate::{command as com, conv, device, native, staging, state, window};
+use crate::{Backend, Error, GlContext, Share};
 
 // State caching system for command queue.
 //
-// We track the current global state, which is based on
-// the restriction that we only expose _one_ command queue.
+// We track the current global state of the context. Virtual queues
+// share the context, each one caches the state it set and flushes
+// its cache when another queue submitted in between, see
+// `CommandQueue::activate`.
 //
 // This allows us to minimize additional driver calls to
 // ensure that command buffers are handled isolated of each other.
@@ -33,6 +37,16 @@ struct State {
     num_scissors: usize,
     // Currently bound fbo
     fbo: Option<native::FrameBuffer>,
+    // Enabled vertex attribute arrays, only tracked if VAOs are emulated.
+    vertex_attribs: u32,
+    // Whether draws discard primitives before rasterization.
+    rasterizer_discard: bool,
+    // Whether draws blend with an advanced blend equation.
+    advanced_blend: bool,
+    // Flattened uniform blocks of the bound program.
+    block_uniforms: Option<Arc<native::BlockUniforms>>,
+    // Block memory bound to each uniform block binding, if UBOs are emulated.
+    uniform_blocks: Vec<Option<(native::RawBuffer, i32, i32)>>,
 }
 
 impl State {
@@ -45,6 +59,11 @@ impl State {
             num_viewports: 0,
             num_scissors: 0,
             fbo: None,
+            vertex_attribs: 0,
+            rasterizer_discard: false,
+            advanced_blend: false,
+            block_uniforms: None,
+            uniform_blocks: Vec::new(),
         }
     }
 
@@ -59,20 +78,60 @@ impl State {
     }
 }
 
+/// Whether the command draws primitives.
+fn is_draw(cmd: &com::Command) -> bool {
+    match *cmd {
+        com::Command::Draw { .. } | com::Command::DrawIndexed { .. } => true,
+        _ => false,
+    }
+}
+
 #[derive(Debug)]
 pub struct CommandQueue {
     pub(crate) share: Starc<Share>,
     vao: Option<native::VertexArray>,
     state: State,
+    // Index of the virtual queue in the family.
+    index: usize,
+    // Ring buffer for data written by the queue.
+    staging: staging::StagingRing,
+    // Pixel buffers for texels uploaded from client memory.
+    belt: staging::StagingBelt,
+    // Timestamp queries around submissions.
+    #[cfg(all(not(target_arch = "wasm32"), feature = "timings"))]
+    timings: crate::query::Timings,
 }
 
 impl CommandQueue {
     /// Create a new command queue.
-    pub(crate) fn new(share: &Starc<Share>, vao: Option<native::VertexArray>) -> Self {
+    pub(crate) fn new(
+        share: &Starc<Share>,
+        vao: Option<native::VertexArray>,
+        index: usize,
+    ) -> Self {
         CommandQueue {
             share: share.clone(),
             vao,
             state: State::new(),
+            index,
+            staging: staging::StagingRing::new(),
+            belt: staging::StagingBelt::new(),
+            #[cfg(all(not(target_arch = "wasm32"), feature = "timings"))]
+            timings: Default::default(),
+        }
+    }
+
+    /// Make this queue the active one, invalidating the cached state if
+    /// another virtual queue submitted in between.
+    fn activate(&mut self) {
+        if self.share.active_queue.replace(self.index) == self.index {
+            return;
+        }
+        self.state.flush();
+        // Attribute arrays may have been left enabled by the other queue.
+        if !self.share.private_caps.vertex_array {
+            let max_attribs = self.share.limits.max_vertex_input_attributes.min(32);
+            self.state.vertex_attribs = ((1u64 << max_attribs) - 1) as u32;
         }
     }
 
@@ -82,6 +141,7 @@ impl CommandQueue {
     /// > Note: Calling this function can have a noticeable impact on the performance
     ///         because the internal state cache will flushed.
     pub unsafe fn with_gl<F: FnMut(&GlContext)>(&mut self, mut fun: F) {
+        self.activate();
         self.reset_state();
         fun(&self.share.context);
         // Flush the state to enforce a reset once a new command buffer
@@ -89,6 +149,16 @@ impl CommandQueue {
         self.state.flush();
     }
 
+    /// GPU durations of the submissions which finished since the last call,
+    /// in submission order. Doesn't wait for submissions in flight.
+    ///
+    /// Requires timestamp queries, no submission is timed without them.
+    /// Timings are discarded if the GPU timer was disjoint.
+    #[cfg(all(not(target_arch = "wasm32"), feature = "timings"))]
+    pub fn timings(&mut self) -> Vec<crate::query::SubmissionTiming> {
+        self.timings.collect(&self.share)
+    }
+
     /*
     fn bind_attribute(&mut self, slot: hal::AttributeSlot, buffer: n::Buffer, bel: BufferElement) {
         use core::format::SurfaceType as S;
@@ -160,8 +230,8 @@ impl CommandQueue {
             &native::ImageView::Surface(surface) => unsafe {
                 gl.framebuffer_renderbuffer(point, attachment, glow::RENDERBUFFER, Some(surface));
             },
-            &native::ImageView::Texture(texture, _, level) => unsafe {
-                gl.framebuffer_texture(point, attachment, Some(texture), level as i32);
+            &native::ImageView::Texture(texture, _, levels) => unsafe {
+                gl.framebuffer_texture(point, attachment, Some(texture), levels.start as i32);
             },
             &native::ImageView::TextureLayer(texture, _, level, layer) => unsafe {
                 gl.framebuffer_texture_layer(
@@ -208,6 +278,24 @@ impl CommandQueue {
             self.state.vao = true
         }
 
+        // Without VAOs the attribute arrays are global state, disable the ones
+        // enabled by previous command buffers.
+        if !self.share.private_caps.vertex_array {
+            let mut attribs = self.state.vertex_attribs;
+            while attribs != 0 {
+                let location = attribs.trailing_zeros();
+                unsafe { gl.disable_vertex_attrib_array(location) };
+                attribs &= !(1 << location);
+            }
+            self.state.vertex_attribs = 0;
+        }
+
+        // Set by the pipelines of the command buffer.
+        self.state.rasterizer_discard = false;
+        self.state.advanced_blend = false;
+        self.state.block_uniforms = None;
+        self.state.uniform_blocks.clear();
+
         // Reset indirect draw buffer
         if self
             .share
@@ -252,6 +340,100 @@ impl CommandQueue {
         }
     }
 
+    /// Issue consecutive non-instanced draws of the same primitive with one
+    /// multi-draw call, returns the number of executed commands.
+    ///
+    /// Consecutive draws share all state, state changes are recorded as
+    /// commands in between.
+    fn multi_draw(&mut self, commands: &[com::Command]) -> usize {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if !self.share.private_caps.multi_draw {
+                return 0;
+            }
+            let fns = &self.share.context.draw;
+            match commands.first() {
+                Some(&com::Command::Draw { primitive, .. }) => {
+                    let multi_draw_arrays = match fns.multi_draw_arrays {
+                        Some(multi_draw_arrays) => multi_draw_arrays,
+                        None => return 0,
+                    };
+                    let mut firsts = Vec::new();
+                    let mut counts = Vec::new();
+                    for cmd in commands {
+                        match *cmd {
+                            com::Command::Draw {
+                                primitive: p,
+                                ref vertices,
+                                ref instances,
+                            } if p == primitive && instances == &(0u32..1) => {
+                                firsts.push(vertices.start as i32);
+                                counts.push((vertices.end - vertices.start) as i32);
+                            }
+                            _ => break,
+                        }
+                    }
+                    if counts.len() < 2 {
+                        return 0;
+                    }
+                    unsafe {
+                        multi_draw_arrays(
+                            primitive,
+                            firsts.as_ptr(),
+                            counts.as_ptr(),
+                            counts.len() as i32,
+                        );
+                    }
+                    counts.len()
+                }
+                Some(&com::Command::DrawIndexed { primitive, index_type, .. }) => {
+                    let multi_draw_elements = match fns.multi_draw_elements {
+                        Some(multi_draw_elements) => multi_draw_elements,
+                        None => return 0,
+                    };
+                    let mut counts = Vec::new();
+                    let mut offsets = Vec::new();
+                    for cmd in commands {
+                        match *cmd {
+                            com::Command::DrawIndexed {
+                                primitive: p,
+                                index_type: ty,
+                                index_count,
+                                index_buffer_offset,
+                                base_vertex: 0,
+                                ref instances,
+                            } if p == primitive && ty == index_type && instances == &(0u32..1) => {
+                                counts.push(index_count as i32);
+                                let offset = index_buffer_offset as usize;
+                                offsets.push(offset as *const std::os::raw::c_void);
+                            }
+                            _ => break,
+                        }
+                    }
+                    if counts.len() < 2 {
+                        return 0;
+                    }
+                    unsafe {
+                        multi_draw_elements(
+                            primitive,
+                            counts.as_ptr(),
+                            index_type,
+                            offsets.as_ptr(),
+                            counts.len() as i32,
+                        );
+                    }
+                    counts.len()
+                }
+                _ => 0,
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = commands;
+            0
+        }
+    }
+
     fn process(&mut self, cmd: &com::Command, data_buf: &[u8]) {
         match *cmd {
             com::Command::BindIndexBuffer(buffer) => {
@@ -517,12 +699,90 @@ impl CommandQueue {
                     error!("Tried to bind FBO without FBO support!");
                 }
             }
+            com::Command::SetFrameBufferSrgb(srgb) => {
+                // The default framebuffer only encodes for sRGB swapchain images,
+                // other images are written as they are.
+                if self.share.legacy_features.contains(LegacyFeatures::SRGB_COLOR) {
+                    let gl = &self.share.context;
+                    if srgb && (self.state.fbo.is_some() || self.share.backbuffer_srgb()) {
+                        unsafe { gl.enable(glow::FRAMEBUFFER_SRGB) };
+                    } else {
+                        unsafe { gl.disable(glow::FRAMEBUFFER_SRGB) };
+                    }
+                }
+            }
+            com::Command::BeginSubpass(frame_buffer, subpass, reads_attachments) => {
+                let subpass_framebuffer = self
+                    .share
+                    .subpass_framebuffers
+                    .borrow()
+                    .get(&frame_buffer)
+                    .map(|framebuffers| framebuffers[subpass]);
+                match subpass_framebuffer {
+                    Some(subpass_framebuffer) => self.process(
+                        &com::Command::BindFrameBuffer(
+                            glow::DRAW_FRAMEBUFFER,
+                            Some(subpass_framebuffer),
+                        ),
+                        data_buf,
+                    ),
+                    // Without texture barriers, subpasses have framebuffers of their own.
+                    None if reads_attachments => self.share.texture_barrier(),
+                    _ => {}
+                }
+            }
+            com::Command::TextureBarrier => self.share.texture_barrier(),
+            com::Command::SetEvent(ref event, value) => event.set(value),
+            com::Command::WaitEvents(ref events) => {
+                // Commands are executed in submission order, an event which isn't
+                // set by now can only be set by the host after the submission.
+                if events.iter().any(|event| !event.is_set()) {
+                    warn!("Waiting for events which are not set, this can't be done in GL");
+                }
+            }
+            com::Command::SetFrameBufferSamples(samples) => {
+                self.share.set_framebuffer_default(
+                    glow::DRAW_FRAMEBUFFER,
+                    glow::FRAMEBUFFER_DEFAULT_SAMPLES,
+                    samples,
+                );
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            com::Command::BeginOcclusionQuery(query, precise) => {
+                let target = self.share.occlusion_query_target(precise);
+                self.share.begin_query(target, query);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            com::Command::EndOcclusionQuery(precise) => {
+                let target = self.share.occlusion_query_target(precise);
+                self.share.end_query(target);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            com::Command::WriteTimestamp(query) => self.share.write_timestamp(query),
+            #[cfg(not(target_arch = "wasm32"))]
+            com::Command::CopyQueryResult(query, buffer, offset, flags) => {
+                self.share.copy_query_result(query, buffer, offset, flags)
+            }
+            #[cfg(target_arch = "wasm32")]
+            com::Command::BeginOcclusionQuery(..)
+            | com::Command::EndOcclusionQuery(_)
+            | com::Command::WriteTimestamp(_)
+            | com::Command::CopyQueryResult(..) => unreachable!(),
+            com::Command::MemoryBarrier(barriers) => self.share.memory_barrier(barriers),
             com::Command::BindTargetView(point, attachment, view) => {
                 self.bind_target(point, attachment, &view)
             }
             com::Command::SetDrawColorBuffers(num) => {
                 state::bind_draw_color_buffers(&self.share.context, num);
             }
+            com::Command::InvalidateFrameBuffer(point, attachments) => unsafe {
+                if self.share.private_caps.invalidate_framebuffer {
+                    let attachments = Self::get::<u32>(data_buf, attachments);
+                    self.share.context.invalidate_framebuffer(point, attachments);
+                }
+            },
+            com::Command::SetDepthBias(bias) => self.share.polygon_offset(bias),
+            com::Command::SetRasterizerDiscard(discard) => self.state.rasterizer_discard = discard,
             com::Command::SetPatchSize(num) => unsafe {
                 self.share
                     .context
@@ -534,6 +794,20 @@ impl CommandQueue {
             com::Command::BindBlendSlot(slot, ref blend) => {
                 state::bind_blend_slot(&self.share, slot, blend);
             }
+            com::Command::BindBlockUniforms(ref block_uniforms) => {
+                self.state.block_uniforms = block_uniforms.clone();
+            }
+            com::Command::SetAdvancedBlend(operation) => {
+                self.state.advanced_blend = false;
+                if let Some(operation) = operation {
+                    if self.share.advanced_blend() {
+                        state::bind_advanced_blend(&self.share.context, operation);
+                        self.state.advanced_blend = true;
+                    } else {
+                        error!("Advanced blend equations are not supported");
+                    }
+                }
+            }
             com::Command::BindAttribute(ref attribute, handle, stride, rate) => unsafe {
                 use crate::native::VertexAttribFunction::*;
 
@@ -558,6 +832,14 @@ impl CommandQueue {
                         stride,
                         offset as i32,
                     ),
+                    Normalized => gl.vertex_attrib_pointer_f32(
+                        location,
+                        size,
+                        format,
+                        true,
+                        stride,
+                        offset as i32,
+                    ),
                     Integer => {
                         gl.vertex_attrib_pointer_i32(location, size, format, stride, offset as i32)
                     }
@@ -566,15 +848,14 @@ impl CommandQueue {
                     }
                 }
 
-                if rate != 0 {
-                    if self.share.legacy_features.contains(LegacyFeatures::INSTANCED_ATTRIBUTE_BINDING) {
-                        gl.vertex_attrib_divisor(location, rate);
-                    } else {
-                        error!("Binding attribute with instanced input rate is not supported");
-                    }
-                }
+                // The divisor is part of the vertex array state and persists, so
+                // per-vertex attributes reset it.
+                self.share.vertex_attrib_divisor(location, rate);
 
                 gl.enable_vertex_attrib_array(location);
+                if !self.share.private_caps.vertex_array {
+                    self.state.vertex_attribs |= 1 << location;
+                }
                 gl.bind_buffer(glow::ARRAY_BUFFER, None);
             },
             /*
@@ -583,6 +864,7 @@ impl CommandQueue {
             }*/
             com::Command::CopyBufferToBuffer(src, dst, ref r) => unsafe {
                 let gl = &self.share.context;
+                self.share.copy_buffer_shadow(src, dst, r);
                 gl.bind_buffer(glow::COPY_READ_BUFFER, Some(src));
                 gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(dst));
                 gl.copy_buffer_sub_data(
@@ -595,26 +877,114 @@ impl CommandQueue {
                 gl.bind_buffer(glow::COPY_READ_BUFFER, None);
                 gl.bind_buffer(glow::COPY_WRITE_BUFFER, None);
             },
-            com::Command::CopyBufferToTexture(buffer, texture, textype, ref r) => unsafe {
-                // TODO: Fix format and active texture
+            com::Command::UpdateBuffer(buffer, offset, data) => unsafe {
+                let data = Self::get_raw(data_buf, data);
+                let gl = &self.share.context;
+                self.share.write_buffer_shadow(buffer, offset, data);
+                match self.staging.write(&self.share, data) {
+                    Some((staging, src_offset)) => {
+                        gl.bind_buffer(glow::COPY_READ_BUFFER, Some(staging));
+                        gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(buffer));
+                        gl.copy_buffer_sub_data(
+                            glow::COPY_READ_BUFFER,
+                            glow::COPY_WRITE_BUFFER,
+                            src_offset as _,
+                            offset as _,
+                            data.len() as _,
+                        );
+                        gl.bind_buffer(glow::COPY_READ_BUFFER, None);
+                        gl.bind_buffer(glow::COPY_WRITE_BUFFER, None);
+                    }
+                    None => {
+                        gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+                        gl.buffer_sub_data_u8_slice(glow::ARRAY_BUFFER, offset as _, data);
+                        gl.bind_buffer(glow::ARRAY_BUFFER, None);
+                    }
+                }
+            },
+            com::Command::CopyBufferToTexture(buffer, texture, textype, upload, ref r) => unsafe {
+                // TODO: Fix active texture
                 assert_eq!(r.image_offset.z, 0);
                 assert_eq!(textype, glow::TEXTURE_2D);
                 let gl = &self.share.context;
                 gl.active_texture(glow::TEXTURE0);
-                gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, Some(buffer));
                 gl.bind_texture(glow::TEXTURE_2D, Some(texture));
-                gl.tex_sub_image_2d_pixel_buffer_offset(
-                    glow::TEXTURE_2D,
-                    r.image_layers.level as _,
-                    r.image_offset.x,
-                    r.image_offset.y,
-                    r.image_extent.width as _,
-                    r.image_extent.height as _,
-                    glow::RGBA,
-                    glow::UNSIGNED_BYTE,
-                    r.buffer_offset as i32,
-                );
-                gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, None);
+                if upload.conversion != native::TexelConversion::None {
+                    // Convert the texels from the client-side copy of the buffer, see
+                    // `shadowed_buffer_usage`.
+                    let width = r.image_extent.width as usize;
+                    let height = r.image_extent.height as usize;
+                    let row_texels = match r.buffer_width {
+                        0 => width,
+                        buffer_width => buffer_width as usize,
+                    };
+                    let src_texel = upload.conversion.src_bytes_per_texel();
+                    let size = (row_texels * height.saturating_sub(1) + width) * src_texel;
+                    let start = r.buffer_offset as usize;
+                    let texels = self.share.buffer_shadows.borrow().get(&buffer).map(|shadow| {
+                        let src = &shadow[start..start + size];
+                        conv::convert_texels(upload.conversion, src, width, height, row_texels)
+                    });
+                    match texels {
+                        None => {
+                            error!("Copy source {:?} has no client-side copy to convert", buffer)
+                        }
+                        Some(texels) => match self.belt.write(&self.share, &texels) {
+                            Some((pbo, offset)) => {
+                                gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, Some(pbo));
+                                gl.tex_sub_image_2d_pixel_buffer_offset(
+                                    glow::TEXTURE_2D,
+                                    r.image_layers.level as _,
+                                    r.image_offset.x,
+                                    r.image_offset.y,
+                                    r.image_extent.width as _,
+                                    r.image_extent.height as _,
+                                    upload.format,
+                                    upload.ty,
+                                    offset as i32,
+                                );
+                                gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, None);
+                            }
+                            None => gl.tex_sub_image_2d_u8_slice(
+                                glow::TEXTURE_2D,
+                                r.image_layers.level as _,
+                                r.image_offset.x,
+                                r.image_offset.y,
+                                r.image_extent.width as _,
+                                r.image_extent.height as _,
+                                upload.format,
+                                upload.ty,
+                                Some(&texels),
+                            ),
+                        }
+                    }
+                } else {
+                    gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, Some(buffer));
+                    let row_length =
+                        r.buffer_width != 0 && r.buffer_width != r.image_extent.width;
+                    if row_length {
+                        if self.share.private_caps.unpack_row_length {
+                            gl.pixel_store_i32(glow::UNPACK_ROW_LENGTH, r.buffer_width as i32);
+                        } else {
+                            error!("Buffer row pitch {} is not supported", r.buffer_width);
+                        }
+                    }
+                    gl.tex_sub_image_2d_pixel_buffer_offset(
+                        glow::TEXTURE_2D,
+                        r.image_layers.level as _,
+                        r.image_offset.x,
+                        r.image_offset.y,
+                        r.image_extent.width as _,
+                        r.image_extent.height as _,
+                        upload.format,
+                        upload.ty,
+                        r.buffer_offset as i32,
+                    );
+                    if row_length && self.share.private_caps.unpack_row_length {
+                        gl.pixel_store_i32(glow::UNPACK_ROW_LENGTH, 0);
+                    }
+                    gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, None);
+                }
             },
             com::Command::CopyBufferToSurface(..) => {
                 unimplemented!() //TODO: use FBO
@@ -624,6 +994,9 @@ impl CommandQueue {
                 // TODO: handle partial copies gracefully
                 assert_eq!(r.image_offset, hal::image::Offset { x: 0, y: 0, z: 0 });
                 assert_eq!(textype, glow::TEXTURE_2D);
+                if self.share.quirks.contains(Quirks::SLOW_READBACK) {
+                    warn!("Image readback stalls the pipeline on this driver");
+                }
                 let gl = &self.share.context;
                 gl.active_texture(glow::TEXTURE0);
                 gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(buffer));
@@ -650,14 +1023,36 @@ impl CommandQueue {
             com::Command::CopyImageToSurface(..) => {
                 unimplemented!() //TODO: use FBO
             }
+            com::Command::BindBufferRange(glow::UNIFORM_BUFFER, index, buffer, offset, size)
+                if self.share.private_caps.emulate_uniform_blocks =>
+            {
+                // Uploaded through plain uniforms before draws.
+                let index = index as usize;
+                if self.state.uniform_blocks.len() <= index {
+                    self.state.uniform_blocks.resize(index + 1, None);
+                }
+                self.state.uniform_blocks[index] = Some((buffer, offset, size));
+            }
             com::Command::BindBufferRange(target, index, buffer, offset, size) => unsafe {
                 let gl = &self.share.context;
                 gl.bind_buffer_range(target, index, Some(buffer), offset, size);
             },
-            com::Command::BindTexture(index, texture, textype) => unsafe {
+            com::Command::BindTexture(index, texture, textype, levels) => unsafe {
                 let gl = &self.share.context;
                 gl.active_texture(glow::TEXTURE0 + index);
                 gl.bind_texture(textype, Some(texture));
+                // Views of the same texture share its level range, it's set for each bind.
+                match levels {
+                    Some(levels) if self.share.private_caps.texture_level_range => {
+                        gl.tex_parameter_i32(textype, glow::TEXTURE_BASE_LEVEL, levels.start as _);
+                        let max_level = levels.end as i32 - 1;
+                        gl.tex_parameter_i32(textype, glow::TEXTURE_MAX_LEVEL, max_level);
+                    }
+                    Some(levels) if levels.start != 0 => {
+                        warn!("Sampling level {} requires texture level ranges", levels.start);
+                    }
+                    _ => {}
+                }
             },
             com::Command::BindSampler(index, sampler) => unsafe {
                 let gl = &self.share.context;
@@ -821,11 +1216,32 @@ impl CommandQueue {
                 unsafe { gl.polygon_mode(glow::FRONT_AND_BACK, gl_draw) };
 
                 match rasterizer.depth_bias {
-                    Some(hal::pso::State::Static(bias)) => unsafe {
-                        gl.enable(gl_offset);
-                        gl.polygon_offset(bias.slope_factor as _, bias.const_factor as _);
-                    },
-                    _ => unsafe { gl.disable(gl_offset) },
+                    Some(hal::pso::State::Static(bias)) => {
+                        unsafe { gl.enable(gl_offset) };
+                        self.share.polygon_offset(bias);
+                    }
+                    // Set by `set_depth_bias`.
+                    Some(hal::pso::State::Dynamic) => unsafe { gl.enable(gl_offset) },
+                    None => unsafe { gl.disable(gl_offset) },
+                }
+
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    if self.share.private_caps.conservative_raster {
+                        let cap = crate::raster::CONSERVATIVE_RASTERIZATION_NV;
+                        match rasterizer.conservative {
+                            true => unsafe { gl.enable(cap) },
+                            false => unsafe { gl.disable(cap) },
+                        }
+                    } else if rasterizer.conservative {
+                        warn!("Conservative rasterization is not supported");
+                    }
+                }
+                #[cfg(target_arch = "wasm32")]
+                {
+                    if rasterizer.conservative {
+                        warn!("Conservative rasterization is not supported");
+                    }
                 }
 
                 if !self.share.info.is_webgl() && !self.share.info.version.is_embedded {
@@ -918,8 +1334,106 @@ impl CommandQueue {
               },
               */
         }
-        if let Err(err) = self.share.check() {
-            panic!("Error {:?} executing command: {:?}", err, cmd)
+        match self.share.check() {
+            Ok(()) => {}
+            Err(Error::ContextLost) => {
+                error!("Context lost executing command: {:?}", cmd);
+                self.share.device_lost.set(true);
+            }
+            Err(err) => panic!("Error {:?} executing command: {:?}", err, cmd),
+        }
+    }
+
+    /// Upload the members of flattened uniform blocks from the client-side
+    /// copy of the bound block memory, skipping the ones which are unchanged
+    /// since the last upload.
+    fn upload_block_uniforms(&self) {
+        let block_uniforms = match self.state.block_uniforms {
+            Some(ref block_uniforms) => block_uniforms,
+            None => return,
+        };
+        let gl = &self.share.context;
+        let shadows = self.share.buffer_shadows.borrow();
+        let mut uploaded = block_uniforms.uploaded.lock().unwrap();
+        for (uniform, uploaded) in block_uniforms.uniforms.iter().zip(uploaded.iter_mut()) {
+            let binding = uniform.binding as usize;
+            let (buffer, offset, size) = match self.state.uniform_blocks.get(binding) {
+                Some(&Some(range)) => range,
+                _ => {
+                    warn!("Uniform block {} is not bound", uniform.binding);
+                    continue;
+                }
+            };
+            let memory = match shadows.get(&buffer) {
+                Some(shadow) => &shadow[offset as usize..(offset + size) as usize],
+                None => {
+                    warn!("Uniform block {} has no client-side copy", uniform.binding);
+                    continue;
+                }
+            };
+
+            // `std140` aligns array elements and matrix columns to 16 bytes.
+            let (columns, rows) = match uniform.utype {
+                glow::FLOAT | glow::INT => (1, 1),
+                glow::FLOAT_VEC2 | glow::INT_VEC2 => (1, 2),
+                glow::FLOAT_VEC3 | glow::INT_VEC3 => (1, 3),
+                glow::FLOAT_VEC4 | glow::INT_VEC4 => (1, 4),
+                glow::FLOAT_MAT2 => (2, 2),
+                glow::FLOAT_MAT3 => (3, 3),
+                glow::FLOAT_MAT4 => (4, 4),
+                other => {
+                    warn!("Uniform block member type {:#x} is not supported", other);
+                    continue;
+                }
+            };
+            let mut values = Vec::with_capacity((uniform.count * columns * rows * 4) as usize);
+            for element in 0..uniform.count {
+                for column in 0..columns {
+                    let start = (uniform.offset + (element * columns + column) * 16) as usize;
+                    match memory.get(start..start + rows as usize * 4) {
+                        Some(bytes) => values.extend_from_slice(bytes),
+                        None => values.extend((0..rows * 4).map(|_| 0)),
+                    }
+                }
+            }
+            if *uploaded == values {
+                continue;
+            }
+
+            let location = Some(uniform.location);
+            let words = values.chunks(4).map(|word| [word[0], word[1], word[2], word[3]]);
+            unsafe {
+                match uniform.utype {
+                    glow::INT | glow::INT_VEC2 | glow::INT_VEC3 | glow::INT_VEC4 => {
+                        let mut ints = words.map(i32::from_ne_bytes).collect::<Vec<_>>();
+                        match uniform.utype {
+                            glow::INT => gl.uniform_1_i32_slice(location, &mut ints),
+                            glow::INT_VEC2 => gl.uniform_2_i32_slice(location, &mut ints),
+                            glow::INT_VEC3 => gl.uniform_3_i32_slice(location, &mut ints),
+                            _ => gl.uniform_4_i32_slice(location, &mut ints),
+                        }
+                    }
+                    _ => {
+                        let mut floats = words
+                            .map(|word| f32::from_bits(u32::from_ne_bytes(word)))
+                            .collect::<Vec<_>>();
+                        match uniform.utype {
+                            glow::FLOAT => gl.uniform_1_f32_slice(location, &mut floats),
+                            glow::FLOAT_VEC2 => gl.uniform_2_f32_slice(location, &mut floats),
+                            glow::FLOAT_VEC3 => gl.uniform_3_f32_slice(location, &mut floats),
+                            glow::FLOAT_VEC4 => gl.uniform_4_f32_slice(location, &mut floats),
+                            glow::FLOAT_MAT2 => {
+                                gl.uniform_matrix_2_f32_slice(location, false, &floats)
+                            }
+                            glow::FLOAT_MAT3 => {
+                                gl.uniform_matrix_3_f32_slice(location, false, &floats)
+                            }
+                            _ => gl.uniform_matrix_4_f32_slice(location, false, &floats),
+                        }
+                    }
+                }
+            }
+            *uploaded = values;
         }
     }
 
@@ -950,6 +1464,24 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
         Is: IntoIterator<Item = &'a S>,
     {
         use crate::pool::BufferMemory;
+
+        if self.share.check_device_lost().is_err() {
+            error!("Skipping submission, the device is lost");
+            return;
+        }
+        self.activate();
+        self.share.collect_deletions();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        for (semaphore, _) in submit_info.wait_semaphores {
+            if let Some(raw) = semaphore.borrow().external {
+                self.share.context.external.wait_semaphore(raw);
+            }
+        }
+
+        #[cfg(all(not(target_arch = "wasm32"), feature = "timings"))]
+        self.timings.begin(&self.share);
+
         {
             for buf in submit_info.command_buffers {
                 let cb = buf.borrow();
@@ -967,50 +1499,145 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
                 let commands = &buffer.commands
                     [cb.buf.offset as usize..(cb.buf.offset + cb.buf.size) as usize];
                 self.reset_state();
-                for com in commands {
-                    self.process(com, &buffer.data);
+                let mut i = 0;
+                while i < commands.len() && !self.share.device_lost.get() {
+                    // Clears are discarded too, so discarding is only enabled for draws.
+                    let discard = self.state.rasterizer_discard && is_draw(&commands[i]);
+                    if discard {
+                        unsafe { self.share.context.enable(glow::RASTERIZER_DISCARD) };
+                    }
+                    if is_draw(&commands[i]) {
+                        self.upload_block_uniforms();
+                    }
+                    // Advanced blending reads the framebuffer, overlapping draws
+                    // are separated by barriers and can't be merged.
+                    let blend_barrier = self.state.advanced_blend
+                        && self.share.needs_blend_barrier()
+                        && is_draw(&commands[i]);
+                    if blend_barrier {
+                        self.share.blend_barrier();
+                    }
+                    match if blend_barrier { 0 } else { self.multi_draw(&commands[i..]) } {
+                        0 => {
+                            self.process(&commands[i], &buffer.data);
+                            i += 1;
+                        }
+                        count => i += count,
+                    }
+                    if discard {
+                        unsafe { self.share.context.disable(glow::RASTERIZER_DISCARD) };
+                    }
                 }
             }
         }
-        fence.map(|fence| self.signal_fence(fence));
+
+        #[cfg(all(not(target_arch = "wasm32"), feature = "timings"))]
+        self.timings.end(&self.share);
+
+        if self.share.check_device_lost().is_err() {
+            return;
+        }
+        self.share.end_submission();
+        self.staging.fence(&self.share);
+        self.belt.fence(&self.share);
+
+        // Batched submissions are only flushed when they are waited on,
+        // presenting flushes implicitly.
+        let mut flush = !self.share.batch_submissions;
+        #[cfg(not(target_arch = "wasm32"))]
+        for semaphore in submit_info.signal_semaphores {
+            if let Some(raw) = semaphore.borrow().external {
+                self.share.context.external.signal_semaphore(raw);
+                // Make sure the signal reaches the driver for the other API to see it.
+                flush = true;
+            }
+        }
+        if let Some(fence) = fence {
+            self.signal_fence(fence);
+            flush = true;
+        }
+        if flush {
+            self.share.context.flush();
+        }
     }
 
-    #[cfg(all(not(target_arch = "wasm32"), feature = "glutin"))]
+    #[cfg(all(
+        not(target_arch = "wasm32"),
+        any(
+            feature = "glutin",
+            feature = "egl",
+            feature = "sdl2",
+            feature = "wgl",
+            feature = "glx"
+        )
+    ))]
     unsafe fn present<'a, W, Is, S, Iw>(
         &mut self,
         swapchains: Is,
         _wait_semaphores: Iw,
     ) -> Result<Option<hal::window::Suboptimal>, hal::window::PresentError>
     where
-        W: 'a + Borrow<window::glutin::Swapchain>,
+        W: 'a + Borrow<crate::Swapchain>,
         Is: IntoIterator<Item = (&'a W, hal::SwapImageIndex)>,
         S: 'a + Borrow<native::Semaphore>,
         Iw: IntoIterator<Item = &'a S>,
     {
+        self.share
+            .check_device_lost()
+            .map_err(hal::window::PresentError::DeviceLost)?;
+
         let gl = &self.share.context;
+        // Same-size blit, `NEAREST` yields identical results.
+        let filter = if self.share.quirks.contains(Quirks::BLIT_NEAREST_ONLY) {
+            glow::NEAREST
+        } else {
+            glow::LINEAR
+        };
+
+        // Copy the images as they are, sRGB ones already hold encoded values.
+        // Render passes enable encoding again if they need it.
+        if self.share.legacy_features.contains(LegacyFeatures::SRGB_COLOR) {
+            gl.disable(glow::FRAMEBUFFER_SRGB);
+        }
+
+        let mut suboptimal = None;
+        for (swapchain, index) in swapchains {
+            let swapchain = swapchain.borrow();
+            let extent = swapchain.extent;
+            // Presented, but stretched or cropped to the resized window.
+            if swapchain.window_extent() != extent {
+                suboptimal = Some(hal::window::Suboptimal);
+            }
+
+            // Rendered to the default framebuffer already.
+            if !swapchain.images.is_direct(index) {
+                let framebuffer = swapchain.images.framebuffer(index).or(self.state.fbo);
+                let (dst_y0, dst_y1) = self.share.y_flip.present_rows(extent.height as _);
+                gl.bind_framebuffer(glow::READ_FRAMEBUFFER, framebuffer);
+                gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, None);
+                gl.blit_framebuffer(
+                    0,
+                    0,
+                    extent.width as _,
+                    extent.height as _,
+                    0,
+                    dst_y0,
+                    extent.width as _,
+                    dst_y1,
+                    glow::COLOR_BUFFER_BIT,
+                    filter,
+                );
+            }
 
-        for swapchain in swapchains {
-            let extent = swapchain.0.borrow().extent;
-
-            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, self.state.fbo);
-            gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, None);
-            gl.blit_framebuffer(
-                0,
-                0,
-                extent.width as _,
-                extent.height as _,
-                0,
-                0,
-                extent.width as _,
-                extent.height as _,
-                glow::COLOR_BUFFER_BIT,
-                glow::LINEAR,
-            );
-
-            swapchain.0.borrow().window.swap_buffers().unwrap();
+            swapchain.images.presented(index);
+            swapchain.swap_buffers();
         }
 
-        Ok(None)
+        // Swapping waits for the GPU on some drivers, which notice resets then.
+        self.share
+            .check_device_lost()
+            .map_err(hal::window::PresentError::DeviceLost)?;
+        Ok(suboptimal)
     }
 
     // TODO: Share most of this implementation with `glutin`
@@ -1026,34 +1653,64 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
         S: 'a + Borrow<native::Semaphore>,
         Iw: IntoIterator<Item = &'a S>,
     {
+        self.share
+            .check_device_lost()
+            .map_err(hal::window::PresentError::DeviceLost)?;
+
         let gl = &self.share.context;
+        // Same-size blit, `NEAREST` yields identical results.
+        let filter = if self.share.quirks.contains(Quirks::BLIT_NEAREST_ONLY) {
+            glow::NEAREST
+        } else {
+            glow::LINEAR
+        };
 
-        for swapchain in swapchains {
-            let extent = swapchain.0.borrow().extent;
-
-            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, self.state.fbo);
-            gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, None);
-            gl.blit_framebuffer(
-                0,
-                0,
-                extent.width as _,
-                extent.height as _,
-                0,
-                0,
-                extent.width as _,
-                extent.height as _,
-                glow::COLOR_BUFFER_BIT,
-                glow::LINEAR,
-            );
+        let mut suboptimal = None;
+        for (swapchain, index) in swapchains {
+            let swapchain = swapchain.borrow();
+            let extent = swapchain.extent;
+            // Presented, but stretched or cropped to the resized canvas.
+            if swapchain.window_extent() != extent {
+                suboptimal = Some(hal::window::Suboptimal);
+            }
+
+            // Rendered to the canvas already, WebGL 1 can't blit.
+            if !swapchain.images.is_direct(index) {
+                let framebuffer = swapchain.images.framebuffer(index).or(self.state.fbo);
+                gl.bind_framebuffer(glow::READ_FRAMEBUFFER, framebuffer);
+                match (&swapchain.xr_framebuffer, &self.share.context.webgl2) {
+                    (Some(framebuffer), Some(raw)) => {
+                        raw.bind_framebuffer(glow::DRAW_FRAMEBUFFER, Some(framebuffer))
+                    }
+                    _ => gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, None),
+                }
+                let (dst_y0, dst_y1) = self.share.y_flip.present_rows(extent.height as _);
+                gl.blit_framebuffer(
+                    0,
+                    0,
+                    extent.width as _,
+                    extent.height as _,
+                    0,
+                    dst_y0,
+                    extent.width as _,
+                    dst_y1,
+                    glow::COLOR_BUFFER_BIT,
+                    filter,
+                );
+            } else if swapchain.xr_framebuffer.is_some() {
+                warn!("XR framebuffers can't be presented to without WebGL 2");
+            }
+
+            swapchain.images.presented(index);
+            swapchain.schedule_frame();
         }
 
-        Ok(None)
+        Ok(suboptimal)
     }
 
     fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
-        unsafe {
-            self.share.context.finish();
-        }
-        Ok(())
+        self.share
+            .wait_idle()
+            .map_err(|_| error::HostExecutionError::DeviceLost)
     }
 }
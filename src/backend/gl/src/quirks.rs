@@ -0,0 +1,98 @@
+//! Known driver bugs and the workarounds applied for them.
+//!
+//! Quirks are matched against the `Info` of a context when the adapter is
+//! created, and can be queried or overridden on the `PhysicalDevice` before
+//! a logical device is opened.
+
+use crate::info::{Info, Requirement::{Es, Ext}};
+
+bitflags! {
+    /// Driver bugs and performance pitfalls the backend works around.
+    pub struct Quirks: u32 {
+        /// `glBufferStorage` is exposed but persistent mappings are corrupted
+        /// (Mali Midgard drivers). Buffers are allocated with `glBufferData`.
+        const BROKEN_BUFFER_STORAGE = 0x00000001;
+        /// Reading images back through pixel pack buffers stalls the
+        /// pipeline (ANGLE on D3D, Adreno).
+        const SLOW_READBACK = 0x00000002;
+        /// Framebuffer blits only support `NEAREST` filtering (ANGLE on ES 2.0).
+        const BLIT_NEAREST_ONLY = 0x00000004;
+        /// Upload formats are missing (BGRA or R8 on GLES without
+        /// `GL_EXT_texture_format_BGRA8888` or `GL_EXT_texture_rg`). Texels are
+        /// converted on the CPU in `copy_buffer_to_image`, RGB8 images are
+        /// stored as RGBA8.
+        const CONVERT_UPLOADS = 0x00000008;
+    }
+}
+
+/// Detect the quirks of the implementation described by `info`.
+pub(crate) fn detect(info: &Info) -> Quirks {
+    let renderer = &info.platform_name.renderer;
+    let mut quirks = Quirks::empty();
+
+    if renderer.contains("Mali-T") {
+        quirks |= Quirks::BROKEN_BUFFER_STORAGE;
+    }
+    if info.is_angle_d3d() || renderer.contains("Adreno") {
+        quirks |= Quirks::SLOW_READBACK;
+    }
+    if info.is_angle() && !info.is_supported(&[Es(3, 0)]) {
+        quirks |= Quirks::BLIT_NEAREST_ONLY;
+    }
+    if info.version.is_embedded
+        && !(info.is_extension_supported("GL_EXT_texture_format_BGRA8888")
+            && info.is_supported(&[Es(3, 0), Ext("GL_EXT_texture_rg")]))
+    {
+        quirks |= Quirks::CONVERT_UPLOADS;
+    }
+
+    quirks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect, Quirks};
+    use crate::info::{Info, PlatformName, Version};
+    use std::collections::HashSet;
+
+    fn info(renderer: &str, version: Version) -> Info {
+        Info {
+            platform_name: PlatformName {
+                vendor: String::new(),
+                renderer: renderer.to_string(),
+            },
+            shading_language: version.clone(),
+            version,
+            profile: None,
+            extensions: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        let es2 = Version::new_embedded(2, 0, String::new());
+        let es3 = Version::new_embedded(3, 0, String::new());
+        let gl3 = Version::new(3, 3, None, String::new());
+        let convert = Quirks::CONVERT_UPLOADS;
+        assert_eq!(
+            detect(&info("Mali-T860", es3.clone())),
+            Quirks::BROKEN_BUFFER_STORAGE | convert
+        );
+        assert_eq!(detect(&info("Mali-G78", es3.clone())), convert);
+        assert_eq!(
+            detect(&info("Adreno (TM) 540", es3.clone())),
+            Quirks::SLOW_READBACK | convert
+        );
+        assert_eq!(
+            detect(&info("ANGLE (Direct3D9Ex)", es2)),
+            Quirks::SLOW_READBACK | Quirks::BLIT_NEAREST_ONLY | convert
+        );
+        assert_eq!(detect(&info("ANGLE (Vulkan)", es3)), convert);
+        assert_eq!(detect(&info("GeForce GTX 1080", gl3.clone())), Quirks::empty());
+        assert_eq!(detect(&info("D3D12 (Intel(R) UHD Graphics 620)", gl3)), Quirks::empty());
+
+        let mut bgra = info("Mali-G78", Version::new_embedded(3, 0, String::new()));
+        bgra.extensions.insert("GL_EXT_texture_format_BGRA8888".to_string());
+        assert_eq!(detect(&bgra), Quirks::empty());
+    }
+}
@@ -0,0 +1,44 @@
+//! Rasterizer state entry points not covered by `glow`.
+
+use std::os::raw::c_void;
+
+use crate::fns;
+
+type PolygonOffsetClamp = unsafe extern "system" fn(f32, f32, f32);
+type ClipControl = unsafe extern "system" fn(u32, u32);
+type ProvokingVertex = unsafe extern "system" fn(u32);
+
+/// Capability of `GL_NV_conservative_raster`, rasterizing every pixel which is
+/// partially covered by a primitive.
+pub(crate) const CONSERVATIVE_RASTERIZATION_NV: u32 = 0x9346;
+
+/// Entry points of `GL_ARB_polygon_offset_clamp`, `GL_ARB_clip_control` and
+/// `GL_ARB_provoking_vertex`.
+#[derive(Default)]
+pub(crate) struct RasterFns {
+    /// Sets the depth bias like `glPolygonOffset`, clamped to a maximum bias.
+    pub(crate) polygon_offset_clamp: Option<PolygonOffsetClamp>,
+    /// Sets the origin of window coordinates and the depth range of clip space.
+    pub(crate) clip_control: Option<ClipControl>,
+    /// Selects the vertex of a primitive which flat shaded attributes are taken from.
+    pub(crate) provoking_vertex: Option<ProvokingVertex>,
+}
+
+impl RasterFns {
+    pub(crate) fn load<F>(fn_proc: &mut F) -> Self
+    where F: FnMut(&str) -> *const c_void {
+        unsafe {
+            RasterFns {
+                polygon_offset_clamp: fns::load(
+                    fn_proc,
+                    &["glPolygonOffsetClamp", "glPolygonOffsetClampEXT"],
+                ),
+                clip_control: fns::load(fn_proc, &["glClipControl", "glClipControlEXT"]),
+                provoking_vertex: fns::load(
+                    fn_proc,
+                    &["glProvokingVertex", "glProvokingVertexEXT"],
+                ),
+            }
+        }
+    }
+}
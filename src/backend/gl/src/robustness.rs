@@ -0,0 +1,44 @@
+//! Graphics reset queries of `GL_KHR_robustness`, not covered by `glow`.
+//!
+//! Drivers exposing only `GL_ARB_robustness` or `GL_EXT_robustness` export
+//! the entry point with the suffix of their extension.
+
+use std::os::raw::c_void;
+
+use crate::fns;
+
+type GetGraphicsResetStatus = unsafe extern "system" fn() -> u32;
+
+/// The reset was caused by this context.
+pub(crate) const GUILTY_CONTEXT_RESET: u32 = 0x8253;
+/// The reset was caused by another context.
+pub(crate) const INNOCENT_CONTEXT_RESET: u32 = 0x8254;
+
+/// Entry points of `GL_KHR_robustness` and its predecessors.
+#[derive(Default)]
+pub(crate) struct RobustnessFns {
+    /// Returns the reset status of the context, `GL_NO_ERROR` without a reset.
+    get_graphics_reset_status: Option<GetGraphicsResetStatus>,
+}
+
+impl RobustnessFns {
+    pub(crate) fn load<F>(fn_proc: &mut F) -> Self
+    where F: FnMut(&str) -> *const c_void {
+        let names = [
+            "glGetGraphicsResetStatus",
+            "glGetGraphicsResetStatusKHR",
+            "glGetGraphicsResetStatusARB",
+            "glGetGraphicsResetStatusEXT",
+        ];
+        let get_graphics_reset_status = unsafe { fns::load(fn_proc, &names) };
+
+        RobustnessFns {
+            get_graphics_reset_status,
+        }
+    }
+
+    /// Reset status of the context, `None` if it can't be queried.
+    pub(crate) fn reset_status(&self) -> Option<u32> {
+        self.get_graphics_reset_status.map(|get_status| unsafe { get_status() })
+    }
+}
@@ -0,0 +1,348 @@
+//! Staging ring buffer of a command queue.
+//!
+//! Data recorded into command buffers, e.g. by `update_buffer`, is written to
+//! a ring buffer and copied to its destination on the GPU. Writing into a
+//! buffer with `glBufferSubData` while previous draws may still read from it
+//! stalls the pipeline on most drivers, while the copy is ordered like any
+//! other command.
+//!
+//! The writes of each submission are followed by a fence, regions are reused
+//! once it is signaled. The ring is persistently mapped with
+//! `GL_ARB_buffer_storage`, otherwise unused regions are mapped unsynchronized.
+//!
+//! Push constants are set with `glUniform*`, which doesn't touch buffer
+//! memory and doesn't need the ring.
+//!
+//! Texels uploaded from client memory, e.g. after converting them on the CPU
+//! for formats the driver lacks, go through a belt of pixel buffers instead.
+//! `glTexSubImage2D` copies client memory before returning, while uploads
+//! from a pixel buffer are transferred asynchronously. Chunks are recycled
+//! with fences like the ring, but the belt grows with large uploads.
+
+use std::collections::VecDeque;
+use std::ptr;
+
+use glow::Context;
+
+use crate::info::LegacyFeatures;
+use crate::{native as n, GlContext, Share};
+
+type GlFence = <GlContext as glow::Context>::Fence;
+
+/// Size of the ring, larger writes go to their destination directly.
+const RING_SIZE: u32 = 4 << 20;
+/// Alignment of writes, matching the one of `update_buffer` offsets.
+const WRITE_ALIGNMENT: u32 = 4;
+/// Timeout of each wait for a region to be released, in nanoseconds.
+const WAIT_TIMEOUT: i32 = 1_000_000_000;
+/// Minimal size of belt chunks.
+const CHUNK_SIZE: u32 = 4 << 20;
+
+#[derive(Debug)]
+pub(crate) struct StagingRing {
+    // Allocated on first use, most queues never write through the ring.
+    buffer: Option<n::RawBuffer>,
+    // Persistently mapped memory of the ring.
+    mapping: Option<*mut u8>,
+    // Offset of the next write.
+    head: u32,
+    // Bytes in use by written regions, including the skipped end of the ring.
+    used: u32,
+    // Bytes written since the last fence.
+    pending: u32,
+    // Fences of submitted regions with their sizes, oldest first.
+    fences: VecDeque<(GlFence, u32)>,
+}
+
+unsafe impl Send for StagingRing {}
+unsafe impl Sync for StagingRing {}
+
+impl StagingRing {
+    pub(crate) fn new() -> Self {
+        StagingRing {
+            buffer: None,
+            mapping: None,
+            head: 0,
+            used: 0,
+            pending: 0,
+            fences: VecDeque::new(),
+        }
+    }
+
+    /// Whether the ring is used, copies between buffers are required.
+    pub(crate) fn is_supported(share: &Share) -> bool {
+        share.legacy_features.contains(LegacyFeatures::COPY_BUFFER)
+    }
+
+    /// Write `data` to the ring, returning the buffer and the offset to copy from.
+    ///
+    /// Returns `None` if the ring isn't supported or too small.
+    pub(crate) unsafe fn write(
+        &mut self,
+        share: &Share,
+        data: &[u8],
+    ) -> Option<(n::RawBuffer, u32)> {
+        let size = align(data.len() as u32);
+        if !Self::is_supported(share) || size > RING_SIZE {
+            return None;
+        }
+        let buffer = self.allocate(share);
+        let start = self.reserve(share, size);
+
+        let gl = &share.context;
+        match self.mapping {
+            Some(mapping) => {
+                ptr::copy_nonoverlapping(data.as_ptr(), mapping.add(start as usize), data.len());
+            }
+            None => {
+                gl.bind_buffer(glow::COPY_READ_BUFFER, Some(buffer));
+                if share.private_caps.map {
+                    // The region is released, there is nothing to synchronize with.
+                    let flags = glow::MAP_WRITE_BIT
+                        | glow::MAP_UNSYNCHRONIZED_BIT
+                        | glow::MAP_INVALIDATE_RANGE_BIT;
+                    let len = data.len() as i32;
+                    let raw = gl.map_buffer_range(glow::COPY_READ_BUFFER, start as i32, len, flags);
+                    ptr::copy_nonoverlapping(data.as_ptr(), raw, data.len());
+                    gl.unmap_buffer(glow::COPY_READ_BUFFER);
+                } else {
+                    gl.buffer_sub_data_u8_slice(glow::COPY_READ_BUFFER, start as i32, data);
+                }
+                gl.bind_buffer(glow::COPY_READ_BUFFER, None);
+            }
+        }
+
+        Some((buffer, start))
+    }
+
+    /// Insert a fence after the writes of a submission.
+    pub(crate) unsafe fn fence(&mut self, share: &Share) {
+        if self.pending == 0 || !share.private_caps.sync {
+            return;
+        }
+        let sync = share
+            .context
+            .fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0)
+            .unwrap();
+        self.fences.push_back((sync, self.pending));
+        self.pending = 0;
+    }
+
+    unsafe fn allocate(&mut self, share: &Share) -> n::RawBuffer {
+        if let Some(buffer) = self.buffer {
+            return buffer;
+        }
+        let gl = &share.context;
+        let buffer = gl.create_buffer().unwrap();
+        gl.bind_buffer(glow::COPY_READ_BUFFER, Some(buffer));
+        // Persistent mappings are released by fences only.
+        if share.buffer_storage() && share.private_caps.sync {
+            let flags = glow::MAP_WRITE_BIT | glow::MAP_PERSISTENT_BIT | glow::MAP_COHERENT_BIT;
+            gl.buffer_storage(glow::COPY_READ_BUFFER, RING_SIZE as i32, None, flags);
+            let raw = gl.map_buffer_range(glow::COPY_READ_BUFFER, 0, RING_SIZE as i32, flags);
+            self.mapping = Some(raw);
+        } else {
+            gl.buffer_data_size(glow::COPY_READ_BUFFER, RING_SIZE as i32, glow::STREAM_DRAW);
+        }
+        gl.bind_buffer(glow::COPY_READ_BUFFER, None);
+
+        if let Err(err) = share.check() {
+            panic!("Error allocating staging ring: {:?}", err);
+        }
+        self.buffer = Some(buffer);
+        buffer
+    }
+
+    /// Reserve `size` bytes, waiting for submitted regions to be released.
+    unsafe fn reserve(&mut self, share: &Share, size: u32) -> u32 {
+        loop {
+            // Writes are contiguous, skip the end of the ring if it doesn't fit.
+            let (start, skipped) = if self.head + size > RING_SIZE {
+                (0, RING_SIZE - self.head)
+            } else {
+                (self.head, 0)
+            };
+            if self.used + skipped + size <= RING_SIZE {
+                self.head = start + size;
+                self.used += skipped + size;
+                self.pending += skipped + size;
+                return start;
+            }
+            self.release_oldest(share);
+        }
+    }
+
+    /// Wait for the oldest region to be released.
+    unsafe fn release_oldest(&mut self, share: &Share) {
+        let gl = &share.context;
+        if !share.private_caps.sync {
+            // Orphan the storage, the driver keeps the old one while it's in use.
+            let buffer = self.buffer;
+            gl.bind_buffer(glow::COPY_READ_BUFFER, buffer);
+            gl.buffer_data_size(glow::COPY_READ_BUFFER, RING_SIZE as i32, glow::STREAM_DRAW);
+            gl.bind_buffer(glow::COPY_READ_BUFFER, None);
+            self.head = 0;
+            self.used = 0;
+            self.pending = 0;
+            return;
+        }
+        // The writes of the current submission fill the ring.
+        if self.fences.is_empty() {
+            self.fence(share);
+        }
+        let (sync, size) = self.fences.pop_front().unwrap();
+        // Only the first wait has to flush the commands.
+        let mut flags = glow::SYNC_FLUSH_COMMANDS_BIT;
+        loop {
+            match gl.client_wait_sync(sync, flags, WAIT_TIMEOUT) {
+                // Fences of a reset context are never signaled, nothing reads
+                // the region anymore once the device is lost.
+                glow::TIMEOUT_EXPIRED if share.check_device_lost().is_ok() => {
+                    flags = 0;
+                    continue;
+                }
+                glow::TIMEOUT_EXPIRED => error!("Device lost when waiting on staging ring"),
+                glow::WAIT_FAILED => error!("Error when waiting on staging ring"),
+                _ => {}
+            }
+            break;
+        }
+        gl.delete_sync(sync);
+        self.used -= size;
+    }
+}
+
+#[derive(Debug)]
+struct Chunk {
+    buffer: n::RawBuffer,
+    size: u32,
+    // Offset of the next write.
+    offset: u32,
+    // Whether the chunk is written by the current submission.
+    active: bool,
+    // Signaled once the submission which wrote the chunk finished.
+    fence: Option<GlFence>,
+}
+
+/// Pixel buffers for texel uploads of a command queue.
+#[derive(Debug)]
+pub(crate) struct StagingBelt {
+    chunks: Vec<Chunk>,
+}
+
+unsafe impl Send for StagingBelt {}
+unsafe impl Sync for StagingBelt {}
+
+impl StagingBelt {
+    pub(crate) fn new() -> Self {
+        StagingBelt { chunks: Vec::new() }
+    }
+
+    /// Write texels to a pixel buffer, returning the buffer and the offset to upload from.
+    ///
+    /// Returns `None` if texels are uploaded from client memory.
+    pub(crate) unsafe fn write(
+        &mut self,
+        share: &Share,
+        data: &[u8],
+    ) -> Option<(n::RawBuffer, u32)> {
+        if !share.pbo_uploads() {
+            return None;
+        }
+        let size = align(data.len() as u32);
+        let index = self.find_chunk(share, size);
+        let chunk = &mut self.chunks[index];
+        let offset = chunk.offset;
+        chunk.offset += size;
+        chunk.active = true;
+
+        let gl = &share.context;
+        gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, Some(chunk.buffer));
+        if share.private_caps.map {
+            // Released chunks aren't in use, there is nothing to synchronize with.
+            let flags =
+                glow::MAP_WRITE_BIT | glow::MAP_UNSYNCHRONIZED_BIT | glow::MAP_INVALIDATE_RANGE_BIT;
+            let len = data.len() as i32;
+            let raw = gl.map_buffer_range(glow::PIXEL_UNPACK_BUFFER, offset as i32, len, flags);
+            ptr::copy_nonoverlapping(data.as_ptr(), raw, data.len());
+            gl.unmap_buffer(glow::PIXEL_UNPACK_BUFFER);
+        } else {
+            gl.buffer_sub_data_u8_slice(glow::PIXEL_UNPACK_BUFFER, offset as i32, data);
+        }
+        gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, None);
+
+        Some((chunk.buffer, offset))
+    }
+
+    /// Insert fences after the writes of a submission.
+    pub(crate) unsafe fn fence(&mut self, share: &Share) {
+        let gl = &share.context;
+        for chunk in self.chunks.iter_mut().filter(|chunk| chunk.active) {
+            chunk.active = false;
+            if share.private_caps.sync {
+                let sync = gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0).unwrap();
+                chunk.fence = Some(sync);
+            }
+        }
+    }
+
+    /// Find a chunk with `size` free bytes, recycling or allocating one if needed.
+    unsafe fn find_chunk(&mut self, share: &Share, size: u32) -> usize {
+        let gl = &share.context;
+        if let Some(index) = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.active && chunk.offset + size <= chunk.size)
+        {
+            return index;
+        }
+
+        for (index, chunk) in self.chunks.iter_mut().enumerate() {
+            if chunk.active || chunk.size < size {
+                continue;
+            }
+            match chunk.fence {
+                Some(sync) => {
+                    let status = gl.client_wait_sync(sync, 0, 0);
+                    if status == glow::TIMEOUT_EXPIRED {
+                        continue;
+                    }
+                    gl.delete_sync(sync);
+                    chunk.fence = None;
+                }
+                // Orphan the storage, the driver keeps the old one while it's in use.
+                None if !share.private_caps.sync => {
+                    gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, Some(chunk.buffer));
+                    let len = chunk.size as i32;
+                    gl.buffer_data_size(glow::PIXEL_UNPACK_BUFFER, len, glow::STREAM_DRAW);
+                    gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, None);
+                }
+                None => {}
+            }
+            chunk.offset = 0;
+            return index;
+        }
+
+        let size = size.max(CHUNK_SIZE);
+        let buffer = gl.create_buffer().unwrap();
+        gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, Some(buffer));
+        gl.buffer_data_size(glow::PIXEL_UNPACK_BUFFER, size as i32, glow::STREAM_DRAW);
+        gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, None);
+        if let Err(err) = share.check() {
+            panic!("Error allocating staging belt chunk: {:?}", err);
+        }
+        info!("Allocated staging belt chunk of {} bytes", size);
+        self.chunks.push(Chunk {
+            buffer,
+            size,
+            offset: 0,
+            active: false,
+            fence: None,
+        });
+        self.chunks.len() - 1
+    }
+}
+
+fn align(size: u32) -> u32 {
+    (size + WRITE_ALIGNMENT - 1) / WRITE_ALIGNMENT * WRITE_ALIGNMENT
+}
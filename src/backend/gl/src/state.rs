@@ -131,6 +131,56 @@ fn map_blend_op(operation: pso::BlendOp) -> (u32, u32, u32) {
     }
 }
 
+/// Blend equations of `KHR_blend_equation_advanced`, which replace the blend
+/// operations and factors of the bound pipeline, see
+/// `RawCommandBuffer::set_advanced_blend`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AdvancedBlendOp {
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    HslHue,
+    HslSaturation,
+    HslColor,
+    HslLuminosity,
+}
+
+fn map_advanced_blend_op(operation: AdvancedBlendOp) -> u32 {
+    use self::AdvancedBlendOp::*;
+    match operation {
+        Multiply => 0x9294,
+        Screen => 0x9295,
+        Overlay => 0x9296,
+        Darken => 0x9297,
+        Lighten => 0x9298,
+        ColorDodge => 0x9299,
+        ColorBurn => 0x929A,
+        HardLight => 0x929B,
+        SoftLight => 0x929C,
+        Difference => 0x929E,
+        Exclusion => 0x92A0,
+        HslHue => 0x92AD,
+        HslSaturation => 0x92AE,
+        HslColor => 0x92AF,
+        HslLuminosity => 0x92B0,
+    }
+}
+
+pub(crate) fn bind_advanced_blend(gl: &GlContainer, operation: AdvancedBlendOp) {
+    unsafe {
+        gl.enable(glow::BLEND);
+        gl.blend_equation(map_advanced_blend_op(operation));
+    }
+}
+
 pub(crate) fn bind_blend(gl: &GlContainer, desc: &pso::ColorBlendDesc) {
     use crate::hal::pso::ColorMask as Cm;
 
@@ -161,20 +211,30 @@ pub(crate) fn bind_blend_slot(share: &Share, slot: ColorSlot, desc: &pso::ColorB
     use crate::hal::pso::ColorMask as Cm;
 
     let gl = &share.context;
-    let supports_draw_buffers = share.private_caps.draw_buffers;
+    let caps = &share.private_caps;
+    if !caps.draw_buffers_enable_indexed {
+        // The state of the first slot applies to all draw buffers.
+        if slot == 0 {
+            bind_blend(gl, desc);
+        }
+        return;
+    }
 
     match desc.1 {
         pso::BlendState::On { color, alpha } => unsafe {
             let (color_eq, color_src, color_dst) = map_blend_op(color);
             let (alpha_eq, alpha_src, alpha_dst) = map_blend_op(alpha);
-            if supports_draw_buffers {
-                gl.enable_draw_buffer(glow::BLEND, slot as _);
+            gl.enable_draw_buffer(glow::BLEND, slot as _);
+            if caps.draw_buffers_indexed {
                 gl.blend_equation_separate_draw_buffer(slot as _, color_eq, alpha_eq);
                 gl.blend_func_separate_draw_buffer(
                     slot as _, color_src, color_dst, alpha_src, alpha_dst,
                 );
-            } else {
-                warn!("Draw buffers are not supported");
+            } else if slot == 0 {
+                // Blend functions of the first slot apply to all draw buffers,
+                // pipelines with others warn on creation.
+                gl.blend_equation_separate(color_eq, alpha_eq);
+                gl.blend_func_separate(color_src, color_dst, alpha_src, alpha_dst);
             }
         },
         pso::BlendState::Off => unsafe {
@@ -182,18 +242,14 @@ pub(crate) fn bind_blend_slot(share: &Share, slot: ColorSlot, desc: &pso::ColorB
         },
     };
 
-    if supports_draw_buffers {
-        unsafe {
-            gl.color_mask_draw_buffer(
-                slot as _,
-                desc.0.contains(Cm::RED) as _,
-                desc.0.contains(Cm::GREEN) as _,
-                desc.0.contains(Cm::BLUE) as _,
-                desc.0.contains(Cm::ALPHA) as _,
-            );
-        }
-    } else {
-        warn!("Draw buffers are not supported");
+    unsafe {
+        gl.color_mask_draw_buffer(
+            slot as _,
+            desc.0.contains(Cm::RED) as _,
+            desc.0.contains(Cm::GREEN) as _,
+            desc.0.contains(Cm::BLUE) as _,
+            desc.0.contains(Cm::ALPHA) as _,
+        );
     }
 }
 
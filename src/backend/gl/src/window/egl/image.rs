@@ -0,0 +1,175 @@
+//! Importing external memory as images through `EGLImage`s, e.g. dma-bufs
+//! of video decoders or Wayland clients (`EGL_EXT_image_dma_buf_import`).
+
+use std::os::raw::c_void;
+use std::os::unix::io::RawFd;
+use std::ptr;
+
+use glow::Context;
+
+use super::{ffi, get_proc, is_extension_supported, last_error, Display, EglError};
+use crate::hal::{format as f, image, memory};
+use crate::{native, Device};
+
+/// Plane of a dma-buf, the offset and pitch are in bytes.
+#[derive(Clone, Copy, Debug)]
+pub struct DmaBufPlane {
+    pub fd: RawFd,
+    pub offset: u32,
+    pub pitch: u32,
+}
+
+/// `EGLImage` owned by the application, destroyed on drop.
+///
+/// The display has to outlive the image. Images created from it keep
+/// their storage after the `EGLImage` is destroyed.
+#[derive(Debug)]
+pub struct EglImage {
+    display: ffi::EGLDisplay,
+    raw: ffi::EGLImageKHR,
+}
+
+impl EglImage {
+    /// Raw `EGLImageKHR` handle.
+    pub fn raw(&self) -> *mut c_void {
+        self.raw
+    }
+}
+
+impl Drop for EglImage {
+    fn drop(&mut self) {
+        unsafe {
+            if let Ok(destroy_image) =
+                get_proc::<ffi::PFNEGLDESTROYIMAGEKHRPROC>("eglDestroyImageKHR")
+            {
+                destroy_image(self.display, self.raw);
+            }
+        }
+    }
+}
+
+impl Display {
+    /// Import a dma-buf with up to three planes as `EGLImage`.
+    ///
+    /// `fourcc` is the DRM format code of the buffer, e.g. `DRM_FORMAT_ABGR8888`.
+    /// The file descriptors stay owned by the caller.
+    pub unsafe fn import_dma_buf(
+        &self,
+        width: u32,
+        height: u32,
+        fourcc: u32,
+        planes: &[DmaBufPlane],
+    ) -> Result<EglImage, EglError> {
+        // File descriptor, offset and pitch attributes of each plane.
+        const PLANE_ATTRIBS: [[ffi::EGLint; 3]; 3] = [
+            [
+                ffi::EGL_DMA_BUF_PLANE0_FD_EXT,
+                ffi::EGL_DMA_BUF_PLANE0_OFFSET_EXT,
+                ffi::EGL_DMA_BUF_PLANE0_PITCH_EXT,
+            ],
+            [
+                ffi::EGL_DMA_BUF_PLANE1_FD_EXT,
+                ffi::EGL_DMA_BUF_PLANE1_OFFSET_EXT,
+                ffi::EGL_DMA_BUF_PLANE1_PITCH_EXT,
+            ],
+            [
+                ffi::EGL_DMA_BUF_PLANE2_FD_EXT,
+                ffi::EGL_DMA_BUF_PLANE2_OFFSET_EXT,
+                ffi::EGL_DMA_BUF_PLANE2_PITCH_EXT,
+            ],
+        ];
+
+        if planes.is_empty()
+            || planes.len() > PLANE_ATTRIBS.len()
+            || !is_extension_supported(self.raw, "EGL_EXT_image_dma_buf_import")
+        {
+            return Err(EglError(ffi::EGL_BAD_PARAMETER));
+        }
+        let create_image: ffi::PFNEGLCREATEIMAGEKHRPROC = get_proc("eglCreateImageKHR")?;
+
+        let mut attribs = vec![
+            ffi::EGL_WIDTH,
+            width as ffi::EGLint,
+            ffi::EGL_HEIGHT,
+            height as ffi::EGLint,
+            ffi::EGL_LINUX_DRM_FOURCC_EXT,
+            fourcc as ffi::EGLint,
+        ];
+        for (plane, &[fd, offset, pitch]) in planes.iter().zip(PLANE_ATTRIBS.iter()) {
+            attribs.extend_from_slice(&[
+                fd,
+                plane.fd,
+                offset,
+                plane.offset as ffi::EGLint,
+                pitch,
+                plane.pitch as ffi::EGLint,
+            ]);
+        }
+        attribs.push(ffi::EGL_NONE);
+
+        // dma-buf imports take no context and no client buffer.
+        let raw = create_image(
+            self.raw,
+            ptr::null_mut(),
+            ffi::EGL_LINUX_DMA_BUF_EXT,
+            ptr::null_mut(),
+            attribs.as_ptr(),
+        );
+        if raw.is_null() {
+            return Err(last_error());
+        }
+        Ok(EglImage {
+            display: self.raw,
+            raw,
+        })
+    }
+}
+
+impl Device {
+    /// Create a 2D image sharing the storage of an `EGLImage` (`GL_OES_EGL_image`),
+    /// without copying.
+    ///
+    /// The image doesn't need to be bound to memory. `format` has to match the
+    /// format of the `EGLImage`, which has to be valid for the current context.
+    pub unsafe fn create_image_from_egl_image(
+        &self,
+        egl_image: *mut c_void,
+        width: image::Size,
+        height: image::Size,
+        format: f::Format,
+    ) -> Result<native::Image, image::CreationError> {
+        if !self.share.info.is_extension_supported("GL_OES_EGL_image") {
+            error!("Importing EGL images requires GL_OES_EGL_image");
+            return Err(image::CreationError::Kind);
+        }
+        let image_target_texture: ffi::PFNGLEGLIMAGETARGETTEXTURE2DOESPROC =
+            match get_proc("glEGLImageTargetTexture2DOES") {
+                Ok(f) => f,
+                Err(_) => return Err(image::CreationError::Kind),
+            };
+
+        let gl = &self.share.context;
+        let name = gl.create_texture().unwrap();
+        gl.bind_texture(glow::TEXTURE_2D, Some(name));
+        image_target_texture(glow::TEXTURE_2D, egl_image);
+        gl.bind_texture(glow::TEXTURE_2D, None);
+
+        if let Err(err) = self.share.check() {
+            gl.delete_texture(name);
+            error!("Error importing EGL image: {:?}", err);
+            return Err(image::CreationError::Format(format));
+        }
+
+        let bytes_per_texel = format.surface_desc().bits / 8;
+        Ok(native::Image {
+            kind: native::ImageKind::Texture(name, glow::TEXTURE_2D),
+            channel: format.base_format().1,
+            requirements: memory::Requirements {
+                size: (width * height) as u64 * bytes_per_texel as u64,
+                alignment: 1,
+                type_mask: self.share.image_memory_type_mask(false, false),
+            },
+            host_image: None,
+        })
+    }
+}
@@ -0,0 +1,643 @@
+//! Window creation using EGL directly, without glutin.
+//!
+//! Displays are created through `EGL_EXT_platform_base` for the device, GBM,
+//! Wayland and X11 platforms. Native handles (displays, windows) are owned
+//! by the application, which keeps them alive as long as the surface.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! extern crate gfx_backend_gl;
+//! extern crate gfx_hal;
+//!
+//! use gfx_hal::Instance;
+//! use gfx_backend_gl::egl::{Api, Display};
+//!
+//! fn main() {
+//!     // Render on the first EGL device, without any windowing system.
+//!     let display = unsafe { Display::from_device(0, Api::Gles) }
+//!         .expect("Failed to create EGL display");
+//!     let _adapters = display.enumerate_adapters();
+//! }
+//! ```
+
+use std::os::raw::c_void;
+use std::{fmt, ptr};
+
+use crate::hal;
+use crate::{Backend as B, GlContainer, PhysicalDevice, Profile};
+
+// External images, e.g. dma-bufs.
+#[cfg(unix)]
+mod image;
+#[cfg(unix)]
+pub use self::image::{DmaBufPlane, EglImage};
+
+// Window surfaces, only used if EGL is the windowing path.
+#[cfg(not(feature = "glutin"))]
+mod surface;
+#[cfg(not(feature = "glutin"))]
+pub use self::surface::{Surface, Swapchain};
+
+#[allow(non_camel_case_types, non_snake_case, dead_code)]
+pub(crate) mod ffi {
+    use std::os::raw::{c_char, c_void};
+
+    pub type EGLint = i32;
+    pub type EGLenum = u32;
+    pub type EGLBoolean = u32;
+    pub type EGLDisplay = *mut c_void;
+    pub type EGLConfig = *mut c_void;
+    pub type EGLContext = *mut c_void;
+    pub type EGLSurface = *mut c_void;
+    pub type EGLDeviceEXT = *mut c_void;
+    pub type EGLAttrib = isize;
+    pub type EGLImageKHR = *mut c_void;
+    pub type EGLClientBuffer = *mut c_void;
+
+    pub const EGL_FALSE: EGLBoolean = 0;
+    pub const EGL_TRUE: EGLBoolean = 1;
+    pub const EGL_SUCCESS: EGLint = 0x3000;
+    pub const EGL_BAD_PARAMETER: EGLint = 0x300C;
+    pub const EGL_NONE: EGLint = 0x3038;
+
+    pub const EGL_ALPHA_SIZE: EGLint = 0x3021;
+    pub const EGL_BLUE_SIZE: EGLint = 0x3022;
+    pub const EGL_GREEN_SIZE: EGLint = 0x3023;
+    pub const EGL_RED_SIZE: EGLint = 0x3024;
+    pub const EGL_DEPTH_SIZE: EGLint = 0x3025;
+    pub const EGL_STENCIL_SIZE: EGLint = 0x3026;
+    pub const EGL_SURFACE_TYPE: EGLint = 0x3033;
+    pub const EGL_RENDERABLE_TYPE: EGLint = 0x3040;
+    pub const EGL_EXTENSIONS: EGLint = 0x3055;
+    pub const EGL_HEIGHT: EGLint = 0x3056;
+    pub const EGL_WIDTH: EGLint = 0x3057;
+    pub const EGL_GL_COLORSPACE_KHR: EGLint = 0x309D;
+    pub const EGL_COLOR_COMPONENT_TYPE_EXT: EGLint = 0x3339;
+    pub const EGL_COLOR_COMPONENT_TYPE_FIXED_EXT: EGLint = 0x333A;
+    pub const EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT: EGLint = 0x333B;
+    pub const EGL_GL_COLORSPACE_SCRGB_LINEAR_EXT: EGLint = 0x3350;
+    pub const EGL_DEVICE_EXT: EGLint = 0x322C;
+    pub const EGL_DRM_DEVICE_FILE_EXT: EGLint = 0x3233;
+
+    pub const EGL_LINUX_DMA_BUF_EXT: EGLenum = 0x3270;
+    pub const EGL_LINUX_DRM_FOURCC_EXT: EGLint = 0x3271;
+    pub const EGL_DMA_BUF_PLANE0_FD_EXT: EGLint = 0x3272;
+    pub const EGL_DMA_BUF_PLANE0_OFFSET_EXT: EGLint = 0x3273;
+    pub const EGL_DMA_BUF_PLANE0_PITCH_EXT: EGLint = 0x3274;
+    pub const EGL_DMA_BUF_PLANE1_FD_EXT: EGLint = 0x3275;
+    pub const EGL_DMA_BUF_PLANE1_OFFSET_EXT: EGLint = 0x3276;
+    pub const EGL_DMA_BUF_PLANE1_PITCH_EXT: EGLint = 0x3277;
+    pub const EGL_DMA_BUF_PLANE2_FD_EXT: EGLint = 0x3278;
+    pub const EGL_DMA_BUF_PLANE2_OFFSET_EXT: EGLint = 0x3279;
+    pub const EGL_DMA_BUF_PLANE2_PITCH_EXT: EGLint = 0x327A;
+
+    pub const EGL_PBUFFER_BIT: EGLint = 0x0001;
+    pub const EGL_WINDOW_BIT: EGLint = 0x0004;
+    pub const EGL_OPENGL_BIT: EGLint = 0x0008;
+    pub const EGL_OPENGL_ES3_BIT: EGLint = 0x0040;
+
+    pub const EGL_OPENGL_ES_API: EGLenum = 0x30A0;
+    pub const EGL_OPENGL_API: EGLenum = 0x30A2;
+    pub const EGL_CONTEXT_MAJOR_VERSION: EGLint = 0x3098;
+    pub const EGL_CONTEXT_MINOR_VERSION: EGLint = 0x30FB;
+    pub const EGL_CONTEXT_OPENGL_PROFILE_MASK: EGLint = 0x30FD;
+    pub const EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT: EGLint = 0x0001;
+    pub const EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT: EGLint = 0x0002;
+
+    pub const EGL_PLATFORM_X11_KHR: EGLenum = 0x31D5;
+    pub const EGL_PLATFORM_GBM_KHR: EGLenum = 0x31D7;
+    pub const EGL_PLATFORM_WAYLAND_KHR: EGLenum = 0x31D8;
+    pub const EGL_PLATFORM_DEVICE_EXT: EGLenum = 0x313F;
+    pub const EGL_PLATFORM_SURFACELESS_MESA: EGLenum = 0x31DD;
+
+    pub type PFNEGLGETPLATFORMDISPLAYEXTPROC = unsafe extern "C" fn(
+        platform: EGLenum,
+        native_display: *mut c_void,
+        attrib_list: *const EGLint,
+    ) -> EGLDisplay;
+    pub type PFNEGLCREATEPLATFORMWINDOWSURFACEEXTPROC = unsafe extern "C" fn(
+        display: EGLDisplay,
+        config: EGLConfig,
+        native_window: *mut c_void,
+        attrib_list: *const EGLint,
+    ) -> EGLSurface;
+    pub type PFNEGLQUERYDEVICESEXTPROC = unsafe extern "C" fn(
+        max_devices: EGLint,
+        devices: *mut EGLDeviceEXT,
+        num_devices: *mut EGLint,
+    ) -> EGLBoolean;
+
+    pub type PFNEGLCREATEIMAGEKHRPROC = unsafe extern "C" fn(
+        display: EGLDisplay,
+        context: EGLContext,
+        target: EGLenum,
+        buffer: EGLClientBuffer,
+        attrib_list: *const EGLint,
+    ) -> EGLImageKHR;
+    pub type PFNEGLDESTROYIMAGEKHRPROC =
+        unsafe extern "C" fn(display: EGLDisplay, image: EGLImageKHR) -> EGLBoolean;
+    pub type PFNGLEGLIMAGETARGETTEXTURE2DOESPROC =
+        unsafe extern "C" fn(target: u32, image: *mut c_void);
+    pub type PFNEGLQUERYDISPLAYATTRIBEXTPROC = unsafe extern "C" fn(
+        display: EGLDisplay,
+        attribute: EGLint,
+        value: *mut EGLAttrib,
+    ) -> EGLBoolean;
+    pub type PFNEGLQUERYDEVICESTRINGEXTPROC = unsafe extern "C" fn(
+        device: EGLDeviceEXT,
+        name: EGLint,
+    ) -> *const c_char;
+
+    #[link(name = "EGL")]
+    extern "C" {
+        pub fn eglGetError() -> EGLint;
+        pub fn eglGetProcAddress(procname: *const c_char) -> *const c_void;
+        pub fn eglInitialize(display: EGLDisplay, major: *mut EGLint, minor: *mut EGLint) -> EGLBoolean;
+        pub fn eglTerminate(display: EGLDisplay) -> EGLBoolean;
+        pub fn eglQueryString(display: EGLDisplay, name: EGLint) -> *const c_char;
+        pub fn eglBindAPI(api: EGLenum) -> EGLBoolean;
+        pub fn eglChooseConfig(
+            display: EGLDisplay,
+            attrib_list: *const EGLint,
+            configs: *mut EGLConfig,
+            config_size: EGLint,
+            num_config: *mut EGLint,
+        ) -> EGLBoolean;
+        pub fn eglGetConfigAttrib(
+            display: EGLDisplay,
+            config: EGLConfig,
+            attribute: EGLint,
+            value: *mut EGLint,
+        ) -> EGLBoolean;
+        pub fn eglCreateContext(
+            display: EGLDisplay,
+            config: EGLConfig,
+            share_context: EGLContext,
+            attrib_list: *const EGLint,
+        ) -> EGLContext;
+        pub fn eglDestroyContext(display: EGLDisplay, context: EGLContext) -> EGLBoolean;
+        pub fn eglCreatePbufferSurface(
+            display: EGLDisplay,
+            config: EGLConfig,
+            attrib_list: *const EGLint,
+        ) -> EGLSurface;
+        pub fn eglDestroySurface(display: EGLDisplay, surface: EGLSurface) -> EGLBoolean;
+        pub fn eglQuerySurface(
+            display: EGLDisplay,
+            surface: EGLSurface,
+            attribute: EGLint,
+            value: *mut EGLint,
+        ) -> EGLBoolean;
+        pub fn eglMakeCurrent(
+            display: EGLDisplay,
+            draw: EGLSurface,
+            read: EGLSurface,
+            context: EGLContext,
+        ) -> EGLBoolean;
+        pub fn eglSwapBuffers(display: EGLDisplay, surface: EGLSurface) -> EGLBoolean;
+        pub fn eglSwapInterval(display: EGLDisplay, interval: EGLint) -> EGLBoolean;
+    }
+}
+
+/// Error reported by EGL, holding the code returned by `eglGetError`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EglError(pub i32);
+
+impl fmt::Display for EglError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EGL error {:#x}", self.0)
+    }
+}
+
+pub(crate) fn last_error() -> EglError {
+    EglError(unsafe { ffi::eglGetError() })
+}
+
+pub(crate) unsafe fn get_proc<T>(name: &str) -> Result<T, EglError> {
+    let name = std::ffi::CString::new(name).unwrap();
+    let address = ffi::eglGetProcAddress(name.as_ptr());
+    if address.is_null() {
+        Err(last_error())
+    } else {
+        Ok(std::mem::transmute_copy(&address))
+    }
+}
+
+/// Query all EGL devices (`EGL_EXT_device_enumeration`).
+unsafe fn query_devices() -> Result<Vec<ffi::EGLDeviceEXT>, EglError> {
+    let query_devices: ffi::PFNEGLQUERYDEVICESEXTPROC = get_proc("eglQueryDevicesEXT")?;
+    let mut num_devices = 0;
+    if query_devices(0, ptr::null_mut(), &mut num_devices) != ffi::EGL_TRUE {
+        return Err(last_error());
+    }
+    let mut devices = vec![ptr::null_mut(); num_devices as usize];
+    if query_devices(num_devices, devices.as_mut_ptr(), &mut num_devices) != ffi::EGL_TRUE {
+        return Err(last_error());
+    }
+    devices.truncate(num_devices as usize);
+    Ok(devices)
+}
+
+/// PCI vendor and device ID of the device behind a display (`EGL_EXT_device_query`).
+///
+/// EGL only exposes the DRM device file (`EGL_EXT_device_drm`), the IDs
+/// are read from sysfs.
+unsafe fn query_pci_ids(display: ffi::EGLDisplay) -> Option<(usize, usize)> {
+    let query_display_attrib: ffi::PFNEGLQUERYDISPLAYATTRIBEXTPROC =
+        get_proc("eglQueryDisplayAttribEXT").ok()?;
+    let query_device_string: ffi::PFNEGLQUERYDEVICESTRINGEXTPROC =
+        get_proc("eglQueryDeviceStringEXT").ok()?;
+
+    let mut device: ffi::EGLAttrib = 0;
+    if query_display_attrib(display, ffi::EGL_DEVICE_EXT, &mut device) != ffi::EGL_TRUE {
+        return None;
+    }
+    let file = query_device_string(device as ffi::EGLDeviceEXT, ffi::EGL_DRM_DEVICE_FILE_EXT);
+    if file.is_null() {
+        return None;
+    }
+    let file = std::ffi::CStr::from_ptr(file).to_string_lossy().into_owned();
+    let node = std::path::Path::new(&file).file_name()?.to_str()?.to_owned();
+    let read_id = |name: &str| {
+        let path = format!("/sys/class/drm/{}/device/{}", node, name);
+        let id = std::fs::read_to_string(path).ok()?;
+        usize::from_str_radix(id.trim().trim_start_matches("0x"), 16).ok()
+    };
+    Some((read_id("vendor")?, read_id("device")?))
+}
+
+unsafe fn is_extension_supported(display: ffi::EGLDisplay, name: &str) -> bool {
+    let extensions = ffi::eglQueryString(display, ffi::EGL_EXTENSIONS);
+    !extensions.is_null()
+        && std::ffi::CStr::from_ptr(extensions)
+            .to_string_lossy()
+            .split(' ')
+            .any(|extension| extension == name)
+}
+
+/// EGL platform of a display.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Platform {
+    /// `EGL_EXT_platform_device`, the native display is an `EGLDeviceEXT`.
+    Device,
+    /// `EGL_KHR_platform_gbm`, the native display is a `gbm_device*`.
+    Gbm,
+    /// `EGL_KHR_platform_wayland`, the native display is a `wl_display*`.
+    Wayland,
+    /// `EGL_KHR_platform_x11`, the native display is a `Display*`.
+    X11,
+    /// `EGL_MESA_platform_surfaceless`, the native display is null.
+    Surfaceless,
+}
+
+impl Platform {
+    fn to_egl(self) -> ffi::EGLenum {
+        match self {
+            Platform::Device => ffi::EGL_PLATFORM_DEVICE_EXT,
+            Platform::Gbm => ffi::EGL_PLATFORM_GBM_KHR,
+            Platform::Wayland => ffi::EGL_PLATFORM_WAYLAND_KHR,
+            Platform::X11 => ffi::EGL_PLATFORM_X11_KHR,
+            Platform::Surfaceless => ffi::EGL_PLATFORM_SURFACELESS_MESA,
+        }
+    }
+
+    fn is_headless(self) -> bool {
+        match self {
+            Platform::Device | Platform::Surfaceless => true,
+            Platform::Gbm | Platform::Wayland | Platform::X11 => false,
+        }
+    }
+}
+
+/// Client API of the created context.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Api {
+    /// Desktop OpenGL 3.2 or newer.
+    Gl,
+    /// OpenGL ES 3.0 or newer.
+    Gles,
+}
+
+/// Color buffer of the config used for window surfaces.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorBuffer {
+    /// 8 bits per channel.
+    Rgba8,
+    /// 10 bits per color channel and 2 bits of alpha, avoids banding
+    /// in smooth gradients.
+    Rgb10A2,
+    /// Half floats per channel (`EGL_EXT_pixel_format_float`), presented as
+    /// linear scRGB if the display supports `EGL_EXT_gl_colorspace_scrgb_linear`.
+    Rgba16Float,
+}
+
+impl ColorBuffer {
+    fn bits(self) -> (ffi::EGLint, ffi::EGLint) {
+        match self {
+            ColorBuffer::Rgba8 => (8, 8),
+            ColorBuffer::Rgb10A2 => (10, 2),
+            ColorBuffer::Rgba16Float => (16, 16),
+        }
+    }
+}
+
+/// Initialized EGL display, together with a context and the config
+/// used for all surfaces of the display.
+pub struct Display {
+    pub(crate) raw: ffi::EGLDisplay,
+    pub(crate) config: ffi::EGLConfig,
+    context: ffi::EGLContext,
+    // Used to make the context current without a window, null if the
+    // context is made current without any surface (`EGL_KHR_surfaceless_context`).
+    pbuffer: ffi::EGLSurface,
+}
+
+impl fmt::Debug for Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Display({:p})", self.raw)
+    }
+}
+
+impl Display {
+    /// Create a display for a platform from its native display handle.
+    ///
+    /// The native display has to outlive the returned display.
+    pub unsafe fn new(
+        platform: Platform,
+        native_display: *mut c_void,
+        api: Api,
+    ) -> Result<Self, EglError> {
+        let raw = Display::platform_display(platform, native_display)?;
+        Display::init(raw, platform, api, None, ColorBuffer::Rgba8)
+    }
+
+    /// Create a display for a platform, whose window surfaces use a deeper
+    /// color buffer.
+    ///
+    /// The native display has to outlive the returned display.
+    pub unsafe fn with_color_buffer(
+        platform: Platform,
+        native_display: *mut c_void,
+        api: Api,
+        color_buffer: ColorBuffer,
+    ) -> Result<Self, EglError> {
+        let raw = Display::platform_display(platform, native_display)?;
+        Display::init(raw, platform, api, None, color_buffer)
+    }
+
+    /// Create a display for a platform with a desktop OpenGL context
+    /// of a specific version and profile.
+    ///
+    /// The native display has to outlive the returned display.
+    pub unsafe fn with_profile(
+        platform: Platform,
+        native_display: *mut c_void,
+        version: (u8, u8),
+        profile: Profile,
+    ) -> Result<Self, EglError> {
+        let raw = Display::platform_display(platform, native_display)?;
+        Display::init(raw, platform, Api::Gl, Some((version, profile)), ColorBuffer::Rgba8)
+    }
+
+    /// Create a display without any windowing system, using the GPU driver
+    /// (`EGL_MESA_platform_surfaceless`).
+    ///
+    /// Used for hardware accelerated headless rendering, e.g. on CI or servers.
+    pub unsafe fn surfaceless(api: Api) -> Result<Self, EglError> {
+        Display::new(Platform::Surfaceless, ptr::null_mut(), api)
+    }
+
+    /// Create a display on an EGL device, without any windowing system.
+    pub unsafe fn from_device(index: usize, api: Api) -> Result<Self, EglError> {
+        let devices = query_devices()?;
+        match devices.get(index) {
+            Some(&device) => Display::new(Platform::Device, device, api),
+            None => {
+                error!("EGL device {} not found, {} available", index, devices.len());
+                Err(EglError(ffi::EGL_BAD_PARAMETER))
+            }
+        }
+    }
+
+    /// Get the EGL display of a platform for a native display handle.
+    unsafe fn platform_display(
+        platform: Platform,
+        native_display: *mut c_void,
+    ) -> Result<ffi::EGLDisplay, EglError> {
+        let get_platform_display: ffi::PFNEGLGETPLATFORMDISPLAYEXTPROC =
+            get_proc("eglGetPlatformDisplayEXT")?;
+        let raw = get_platform_display(platform.to_egl(), native_display, ptr::null());
+        if raw.is_null() {
+            return Err(last_error());
+        }
+        Ok(raw)
+    }
+
+    unsafe fn init(
+        raw: ffi::EGLDisplay,
+        platform: Platform,
+        api: Api,
+        request: Option<((u8, u8), Profile)>,
+        color_buffer: ColorBuffer,
+    ) -> Result<Self, EglError> {
+        let (mut major, mut minor) = (0, 0);
+        if ffi::eglInitialize(raw, &mut major, &mut minor) != ffi::EGL_TRUE {
+            return Err(last_error());
+        }
+        info!("EGL version {}.{}", major, minor);
+
+        let (egl_api, renderable_type, version) = match api {
+            Api::Gl => (ffi::EGL_OPENGL_API, ffi::EGL_OPENGL_BIT, (3, 2)),
+            Api::Gles => (ffi::EGL_OPENGL_ES_API, ffi::EGL_OPENGL_ES3_BIT, (3, 0)),
+        };
+        if ffi::eglBindAPI(egl_api) != ffi::EGL_TRUE {
+            return Err(last_error());
+        }
+
+        // Headless platforms have no windows, and may not even support pbuffers.
+        let surface_type = if platform.is_headless() {
+            0
+        } else {
+            ffi::EGL_WINDOW_BIT | ffi::EGL_PBUFFER_BIT
+        };
+        let (color_bits, alpha_bits) = color_buffer.bits();
+        #[rustfmt::skip]
+        let mut config_attribs = vec![
+            ffi::EGL_SURFACE_TYPE, surface_type,
+            ffi::EGL_RENDERABLE_TYPE, renderable_type,
+            ffi::EGL_RED_SIZE, color_bits,
+            ffi::EGL_GREEN_SIZE, color_bits,
+            ffi::EGL_BLUE_SIZE, color_bits,
+            ffi::EGL_ALPHA_SIZE, alpha_bits,
+            ffi::EGL_DEPTH_SIZE, 24,
+            ffi::EGL_STENCIL_SIZE, 8,
+        ];
+        if color_buffer == ColorBuffer::Rgba16Float {
+            config_attribs.extend_from_slice(&[
+                ffi::EGL_COLOR_COMPONENT_TYPE_EXT,
+                ffi::EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT,
+            ]);
+        }
+        config_attribs.push(ffi::EGL_NONE);
+        let mut config = ptr::null_mut();
+        let mut num_configs = 0;
+        if ffi::eglChooseConfig(raw, config_attribs.as_ptr(), &mut config, 1, &mut num_configs)
+            != ffi::EGL_TRUE
+            || num_configs == 0
+        {
+            ffi::eglTerminate(raw);
+            return Err(last_error());
+        }
+
+        let (version, profile) = match request {
+            Some(((major, minor), profile)) => {
+                let mask = match profile {
+                    Profile::Core => ffi::EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT,
+                    Profile::Compatibility => ffi::EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT,
+                };
+                ((major as ffi::EGLint, minor as ffi::EGLint), mask)
+            }
+            None => (version, 0),
+        };
+        #[rustfmt::skip]
+        let mut context_attribs = vec![
+            ffi::EGL_CONTEXT_MAJOR_VERSION, version.0,
+            ffi::EGL_CONTEXT_MINOR_VERSION, version.1,
+        ];
+        if profile != 0 {
+            context_attribs.extend_from_slice(&[ffi::EGL_CONTEXT_OPENGL_PROFILE_MASK, profile]);
+        }
+        context_attribs.push(ffi::EGL_NONE);
+        let context = ffi::eglCreateContext(raw, config, ptr::null_mut(), context_attribs.as_ptr());
+        if context.is_null() {
+            ffi::eglTerminate(raw);
+            return Err(last_error());
+        }
+
+        let pbuffer_attribs = [ffi::EGL_WIDTH, 1, ffi::EGL_HEIGHT, 1, ffi::EGL_NONE];
+        let pbuffer = ffi::eglCreatePbufferSurface(raw, config, pbuffer_attribs.as_ptr());
+        if pbuffer.is_null() && !is_extension_supported(raw, "EGL_KHR_surfaceless_context") {
+            ffi::eglDestroyContext(raw, context);
+            ffi::eglTerminate(raw);
+            return Err(last_error());
+        }
+
+        Ok(Display {
+            raw,
+            config,
+            context,
+            pbuffer,
+        })
+    }
+
+    pub(crate) fn config_attrib(&self, attribute: ffi::EGLint) -> ffi::EGLint {
+        let mut value = 0;
+        unsafe { ffi::eglGetConfigAttrib(self.raw, self.config, attribute, &mut value) };
+        value
+    }
+
+    pub(crate) fn make_current(&self, surface: ffi::EGLSurface) {
+        if unsafe { ffi::eglMakeCurrent(self.raw, surface, surface, self.context) } != ffi::EGL_TRUE {
+            panic!("Failed to make EGL context current: {}", last_error());
+        }
+    }
+
+    pub(crate) fn new_adapter(&self) -> hal::Adapter<B> {
+        let gl = GlContainer::from_fn_proc(|s| {
+            let name = std::ffi::CString::new(s).unwrap();
+            unsafe { ffi::eglGetProcAddress(name.as_ptr()) }
+        });
+        PhysicalDevice::new_adapter_with_pci_ids(gl, unsafe { query_pci_ids(self.raw) })
+    }
+}
+
+impl Drop for Display {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::eglMakeCurrent(self.raw, ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+            if !self.pbuffer.is_null() {
+                ffi::eglDestroySurface(self.raw, self.pbuffer);
+            }
+            ffi::eglDestroyContext(self.raw, self.context);
+            ffi::eglTerminate(self.raw);
+        }
+    }
+}
+
+unsafe impl Send for Display {}
+unsafe impl Sync for Display {}
+
+/// Headless rendering on a display, through an internal 1x1 pbuffer
+/// or without any surface.
+impl hal::Instance for Display {
+    type Backend = B;
+    fn enumerate_adapters(&self) -> Vec<hal::Adapter<B>> {
+        self.make_current(self.pbuffer);
+        vec![self.new_adapter()]
+    }
+}
+
+/// Displays for all EGL devices, e.g. the integrated and discrete GPU
+/// of a laptop (`EGL_EXT_device_enumeration`, `EGL_EXT_platform_device`).
+///
+/// Each device has its own context. Enumerating adapters leaves the context
+/// of the last device current, `make_current` has to be called before
+/// opening the adapter of another device.
+///
+/// `Instance::create` doesn't use it for this reason, the generic adapter
+/// selection of hal would render with the context of the wrong device.
+#[derive(Debug)]
+pub struct Devices {
+    displays: Vec<Display>,
+}
+
+impl Devices {
+    /// Create a display for every EGL device.
+    ///
+    /// Devices which fail to initialize (e.g. software devices without
+    /// the requested API) are skipped.
+    pub unsafe fn new(api: Api) -> Result<Self, EglError> {
+        let displays = query_devices()?
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, device)| match Display::new(Platform::Device, device, api) {
+                Ok(display) => Some(display),
+                Err(err) => {
+                    warn!("Skipping EGL device {}: {}", index, err);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        if displays.is_empty() {
+            Err(EglError(ffi::EGL_BAD_PARAMETER))
+        } else {
+            Ok(Devices { displays })
+        }
+    }
+
+    /// Displays of the devices, in the order of the enumerated adapters.
+    pub fn displays(&self) -> &[Display] {
+        &self.displays
+    }
+
+    /// Make the context of a device current on the calling thread.
+    pub fn make_current(&self, index: usize) {
+        let display = &self.displays[index];
+        display.make_current(display.pbuffer);
+    }
+}
+
+/// One adapter per device.
+impl hal::Instance for Devices {
+    type Backend = B;
+    fn enumerate_adapters(&self) -> Vec<hal::Adapter<B>> {
+        self.displays
+            .iter()
+            .map(|display| {
+                display.make_current(display.pbuffer);
+                display.new_adapter()
+            })
+            .collect()
+    }
+}
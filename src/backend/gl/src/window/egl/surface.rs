@@ -0,0 +1,224 @@
+use std::os::raw::c_void;
+use std::ptr;
+
+use super::{ffi, is_extension_supported, last_error, get_proc, Display, EglError};
+use crate::window::swapchain::{
+    add_srgb_formats, color_buffer_formats, present_modes, surface_capabilities, swap_interval,
+    SwapchainImages,
+};
+use crate::hal::window::Extent2D;
+use crate::hal::{self, format as f, image, CompositeAlpha};
+use crate::{native, Backend as B, Device, PhysicalDevice, QueueFamily, Starc};
+
+#[derive(Debug)]
+struct WindowSurface {
+    display: Display,
+    raw: ffi::EGLSurface,
+}
+
+impl WindowSurface {
+    fn extent(&self) -> image::Extent {
+        let (mut width, mut height) = (0, 0);
+        unsafe {
+            ffi::eglQuerySurface(self.display.raw, self.raw, ffi::EGL_WIDTH, &mut width);
+            ffi::eglQuerySurface(self.display.raw, self.raw, ffi::EGL_HEIGHT, &mut height);
+        }
+        image::Extent {
+            width: width as image::Size,
+            height: height as image::Size,
+            depth: 1,
+        }
+    }
+
+    fn set_present_mode(&self, mode: hal::PresentMode) {
+        // The interval applies to the surface bound to the current context.
+        self.display.make_current(self.raw);
+        let interval = swap_interval(mode, false);
+        if unsafe { ffi::eglSwapInterval(self.display.raw, interval) } != ffi::EGL_TRUE {
+            warn!("Failed to set swap interval {}: {}", interval, last_error());
+        }
+    }
+}
+
+impl Drop for WindowSurface {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::eglDestroySurface(self.display.raw, self.raw);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Swapchain {
+    // Underlying surface, required for presentation
+    surface: Starc<WindowSurface>,
+    // Extent because the window lies
+    pub(crate) extent: Extent2D,
+    pub(crate) images: SwapchainImages,
+}
+
+impl Swapchain {
+    /// Current extent of the window, which differs from the swapchain extent after a resize.
+    pub(crate) fn window_extent(&self) -> Extent2D {
+        Extent2D::from(self.surface.extent())
+    }
+
+    /// Read the last presented frame as RGBA8, with rows from top to bottom.
+    ///
+    /// Meant for screenshots and automated tests. Returns `false` if no frame
+    /// was presented yet or it can't be read back.
+    pub fn read_frame(&self, data: &mut [u8]) -> bool {
+        self.images.read_presented(self.extent, data)
+    }
+
+    /// Change the present mode without re-creating the swapchain and its images.
+    ///
+    /// Unsupported modes fall back to `Fifo`.
+    pub fn set_present_mode(&mut self, mode: hal::PresentMode) {
+        self.surface.set_present_mode(mode);
+    }
+
+    /// Enable or disable vertical sync, switching between `Fifo` and `Immediate`.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.set_present_mode(if vsync {
+            hal::PresentMode::Fifo
+        } else {
+            hal::PresentMode::Immediate
+        });
+    }
+
+    pub(crate) fn swap_buffers(&self) {
+        let surface = &self.surface;
+        if unsafe { ffi::eglSwapBuffers(surface.display.raw, surface.raw) } != ffi::EGL_TRUE {
+            error!("Failed to swap buffers: {}", last_error());
+        }
+    }
+}
+
+impl hal::Swapchain<B> for Swapchain {
+    unsafe fn acquire_image(
+        &mut self,
+        timeout_ns: u64,
+        _semaphore: Option<&native::Semaphore>,
+        fence: Option<&native::Fence>,
+    ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::AcquireError> {
+        // The images have to be re-created to match the window.
+        if self.window_extent() != self.extent {
+            return Err(hal::AcquireError::OutOfDate);
+        }
+        self.images.acquire(timeout_ns, fence)
+    }
+}
+
+#[derive(Debug)]
+pub struct Surface {
+    surface: Starc<WindowSurface>,
+}
+
+impl Surface {
+    /// Create a surface for a native window of the display's platform.
+    ///
+    /// The native window is passed as expected by `eglCreatePlatformWindowSurface`:
+    /// a `gbm_surface*`, a `wl_egl_window*` or a pointer to the X11 `Window`.
+    /// It has to outlive the surface.
+    pub unsafe fn from_native_window(
+        display: Display,
+        native_window: *mut c_void,
+    ) -> Result<Self, EglError> {
+        let create_window_surface: ffi::PFNEGLCREATEPLATFORMWINDOWSURFACEEXTPROC =
+            get_proc("eglCreatePlatformWindowSurfaceEXT")?;
+        // Float configs are presented as scRGB, otherwise values above 1 would be clamped.
+        let float = display.config_attrib(ffi::EGL_COLOR_COMPONENT_TYPE_EXT)
+            == ffi::EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT;
+        let attribs = if float
+            && is_extension_supported(display.raw, "EGL_EXT_gl_colorspace_scrgb_linear")
+        {
+            vec![
+                ffi::EGL_GL_COLORSPACE_KHR,
+                ffi::EGL_GL_COLORSPACE_SCRGB_LINEAR_EXT,
+                ffi::EGL_NONE,
+            ]
+        } else {
+            vec![ffi::EGL_NONE]
+        };
+        let raw =
+            create_window_surface(display.raw, display.config, native_window, attribs.as_ptr());
+        if raw.is_null() {
+            return Err(last_error());
+        }
+        Ok(Surface {
+            surface: Starc::new(WindowSurface { display, raw }),
+        })
+    }
+
+    fn swapchain_formats(&self) -> Vec<f::Format> {
+        // Matches the config chosen in `Display::init`
+        let display = &self.surface.display;
+        let color_bits = display.config_attrib(ffi::EGL_RED_SIZE)
+            + display.config_attrib(ffi::EGL_GREEN_SIZE)
+            + display.config_attrib(ffi::EGL_BLUE_SIZE);
+        let alpha_bits = display.config_attrib(ffi::EGL_ALPHA_SIZE);
+        let float = display.config_attrib(ffi::EGL_COLOR_COMPONENT_TYPE_EXT)
+            == ffi::EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT;
+        color_buffer_formats(color_bits as u8, alpha_bits as u8, false, float)
+    }
+}
+
+impl hal::Surface<B> for Surface {
+    fn kind(&self) -> hal::image::Kind {
+        let ex = self.surface.extent();
+        hal::image::Kind::D2(ex.width, ex.height, 1, 1)
+    }
+
+    fn compatibility(
+        &self,
+        physical_device: &PhysicalDevice,
+    ) -> (
+        hal::SurfaceCapabilities,
+        Option<Vec<f::Format>>,
+        Vec<hal::PresentMode>,
+    ) {
+        let extent = Extent2D::from(self.surface.extent());
+        // The alpha of the default framebuffer isn't used for compositing.
+        let caps = surface_capabilities(extent, CompositeAlpha::OPAQUE);
+        // EGL has no equivalent of `*_EXT_swap_control_tear`.
+        let present_modes = present_modes(false);
+
+        let mut formats = self.swapchain_formats();
+        add_srgb_formats(&mut formats, physical_device);
+        (caps, Some(formats), present_modes)
+    }
+
+    fn supports_queue_family(&self, _: &QueueFamily) -> bool {
+        true
+    }
+}
+
+impl Device {
+    pub(crate) fn create_swapchain_impl(
+        &self,
+        surface: &mut Surface,
+        config: hal::SwapchainConfig,
+    ) -> Result<(Swapchain, Vec<native::Image>), hal::window::CreationError> {
+        surface.surface.set_present_mode(config.present_mode);
+
+        let (swapchain_images, images) = self.create_swapchain_images(&config, false)?;
+
+        let swapchain = Swapchain {
+            extent: config.extent,
+            images: swapchain_images,
+            surface: surface.surface.clone(),
+        };
+
+        Ok((swapchain, images))
+    }
+}
+
+impl hal::Instance for Surface {
+    type Backend = B;
+    fn enumerate_adapters(&self) -> Vec<hal::Adapter<B>> {
+        let display = &self.surface.display;
+        display.make_current(self.surface.raw);
+        vec![display.new_adapter()]
+    }
+}
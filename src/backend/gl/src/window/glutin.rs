@@ -23,6 +23,26 @@
 //! }
 //! ```
 //!
+//! Sharing a context managed by the application.
+//!
+//! ```no_run
+//! extern crate glutin;
+//! extern crate gfx_backend_gl;
+//!
+//! fn main() {
+//!     use gfx_backend_gl::Surface;
+//!     use glutin::{EventsLoop, WindowBuilder, ContextBuilder, WindowedContext};
+//!
+//!     let events_loop = EventsLoop::new();
+//!     let context = WindowedContext::new_windowed(WindowBuilder::new(), ContextBuilder::new(), &events_loop)
+//!         .unwrap();
+//!
+//!     let surface = Surface::from_context(context);
+//!     // The application keeps a handle, e.g. to resize on window events.
+//!     let context = surface.context();
+//! }
+//! ```
+//!
 //! Headless initialization without a window.
 //!
 //! ```no_run
@@ -43,9 +63,19 @@
 //! }
 //! ```
 
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_uint};
+
+use super::swapchain::{
+    add_srgb_formats, color_buffer_formats, present_modes, surface_capabilities, swap_interval,
+    SwapchainImages,
+};
 use crate::hal::window::Extent2D;
-use crate::hal::{self, format as f, image, memory, CompositeAlpha};
-use crate::{native, Backend as B, Device, GlContainer, PhysicalDevice, QueueFamily, Starc};
+use crate::hal::{self, format as f, image, CompositeAlpha};
+use crate::{
+    native, Backend as B, Device, GlContainer, PhysicalDevice, Profile, QueueFamily, Starc,
+};
 
 use glow::Context;
 
@@ -63,23 +93,117 @@ fn get_window_extent(window: &glutin::WindowedContext) -> image::Extent {
     }
 }
 
+/// Set the swap interval through the platform extensions,
+/// glutin only sets it on context creation.
+fn set_swap_interval(window: &glutin::WindowedContext, interval: i32) -> bool {
+    unsafe {
+        let wgl = window.get_proc_address("wglSwapIntervalEXT");
+        if !wgl.is_null() {
+            let swap_interval: extern "system" fn(c_int) -> c_int = mem::transmute(wgl);
+            return swap_interval(interval) != 0;
+        }
+        let mesa = window.get_proc_address("glXSwapIntervalMESA");
+        if !mesa.is_null() && interval >= 0 {
+            let swap_interval: extern "C" fn(c_uint) -> c_int = mem::transmute(mesa);
+            return swap_interval(interval as _) == 0;
+        }
+        let sgi = window.get_proc_address("glXSwapIntervalSGI");
+        if !sgi.is_null() && interval > 0 {
+            let swap_interval: extern "C" fn(c_int) -> c_int = mem::transmute(sgi);
+            return swap_interval(interval) == 0;
+        }
+        false
+    }
+}
+
+/// Set the swap interval matching a present mode, logging a warning on failure.
+fn set_present_mode(window: &glutin::WindowedContext, mode: hal::PresentMode) {
+    let interval = swap_interval(mode, supports_swap_control_tear(window));
+    if !set_swap_interval(window, interval) {
+        warn!("Failed to set swap interval {}", interval);
+    }
+}
+
+/// Whether the swap interval can be changed after context creation.
+fn supports_swap_control(window: &glutin::WindowedContext) -> bool {
+    ["wglSwapIntervalEXT", "glXSwapIntervalMESA", "glXSwapIntervalSGI"]
+        .iter()
+        .any(|name| !window.get_proc_address(name).is_null())
+}
+
+/// Whether negative swap intervals are supported (`WGL_EXT_swap_control_tear`).
+fn supports_swap_control_tear(window: &glutin::WindowedContext) -> bool {
+    unsafe {
+        let get_extensions = window.get_proc_address("wglGetExtensionsStringEXT");
+        if get_extensions.is_null() {
+            return false;
+        }
+        let get_extensions: extern "system" fn() -> *const c_char = mem::transmute(get_extensions);
+        let extensions = get_extensions();
+        !extensions.is_null()
+            && CStr::from_ptr(extensions)
+                .to_string_lossy()
+                .split(' ')
+                .any(|extension| extension == "WGL_EXT_swap_control_tear")
+    }
+}
+
 #[derive(Debug)]
 pub struct Swapchain {
     // Underlying window, required for presentation
     pub(crate) window: Starc<glutin::WindowedContext>,
     // Extent because the window lies
     pub(crate) extent: Extent2D,
+    pub(crate) images: SwapchainImages,
+}
+
+impl Swapchain {
+    /// Current extent of the window, which differs from the swapchain extent after a resize.
+    pub(crate) fn window_extent(&self) -> Extent2D {
+        Extent2D::from(get_window_extent(&self.window))
+    }
+
+    /// Read the last presented frame as RGBA8, with rows from top to bottom.
+    ///
+    /// Meant for screenshots and automated tests. Returns `false` if no frame
+    /// was presented yet or it can't be read back.
+    pub fn read_frame(&self, data: &mut [u8]) -> bool {
+        self.images.read_presented(self.extent, data)
+    }
+
+    /// Change the present mode without re-creating the swapchain and its images.
+    ///
+    /// Unsupported modes fall back to `Fifo`.
+    pub fn set_present_mode(&mut self, mode: hal::PresentMode) {
+        set_present_mode(&self.window, mode);
+    }
+
+    /// Enable or disable vertical sync, switching between `Fifo` and `Immediate`.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.set_present_mode(if vsync {
+            hal::PresentMode::Fifo
+        } else {
+            hal::PresentMode::Immediate
+        });
+    }
+
+    pub(crate) fn swap_buffers(&self) {
+        self.window.swap_buffers().unwrap();
+    }
 }
 
 impl hal::Swapchain<B> for Swapchain {
     unsafe fn acquire_image(
         &mut self,
-        _timeout_ns: u64,
+        timeout_ns: u64,
         _semaphore: Option<&native::Semaphore>,
-        _fence: Option<&native::Fence>,
+        fence: Option<&native::Fence>,
     ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::AcquireError> {
-        // TODO: sync
-        Ok((0, None))
+        // The images have to be re-created to match the window.
+        if self.window_extent() != self.extent {
+            return Err(hal::AcquireError::OutOfDate);
+        }
+        self.images.acquire(timeout_ns, fence)
     }
 }
 
@@ -93,11 +217,26 @@ pub struct Surface {
 
 impl Surface {
     pub fn from_window(window: glutin::WindowedContext) -> Self {
+        Surface::from_context(window)
+    }
+
+    /// Create a surface from a context shared with the application.
+    ///
+    /// Useful if the application manages the window and event loop on its own
+    /// (e.g. winit integrations or editors), it keeps a handle to the context
+    /// through `Surface::context`. The handle can only be dereferenced on the
+    /// thread which created the surface.
+    pub fn from_context(context: glutin::WindowedContext) -> Self {
         Surface {
-            window: Starc::new(window),
+            window: Starc::new(context),
         }
     }
 
+    /// Shared handle to the underlying context.
+    pub fn context(&self) -> Starc<glutin::WindowedContext> {
+        self.window.clone()
+    }
+
     pub fn get_window(&self) -> &glutin::WindowedContext {
         &*self.window
     }
@@ -112,12 +251,8 @@ impl Surface {
         let alpha_bits = pixel_format.alpha_bits;
         let srgb = pixel_format.srgb;
 
-        // TODO: expose more formats
-        match (color_bits, alpha_bits, srgb) {
-            (24, 8, true) => vec![f::Format::Rgba8Srgb, f::Format::Bgra8Srgb],
-            (24, 8, false) => vec![f::Format::Rgba8Unorm, f::Format::Bgra8Unorm],
-            _ => vec![],
-        }
+        // Float color buffers can't be requested through glutin.
+        color_buffer_formats(color_bits, alpha_bits, srgb, false)
     }
 }
 
@@ -130,35 +265,24 @@ impl hal::Surface<B> for Surface {
 
     fn compatibility(
         &self,
-        _: &PhysicalDevice,
+        physical_device: &PhysicalDevice,
     ) -> (
         hal::SurfaceCapabilities,
         Option<Vec<f::Format>>,
         Vec<hal::PresentMode>,
     ) {
-        let ex = get_window_extent(&self.window);
-        let extent = hal::window::Extent2D::from(ex);
-
-        let caps = hal::SurfaceCapabilities {
-            image_count: if self.window.get_pixel_format().double_buffer {
-                2..3
-            } else {
-                1..2
-            },
-            current_extent: Some(extent),
-            extents: extent..hal::window::Extent2D {
-                width: ex.width + 1,
-                height: ex.height + 1,
-            },
-            max_image_layers: 1,
-            usage: image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
-            composite_alpha: CompositeAlpha::OPAQUE, //TODO
+        let extent = Extent2D::from(get_window_extent(&self.window));
+        // The alpha of the default framebuffer isn't used for compositing.
+        let caps = surface_capabilities(extent, CompositeAlpha::OPAQUE);
+        let present_modes = if supports_swap_control(&self.window) {
+            present_modes(supports_swap_control_tear(&self.window))
+        } else {
+            vec![hal::PresentMode::Fifo]
         };
-        let present_modes = vec![
-            hal::PresentMode::Fifo, //TODO
-        ];
 
-        (caps, Some(self.swapchain_formats()), present_modes)
+        let mut formats = self.swapchain_formats();
+        add_srgb_formats(&mut formats, physical_device);
+        (caps, Some(formats), present_modes)
     }
 
     fn supports_queue_family(&self, _: &QueueFamily) -> bool {
@@ -171,111 +295,19 @@ impl Device {
         &self,
         surface: &mut Surface,
         config: hal::SwapchainConfig,
-    ) -> (Swapchain, Vec<native::Image>) {
+    ) -> Result<(Swapchain, Vec<native::Image>), hal::window::CreationError> {
+        set_present_mode(&surface.window, config.present_mode);
+
+        let srgb_backbuffer = surface.window.get_pixel_format().srgb;
+        let (swapchain_images, images) = self.create_swapchain_images(&config, srgb_backbuffer)?;
+
         let swapchain = Swapchain {
             extent: config.extent,
+            images: swapchain_images,
             window: surface.window.clone(),
         };
 
-        let gl = &self.share.context;
-
-        let (int_format, iformat, itype) = match config.format {
-            f::Format::Rgba8Unorm => (glow::RGBA8, glow::RGBA, glow::UNSIGNED_BYTE),
-            f::Format::Bgra8Unorm => (glow::RGBA8, glow::BGRA, glow::UNSIGNED_BYTE),
-            f::Format::Rgba8Srgb => (glow::SRGB8_ALPHA8, glow::RGBA, glow::UNSIGNED_BYTE),
-            _ => unimplemented!(),
-        };
-
-        let channel = config.format.base_format().1;
-
-        let images = (0..config.image_count)
-            .map(|_| unsafe {
-                let image = if config.image_layers > 1
-                    || config.image_usage.contains(image::Usage::STORAGE)
-                    || config.image_usage.contains(image::Usage::SAMPLED)
-                {
-                    let name = gl.create_texture().unwrap();
-                    match config.extent {
-                        Extent2D {
-                            width: w,
-                            height: h,
-                        } => {
-                            gl.bind_texture(glow::TEXTURE_2D, Some(name));
-                            if self.share.private_caps.image_storage {
-                                gl.tex_storage_2d(
-                                    glow::TEXTURE_2D,
-                                    config.image_layers as _,
-                                    int_format,
-                                    w as _,
-                                    h as _,
-                                );
-                            } else {
-                                gl.tex_parameter_i32(
-                                    glow::TEXTURE_2D,
-                                    glow::TEXTURE_MAX_LEVEL,
-                                    (config.image_layers - 1) as _,
-                                );
-                                let mut w = w;
-                                let mut h = h;
-                                for i in 0..config.image_layers {
-                                    gl.tex_image_2d(
-                                        glow::TEXTURE_2D,
-                                        i as _,
-                                        int_format as _,
-                                        w as _,
-                                        h as _,
-                                        0,
-                                        iformat,
-                                        itype,
-                                        None,
-                                    );
-                                    w = std::cmp::max(w / 2, 1);
-                                    h = std::cmp::max(h / 2, 1);
-                                }
-                            }
-                        }
-                    };
-                    native::ImageKind::Texture(name, glow::TEXTURE_2D)
-                } else {
-                    let name = gl.create_renderbuffer().unwrap();
-                    match config.extent {
-                        Extent2D {
-                            width: w,
-                            height: h,
-                        } => {
-                            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(name));
-                            gl.renderbuffer_storage(glow::RENDERBUFFER, int_format, w as _, h as _);
-                        }
-                    };
-                    native::ImageKind::Surface(name)
-                };
-
-                let surface_desc = config.format.base_format().0.desc();
-                let bytes_per_texel = surface_desc.bits / 8;
-                let ext = config.extent;
-                let size = (ext.width * ext.height) as u64 * bytes_per_texel as u64;
-                let type_mask = self.share.image_memory_type_mask();
-
-                if let Err(err) = self.share.check() {
-                    panic!(
-                        "Error creating swapchain image: {:?} with {:?} format",
-                        err, config.format
-                    );
-                }
-
-                native::Image {
-                    kind: image,
-                    channel,
-                    requirements: memory::Requirements {
-                        size,
-                        alignment: 1,
-                        type_mask,
-                    },
-                }
-            })
-            .collect::<Vec<_>>();
-
-        (swapchain, images)
+        Ok((swapchain, images))
     }
 }
 
@@ -308,6 +340,32 @@ pub fn config_context(
         .with_srgb(color_base.1 == f::ChannelType::Srgb)
 }
 
+/// Request a context which reports graphics resets (`KHR_robustness`).
+///
+/// Falls back to a regular context if the driver doesn't support robustness.
+/// Resets are then reported as `DeviceLost` by the queue and device.
+pub fn config_robust_context(builder: glutin::ContextBuilder) -> glutin::ContextBuilder {
+    builder.with_gl_robustness(glutin::Robustness::TryRobustLoseContextOnReset)
+}
+
+/// Request a specific OpenGL version and profile.
+///
+/// Context creation fails if the driver doesn't provide the requested version,
+/// the actual profile is reported by `Info::profile`.
+pub fn config_version(
+    builder: glutin::ContextBuilder,
+    version: (u8, u8),
+    profile: Profile,
+) -> glutin::ContextBuilder {
+    let profile = match profile {
+        Profile::Core => glutin::GlProfile::Core,
+        Profile::Compatibility => glutin::GlProfile::Compatibility,
+    };
+    builder
+        .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, version))
+        .with_gl_profile(profile)
+}
+
 pub struct Headless(pub glutin::Context);
 
 unsafe impl Send for Headless {}
@@ -0,0 +1,365 @@
+//! Window creation using GLX, attaching to an existing X11 window.
+//!
+//! Meant for embedding into windows owned by other toolkits (e.g. editor
+//! widgets), which keep control of the event loop.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_int, c_uint, c_void};
+use std::{mem, ptr, slice};
+
+use x11::{glx, xlib};
+
+use super::swapchain::{
+    add_srgb_formats, color_buffer_formats, present_modes, surface_capabilities, swap_interval,
+    SwapchainImages,
+};
+use crate::hal::window::Extent2D;
+use crate::hal::{self, format as f, image, CompositeAlpha};
+use crate::{native, Backend as B, Device, GlContainer, PhysicalDevice, QueueFamily, Starc};
+
+/// GL context created for a X11 window.
+#[derive(Debug)]
+pub struct Window {
+    display: *mut xlib::Display,
+    window: xlib::Window,
+    context: glx::GLXContext,
+    // Bits of the color buffer, excluding and only alpha.
+    color_bits: u8,
+    alpha_bits: u8,
+}
+
+impl Window {
+    /// Create a GL context for a X11 window.
+    ///
+    /// The framebuffer config is picked to match the visual of the window, which
+    /// has to be double buffered with at least 8 bits per channel, 10-bit visuals
+    /// are exposed as `A2r10g10b10Unorm`. Display and window have to outlive the context.
+    pub unsafe fn from_xlib(display: *mut xlib::Display, window: xlib::Window) -> Result<Self, String> {
+        let mut attributes: xlib::XWindowAttributes = mem::zeroed();
+        if xlib::XGetWindowAttributes(display, window, &mut attributes) == 0 {
+            return Err("Failed to query the window attributes".into());
+        }
+        let visual_id = xlib::XVisualIDFromVisual(attributes.visual);
+
+        #[rustfmt::skip]
+        let config_attribs = [
+            glx::GLX_DRAWABLE_TYPE, glx::GLX_WINDOW_BIT,
+            glx::GLX_RENDER_TYPE, glx::GLX_RGBA_BIT,
+            glx::GLX_DOUBLEBUFFER, xlib::True,
+            glx::GLX_RED_SIZE, 8,
+            glx::GLX_GREEN_SIZE, 8,
+            glx::GLX_BLUE_SIZE, 8,
+            0,
+        ];
+        let mut num_configs = 0;
+        let configs = glx::glXChooseFBConfig(
+            display,
+            xlib::XDefaultScreen(display),
+            config_attribs.as_ptr(),
+            &mut num_configs,
+        );
+        if configs.is_null() {
+            return Err("No matching framebuffer config".into());
+        }
+        let config = slice::from_raw_parts(configs, num_configs as usize)
+            .iter()
+            .cloned()
+            .find(|&config| {
+                let mut id: c_int = 0;
+                glx::glXGetFBConfigAttrib(display, config, glx::GLX_VISUAL_ID, &mut id);
+                id as xlib::VisualID == visual_id
+            });
+        xlib::XFree(configs as *mut _);
+        let config = match config {
+            Some(config) => config,
+            None => return Err("No framebuffer config matches the window visual".into()),
+        };
+
+        let get_attrib = |attrib| {
+            let mut value: c_int = 0;
+            glx::glXGetFBConfigAttrib(display, config, attrib, &mut value);
+            value as u8
+        };
+        let color_bits = get_attrib(glx::GLX_RED_SIZE)
+            + get_attrib(glx::GLX_GREEN_SIZE)
+            + get_attrib(glx::GLX_BLUE_SIZE);
+        let alpha_bits = get_attrib(glx::GLX_ALPHA_SIZE);
+
+        let context =
+            glx::glXCreateNewContext(display, config, glx::GLX_RGBA_TYPE, ptr::null_mut(), xlib::True);
+        if context.is_null() {
+            return Err("Failed to create the GL context".into());
+        }
+
+        Ok(Window {
+            display,
+            window,
+            context,
+            color_bits,
+            alpha_bits,
+        })
+    }
+
+    pub fn xlib_window(&self) -> xlib::Window {
+        self.window
+    }
+
+    fn make_current(&self) {
+        let ok = unsafe {
+            glx::glXMakeContextCurrent(self.display, self.window, self.window, self.context)
+        };
+        if ok == 0 {
+            panic!("Failed to make the GL context current");
+        }
+    }
+
+    fn get_proc_address(&self, name: &str) -> *const c_void {
+        let name = CString::new(name).unwrap();
+        unsafe {
+            match glx::glXGetProcAddress(name.as_ptr() as *const _) {
+                Some(address) => address as *const _,
+                None => ptr::null(),
+            }
+        }
+    }
+
+    /// Whether the swap interval can be set (`GLX_EXT_swap_control`, `GLX_MESA_swap_control`)
+    /// and may be negative (`GLX_EXT_swap_control_tear`).
+    fn swap_control(&self) -> (bool, bool) {
+        (
+            self.has_extension("GLX_EXT_swap_control")
+                || self.has_extension("GLX_MESA_swap_control"),
+            self.has_extension("GLX_EXT_swap_control_tear"),
+        )
+    }
+
+    fn has_extension(&self, name: &str) -> bool {
+        unsafe {
+            let screen = xlib::XDefaultScreen(self.display);
+            let extensions = glx::glXQueryExtensionsString(self.display, screen);
+            !extensions.is_null()
+                && CStr::from_ptr(extensions)
+                    .to_string_lossy()
+                    .split(' ')
+                    .any(|extension| extension == name)
+        }
+    }
+
+    /// PCI vendor and device ID of the renderer of the current context (`GLX_MESA_query_renderer`).
+    fn pci_ids(&self) -> Option<(usize, usize)> {
+        const GLX_RENDERER_VENDOR_ID_MESA: c_int = 0x8183;
+        const GLX_RENDERER_DEVICE_ID_MESA: c_int = 0x8184;
+
+        if !self.has_extension("GLX_MESA_query_renderer") {
+            return None;
+        }
+        let query = self.get_proc_address("glXQueryCurrentRendererIntegerMESA");
+        if query.is_null() {
+            return None;
+        }
+        unsafe {
+            let query: extern "C" fn(c_int, *mut c_uint) -> xlib::Bool = mem::transmute(query);
+            let (mut vendor, mut device) = (0, 0);
+            if query(GLX_RENDERER_VENDOR_ID_MESA, &mut vendor) == 0
+                || query(GLX_RENDERER_DEVICE_ID_MESA, &mut device) == 0
+            {
+                return None;
+            }
+            Some((vendor as usize, device as usize))
+        }
+    }
+
+    fn set_present_mode(&self, mode: hal::PresentMode) {
+        let (_, tear_control) = self.swap_control();
+        self.set_swap_interval(swap_interval(mode, tear_control));
+    }
+
+    fn set_swap_interval(&self, interval: i32) {
+        unsafe {
+            let ext = self.get_proc_address("glXSwapIntervalEXT");
+            if !ext.is_null() {
+                let swap_interval: extern "C" fn(*mut xlib::Display, glx::GLXDrawable, c_int) =
+                    mem::transmute(ext);
+                swap_interval(self.display, self.window, interval);
+                return;
+            }
+            let mesa = self.get_proc_address("glXSwapIntervalMESA");
+            if !mesa.is_null() && interval >= 0 {
+                let swap_interval: extern "C" fn(c_uint) -> c_int = mem::transmute(mesa);
+                if swap_interval(interval as _) == 0 {
+                    return;
+                }
+            }
+        }
+        warn!("Failed to set swap interval {}", interval);
+    }
+
+    fn extent(&self) -> image::Extent {
+        let mut attributes: xlib::XWindowAttributes = unsafe { mem::zeroed() };
+        unsafe { xlib::XGetWindowAttributes(self.display, self.window, &mut attributes) };
+        image::Extent {
+            width: attributes.width as image::Size,
+            height: attributes.height as image::Size,
+            depth: 1,
+        }
+    }
+}
+
+impl Drop for Window {
+    fn drop(&mut self) {
+        unsafe {
+            glx::glXMakeContextCurrent(self.display, 0, 0, ptr::null_mut());
+            glx::glXDestroyContext(self.display, self.context);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Swapchain {
+    // Underlying window, required for presentation
+    pub(crate) window: Starc<Window>,
+    // Extent because the window lies
+    pub(crate) extent: Extent2D,
+    pub(crate) images: SwapchainImages,
+}
+
+impl Swapchain {
+    /// Current extent of the window, which differs from the swapchain extent after a resize.
+    pub(crate) fn window_extent(&self) -> Extent2D {
+        Extent2D::from(self.window.extent())
+    }
+
+    /// Read the last presented frame as RGBA8, with rows from top to bottom.
+    ///
+    /// Meant for screenshots and automated tests. Returns `false` if no frame
+    /// was presented yet or it can't be read back.
+    pub fn read_frame(&self, data: &mut [u8]) -> bool {
+        self.images.read_presented(self.extent, data)
+    }
+
+    /// Change the present mode without re-creating the swapchain and its images.
+    ///
+    /// Unsupported modes fall back to `Fifo`.
+    pub fn set_present_mode(&mut self, mode: hal::PresentMode) {
+        self.window.set_present_mode(mode);
+    }
+
+    /// Enable or disable vertical sync, switching between `Fifo` and `Immediate`.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.set_present_mode(if vsync {
+            hal::PresentMode::Fifo
+        } else {
+            hal::PresentMode::Immediate
+        });
+    }
+
+    pub(crate) fn swap_buffers(&self) {
+        unsafe { glx::glXSwapBuffers(self.window.display, self.window.window) };
+    }
+}
+
+impl hal::Swapchain<B> for Swapchain {
+    unsafe fn acquire_image(
+        &mut self,
+        timeout_ns: u64,
+        _semaphore: Option<&native::Semaphore>,
+        fence: Option<&native::Fence>,
+    ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::AcquireError> {
+        // The images have to be re-created to match the window.
+        if self.window_extent() != self.extent {
+            return Err(hal::AcquireError::OutOfDate);
+        }
+        self.images.acquire(timeout_ns, fence)
+    }
+}
+
+#[derive(Debug)]
+pub struct Surface {
+    window: Starc<Window>,
+}
+
+impl Surface {
+    pub fn from_window(window: Window) -> Self {
+        Surface {
+            window: Starc::new(window),
+        }
+    }
+
+    /// Create a GL context for a X11 window and a surface presenting to it.
+    pub unsafe fn from_xlib(display: *mut xlib::Display, window: xlib::Window) -> Result<Self, String> {
+        Window::from_xlib(display, window).map(Surface::from_window)
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    fn swapchain_formats(&self) -> Vec<f::Format> {
+        // Matches the config picked in `Window::from_xlib`, which is never float
+        color_buffer_formats(self.window.color_bits, self.window.alpha_bits, false, false)
+    }
+}
+
+impl hal::Surface<B> for Surface {
+    fn kind(&self) -> hal::image::Kind {
+        let ex = self.window.extent();
+        hal::image::Kind::D2(ex.width, ex.height, 1, 1)
+    }
+
+    fn compatibility(
+        &self,
+        physical_device: &PhysicalDevice,
+    ) -> (
+        hal::SurfaceCapabilities,
+        Option<Vec<f::Format>>,
+        Vec<hal::PresentMode>,
+    ) {
+        let extent = Extent2D::from(self.window.extent());
+        // The alpha of the default framebuffer isn't used for compositing.
+        let caps = surface_capabilities(extent, CompositeAlpha::OPAQUE);
+        let present_modes = match self.window.swap_control() {
+            (true, tear_control) => present_modes(tear_control),
+            (false, _) => vec![hal::PresentMode::Fifo],
+        };
+
+        let mut formats = self.swapchain_formats();
+        add_srgb_formats(&mut formats, physical_device);
+        (caps, Some(formats), present_modes)
+    }
+
+    fn supports_queue_family(&self, _: &QueueFamily) -> bool {
+        true
+    }
+}
+
+impl Device {
+    pub(crate) fn create_swapchain_impl(
+        &self,
+        surface: &mut Surface,
+        config: hal::SwapchainConfig,
+    ) -> Result<(Swapchain, Vec<native::Image>), hal::window::CreationError> {
+        surface.window.set_present_mode(config.present_mode);
+
+        let (swapchain_images, images) = self.create_swapchain_images(&config, false)?;
+
+        let swapchain = Swapchain {
+            extent: config.extent,
+            images: swapchain_images,
+            window: surface.window.clone(),
+        };
+
+        Ok((swapchain, images))
+    }
+}
+
+impl hal::Instance for Surface {
+    type Backend = B;
+    fn enumerate_adapters(&self) -> Vec<hal::Adapter<B>> {
+        self.window.make_current();
+        let adapter = PhysicalDevice::new_adapter_with_pci_ids(
+            GlContainer::from_fn_proc(|s| self.window.get_proc_address(s)),
+            self.window.pci_ids(),
+        );
+        vec![adapter]
+    }
+}
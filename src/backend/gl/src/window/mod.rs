@@ -1,4 +1,28 @@
 #[cfg(all(not(target_arch = "wasm32"), feature = "glutin"))]
 pub mod glutin;
+#[cfg(all(not(target_arch = "wasm32"), feature = "egl"))]
+pub mod egl;
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    feature = "sdl2",
+    not(any(feature = "glutin", feature = "egl"))
+))]
+pub mod sdl2;
+#[cfg(all(
+    target_os = "windows",
+    feature = "wgl",
+    not(any(feature = "glutin", feature = "egl", feature = "sdl2"))
+))]
+pub mod wgl;
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "ios", target_os = "android")),
+    feature = "glx",
+    not(any(feature = "glutin", feature = "egl", feature = "sdl2"))
+))]
+pub mod glx;
 #[cfg(target_arch = "wasm32")]
 pub mod web;
+
+// Swapchain images shared by the windowing paths.
+pub(crate) mod swapchain;
@@ -0,0 +1,236 @@
+//! Window creation using SDL2 for gfx.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! extern crate sdl2;
+//! extern crate gfx_backend_gl;
+//! extern crate gfx_hal;
+//!
+//! use gfx_hal::Instance;
+//! use gfx_backend_gl::Surface;
+//!
+//! fn main() {
+//!     let sdl = sdl2::init().unwrap();
+//!     let video = sdl.video().unwrap();
+//!     // GL attributes have to be set before the window is created.
+//!     video.gl_attr().set_context_version(3, 2);
+//!     let window = video
+//!         .window("gfx", 800, 600)
+//!         .opengl()
+//!         .build()
+//!         .unwrap();
+//!
+//!     let surface = Surface::from_sdl_window(window).unwrap();
+//!     let _adapters = surface.enumerate_adapters();
+//! }
+//! ```
+
+use super::swapchain::{
+    add_srgb_formats, color_buffer_formats, present_modes, surface_capabilities, swap_interval,
+    SwapchainImages,
+};
+use crate::hal::window::Extent2D;
+use crate::hal::{self, format as f, image, CompositeAlpha};
+use crate::{native, Backend as B, Device, GlContainer, PhysicalDevice, QueueFamily, Starc};
+
+/// SDL window together with its GL context.
+pub struct Window {
+    // Dropped before the window.
+    context: sdl2::video::GLContext,
+    window: sdl2::video::Window,
+}
+
+impl std::fmt::Debug for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Window({})", self.window.id())
+    }
+}
+
+impl Window {
+    pub fn window(&self) -> &sdl2::video::Window {
+        &self.window
+    }
+
+    pub fn context(&self) -> &sdl2::video::GLContext {
+        &self.context
+    }
+
+    fn extent(&self) -> image::Extent {
+        let (width, height) = self.window.drawable_size();
+        image::Extent {
+            width: width as image::Size,
+            height: height as image::Size,
+            depth: 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Swapchain {
+    // Underlying window, required for presentation
+    pub(crate) window: Starc<Window>,
+    // Extent because the window lies
+    pub(crate) extent: Extent2D,
+    pub(crate) images: SwapchainImages,
+}
+
+impl Swapchain {
+    /// Current extent of the window, which differs from the swapchain extent after a resize.
+    pub(crate) fn window_extent(&self) -> Extent2D {
+        Extent2D::from(self.window.extent())
+    }
+
+    /// Read the last presented frame as RGBA8, with rows from top to bottom.
+    ///
+    /// Meant for screenshots and automated tests. Returns `false` if no frame
+    /// was presented yet or it can't be read back.
+    pub fn read_frame(&self, data: &mut [u8]) -> bool {
+        self.images.read_presented(self.extent, data)
+    }
+
+    /// Change the present mode without re-creating the swapchain and its images.
+    ///
+    /// Unsupported modes fall back to `Fifo`.
+    pub fn set_present_mode(&mut self, mode: hal::PresentMode) {
+        set_present_mode(mode);
+    }
+
+    /// Enable or disable vertical sync, switching between `Fifo` and `Immediate`.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.set_present_mode(if vsync {
+            hal::PresentMode::Fifo
+        } else {
+            hal::PresentMode::Immediate
+        });
+    }
+
+    pub(crate) fn swap_buffers(&self) {
+        self.window.window.gl_swap_window();
+    }
+}
+
+impl hal::Swapchain<B> for Swapchain {
+    unsafe fn acquire_image(
+        &mut self,
+        timeout_ns: u64,
+        _semaphore: Option<&native::Semaphore>,
+        fence: Option<&native::Fence>,
+    ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::AcquireError> {
+        // The images have to be re-created to match the window.
+        if self.window_extent() != self.extent {
+            return Err(hal::AcquireError::OutOfDate);
+        }
+        self.images.acquire(timeout_ns, fence)
+    }
+}
+
+#[derive(Debug)]
+pub struct Surface {
+    window: Starc<Window>,
+}
+
+impl Surface {
+    /// Create a surface from a SDL window, creating its GL context.
+    ///
+    /// The window has to be built with `opengl()`, the GL attributes of the
+    /// video subsystem select the context version and pixel format.
+    pub fn from_sdl_window(window: sdl2::video::Window) -> Result<Self, String> {
+        let context = window.gl_create_context()?;
+        Ok(Surface {
+            window: Starc::new(Window { context, window }),
+        })
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    fn swapchain_formats(&self) -> Vec<f::Format> {
+        let gl_attr = self.window.window.subsystem().gl_attr();
+        let color_bits = gl_attr.red_size() + gl_attr.green_size() + gl_attr.blue_size();
+        let alpha_bits = gl_attr.alpha_size();
+        let srgb = gl_attr.framebuffer_srgb_compatible();
+
+        color_buffer_formats(color_bits, alpha_bits, srgb, false)
+    }
+}
+
+/// Applies to the window of the current context.
+fn set_present_mode(mode: hal::PresentMode) {
+    let interval = swap_interval(mode, false);
+    if unsafe { sdl2::sys::SDL_GL_SetSwapInterval(interval) } != 0 {
+        warn!("Failed to set swap interval {}: {}", interval, sdl2::get_error());
+    }
+}
+
+impl hal::Surface<B> for Surface {
+    fn kind(&self) -> hal::image::Kind {
+        let ex = self.window.extent();
+        let gl_attr = self.window.window.subsystem().gl_attr();
+        let samples = if gl_attr.multisample_buffers() > 0 {
+            gl_attr.multisample_samples()
+        } else {
+            1
+        };
+        hal::image::Kind::D2(ex.width, ex.height, 1, samples as _)
+    }
+
+    fn compatibility(
+        &self,
+        physical_device: &PhysicalDevice,
+    ) -> (
+        hal::SurfaceCapabilities,
+        Option<Vec<f::Format>>,
+        Vec<hal::PresentMode>,
+    ) {
+        let extent = Extent2D::from(self.window.extent());
+        // The alpha of the default framebuffer isn't used for compositing.
+        let caps = surface_capabilities(extent, CompositeAlpha::OPAQUE);
+        // Adaptive vsync can't be queried without setting it.
+        let present_modes = present_modes(false);
+
+        let mut formats = self.swapchain_formats();
+        add_srgb_formats(&mut formats, physical_device);
+        (caps, Some(formats), present_modes)
+    }
+
+    fn supports_queue_family(&self, _: &QueueFamily) -> bool {
+        true
+    }
+}
+
+impl Device {
+    pub(crate) fn create_swapchain_impl(
+        &self,
+        surface: &mut Surface,
+        config: hal::SwapchainConfig,
+    ) -> Result<(Swapchain, Vec<native::Image>), hal::window::CreationError> {
+        set_present_mode(config.present_mode);
+
+        let gl_attr = surface.window.window.subsystem().gl_attr();
+        let srgb_backbuffer = gl_attr.framebuffer_srgb_compatible();
+        let (swapchain_images, images) = self.create_swapchain_images(&config, srgb_backbuffer)?;
+
+        let swapchain = Swapchain {
+            extent: config.extent,
+            images: swapchain_images,
+            window: surface.window.clone(),
+        };
+
+        Ok((swapchain, images))
+    }
+}
+
+impl hal::Instance for Surface {
+    type Backend = B;
+    fn enumerate_adapters(&self) -> Vec<hal::Adapter<B>> {
+        let window = &self.window;
+        window.window.gl_make_current(&window.context).unwrap();
+        let video = window.window.subsystem();
+        let adapter = PhysicalDevice::new_adapter(GlContainer::from_fn_proc(
+            |s| video.gl_get_proc_address(s) as *const _
+        ));
+        vec![adapter]
+    }
+}
@@ -0,0 +1,634 @@
+use std::cell::Cell;
+
+use crate::hal::window::{Extent2D, Suboptimal};
+use crate::hal::{self, format as f, image, memory, AcquireError, SwapImageIndex};
+use crate::{info, native, Device, GlContext, PhysicalDevice, Share, Starc, YFlip};
+
+use glow::Context;
+
+type GlFence = <GlContext as glow::Context>::Fence;
+
+/// Images of a swapchain, handed out in turn by `acquire_image`.
+///
+/// Presenting an image blits it to the default framebuffer and inserts
+/// a fence, acquiring it again waits on the fence to throttle the CPU.
+#[derive(Debug)]
+pub(crate) struct SwapchainImages {
+    share: Starc<Share>,
+    // Read framebuffer of each image, `None` without framebuffer blits.
+    framebuffers: Vec<Option<native::FrameBuffer>>,
+    // Signaled when the last present of each image finished.
+    fences: Vec<Cell<Option<GlFence>>>,
+    // Renderbuffer of each image standing in for the default framebuffer.
+    backbuffers: Vec<Option<native::Surface>>,
+    next: SwapImageIndex,
+    last_presented: Cell<Option<SwapImageIndex>>,
+}
+
+unsafe impl Send for SwapchainImages {}
+unsafe impl Sync for SwapchainImages {}
+
+impl SwapchainImages {
+    /// Acquire the next image, waiting until its previous present finished.
+    pub(crate) fn acquire(
+        &mut self,
+        timeout_ns: u64,
+        fence: Option<&native::Fence>,
+    ) -> Result<(SwapImageIndex, Option<Suboptimal>), AcquireError> {
+        let gl = &self.share.context;
+        let index = self.next;
+
+        if let Some(sync) = self.fences[index as usize].get() {
+            // Timeouts of single waits are 32 bits, the device is checked for
+            // loss in between.
+            const WAIT_SLICE_NS: u64 = 1_000_000_000;
+            let mut remaining = timeout_ns;
+            // Only the first wait has to flush the commands.
+            let mut flags = glow::SYNC_FLUSH_COMMANDS_BIT;
+            let status = loop {
+                let slice = remaining.min(WAIT_SLICE_NS);
+                let status = unsafe { gl.client_wait_sync(sync, flags, slice as i32) };
+                self.share
+                    .check_device_lost()
+                    .map_err(AcquireError::DeviceLost)?;
+                if status != glow::TIMEOUT_EXPIRED || remaining == slice {
+                    break status;
+                }
+                remaining -= slice;
+                flags = 0;
+            };
+            match status {
+                glow::TIMEOUT_EXPIRED if timeout_ns == 0 => return Err(AcquireError::NotReady),
+                glow::TIMEOUT_EXPIRED => return Err(AcquireError::Timeout),
+                glow::WAIT_FAILED => error!("Error when waiting on swapchain image {}", index),
+                _ => {}
+            }
+            unsafe { gl.delete_sync(sync) };
+            self.fences[index as usize].set(None);
+        }
+        self.next = (index + 1) % self.framebuffers.len() as SwapImageIndex;
+
+        // The image is available right away, all previous work on it finished.
+        if let Some(fence) = fence {
+            if self.share.private_caps.sync {
+                let sync = unsafe { gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0).unwrap() };
+                fence.0.set(Some(sync));
+            }
+        }
+
+        Ok((index, None))
+    }
+
+    /// Framebuffer to blit from when presenting an image.
+    pub(crate) fn framebuffer(&self, index: SwapImageIndex) -> Option<native::FrameBuffer> {
+        self.framebuffers[index as usize]
+    }
+
+    /// Whether an image was rendered to the default framebuffer directly,
+    /// presenting it doesn't need a blit.
+    pub(crate) fn is_direct(&self, index: SwapImageIndex) -> bool {
+        match self.backbuffers[index as usize] {
+            Some(surface) => self.share.is_backbuffer(surface),
+            None => false,
+        }
+    }
+
+    /// Mark an image as presented, after its blit was submitted.
+    pub(crate) fn presented(&self, index: SwapImageIndex) {
+        self.last_presented.set(Some(index));
+        if !self.share.private_caps.sync {
+            return;
+        }
+        let gl = &self.share.context;
+        let sync = unsafe { gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0).unwrap() };
+        if let Some(old) = self.fences[index as usize].replace(Some(sync)) {
+            unsafe { gl.delete_sync(old) };
+        }
+    }
+}
+
+impl SwapchainImages {
+    /// Read the last presented image as RGBA8, with rows from top to bottom.
+    pub(crate) fn read_presented(&self, extent: Extent2D, data: &mut [u8]) -> bool {
+        let index = match self.last_presented.get() {
+            Some(index) => index,
+            None => return false,
+        };
+        let width = extent.width as usize;
+        let height = extent.height as usize;
+        let stride = width * 4;
+        assert!(
+            data.len() >= stride * height,
+            "Frame of {}x{} doesn't fit into {} bytes",
+            width,
+            height,
+            data.len()
+        );
+
+        let gl = &self.share.context;
+        // Direct images live in the front buffer after the swap. WebGL keeps them
+        // in the drawing buffer until the browser composited the frame.
+        let direct = self.is_direct(index);
+        let front = direct && cfg!(not(target_arch = "wasm32"));
+        if front && self.share.info.version.is_embedded {
+            warn!("The front buffer can't be read back on GLES");
+            return false;
+        }
+        unsafe {
+            if direct {
+                if self.share.private_caps.framebuffer_blit {
+                    gl.bind_framebuffer(glow::READ_FRAMEBUFFER, None);
+                } else if self.share.private_caps.framebuffer {
+                    // ES 2.0 and WebGL 1 only have a single framebuffer binding.
+                    gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+                }
+                if front {
+                    gl.read_buffer(glow::FRONT);
+                }
+            } else {
+                gl.bind_framebuffer(glow::READ_FRAMEBUFFER, self.framebuffer(index));
+            }
+            gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+            gl.read_pixels(
+                0,
+                0,
+                extent.width as _,
+                extent.height as _,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                &mut data[..stride * height],
+            );
+            if front {
+                gl.read_buffer(glow::BACK);
+            } else if !direct {
+                gl.bind_framebuffer(glow::READ_FRAMEBUFFER, None);
+            }
+        }
+
+        // Rows are stored from bottom to top if the render target is flipped.
+        if !self.share.y_flip.flips_render_target() {
+            return true;
+        }
+        for row in 0..height / 2 {
+            let (top, bottom) = data.split_at_mut((height - 1 - row) * stride);
+            top[row * stride..(row + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+        }
+        true
+    }
+}
+
+impl Drop for SwapchainImages {
+    fn drop(&mut self) {
+        let surfaces = self
+            .backbuffers
+            .drain(..)
+            .filter_map(|surface| surface)
+            .collect::<Vec<_>>();
+        self.share
+            .backbuffers
+            .borrow_mut()
+            .retain(|backbuffer| !surfaces.contains(&backbuffer.surface));
+
+        let gl = &self.share.context;
+        for framebuffer in self.framebuffers.drain(..).filter_map(|fbo| fbo) {
+            unsafe { gl.delete_framebuffer(framebuffer) };
+        }
+        for sync in self.fences.drain(..).filter_map(|fence| fence.get()) {
+            unsafe { gl.delete_sync(sync) };
+        }
+    }
+}
+
+/// Upper bound of swapchain images, each of them is a full-size color target.
+const MAX_IMAGE_COUNT: SwapImageIndex = 8;
+
+/// Capabilities of a window surface with the current extent of the window.
+///
+/// Swapchain images are separate from the default framebuffer and blitted to it,
+/// so their count doesn't depend on the window. The extent has to match the window,
+/// acquiring returns `OutOfDate` otherwise.
+pub(crate) fn surface_capabilities(
+    extent: Extent2D,
+    composite_alpha: hal::CompositeAlpha,
+) -> hal::SurfaceCapabilities {
+    hal::SurfaceCapabilities {
+        image_count: 1..MAX_IMAGE_COUNT,
+        current_extent: Some(extent),
+        extents: extent..Extent2D {
+            width: extent.width + 1,
+            height: extent.height + 1,
+        },
+        max_image_layers: 1,
+        // Sampled images are backed by textures instead of renderbuffers.
+        usage: image::Usage::COLOR_ATTACHMENT
+            | image::Usage::TRANSFER_SRC
+            | image::Usage::SAMPLED,
+        composite_alpha,
+    }
+}
+
+/// Swapchain formats matching the color buffer of a window.
+///
+/// Float color buffers are exposed as linear scRGB (`Rgba16Sfloat`),
+/// which holds values outside of `[0, 1]` for HDR output.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn color_buffer_formats(
+    color_bits: u8,
+    alpha_bits: u8,
+    srgb: bool,
+    float: bool,
+) -> Vec<f::Format> {
+    match (color_bits, alpha_bits, srgb, float) {
+        (24, 8, true, false) => vec![f::Format::Rgba8Srgb, f::Format::Bgra8Srgb],
+        (24, 8, false, false) => vec![f::Format::Rgba8Unorm, f::Format::Bgra8Unorm],
+        (30, 2, _, false) => vec![f::Format::A2r10g10b10Unorm, f::Format::A2b10g10r10Unorm],
+        (48, 16, _, true) => vec![f::Format::Rgba16Sfloat],
+        _ => vec![],
+    }
+}
+
+/// Swapchain image rendered to the default framebuffer directly.
+///
+/// The renderbuffer has no storage until the image is attached to a framebuffer
+/// together with other images, which requires presenting through a blit again.
+#[derive(Debug)]
+pub(crate) struct Backbuffer {
+    surface: native::Surface,
+    internal_format: u32,
+    extent: Extent2D,
+    srgb: bool,
+}
+
+impl Share {
+    /// Whether a renderbuffer stands in for the default framebuffer.
+    pub(crate) fn is_backbuffer(&self, surface: native::Surface) -> bool {
+        self.backbuffers
+            .borrow()
+            .iter()
+            .any(|backbuffer| backbuffer.surface == surface)
+    }
+
+    /// Name of a swapchain image standing in for the default framebuffer without
+    /// FBO support, which has no renderbuffer behind it.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn backbuffer_name(&self) -> native::Surface {
+        // Renderbuffer names can't be generated, take them from the top of the range.
+        self.backbuffers
+            .borrow()
+            .iter()
+            .map(|backbuffer| backbuffer.surface)
+            .min()
+            .map_or(!0, |name| name - 1)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn backbuffer_name(&self) -> native::Surface {
+        unreachable!("WebGL always supports FBOs")
+    }
+
+    /// Whether rendering to the default framebuffer has to encode sRGB values.
+    pub(crate) fn backbuffer_srgb(&self) -> bool {
+        self.backbuffers.borrow().iter().any(|backbuffer| backbuffer.srgb)
+    }
+
+    /// Allocate the storage of a renderbuffer standing in for the default framebuffer,
+    /// the image is presented through a blit from now on.
+    pub(crate) fn allocate_backbuffer(&self, surface: native::Surface) {
+        let mut backbuffers = self.backbuffers.borrow_mut();
+        let index = match backbuffers.iter().position(|backbuffer| backbuffer.surface == surface) {
+            Some(index) => index,
+            None => return,
+        };
+        let backbuffer = backbuffers.swap_remove(index);
+        info!("Swapchain image attached with other images, presenting through a blit");
+
+        let gl = &self.context;
+        let Extent2D { width, height } = backbuffer.extent;
+        unsafe {
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(surface));
+            gl.renderbuffer_storage(
+                glow::RENDERBUFFER,
+                backbuffer.internal_format,
+                width as _,
+                height as _,
+            );
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+        }
+    }
+}
+
+/// Add the sRGB variants of the 8-bit swapchain formats.
+///
+/// sRGB images hold encoded values, present copies them to the default framebuffer
+/// with `GL_FRAMEBUFFER_SRGB` disabled whatever its color space is. Without control
+/// over `GL_FRAMEBUFFER_SRGB` (GLES, WebGL) the blit would decode them.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn add_srgb_formats(formats: &mut Vec<f::Format>, physical_device: &PhysicalDevice) {
+    let legacy = physical_device.0.legacy_features;
+    if !legacy.contains(info::LegacyFeatures::SRGB_COLOR) {
+        return;
+    }
+    for srgb in &[f::Format::Rgba8Srgb, f::Format::Bgra8Srgb] {
+        if !formats.contains(srgb) {
+            formats.push(*srgb);
+        }
+    }
+}
+
+/// Present modes which can be expressed with a swap interval.
+#[cfg(not(target_arch = "wasm32"))]
+///
+/// `Relaxed` requires `*_EXT_swap_control_tear` for negative intervals.
+pub(crate) fn present_modes(tear_control: bool) -> Vec<hal::PresentMode> {
+    let mut modes = vec![hal::PresentMode::Fifo, hal::PresentMode::Immediate];
+    if tear_control {
+        modes.push(hal::PresentMode::Relaxed);
+    }
+    modes
+}
+
+/// Swap interval implementing a present mode, unsupported modes fall back to `Fifo`.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn swap_interval(mode: hal::PresentMode, tear_control: bool) -> i32 {
+    match mode {
+        hal::PresentMode::Immediate => 0,
+        hal::PresentMode::Fifo => 1,
+        hal::PresentMode::Relaxed if tear_control => -1,
+        other => {
+            warn!("Present mode {:?} is not supported, using Fifo", other);
+            1
+        }
+    }
+}
+
+/// Internal format, pixel format and type of swapchain images with `format`.
+///
+/// Without sized formats (ES 2.0, WebGL 1) the internal format of textures has to
+/// match the pixel format, which leaves `Rgba8Unorm` as the only renderable one.
+fn image_format(format: f::Format, unsized_formats: bool) -> Option<(u32, u32, u32)> {
+    if unsized_formats {
+        return match format {
+            f::Format::Rgba8Unorm => Some((glow::RGBA, glow::RGBA, glow::UNSIGNED_BYTE)),
+            _ => None,
+        };
+    }
+    let formats = match format {
+        f::Format::Rgba8Unorm => (glow::RGBA8, glow::RGBA, glow::UNSIGNED_BYTE),
+        f::Format::Bgra8Unorm => (glow::RGBA8, glow::BGRA, glow::UNSIGNED_BYTE),
+        f::Format::Rgba8Srgb => (glow::SRGB8_ALPHA8, glow::RGBA, glow::UNSIGNED_BYTE),
+        f::Format::Bgra8Srgb => (glow::SRGB8_ALPHA8, glow::BGRA, glow::UNSIGNED_BYTE),
+        f::Format::A2r10g10b10Unorm => {
+            (glow::RGB10_A2, glow::BGRA, glow::UNSIGNED_INT_2_10_10_10_REV)
+        }
+        f::Format::A2b10g10r10Unorm => {
+            (glow::RGB10_A2, glow::RGBA, glow::UNSIGNED_INT_2_10_10_10_REV)
+        }
+        f::Format::Rgba16Sfloat => (glow::RGBA16F, glow::RGBA, glow::HALF_FLOAT),
+        _ => return None,
+    };
+    Some(formats)
+}
+
+/// Whether images of a swapchain with `config` can be rendered to the default
+/// framebuffer directly, saving the blit on present.
+///
+/// Only plain color targets are, and only if the render target is flipped so the
+/// orientation matches. sRGB encoding on the default framebuffer is toggled per
+/// render pass, which requires `srgb_framebuffer` for sRGB images.
+fn renders_directly(config: &hal::SwapchainConfig, y_flip: YFlip, srgb_framebuffer: bool) -> bool {
+    y_flip.flips_render_target()
+        && config.image_usage == image::Usage::COLOR_ATTACHMENT
+        && config.image_layers == 1
+        && (config.format.base_format().1 != f::ChannelType::Srgb || srgb_framebuffer)
+}
+
+impl Device {
+    /// Create the images backing a swapchain, which are blitted to the
+    /// default framebuffer on present.
+    ///
+    /// `srgb_backbuffer` tells if the default framebuffer is sRGB capable.
+    pub(crate) fn create_swapchain_images(
+        &self,
+        config: &hal::SwapchainConfig,
+        srgb_backbuffer: bool,
+    ) -> Result<(SwapchainImages, Vec<native::Image>), hal::window::CreationError> {
+        let gl = &self.share.context;
+        let caps = &self.share.private_caps;
+
+        let (int_format, iformat, itype) = match image_format(config.format, caps.unsized_formats) {
+            Some(formats) => formats,
+            None => {
+                error!("Swapchain format {:?} is not supported", config.format);
+                return Err(hal::device::OutOfMemory::OutOfHostMemory.into());
+            }
+        };
+
+        let channel = config.format.base_format().1;
+
+        // WebGL keeps the blit, which can target XR framebuffers, unless it's missing.
+        let srgb_color = self
+            .share
+            .legacy_features
+            .contains(info::LegacyFeatures::SRGB_COLOR);
+        let direct = (cfg!(not(target_arch = "wasm32")) || !caps.framebuffer_blit)
+            && renders_directly(config, self.share.y_flip, srgb_color && srgb_backbuffer);
+        // Without FBOs or blits (ES 2.0, WebGL 1) images can't be copied to the default
+        // framebuffer, so it's the only render target.
+        if !direct && !caps.framebuffer_blit {
+            error!(
+                "Swapchain with {:?} usage and {:?} format can't render to the default \
+                 framebuffer, which is required without framebuffer blits",
+                config.image_usage, config.format
+            );
+            return Err(hal::device::OutOfMemory::OutOfHostMemory.into());
+        }
+
+        let images = (0..config.image_count)
+            .map(|_| unsafe {
+                let image = if config.image_layers > 1
+                    || config.image_usage.contains(image::Usage::STORAGE)
+                    || config.image_usage.contains(image::Usage::SAMPLED)
+                {
+                    let name = gl.create_texture().unwrap();
+                    match config.extent {
+                        Extent2D {
+                            width: w,
+                            height: h,
+                        } => {
+                            gl.bind_texture(glow::TEXTURE_2D, Some(name));
+                            if self.share.private_caps.image_storage {
+                                gl.tex_storage_2d(
+                                    glow::TEXTURE_2D,
+                                    config.image_layers as _,
+                                    int_format,
+                                    w as _,
+                                    h as _,
+                                );
+                            } else {
+                                gl.tex_parameter_i32(
+                                    glow::TEXTURE_2D,
+                                    glow::TEXTURE_MAX_LEVEL,
+                                    (config.image_layers - 1) as _,
+                                );
+                                let mut w = w;
+                                let mut h = h;
+                                for i in 0..config.image_layers {
+                                    gl.tex_image_2d(
+                                        glow::TEXTURE_2D,
+                                        i as _,
+                                        int_format as _,
+                                        w as _,
+                                        h as _,
+                                        0,
+                                        iformat,
+                                        itype,
+                                        None,
+                                    );
+                                    w = std::cmp::max(w / 2, 1);
+                                    h = std::cmp::max(h / 2, 1);
+                                }
+                            }
+                        }
+                    };
+                    native::ImageKind::Texture(name, glow::TEXTURE_2D)
+                } else {
+                    let name = if self.share.private_caps.framebuffer {
+                        gl.create_renderbuffer().unwrap()
+                    } else {
+                        self.share.backbuffer_name()
+                    };
+                    match config.extent {
+                        Extent2D {
+                            width: w,
+                            height: h,
+                        } => {
+                            if direct {
+                                self.share.backbuffers.borrow_mut().push(Backbuffer {
+                                    surface: name,
+                                    internal_format: int_format,
+                                    extent: config.extent,
+                                    srgb: channel == f::ChannelType::Srgb,
+                                });
+                            } else {
+                                gl.bind_renderbuffer(glow::RENDERBUFFER, Some(name));
+                                gl.renderbuffer_storage(
+                                    glow::RENDERBUFFER,
+                                    int_format,
+                                    w as _,
+                                    h as _,
+                                );
+                            }
+                        }
+                    };
+                    native::ImageKind::Surface(name)
+                };
+
+                let surface_desc = config.format.base_format().0.desc();
+                let bytes_per_texel = surface_desc.bits / 8;
+                let ext = config.extent;
+                let size = (ext.width * ext.height) as u64 * bytes_per_texel as u64;
+                let type_mask = self.share.image_memory_type_mask(false, false);
+
+                if let Err(err) = self.share.check() {
+                    panic!(
+                        "Error creating swapchain image: {:?} with {:?} format",
+                        err, config.format
+                    );
+                }
+
+                native::Image {
+                    kind: image,
+                    channel,
+                    requirements: memory::Requirements {
+                        size,
+                        alignment: 1,
+                        type_mask,
+                    },
+                    host_image: None,
+                    upload: native::TexelUpload {
+                        format: iformat,
+                        ty: itype,
+                        conversion: native::TexelConversion::None,
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let framebuffers = images
+            .iter()
+            .map(|image| unsafe {
+                // Images are only read through a framebuffer if they are blitted.
+                if !self.share.private_caps.framebuffer_blit {
+                    return None;
+                }
+                let name = gl.create_framebuffer().unwrap();
+                gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(name));
+                match image.kind {
+                    native::ImageKind::Surface(surface) => gl.framebuffer_renderbuffer(
+                        glow::READ_FRAMEBUFFER,
+                        glow::COLOR_ATTACHMENT0,
+                        glow::RENDERBUFFER,
+                        Some(surface),
+                    ),
+                    native::ImageKind::Texture(texture, textype) => gl.framebuffer_texture_2d(
+                        glow::READ_FRAMEBUFFER,
+                        glow::COLOR_ATTACHMENT0,
+                        textype,
+                        Some(texture),
+                        0,
+                    ),
+                    native::ImageKind::Linear(_) => unreachable!(),
+                }
+                gl.bind_framebuffer(glow::READ_FRAMEBUFFER, None);
+                Some(name)
+            })
+            .collect::<Vec<_>>();
+
+        let backbuffers = images
+            .iter()
+            .map(|image| match image.kind {
+                native::ImageKind::Surface(surface) if direct => Some(surface),
+                _ => None,
+            })
+            .collect();
+
+        let swapchain_images = SwapchainImages {
+            share: self.share.clone(),
+            fences: framebuffers.iter().map(|_| Cell::new(None)).collect(),
+            framebuffers,
+            backbuffers,
+            next: 0,
+            last_presented: Cell::new(None),
+        };
+
+        Ok((swapchain_images, images))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{image_format, renders_directly};
+    use crate::hal::{self, format as f, image};
+    use crate::YFlip;
+
+    #[test]
+    fn test_unblittable_present() {
+        // WebGL 1 can't blit, the default swapchain has to render to the canvas.
+        let mut config = hal::SwapchainConfig::new(640, 480, f::Format::Rgba8Unorm, 2);
+        assert!(renders_directly(&config, YFlip::RenderTarget, false));
+        assert_eq!(
+            image_format(config.format, true),
+            Some((glow::RGBA, glow::RGBA, glow::UNSIGNED_BYTE))
+        );
+        assert_eq!(image_format(f::Format::Bgra8Unorm, true), None);
+        assert_eq!(image_format(f::Format::Rgba8Srgb, true), None);
+
+        assert!(!renders_directly(&config, YFlip::Present, false));
+        config.image_usage |= image::Usage::SAMPLED;
+        assert!(!renders_directly(&config, YFlip::RenderTarget, false));
+        config.image_usage = image::Usage::COLOR_ATTACHMENT;
+        config.format = f::Format::Rgba8Srgb;
+        assert!(!renders_directly(&config, YFlip::RenderTarget, false));
+        assert!(renders_directly(&config, YFlip::RenderTarget, true));
+    }
+}
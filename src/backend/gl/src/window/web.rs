@@ -1,8 +1,12 @@
 use crate::hal::window::Extent2D;
-use crate::hal::{self, format as f, image, memory, CompositeAlpha};
+use crate::hal::{self, format as f, image, CompositeAlpha};
+use crate::window::swapchain::{surface_capabilities, SwapchainImages};
 use crate::{native, Backend as B, Device, GlContainer, PhysicalDevice, QueueFamily};
 
-use glow::Context;
+use wasm_bindgen::{closure::Closure, JsCast};
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 
 fn get_window_extent(window: &Window) -> image::Extent {
     image::Extent {
@@ -20,10 +24,364 @@ struct PixelFormat {
     multisampling: Option<u32>,
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct Window;
+fn create_canvas() -> web_sys::HtmlCanvasElement {
+    let document = web_sys::window()
+        .and_then(|win| win.document())
+        .expect("Cannot get document");
+    let canvas = document
+        .create_element("canvas")
+        .expect("Cannot create canvas")
+        .dyn_into::<web_sys::HtmlCanvasElement>()
+        .expect("Cannot get canvas element");
+    // TODO: Remove hardcoded width/height
+    canvas.set_attribute("width", "640").expect("Cannot set width");
+    canvas.set_attribute("height", "480").expect("Cannot set height");
+    document.body()
+        .expect("Cannot get document body")
+        .append_child(&canvas)
+        .expect("Cannot insert canvas into document body");
+    canvas
+}
+
+fn device_pixel_ratio() -> f64 {
+    // Workers have no window, their offscreen canvases are sized explicitly.
+    web_sys::window()
+        .map(|win| win.device_pixel_ratio())
+        .unwrap_or(1.0)
+}
+
+fn request_animation_frame<F: FnOnce(f64) + 'static>(callback: F) {
+    let callback = Closure::once_into_js(callback);
+    web_sys::window()
+        .expect("requestAnimationFrame requires a window")
+        .request_animation_frame(callback.unchecked_ref())
+        .expect("Cannot request animation frame");
+}
+
+/// Canvas backing a web surface.
+#[derive(Clone, Debug)]
+pub enum Canvas {
+    /// Canvas element of the document.
+    Html(web_sys::HtmlCanvasElement),
+    /// Offscreen canvas, e.g. obtained with `transferControlToOffscreen`
+    /// for rendering from a web worker.
+    Offscreen(web_sys::OffscreenCanvas),
+}
+
+impl Canvas {
+    fn width(&self) -> u32 {
+        match *self {
+            Canvas::Html(ref canvas) => canvas.width(),
+            Canvas::Offscreen(ref canvas) => canvas.width(),
+        }
+    }
+
+    fn height(&self) -> u32 {
+        match *self {
+            Canvas::Html(ref canvas) => canvas.height(),
+            Canvas::Offscreen(ref canvas) => canvas.height(),
+        }
+    }
+
+    /// Size of the canvas in physical pixels, taking the device pixel ratio into account.
+    fn physical_size(&self) -> (u32, u32) {
+        match *self {
+            Canvas::Html(ref canvas) => {
+                let (width, height) = (canvas.client_width(), canvas.client_height());
+                if width <= 0 || height <= 0 {
+                    // Not laid out (yet), fall back to the backing store size.
+                    return (canvas.width(), canvas.height());
+                }
+                let dpr = device_pixel_ratio();
+                (
+                    (width as f64 * dpr).round() as u32,
+                    (height as f64 * dpr).round() as u32,
+                )
+            }
+            Canvas::Offscreen(ref canvas) => (canvas.width(), canvas.height()),
+        }
+    }
+
+    /// Resize the backing store of the canvas.
+    fn set_size(&self, width: u32, height: u32) {
+        match *self {
+            Canvas::Html(ref canvas) => {
+                canvas.set_width(width);
+                canvas.set_height(height);
+            }
+            Canvas::Offscreen(ref canvas) => {
+                canvas.set_width(width);
+                canvas.set_height(height);
+            }
+        }
+    }
+
+    fn event_target(&self) -> &web_sys::EventTarget {
+        match *self {
+            Canvas::Html(ref canvas) => canvas.as_ref(),
+            Canvas::Offscreen(ref canvas) => canvas.as_ref(),
+        }
+    }
+
+    pub(crate) fn get_context_with_context_options(
+        &self,
+        context_id: &str,
+        context_options: &wasm_bindgen::JsValue,
+    ) -> Result<Option<js_sys::Object>, wasm_bindgen::JsValue> {
+        match *self {
+            Canvas::Html(ref canvas) => {
+                canvas.get_context_with_context_options(context_id, context_options)
+            }
+            Canvas::Offscreen(ref canvas) => {
+                canvas.get_context_with_context_options(context_id, context_options)
+            }
+        }
+    }
+}
+
+impl From<web_sys::HtmlCanvasElement> for Canvas {
+    fn from(canvas: web_sys::HtmlCanvasElement) -> Self {
+        Canvas::Html(canvas)
+    }
+}
+
+impl From<web_sys::OffscreenCanvas> for Canvas {
+    fn from(canvas: web_sys::OffscreenCanvas) -> Self {
+        Canvas::Offscreen(canvas)
+    }
+}
+
+/// Loss state of a WebGL context, driven by the `webglcontextlost`
+/// and `webglcontextrestored` events of its canvas.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ContextLoss {
+    lost: Rc<Cell<bool>>,
+    restored: Rc<Cell<bool>>,
+}
+
+/// Listeners of the context loss events of a canvas, registered once per
+/// canvas and removed when dropped.
+#[derive(Debug)]
+struct ContextLossListener {
+    target: web_sys::EventTarget,
+    // State of the context handed out last by the canvas.
+    current: Rc<RefCell<ContextLoss>>,
+    on_lost: Closure<dyn FnMut(web_sys::Event)>,
+    on_restored: Closure<dyn FnMut(web_sys::Event)>,
+}
+
+impl ContextLossListener {
+    fn listen(canvas: &Canvas) -> Self {
+        let current = Rc::new(RefCell::new(ContextLoss::default()));
+
+        let lost = current.clone();
+        let on_lost = Closure::wrap(Box::new(move |event: web_sys::Event| {
+            // The browser only restores the context if the default is prevented.
+            event.prevent_default();
+            lost.borrow().lost.set(true);
+        }) as Box<dyn FnMut(_)>);
+        let restored = current.clone();
+        let on_restored = Closure::wrap(Box::new(move |_: web_sys::Event| {
+            restored.borrow().restored.set(true);
+        }) as Box<dyn FnMut(_)>);
+
+        let target = canvas.event_target().clone();
+        target
+            .add_event_listener_with_callback("webglcontextlost", on_lost.as_ref().unchecked_ref())
+            .expect("Cannot add context lost listener");
+        target
+            .add_event_listener_with_callback(
+                "webglcontextrestored",
+                on_restored.as_ref().unchecked_ref(),
+            )
+            .expect("Cannot add context restored listener");
+
+        ContextLossListener {
+            target,
+            current,
+            on_lost,
+            on_restored,
+        }
+    }
+
+    /// Track the loss of a new context of the canvas with a fresh state.
+    fn track(&self) -> ContextLoss {
+        let context_loss = ContextLoss::default();
+        *self.current.borrow_mut() = context_loss.clone();
+        context_loss
+    }
+}
+
+impl Drop for ContextLossListener {
+    fn drop(&mut self) {
+        let _ = self.target.remove_event_listener_with_callback(
+            "webglcontextlost",
+            self.on_lost.as_ref().unchecked_ref(),
+        );
+        let _ = self.target.remove_event_listener_with_callback(
+            "webglcontextrestored",
+            self.on_restored.as_ref().unchecked_ref(),
+        );
+    }
+}
+
+impl ContextLoss {
+    /// Whether the context has been lost. Stays set after the context is restored,
+    /// as all objects created before the loss are gone.
+    pub(crate) fn is_lost(&self) -> bool {
+        self.lost.get()
+    }
+
+    fn is_restored(&self) -> bool {
+        self.lost.get() && self.restored.get()
+    }
+}
+
+/// Power preference hint of a WebGL context.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PowerPreference {
+    Default,
+    LowPower,
+    HighPerformance,
+}
+
+/// Attributes of the WebGL context created for a window.
+#[derive(Clone, Debug)]
+pub struct ContextAttributes {
+    pub antialias: bool,
+    pub alpha: bool,
+    pub premultiplied_alpha: bool,
+    pub preserve_drawing_buffer: bool,
+    pub power_preference: PowerPreference,
+    /// Low latency rendering, decoupled from the event loop of the page.
+    pub desynchronized: bool,
+    /// Make the context compatible with immersive WebXR devices.
+    pub xr_compatible: bool,
+}
+
+impl Default for ContextAttributes {
+    fn default() -> Self {
+        ContextAttributes {
+            antialias: false,
+            alpha: true,
+            premultiplied_alpha: true,
+            preserve_drawing_buffer: false,
+            power_preference: PowerPreference::Default,
+            desynchronized: false,
+            xr_compatible: false,
+        }
+    }
+}
+
+impl ContextAttributes {
+    pub fn with_antialias(mut self, antialias: bool) -> Self {
+        self.antialias = antialias;
+        self
+    }
+
+    pub fn with_alpha(mut self, alpha: bool) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn with_premultiplied_alpha(mut self, premultiplied_alpha: bool) -> Self {
+        self.premultiplied_alpha = premultiplied_alpha;
+        self
+    }
+
+    pub fn with_preserve_drawing_buffer(mut self, preserve_drawing_buffer: bool) -> Self {
+        self.preserve_drawing_buffer = preserve_drawing_buffer;
+        self
+    }
+
+    pub fn with_power_preference(mut self, power_preference: PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    pub fn with_desynchronized(mut self, desynchronized: bool) -> Self {
+        self.desynchronized = desynchronized;
+        self
+    }
+
+    pub fn with_xr_compatible(mut self, xr_compatible: bool) -> Self {
+        self.xr_compatible = xr_compatible;
+        self
+    }
+
+    /// Build the context options object passed to `getContext`.
+    pub(crate) fn to_js(&self) -> js_sys::Object {
+        let power_preference = match self.power_preference {
+            PowerPreference::Default => "default",
+            PowerPreference::LowPower => "low-power",
+            PowerPreference::HighPerformance => "high-performance",
+        };
+        let options = js_sys::Object::new();
+        let entries: [(&str, wasm_bindgen::JsValue); 7] = [
+            ("antialias", self.antialias.into()),
+            ("alpha", self.alpha.into()),
+            ("premultipliedAlpha", self.premultiplied_alpha.into()),
+            ("preserveDrawingBuffer", self.preserve_drawing_buffer.into()),
+            ("powerPreference", power_preference.into()),
+            ("desynchronized", self.desynchronized.into()),
+            ("xrCompatible", self.xr_compatible.into()),
+        ];
+        for (key, value) in entries.iter() {
+            js_sys::Reflect::set(&options, &(*key).into(), value)
+                .expect("Cannot create context options");
+        }
+        options
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Window {
+    canvas: Option<Canvas>,
+    attributes: ContextAttributes,
+}
 
 impl Window {
+    /// Set the attributes of the WebGL context created for this window.
+    pub fn with_context_attributes(mut self, attributes: ContextAttributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    /// Use an existing canvas, either a canvas element or an offscreen canvas.
+    pub fn from_canvas<C: Into<Canvas>>(canvas: C) -> Self {
+        Window {
+            canvas: Some(canvas.into()),
+            attributes: ContextAttributes::default(),
+        }
+    }
+
+    /// Look up an existing canvas element by its id.
+    ///
+    /// Returns `None` if there is no such element or it isn't a canvas.
+    pub fn from_canvas_id(id: &str) -> Option<Self> {
+        let canvas = web_sys::window()
+            .and_then(|win| win.document())
+            .expect("Cannot get document")
+            .get_element_by_id(id)?
+            .dyn_into::<web_sys::HtmlCanvasElement>()
+            .ok()?;
+        Some(Window::from_canvas(canvas))
+    }
+
+    /// Look up an existing canvas element by a CSS selector.
+    ///
+    /// Returns `None` if there is no such element or it isn't a canvas.
+    pub fn from_selector(selector: &str) -> Option<Self> {
+        let canvas = web_sys::window()
+            .and_then(|win| win.document())
+            .expect("Cannot get document")
+            .query_selector(selector)
+            .ok()??
+            .dyn_into::<web_sys::HtmlCanvasElement>()
+            .ok()?;
+        Some(Window::from_canvas(canvas))
+    }
+
     fn get_pixel_format(&self) -> PixelFormat {
         PixelFormat {
             color_bits: 24,
@@ -34,8 +392,18 @@ impl Window {
         }
     }
 
-    pub fn get_hidpi_factor(&self) -> i32 {
-        1
+    /// Schedule `callback` for the next frame of the browser compositor,
+    /// using `requestAnimationFrame`.
+    ///
+    /// Rendering and presenting from within the callback paces the application
+    /// to the display instead of free-running. The callback receives the frame
+    /// timestamp in milliseconds.
+    pub fn request_animation_frame<F: FnOnce(f64) + 'static>(&self, callback: F) {
+        request_animation_frame(callback);
+    }
+
+    pub fn get_hidpi_factor(&self) -> f64 {
+        device_pixel_ratio()
     }
 
     pub fn resize<T>(&self, parameter: T) {}
@@ -45,28 +413,174 @@ impl Window {
 pub struct Swapchain {
     pub(crate) window: Window,
     pub(crate) extent: Extent2D,
+    canvas: Option<Canvas>,
+    context_loss: ContextLoss,
+    // Set on present until the browser composited the frame.
+    frame_pending: Rc<Cell<bool>>,
+    pub(crate) xr_framebuffer: Option<web_sys::WebGlFramebuffer>,
+    pub(crate) images: SwapchainImages,
+}
+
+impl Swapchain {
+    /// Present into the framebuffer of a `XRWebGLLayer` instead of the canvas.
+    ///
+    /// The framebuffer has to be updated for every XR frame, `None` presents
+    /// to the canvas again (e.g. for inline sessions). The swapchain extent
+    /// should match the framebuffer size of the layer.
+    pub fn bind_xr_framebuffer(&mut self, framebuffer: Option<web_sys::WebGlFramebuffer>) {
+        self.xr_framebuffer = framebuffer;
+    }
+
+    /// Current extent of the canvas, which differs from the swapchain extent after a resize.
+    ///
+    /// XR framebuffers are sized by the session and always match.
+    pub(crate) fn window_extent(&self) -> Extent2D {
+        match (&self.canvas, &self.xr_framebuffer) {
+            (Some(canvas), None) => {
+                let (width, height) = canvas.physical_size();
+                Extent2D { width, height }
+            }
+            _ => self.extent,
+        }
+    }
+
+    /// Read the last presented frame as RGBA8, with rows from top to bottom.
+    ///
+    /// Meant for screenshots and automated tests. Returns `false` if no frame
+    /// was presented yet or it can't be read back.
+    pub fn read_frame(&self, data: &mut [u8]) -> bool {
+        self.images.read_presented(self.extent, data)
+    }
+
+    /// Change the present mode without re-creating the swapchain and its images.
+    ///
+    /// Unsupported modes fall back to `Fifo`.
+    pub fn set_present_mode(&mut self, mode: hal::PresentMode) {
+        // Frames are always synchronized to the browser's refresh.
+        if mode != hal::PresentMode::Fifo {
+            warn!("Present mode {:?} is not supported, using Fifo", mode);
+        }
+    }
+
+    /// Enable or disable vertical sync, switching between `Fifo` and `Immediate`.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.set_present_mode(if vsync {
+            hal::PresentMode::Fifo
+        } else {
+            hal::PresentMode::Immediate
+        });
+    }
+
+    /// Mark the current frame as presented, it stays pending until
+    /// the next animation frame of the browser.
+    pub(crate) fn schedule_frame(&self) {
+        if self.frame_pending.replace(true) {
+            return;
+        }
+        let frame_pending = self.frame_pending.clone();
+        // Workers without animation frames aren't paced.
+        if web_sys::window().is_some() {
+            request_animation_frame(move |_| frame_pending.set(false));
+        } else {
+            frame_pending.set(false);
+        }
+    }
 }
 
 impl hal::Swapchain<B> for Swapchain {
     unsafe fn acquire_image(
         &mut self,
-        _timeout_ns: u64,
+        timeout_ns: u64,
         _semaphore: Option<&native::Semaphore>,
-        _fence: Option<&native::Fence>,
+        fence: Option<&native::Fence>,
     ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::AcquireError> {
-        // TODO: sync
-        Ok((0, None))
+        if self.context_loss.is_restored() {
+            return Err(hal::AcquireError::OutOfDate);
+        }
+        if self.context_loss.is_lost() {
+            return Err(hal::AcquireError::DeviceLost(hal::device::DeviceLost));
+        }
+        // We can't block on the web, only report the pending frame
+        // if the caller doesn't want to wait.
+        if timeout_ns == 0 && self.frame_pending.get() {
+            return Err(hal::AcquireError::NotReady);
+        }
+        // The images have to be re-created to match the canvas.
+        if self.window_extent() != self.extent {
+            return Err(hal::AcquireError::OutOfDate);
+        }
+        self.images.acquire(timeout_ns, fence)
     }
 }
 
 #[derive(Debug)]
 pub struct Surface {
     window: Window,
+    canvas: RefCell<Option<Canvas>>,
+    context_loss: RefCell<ContextLoss>,
+    context_loss_listener: RefCell<Option<ContextLossListener>>,
 }
 
 impl Surface {
     pub fn from_window(window: Window) -> Self {
-        Surface { window: Window }
+        Surface {
+            canvas: RefCell::new(window.canvas.clone()),
+            window,
+            context_loss: RefCell::new(ContextLoss::default()),
+            context_loss_listener: RefCell::new(None),
+        }
+    }
+
+    /// Create a surface rendering into an existing canvas element.
+    pub fn from_canvas(canvas: web_sys::HtmlCanvasElement) -> Self {
+        Surface::from_window(Window::from_canvas(canvas))
+    }
+
+    /// Create a surface rendering into an offscreen canvas.
+    ///
+    /// This allows rendering from a web worker, the canvas is usually
+    /// obtained with `transferControlToOffscreen` on the main thread.
+    pub fn from_offscreen_canvas(canvas: web_sys::OffscreenCanvas) -> Self {
+        Surface::from_window(Window::from_canvas(canvas))
+    }
+
+    /// Create a surface from an existing WebGL2 context.
+    ///
+    /// Querying the canvas of the context for a `webgl2` context again
+    /// hands out the very same context.
+    pub fn from_context(context: web_sys::WebGl2RenderingContext) -> Self {
+        let canvas = context.canvas().expect("Context is not attached to a canvas");
+        let canvas = match canvas.dyn_into::<web_sys::HtmlCanvasElement>() {
+            Ok(canvas) => Canvas::Html(canvas),
+            Err(canvas) => Canvas::Offscreen(
+                canvas
+                    .dyn_into::<web_sys::OffscreenCanvas>()
+                    .expect("Unknown canvas type"),
+            ),
+        };
+        Surface::from_window(Window::from_canvas(canvas))
+    }
+
+    fn extent(&self) -> image::Extent {
+        match *self.canvas.borrow() {
+            Some(ref canvas) => {
+                let (width, height) = canvas.physical_size();
+                image::Extent {
+                    width: width as image::Size,
+                    height: height as image::Size,
+                    depth: 1,
+                }
+            }
+            None => get_window_extent(&self.window),
+        }
+    }
+
+    /// Check if the WebGL context has been restored after it was lost.
+    ///
+    /// All device resources are invalid at this point: the adapters have to be
+    /// enumerated again and the device, swapchain and resources re-created.
+    pub fn is_context_restored(&self) -> bool {
+        self.context_loss.borrow().is_restored()
     }
 
     pub fn get_window(&self) -> &Window {
@@ -94,42 +608,39 @@ impl Surface {
 
 impl hal::Surface<B> for Surface {
     fn kind(&self) -> hal::image::Kind {
-        let ex = get_window_extent(&self.window);
+        let ex = self.extent();
         let samples = self.window.get_pixel_format().multisampling.unwrap_or(1);
         hal::image::Kind::D2(ex.width, ex.height, 1, samples as _)
     }
 
     fn compatibility(
         &self,
-        _: &PhysicalDevice,
+        physical_device: &PhysicalDevice,
     ) -> (
         hal::SurfaceCapabilities,
         Option<Vec<f::Format>>,
         Vec<hal::PresentMode>,
     ) {
-        let ex = get_window_extent(&self.window);
-        let extent = hal::window::Extent2D::from(ex);
-
-        let caps = hal::SurfaceCapabilities {
-            image_count: if self.window.get_pixel_format().double_buffer {
-                2..3
-            } else {
-                1..2
-            },
-            current_extent: Some(extent),
-            extents: extent..hal::window::Extent2D {
-                width: ex.width + 1,
-                height: ex.height + 1,
-            },
-            max_image_layers: 1,
-            usage: image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
-            composite_alpha: CompositeAlpha::OPAQUE, //TODO
+        let extent = Extent2D::from(self.extent());
+        let composite_alpha = match self.window.attributes {
+            ContextAttributes { alpha: false, .. } => CompositeAlpha::OPAQUE,
+            ContextAttributes { premultiplied_alpha: true, .. } => CompositeAlpha::PREMULTIPLIED,
+            ContextAttributes { .. } => CompositeAlpha::POSTMULTIPLIED,
         };
-        let present_modes = vec![
-            hal::PresentMode::Fifo, //TODO
-        ];
+        let mut caps = surface_capabilities(extent, composite_alpha);
+        // Without blits (WebGL 1) images are rendered to the canvas directly.
+        if !physical_device.0.private_caps.framebuffer_blit {
+            caps.usage = image::Usage::COLOR_ATTACHMENT;
+        }
+        // Browsers composite at the display refresh rate.
+        let present_modes = vec![hal::PresentMode::Fifo];
 
-        (caps, Some(self.swapchain_formats()), present_modes)
+        let mut formats = self.swapchain_formats();
+        // WebGL 1 images have unsized formats, of which only `RGBA` is renderable.
+        if physical_device.0.private_caps.unsized_formats {
+            formats.retain(|&format| format == f::Format::Rgba8Unorm);
+        }
+        (caps, Some(formats), present_modes)
     }
 
     fn supports_queue_family(&self, _: &QueueFamily) -> bool {
@@ -143,117 +654,45 @@ impl Device {
         &self,
         surface: &mut Surface,
         config: hal::SwapchainConfig,
-    ) -> (Swapchain, Vec<native::Image>) {
+    ) -> Result<(Swapchain, Vec<native::Image>), hal::window::CreationError> {
+        let (swapchain_images, images) = self.create_swapchain_images(&config, false)?;
+
         let swapchain = Swapchain {
             extent: config.extent,
+            images: swapchain_images,
             window: surface.window.clone(),
+            canvas: surface.canvas.borrow().clone(),
+            context_loss: surface.context_loss.borrow().clone(),
+            frame_pending: Rc::new(Cell::new(false)),
+            xr_framebuffer: None,
         };
 
-        let gl = &self.share.context;
-
-        let (int_format, iformat, itype) = match config.format {
-            f::Format::Rgba8Unorm => (glow::RGBA8, glow::RGBA, glow::UNSIGNED_BYTE),
-            f::Format::Rgba8Srgb => (glow::SRGB8_ALPHA8, glow::RGBA, glow::UNSIGNED_BYTE),
-            _ => unimplemented!(),
-        };
-
-        let channel = config.format.base_format().1;
-
-        let images = (0..config.image_count)
-            .map(|_| unsafe {
-                let image = if config.image_layers > 1
-                    || config.image_usage.contains(image::Usage::STORAGE)
-                    || config.image_usage.contains(image::Usage::SAMPLED)
-                {
-                    let name = gl.create_texture().unwrap();
-                    match config.extent {
-                        Extent2D {
-                            width: w,
-                            height: h,
-                        } => {
-                            gl.bind_texture(glow::TEXTURE_2D, Some(name));
-                            if self.share.private_caps.image_storage {
-                                gl.tex_storage_2d(
-                                    glow::TEXTURE_2D,
-                                    config.image_layers as _,
-                                    int_format,
-                                    w as _,
-                                    h as _,
-                                );
-                            } else {
-                                gl.tex_parameter_i32(
-                                    glow::TEXTURE_2D,
-                                    glow::TEXTURE_MAX_LEVEL,
-                                    (config.image_layers - 1) as _,
-                                );
-                                let mut w = w;
-                                let mut h = h;
-                                for i in 0..config.image_layers {
-                                    gl.tex_image_2d(
-                                        glow::TEXTURE_2D,
-                                        i as _,
-                                        int_format as _,
-                                        w as _,
-                                        h as _,
-                                        0,
-                                        iformat,
-                                        itype,
-                                        None,
-                                    );
-                                    w = std::cmp::max(w / 2, 1);
-                                    h = std::cmp::max(h / 2, 1);
-                                }
-                            }
-                        }
-                    };
-                    native::ImageKind::Texture(name, glow::TEXTURE_2D)
-                } else {
-                    let name = gl.create_renderbuffer().unwrap();
-                    match config.extent {
-                        Extent2D {
-                            width: w,
-                            height: h,
-                        } => {
-                            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(name));
-                            gl.renderbuffer_storage(glow::RENDERBUFFER, int_format, w as _, h as _);
-                        }
-                    };
-                    native::ImageKind::Surface(name)
-                };
-
-                let surface_desc = config.format.base_format().0.desc();
-                let bytes_per_texel = surface_desc.bits / 8;
-                let ext = config.extent;
-                let size = (ext.width * ext.height) as u64 * bytes_per_texel as u64;
-                let type_mask = self.share.image_memory_type_mask();
-
-                if let Err(err) = self.share.check() {
-                    panic!(
-                        "Error creating swapchain image: {:?} with {:?} format",
-                        err, config.format
-                    );
-                }
-
-                native::Image {
-                    kind: image,
-                    channel,
-                    requirements: memory::Requirements {
-                        size,
-                        alignment: 1,
-                        type_mask,
-                    },
-                }
-            })
-            .collect::<Vec<_>>();
+        // Match the backing store with the swapchain, which is sized in physical pixels.
+        if let Some(ref canvas) = *surface.canvas.borrow() {
+            canvas.set_size(config.extent.width, config.extent.height);
+        }
 
-        (swapchain, images)
+        Ok((swapchain, images))
     }
 }
 
 impl hal::Instance for Surface {
     type Backend = B;
     fn enumerate_adapters(&self) -> Vec<hal::Adapter<B>> {
-        let adapter = PhysicalDevice::new_adapter(GlContainer::from_new_canvas()); // TODO: Move to `self` like native/window
+        let canvas = self
+            .canvas
+            .borrow_mut()
+            .get_or_insert_with(|| Canvas::Html(create_canvas()))
+            .clone();
+        // A restored context is handed out by the same canvas, track the
+        // next loss with a fresh state.
+        let context_loss = self
+            .context_loss_listener
+            .borrow_mut()
+            .get_or_insert_with(|| ContextLossListener::listen(&canvas))
+            .track();
+        *self.context_loss.borrow_mut() = context_loss.clone();
+        let adapter = PhysicalDevice::new_adapter(GlContainer::from_canvas(&canvas, &self.window.attributes, context_loss)); // TODO: Move to `self` like native/window
         vec![adapter]
     }
 }
@@ -0,0 +1,318 @@
+//! Window creation using WGL, attaching to an existing native window.
+//!
+//! Meant for embedding into windows owned by other toolkits (e.g. editor
+//! widgets), which keep control of the event loop.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::{mem, ptr};
+
+use winapi::shared::minwindef::HMODULE;
+use winapi::shared::windef::{HDC, HGLRC, HWND, RECT};
+use winapi::um::{libloaderapi, wingdi, winuser};
+
+use super::swapchain::{
+    add_srgb_formats, color_buffer_formats, present_modes, surface_capabilities, swap_interval,
+    SwapchainImages,
+};
+use crate::hal::window::Extent2D;
+use crate::hal::{self, format as f, image, CompositeAlpha};
+use crate::{native, Backend as B, Device, GlContainer, PhysicalDevice, QueueFamily, Starc};
+
+/// GL context created for a native window.
+#[derive(Debug)]
+pub struct Window {
+    hwnd: HWND,
+    hdc: HDC,
+    context: HGLRC,
+    // Exports the GL 1.1 entry points, which `wglGetProcAddress` doesn't return.
+    opengl32: HMODULE,
+    // Bits of the color buffer, excluding and only alpha.
+    color_bits: u8,
+    alpha_bits: u8,
+}
+
+impl Window {
+    /// Create a GL context for a native window.
+    ///
+    /// Sets a RGBA8, D24S8, double buffered pixel format on the window, which
+    /// must not have a pixel format yet. The window has to outlive the context.
+    pub unsafe fn from_hwnd(hwnd: HWND) -> Result<Self, String> {
+        let hdc = winuser::GetDC(hwnd);
+        if hdc.is_null() {
+            return Err("Failed to get the device context".into());
+        }
+
+        let mut pfd: wingdi::PIXELFORMATDESCRIPTOR = mem::zeroed();
+        pfd.nSize = mem::size_of::<wingdi::PIXELFORMATDESCRIPTOR>() as _;
+        pfd.nVersion = 1;
+        pfd.dwFlags = wingdi::PFD_DRAW_TO_WINDOW | wingdi::PFD_SUPPORT_OPENGL | wingdi::PFD_DOUBLEBUFFER;
+        pfd.iPixelType = wingdi::PFD_TYPE_RGBA;
+        pfd.cColorBits = 32;
+        pfd.cAlphaBits = 8;
+        pfd.cDepthBits = 24;
+        pfd.cStencilBits = 8;
+        pfd.iLayerType = wingdi::PFD_MAIN_PLANE;
+
+        let format = wingdi::ChoosePixelFormat(hdc, &pfd);
+        if format == 0 || wingdi::SetPixelFormat(hdc, format, &pfd) == 0 {
+            winuser::ReleaseDC(hwnd, hdc);
+            return Err("Failed to set the pixel format".into());
+        }
+
+        // The driver may pick a deeper format than requested.
+        let mut actual: wingdi::PIXELFORMATDESCRIPTOR = mem::zeroed();
+        wingdi::DescribePixelFormat(hdc, format, pfd.nSize as _, &mut actual);
+        let color_bits = actual.cRedBits + actual.cGreenBits + actual.cBlueBits;
+        let alpha_bits = actual.cAlphaBits;
+
+        let context = wingdi::wglCreateContext(hdc);
+        if context.is_null() {
+            winuser::ReleaseDC(hwnd, hdc);
+            return Err("Failed to create the GL context".into());
+        }
+
+        let name = CString::new("opengl32.dll").unwrap();
+        let opengl32 = libloaderapi::LoadLibraryA(name.as_ptr());
+
+        Ok(Window {
+            hwnd,
+            hdc,
+            context,
+            opengl32,
+            color_bits,
+            alpha_bits,
+        })
+    }
+
+    pub fn hwnd(&self) -> HWND {
+        self.hwnd
+    }
+
+    fn make_current(&self) {
+        if unsafe { wingdi::wglMakeCurrent(self.hdc, self.context) } == 0 {
+            panic!("Failed to make the GL context current");
+        }
+    }
+
+    fn get_proc_address(&self, name: &str) -> *const std::os::raw::c_void {
+        let name = CString::new(name).unwrap();
+        unsafe {
+            let address = wingdi::wglGetProcAddress(name.as_ptr()) as isize;
+            // Some implementations return small values instead of null on failure.
+            match address {
+                -1 | 0 | 1 | 2 | 3 => {
+                    libloaderapi::GetProcAddress(self.opengl32, name.as_ptr()) as *const _
+                }
+                _ => address as *const _,
+            }
+        }
+    }
+
+    /// Whether the swap interval can be set (`WGL_EXT_swap_control`)
+    /// and may be negative (`WGL_EXT_swap_control_tear`).
+    fn swap_control(&self) -> (bool, bool) {
+        let get_extensions = self.get_proc_address("wglGetExtensionsStringEXT");
+        if get_extensions.is_null() {
+            return (false, false);
+        }
+        let extensions = unsafe {
+            let get_extensions: extern "system" fn() -> *const c_char =
+                mem::transmute(get_extensions);
+            CStr::from_ptr(get_extensions()).to_string_lossy().into_owned()
+        };
+        let has = |name: &str| extensions.split(' ').any(|extension| extension == name);
+        (has("WGL_EXT_swap_control"), has("WGL_EXT_swap_control_tear"))
+    }
+
+    fn set_present_mode(&self, mode: hal::PresentMode) {
+        let (_, tear_control) = self.swap_control();
+        self.set_swap_interval(swap_interval(mode, tear_control));
+    }
+
+    fn set_swap_interval(&self, interval: i32) {
+        let address = self.get_proc_address("wglSwapIntervalEXT");
+        let ok = !address.is_null() && unsafe {
+            let swap_interval: extern "system" fn(c_int) -> c_int = mem::transmute(address);
+            swap_interval(interval) != 0
+        };
+        if !ok {
+            warn!("Failed to set swap interval {}", interval);
+        }
+    }
+
+    fn extent(&self) -> image::Extent {
+        let mut rect: RECT = unsafe { mem::zeroed() };
+        unsafe { winuser::GetClientRect(self.hwnd, &mut rect) };
+        image::Extent {
+            width: (rect.right - rect.left) as image::Size,
+            height: (rect.bottom - rect.top) as image::Size,
+            depth: 1,
+        }
+    }
+}
+
+impl Drop for Window {
+    fn drop(&mut self) {
+        unsafe {
+            wingdi::wglMakeCurrent(ptr::null_mut(), ptr::null_mut());
+            wingdi::wglDeleteContext(self.context);
+            winuser::ReleaseDC(self.hwnd, self.hdc);
+            if !self.opengl32.is_null() {
+                libloaderapi::FreeLibrary(self.opengl32);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Swapchain {
+    // Underlying window, required for presentation
+    pub(crate) window: Starc<Window>,
+    // Extent because the window lies
+    pub(crate) extent: Extent2D,
+    pub(crate) images: SwapchainImages,
+}
+
+impl Swapchain {
+    /// Current extent of the window, which differs from the swapchain extent after a resize.
+    pub(crate) fn window_extent(&self) -> Extent2D {
+        Extent2D::from(self.window.extent())
+    }
+
+    /// Read the last presented frame as RGBA8, with rows from top to bottom.
+    ///
+    /// Meant for screenshots and automated tests. Returns `false` if no frame
+    /// was presented yet or it can't be read back.
+    pub fn read_frame(&self, data: &mut [u8]) -> bool {
+        self.images.read_presented(self.extent, data)
+    }
+
+    /// Change the present mode without re-creating the swapchain and its images.
+    ///
+    /// Unsupported modes fall back to `Fifo`.
+    pub fn set_present_mode(&mut self, mode: hal::PresentMode) {
+        self.window.set_present_mode(mode);
+    }
+
+    /// Enable or disable vertical sync, switching between `Fifo` and `Immediate`.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.set_present_mode(if vsync {
+            hal::PresentMode::Fifo
+        } else {
+            hal::PresentMode::Immediate
+        });
+    }
+
+    pub(crate) fn swap_buffers(&self) {
+        if unsafe { wingdi::SwapBuffers(self.window.hdc) } == 0 {
+            error!("Failed to swap buffers");
+        }
+    }
+}
+
+impl hal::Swapchain<B> for Swapchain {
+    unsafe fn acquire_image(
+        &mut self,
+        timeout_ns: u64,
+        _semaphore: Option<&native::Semaphore>,
+        fence: Option<&native::Fence>,
+    ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::AcquireError> {
+        // The images have to be re-created to match the window.
+        if self.window_extent() != self.extent {
+            return Err(hal::AcquireError::OutOfDate);
+        }
+        self.images.acquire(timeout_ns, fence)
+    }
+}
+
+#[derive(Debug)]
+pub struct Surface {
+    window: Starc<Window>,
+}
+
+impl Surface {
+    pub fn from_window(window: Window) -> Self {
+        Surface {
+            window: Starc::new(window),
+        }
+    }
+
+    /// Create a GL context for a native window and a surface presenting to it.
+    pub unsafe fn from_hwnd(hwnd: HWND) -> Result<Self, String> {
+        Window::from_hwnd(hwnd).map(Surface::from_window)
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    fn swapchain_formats(&self) -> Vec<f::Format> {
+        // Matches the pixel format set in `Window::from_hwnd`, float formats
+        // require `WGL_ARB_pixel_format_float` which isn't used.
+        color_buffer_formats(self.window.color_bits, self.window.alpha_bits, false, false)
+    }
+}
+
+impl hal::Surface<B> for Surface {
+    fn kind(&self) -> hal::image::Kind {
+        let ex = self.window.extent();
+        hal::image::Kind::D2(ex.width, ex.height, 1, 1)
+    }
+
+    fn compatibility(
+        &self,
+        physical_device: &PhysicalDevice,
+    ) -> (
+        hal::SurfaceCapabilities,
+        Option<Vec<f::Format>>,
+        Vec<hal::PresentMode>,
+    ) {
+        let extent = Extent2D::from(self.window.extent());
+        // The alpha of the default framebuffer isn't used for compositing.
+        let caps = surface_capabilities(extent, CompositeAlpha::OPAQUE);
+        let present_modes = match self.window.swap_control() {
+            (true, tear_control) => present_modes(tear_control),
+            (false, _) => vec![hal::PresentMode::Fifo],
+        };
+
+        let mut formats = self.swapchain_formats();
+        add_srgb_formats(&mut formats, physical_device);
+        (caps, Some(formats), present_modes)
+    }
+
+    fn supports_queue_family(&self, _: &QueueFamily) -> bool {
+        true
+    }
+}
+
+impl Device {
+    pub(crate) fn create_swapchain_impl(
+        &self,
+        surface: &mut Surface,
+        config: hal::SwapchainConfig,
+    ) -> Result<(Swapchain, Vec<native::Image>), hal::window::CreationError> {
+        surface.window.set_present_mode(config.present_mode);
+
+        let (swapchain_images, images) = self.create_swapchain_images(&config, false)?;
+
+        let swapchain = Swapchain {
+            extent: config.extent,
+            images: swapchain_images,
+            window: surface.window.clone(),
+        };
+
+        Ok((swapchain, images))
+    }
+}
+
+impl hal::Instance for Surface {
+    type Backend = B;
+    fn enumerate_adapters(&self) -> Vec<hal::Adapter<B>> {
+        self.window.make_current();
+        let adapter = PhysicalDevice::new_adapter(GlContainer::from_fn_proc(
+            |s| self.window.get_proc_address(s)
+        ));
+        vec![adapter]
+    }
+}
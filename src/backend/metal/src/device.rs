@@ -402,6 +402,11 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
                 hal::Features::empty()
             }
             | hal::Features::SHADER_CLIP_DISTANCE
+            | if self.shared.private_caps.os_is_mac {
+                hal::Features::SAMPLER_MIRROR_CLAMP_EDGE
+            } else {
+                hal::Features::empty()
+            }
     }
 
     fn limits(&self) -> hal::Limits {
@@ -82,6 +82,8 @@ lazy_static! {
     };
     static ref EXTENSIONS: Vec<&'static CStr> = vec![#[cfg(debug_assertions)] CStr::from_bytes_with_nul(b"VK_EXT_debug_utils\0").unwrap()];
     static ref DEVICE_EXTENSIONS: Vec<&'static CStr> = vec![extensions::khr::Swapchain::name()];
+    static ref MIRROR_CLAMP_EXTENSION: &'static CStr =
+        CStr::from_bytes_with_nul(b"VK_KHR_sampler_mirror_clamp_to_edge\0").unwrap();
     static ref SURFACE_EXTENSIONS: Vec<&'static CStr> = vec![
         extensions::khr::Surface::name(),
         // Platform-specific WSI extensions
@@ -509,6 +511,18 @@ pub struct PhysicalDevice {
     properties: vk::PhysicalDeviceProperties,
 }
 
+impl PhysicalDevice {
+    fn supports_extension(&self, name: &CStr) -> bool {
+        unsafe { self.instance.0.enumerate_device_extension_properties(self.handle) }
+            .map(|extensions| {
+                extensions
+                    .iter()
+                    .any(|ext| unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) } == name)
+            })
+            .unwrap_or(false)
+    }
+}
+
 impl hal::PhysicalDevice<Backend> for PhysicalDevice {
     unsafe fn open(
         &self,
@@ -535,10 +549,13 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
 
         // Create device
         let device_raw = {
-            let cstrings = DEVICE_EXTENSIONS
+            let mut cstrings = DEVICE_EXTENSIONS
                 .iter()
                 .map(|&s| CString::from(s))
                 .collect::<Vec<_>>();
+            if requested_features.contains(Features::SAMPLER_MIRROR_CLAMP_EDGE) {
+                cstrings.push(CString::from(*MIRROR_CLAMP_EXTENSION));
+            }
 
             let str_pointers = cstrings.iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
 
@@ -812,6 +829,9 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
         if features.fragment_stores_and_atomics != 0 {
             bits |= Features::FRAGMENT_STORES_AND_ATOMICS;
         }
+        if self.supports_extension(*MIRROR_CLAMP_EXTENSION) {
+            bits |= Features::SAMPLER_MIRROR_CLAMP_EDGE;
+        }
         //TODO: cover more features
 
         bits
@@ -866,6 +886,7 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
             non_coherent_atom_size: limits.non_coherent_atom_size as _,
             max_sampler_anisotropy: limits.max_sampler_anisotropy,
             min_vertex_input_binding_stride_alignment: 1,
+            timestamp_period: limits.timestamp_period,
             .. Limits::default() //TODO: please halp
         }
     }
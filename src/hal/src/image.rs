@@ -433,6 +433,9 @@ pub enum WrapMode {
     Clamp,
     /// Use border color.
     Border,
+    /// Mirror once, then clamp: sample abs(coord) clamped to `1.0`.
+    /// Requires `Features::SAMPLER_MIRROR_CLAMP_EDGE`.
+    MirrorClamp,
 }
 
 /// A wrapper for the LOD level of an image.
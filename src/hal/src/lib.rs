@@ -212,6 +212,8 @@ bitflags! {
         const VARIABLE_MULTISAMPLE_RATE = 0x020_0000_0000_0000;
         ///
         const INHERITED_QUERIES = 0x040_0000_0000_0000;
+        /// Support the mirror clamp to edge wrap mode (`WrapMode::MirrorClamp`).
+        const SAMPLER_MIRROR_CLAMP_EDGE = 0x080_0000_0000_0000;
 
         /// Support triangle fan primitive topology.
         const TRIANGLE_FAN = 0x1000_0000_0000_0000;
@@ -374,6 +376,9 @@ pub struct Limits {
 
     /// The alignment of the vertex buffer stride.
     pub min_vertex_input_binding_stride_alignment: buffer::Offset,
+
+    /// Number of nanoseconds per increment of timestamp queries.
+    pub timestamp_period: f32,
 }
 
 /// Describes the type of geometric primitives,